@@ -0,0 +1,328 @@
+use std::{ffi::CStr, marker::PhantomData, mem, ptr};
+
+/// A builder of the `hints` argument of `getaddrinfo()`.
+///
+/// The fields that are not set stay zeroed, which `getaddrinfo()` takes as "no restriction".
+/// By default, the hints match any family, socket type and protocol.
+#[derive(Debug, Clone, Copy)]
+pub struct Hints {
+    family: i32,
+    socktype: i32,
+    protocol: i32,
+    flags: i32,
+}
+
+impl Default for Hints {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Hints {
+    pub fn new() -> Self {
+        Hints {
+            family: libc::AF_UNSPEC,
+            socktype: 0,
+            protocol: 0,
+            flags: 0,
+        }
+    }
+
+    /// Restricts the results to `family` (`AF_INET`, `AF_INET6` or `AF_UNSPEC`).
+    pub fn family(mut self, family: i32) -> Self {
+        self.family = family;
+        self
+    }
+
+    /// Restricts the results to `socktype` (e.g. `SOCK_STREAM` or `SOCK_DGRAM`).
+    pub fn socktype(mut self, socktype: i32) -> Self {
+        self.socktype = socktype;
+        self
+    }
+
+    /// Restricts the results to `protocol` (e.g. `IPPROTO_TCP`).
+    pub fn protocol(mut self, protocol: i32) -> Self {
+        self.protocol = protocol;
+        self
+    }
+
+    /// Adds `flags` (e.g. `AI_CANONNAME`) to the flags that are already set.
+    pub fn flags(mut self, flags: i32) -> Self {
+        self.flags |= flags;
+        self
+    }
+
+    /// Sets or clears `AI_PASSIVE`, which makes a `None` node resolve to the wildcard address instead of the loopback.
+    pub fn passive(mut self, passive: bool) -> Self {
+        self.set_flag(libc::AI_PASSIVE, passive);
+        self
+    }
+
+    /// Sets or clears `AI_ADDRCONFIG`, which only returns the addresses of the families that the host has
+    /// a non-loopback address configured for, e.g. no IPv6 addresses on an IPv4-only host.
+    pub fn addrconfig(mut self, addrconfig: bool) -> Self {
+        self.set_flag(libc::AI_ADDRCONFIG, addrconfig);
+        self
+    }
+
+    /// Sets or clears `AI_V4MAPPED`, which returns the IPv4 addresses as IPv4-mapped IPv6 addresses (`::ffff:a.b.c.d`)
+    /// if there are no IPv6 addresses. It only has an effect if the family is `AF_INET6`.
+    pub fn v4mapped(mut self, v4mapped: bool) -> Self {
+        self.set_flag(libc::AI_V4MAPPED, v4mapped);
+        self
+    }
+
+    fn set_flag(&mut self, flag: i32, set: bool) {
+        if set {
+            self.flags |= flag;
+        } else {
+            self.flags &= !flag;
+        }
+    }
+
+    pub fn build(&self) -> libc::addrinfo {
+        // SAFETY: All zero `addrinfo` is a valid initialization, the pointers in it are null.
+        let mut hints: libc::addrinfo = unsafe { mem::zeroed() };
+        hints.ai_family = self.family;
+        hints.ai_socktype = self.socktype;
+        hints.ai_protocol = self.protocol;
+        hints.ai_flags = self.flags;
+
+        hints
+    }
+}
+
+/// The linked list returned by `getaddrinfo()`, which is freed with `freeaddrinfo()` when it goes out of scope.
+///
+/// Holding an `AddrInfoList` instead of a bare `*mut libc::addrinfo` means that the list is freed exactly once,
+/// on every path: early returns, `?` and panics included.
+#[derive(Debug)]
+pub struct AddrInfoList {
+    head: *mut libc::addrinfo,
+}
+
+impl AddrInfoList {
+    /// Resolves `node` and `service` by calling `getaddrinfo()` with `hints`.
+    /// Pass `None` as `node` for a wildcard (with `AI_PASSIVE`) or a loopback address,
+    /// and `None` as `service` to leave the port unset. They cannot both be `None`.
+    ///
+    /// Returns the message of `gai_strerror()` on failure.
+    pub fn new(
+        node: Option<&CStr>,
+        service: Option<&CStr>,
+        hints: &libc::addrinfo,
+    ) -> Result<AddrInfoList, String> {
+        let node = node.map_or(ptr::null(), |node| node.as_ptr());
+        let service = service.map_or(ptr::null(), |service| service.as_ptr());
+        let mut head: *mut libc::addrinfo = ptr::null_mut();
+
+        // SAFETY: There is no uninitialized memory access. `getaddrinfo()` is safe to call.
+        let ecode = unsafe { libc::getaddrinfo(node, service, hints, &mut head) };
+        if ecode != 0 {
+            // SAFETY: `gai_strerror` is valid to call on a failed `getaddrinfo()` call.
+            let err = unsafe { CStr::from_ptr(libc::gai_strerror(ecode)).to_string_lossy() };
+            return Err(err.into_owned());
+        }
+
+        Ok(AddrInfoList { head })
+    }
+
+    /// Returns the first entry, a successful `getaddrinfo()` call always returns at least one.
+    pub fn first(&self) -> &libc::addrinfo {
+        // SAFETY: `head` is not null after a successful `getaddrinfo()` call, and it lives as long as `self`.
+        unsafe { &*self.head }
+    }
+
+    /// Iterates over the entries in the order `getaddrinfo()` returns them.
+    pub fn iter(&self) -> Iter<'_> {
+        Iter {
+            next: self.head,
+            _list: PhantomData,
+        }
+    }
+}
+
+impl Drop for AddrInfoList {
+    fn drop(&mut self) {
+        // SAFETY: `head` is the head of the list returned by `getaddrinfo()`.
+        // It is only freed here, and none of the borrowed entries can outlive `self`.
+        unsafe { libc::freeaddrinfo(self.head) };
+
+        #[cfg(test)]
+        tests::FREED.with(|freed| freed.set(freed.get() + 1));
+    }
+}
+
+impl<'a> IntoIterator for &'a AddrInfoList {
+    type Item = &'a libc::addrinfo;
+    type IntoIter = Iter<'a>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+/// An iterator over the entries of an `AddrInfoList`, see `AddrInfoList::iter()`.
+pub struct Iter<'a> {
+    next: *mut libc::addrinfo,
+    _list: PhantomData<&'a AddrInfoList>,
+}
+
+impl<'a> Iterator for Iter<'a> {
+    type Item = &'a libc::addrinfo;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.next.is_null() {
+            return None;
+        }
+
+        // SAFETY: `next` is not null, and it points to an entry of the list which lives as long as `'a`.
+        let ai = unsafe { &*self.next };
+        self.next = ai.ai_next;
+
+        Some(ai)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        cell::Cell,
+        net::{Ipv4Addr, Ipv6Addr},
+        panic,
+    };
+
+    use super::*;
+
+    thread_local! {
+        // The number of lists freed on this thread, the tests run on separate threads.
+        pub(super) static FREED: Cell<usize> = const { Cell::new(0) };
+    }
+
+    fn freed() -> usize {
+        FREED.with(Cell::get)
+    }
+
+    fn resolve(node: &CStr) -> Result<AddrInfoList, String> {
+        let hints = Hints::new().flags(libc::AI_NUMERICHOST).build();
+        AddrInfoList::new(Some(node), Some(c"3490"), &hints)
+    }
+
+    // Fails after the list is resolved, the list is dropped on the way out.
+    fn resolve_then_fail(node: &CStr) -> Result<(), String> {
+        let list = resolve(node)?;
+        if list.iter().count() > 0 {
+            return Err(String::from("failed after getaddrinfo()"));
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn default_hints_are_unrestricted() {
+        let hints = Hints::new().build();
+
+        assert_eq!(hints.ai_family, libc::AF_UNSPEC);
+        assert_eq!(hints.ai_socktype, 0);
+        assert_eq!(hints.ai_protocol, 0);
+        assert_eq!(hints.ai_flags, 0);
+        assert_eq!(hints.ai_addrlen, 0);
+        assert!(hints.ai_addr.is_null());
+        assert!(hints.ai_canonname.is_null());
+        assert!(hints.ai_next.is_null());
+    }
+
+    #[test]
+    fn hints_set_the_addrinfo_fields() {
+        let hints = Hints::new()
+            .family(libc::AF_INET6)
+            .socktype(libc::SOCK_STREAM)
+            .protocol(libc::IPPROTO_TCP)
+            .flags(libc::AI_CANONNAME)
+            .passive(true)
+            .build();
+
+        assert_eq!(hints.ai_family, libc::AF_INET6);
+        assert_eq!(hints.ai_socktype, libc::SOCK_STREAM);
+        assert_eq!(hints.ai_protocol, libc::IPPROTO_TCP);
+        assert_eq!(hints.ai_flags, libc::AI_CANONNAME | libc::AI_PASSIVE);
+    }
+
+    #[test]
+    fn passive_clears_only_its_own_flag() {
+        let hints = Hints::new()
+            .flags(libc::AI_CANONNAME | libc::AI_PASSIVE)
+            .passive(false)
+            .build();
+
+        assert_eq!(hints.ai_flags, libc::AI_CANONNAME);
+    }
+
+    #[test]
+    fn addrconfig_and_v4mapped_are_set_and_cleared() {
+        let hints = Hints::new()
+            .family(libc::AF_INET6)
+            .addrconfig(true)
+            .v4mapped(true)
+            .build();
+        assert_eq!(hints.ai_flags, libc::AI_ADDRCONFIG | libc::AI_V4MAPPED);
+
+        let hints = Hints::new()
+            .addrconfig(true)
+            .v4mapped(true)
+            .addrconfig(false)
+            .build();
+        assert_eq!(hints.ai_flags, libc::AI_V4MAPPED);
+    }
+
+    #[test]
+    fn v4mapped_returns_a_mapped_address() {
+        let hints = Hints::new()
+            .family(libc::AF_INET6)
+            .flags(libc::AI_NUMERICHOST)
+            .v4mapped(true)
+            .build();
+        let list = AddrInfoList::new(Some(c"127.0.0.1"), Some(c"3490"), &hints).unwrap();
+
+        let ai = list.first();
+        assert_eq!(ai.ai_family, libc::AF_INET6);
+        // SAFETY: An `AF_INET6` entry points to a `sockaddr_in6`.
+        let sin6 = unsafe { &*(ai.ai_addr as *const libc::sockaddr_in6) };
+        assert_eq!(
+            Ipv6Addr::from(sin6.sin6_addr.s6_addr),
+            Ipv4Addr::LOCALHOST.to_ipv6_mapped()
+        );
+    }
+
+    #[test]
+    fn a_resolved_list_is_freed_once() {
+        let list = resolve(c"127.0.0.1").unwrap();
+        assert_eq!(list.iter().count(), list.into_iter().count());
+        assert_eq!(freed(), 0);
+
+        drop(list);
+        assert_eq!(freed(), 1);
+    }
+
+    #[test]
+    fn a_failed_getaddrinfo_frees_nothing() {
+        assert!(resolve(c"not a number").is_err());
+        assert_eq!(freed(), 0);
+    }
+
+    #[test]
+    fn an_early_return_frees_the_list_once() {
+        assert!(resolve_then_fail(c"::1").is_err());
+        assert_eq!(freed(), 1);
+    }
+
+    #[test]
+    fn a_panic_frees_the_list_once() {
+        let res = panic::catch_unwind(|| {
+            let _list = resolve(c"127.0.0.1").unwrap();
+            panic!("panicked while holding the list");
+        });
+
+        assert!(res.is_err());
+        assert_eq!(freed(), 1);
+    }
+}
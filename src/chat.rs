@@ -0,0 +1,21 @@
+/// The messages a chat server sends out for a single received message, as `(target fd, message)` pairs.
+pub type Outgoing = Vec<(i32, Vec<u8>)>;
+
+/// Decides where a message received by a chat server goes, and what is sent there.
+///
+/// A handler is called with the fd of the sender, the received bytes, and the fds of every connected client
+/// (the sender included). The servers only do the I/O: they send each returned message to its target,
+/// in order, and they never call the handler for a client that hung up.
+/// Any `FnMut(i32, &[u8], &[i32]) -> Outgoing` is a handler, see `broadcast_to_others()` for the default one.
+pub trait MessageHandler: FnMut(i32, &[u8], &[i32]) -> Outgoing {}
+
+impl<F: FnMut(i32, &[u8], &[i32]) -> Outgoing> MessageHandler for F {}
+
+/// The default handler: sends the message as is to every client except its sender.
+pub fn broadcast_to_others(sender_fd: i32, msg: &[u8], clients: &[i32]) -> Outgoing {
+    clients
+        .iter()
+        .filter(|fd| **fd != sender_fd)
+        .map(|fd| (*fd, msg.to_vec()))
+        .collect()
+}
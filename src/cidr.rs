@@ -0,0 +1,191 @@
+use std::{error, fmt, net::IpAddr, str::FromStr};
+
+#[derive(Debug)]
+pub enum Error {
+    InvalidAddr(String),
+    InvalidPrefix(String),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::InvalidAddr(addr) => write!(f, "cidr error: invalid address {}", addr),
+            Error::InvalidPrefix(prefix) => {
+                write!(f, "cidr error: invalid prefix length {}", prefix)
+            }
+        }
+    }
+}
+
+impl error::Error for Error {}
+
+/// A block of addresses in the CIDR notation, e.g. `10.0.0.0/8` or `fe80::/10`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Cidr {
+    network: IpAddr,
+    prefix: u8,
+}
+
+impl Cidr {
+    /// Whether `ip` is in the block.
+    ///
+    /// An IPv4-mapped IPv6 address (`::ffff:a.b.c.d`), which a dual-stack socket reports for its IPv4 peers,
+    /// is matched as the IPv4 address it maps.
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        match (self.network, ip.to_canonical()) {
+            (IpAddr::V4(network), IpAddr::V4(ip)) => {
+                let mask = u32::MAX.checked_shl(32 - self.prefix as u32).unwrap_or(0);
+                network.to_bits() & mask == ip.to_bits() & mask
+            }
+            (IpAddr::V6(network), IpAddr::V6(ip)) => {
+                let mask = u128::MAX.checked_shl(128 - self.prefix as u32).unwrap_or(0);
+                network.to_bits() & mask == ip.to_bits() & mask
+            }
+            _ => false,
+        }
+    }
+}
+
+/// Parses `addr/prefix`, a bare address is taken as a block of that address only (`/32` or `/128`).
+impl FromStr for Cidr {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (addr, prefix) = match s.split_once('/') {
+            Some((addr, prefix)) => (addr, Some(prefix)),
+            None => (s, None),
+        };
+
+        let network: IpAddr = addr
+            .parse()
+            .map_err(|_| Error::InvalidAddr(addr.to_string()))?;
+        let max_prefix = if network.is_ipv4() { 32 } else { 128 };
+
+        let prefix = match prefix {
+            Some(prefix) => prefix
+                .parse::<u8>()
+                .ok()
+                .filter(|prefix| *prefix <= max_prefix)
+                .ok_or(Error::InvalidPrefix(prefix.to_string()))?,
+            None => max_prefix,
+        };
+
+        Ok(Cidr { network, prefix })
+    }
+}
+
+impl fmt::Display for Cidr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.network, self.prefix)
+    }
+}
+
+/// Decides whether a datagram is processed, by the address it comes from.
+///
+/// A source that is in any of the `deny` blocks is dropped. Otherwise, if there are `allow` blocks,
+/// the source has to be in one of them. The default filter lets everything through.
+#[derive(Debug, Clone, Default)]
+pub struct SourceFilter {
+    pub allow: Vec<Cidr>,
+    pub deny: Vec<Cidr>,
+}
+
+impl SourceFilter {
+    pub fn permits(&self, ip: IpAddr) -> bool {
+        if self.deny.iter().any(|cidr| cidr.contains(ip)) {
+            return false;
+        }
+
+        self.allow.is_empty() || self.allow.iter().any(|cidr| cidr.contains(ip))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cidr(s: &str) -> Cidr {
+        s.parse().unwrap()
+    }
+
+    fn ip(s: &str) -> IpAddr {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn block_contains_the_addresses_under_its_prefix() {
+        assert!(cidr("10.0.0.0/8").contains(ip("10.255.0.1")));
+        assert!(!cidr("10.0.0.0/8").contains(ip("11.0.0.1")));
+        assert!(cidr("0.0.0.0/0").contains(ip("192.0.2.1")));
+        assert!(cidr("fe80::/10").contains(ip("fe80::1")));
+        assert!(!cidr("fe80::/10").contains(ip("fec0::1")));
+        assert!(cidr("::/0").contains(ip("2001:db8::1")));
+    }
+
+    #[test]
+    fn bare_address_is_a_block_of_one() {
+        assert_eq!(cidr("127.0.0.2"), cidr("127.0.0.2/32"));
+        assert_eq!(cidr("::1"), cidr("::1/128"));
+        assert!(cidr("127.0.0.2").contains(ip("127.0.0.2")));
+        assert!(!cidr("127.0.0.2").contains(ip("127.0.0.3")));
+    }
+
+    #[test]
+    fn mapped_address_matches_as_ipv4() {
+        assert!(cidr("127.0.0.0/8").contains(ip("::ffff:127.0.0.1")));
+        assert!(!cidr("127.0.0.1").contains(ip("::1")));
+        assert!(!cidr("::/0").contains(ip("127.0.0.1")));
+    }
+
+    #[test]
+    fn invalid_blocks_are_rejected() {
+        assert!(matches!(
+            "10.0.0/8".parse::<Cidr>(),
+            Err(Error::InvalidAddr(_))
+        ));
+        assert!(matches!(
+            "10.0.0.0/33".parse::<Cidr>(),
+            Err(Error::InvalidPrefix(_))
+        ));
+        assert!(matches!(
+            "::/129".parse::<Cidr>(),
+            Err(Error::InvalidPrefix(_))
+        ));
+        assert!(matches!(
+            "10.0.0.0/x".parse::<Cidr>(),
+            Err(Error::InvalidPrefix(_))
+        ));
+    }
+
+    #[test]
+    fn default_filter_permits_everything() {
+        let filter = SourceFilter::default();
+
+        assert!(filter.permits(ip("127.0.0.1")));
+        assert!(filter.permits(ip("::1")));
+    }
+
+    #[test]
+    fn deny_wins_over_allow() {
+        let filter = SourceFilter {
+            allow: vec![cidr("127.0.0.0/8")],
+            deny: vec![cidr("127.0.0.3")],
+        };
+
+        assert!(filter.permits(ip("127.0.0.2")));
+        assert!(!filter.permits(ip("127.0.0.3")));
+        // With an allow list, anything outside of it is dropped.
+        assert!(!filter.permits(ip("192.0.2.1")));
+    }
+
+    #[test]
+    fn deny_alone_permits_the_rest() {
+        let filter = SourceFilter {
+            allow: vec![],
+            deny: vec![cidr("127.0.0.3")],
+        };
+
+        assert!(filter.permits(ip("127.0.0.2")));
+        assert!(!filter.permits(ip("127.0.0.3")));
+    }
+}
@@ -0,0 +1,512 @@
+use std::{
+    ffi::{CStr, CString},
+    io,
+    net::SocketAddr,
+    thread,
+    time::Duration,
+};
+
+use crate::{
+    addrinfo::{AddrInfoList, Hints},
+    fd,
+    inet::{self, ConnectError},
+    readiness, sockaddr,
+    socket_fd::Socket,
+    socket_kind::AddressFamily,
+    sockopt, trace,
+};
+
+/// How `Client::connect()` retries a connection that is refused or times out.
+///
+/// A client that is started before its server finds nothing listening on the port yet.
+/// Instead of failing right away, the connection is retried `retries` times, doubling the delay after each attempt.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ConnectRetry {
+    pub retries: u32,
+    pub delay: Duration,
+}
+
+impl Default for ConnectRetry {
+    fn default() -> Self {
+        Self {
+            retries: 0,
+            delay: Duration::from_millis(100),
+        }
+    }
+}
+
+/// Builds a `SOCK_STREAM` client connection.
+///
+/// By default, the client connects to the loopback address of any family, without a timeout.
+/// Every address `getaddrinfo()` returns for the host is tried in order until one of them accepts the connection.
+#[derive(Debug, Clone, Default)]
+pub struct Client {
+    host: Option<String>,
+    scope: Option<String>,
+    port: u16,
+    family: i32,
+    timeout: Option<Duration>,
+    source_addr: Option<SocketAddr>,
+    max_segment: Option<i32>,
+    retry: ConnectRetry,
+}
+
+impl Client {
+    pub fn new() -> Self {
+        Self {
+            family: libc::AF_UNSPEC,
+            ..Default::default()
+        }
+    }
+
+    /// Connects to `host`, which can be a hostname or an IP address.
+    pub fn host(mut self, host: &str) -> Self {
+        self.host = Some(host.to_string());
+        self
+    }
+
+    /// Reaches a link-local IPv6 host through the interface `scope` (a name or an index),
+    /// same as a `%zone` suffix on the host, which takes precedence if both are given, see `inet::parse_ip_addr()`.
+    pub fn scope(mut self, scope: Option<&str>) -> Self {
+        self.scope = scope.map(String::from);
+        self
+    }
+
+    pub fn port(mut self, port: u16) -> Self {
+        self.port = port;
+        self
+    }
+
+    /// Restricts the candidate addresses to `family` (`AF_INET`, `AF_INET6` or `AF_UNSPEC`).
+    pub fn family(mut self, family: i32) -> Self {
+        self.family = family;
+        self
+    }
+
+    /// Gives up on a candidate address if it does not accept the connection within `timeout`.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Binds the socket to `addr` before connecting, instead of letting the kernel pick the source address.
+    ///
+    /// Only the candidate addresses of the same family as `addr` are tried.
+    pub fn source_addr(mut self, addr: SocketAddr) -> Self {
+        self.source_addr = Some(addr);
+        self
+    }
+
+    /// Sets `TCP_MAXSEG` before connecting, so that `max_segment` is advertised as the MSS in the SYN.
+    ///
+    /// It has to be set before `connect()`, the handshake is over by the time the socket is returned.
+    pub fn max_segment(mut self, max_segment: Option<i32>) -> Self {
+        self.max_segment = max_segment;
+        self
+    }
+
+    /// Retries the whole connection attempt if it is refused or times out, see `ConnectRetry`.
+    pub fn retry(mut self, retry: ConnectRetry) -> Self {
+        self.retry = retry;
+        self
+    }
+
+    /// Connects to the first candidate address that accepts the connection.
+    ///
+    /// Returns the connected socket and the address it is connected to.
+    /// If none of the candidates accept it, the error of the last one is returned once the retries run out.
+    pub fn connect(&self) -> Result<(Socket, SocketAddr), ConnectError> {
+        let retry = &self.retry;
+        let mut delay = retry.delay;
+        let mut attempt = 0;
+        loop {
+            let err = match self.connect_once() {
+                Ok(res) => return Ok(res),
+                Err(err) => err,
+            };
+
+            // Only a server that is not up yet is worth waiting for, anything else (e.g. EHOSTUNREACH)
+            // will not go away by itself.
+            let transient = matches!(
+                err,
+                ConnectError::ConnectionRefused(_) | ConnectError::TimedOut(_)
+            );
+            if !transient || attempt == retry.retries {
+                return Err(err);
+            }
+
+            attempt += 1;
+            eprintln!(
+                "connect: {}, retrying in {:?} ({}/{})",
+                err, delay, attempt, retry.retries
+            );
+            thread::sleep(delay);
+            delay *= 2;
+        }
+    }
+
+    fn connect_once(&self) -> Result<(Socket, SocketAddr), ConnectError> {
+        let mut res = Err(io::Error::from(io::ErrorKind::AddrNotAvailable));
+        for addr in self.candidates()? {
+            if self.family != libc::AF_UNSPEC && AddressFamily::of(&addr).as_raw() != self.family {
+                continue;
+            }
+            if self
+                .source_addr
+                .is_some_and(|source_addr| source_addr.is_ipv4() != addr.is_ipv4())
+            {
+                continue;
+            }
+
+            res = self.connect_addr(&addr).map(|sock| (sock, addr));
+            if res.is_ok() {
+                break;
+            }
+        }
+
+        res.map_err(ConnectError::from)
+    }
+
+    // An IP address host is used as is, so that its zone ends up in `sin6_scope_id`.
+    // Anything else (e.g. a host name) is resolved by `getaddrinfo()`.
+    fn candidates(&self) -> Result<Vec<SocketAddr>, ConnectError> {
+        if let Some(host) = &self.host {
+            let zoned_host = match &self.scope {
+                Some(scope) if !host.contains('%') => format!("{}%{}", host, scope),
+                _ => host.clone(),
+            };
+            if let Some(addr) = inet::parse_ip_addr(&zoned_host, self.port) {
+                return addr.map(|addr| vec![addr]).map_err(|err| {
+                    ConnectError::Other(io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        err.to_string(),
+                    ))
+                });
+            }
+        }
+
+        let node = match &self.host {
+            Some(host) => Some(
+                CString::new(host.as_str())
+                    .map_err(|err| ConnectError::Other(io::Error::other(err)))?,
+            ),
+            None => None,
+        };
+        let port = CString::new(self.port.to_string()).unwrap();
+
+        let ai_list = self
+            .getaddrinfo(node.as_deref(), &port)
+            .map_err(ConnectError::Other)?;
+
+        let addrs = ai_list
+            .iter()
+            .filter_map(|ai| {
+                // SAFETY: `ai.ai_addr` is filled by `getaddrinfo()` and it is valid for `ai.ai_addrlen` bytes.
+                let sa = unsafe { sockaddr::copy_to_storage(ai.ai_addr, ai.ai_addrlen) };
+                sockaddr::to_socket_addr(&sa)
+            })
+            .collect();
+
+        Ok(addrs)
+    }
+
+    fn getaddrinfo(&self, node: Option<&CStr>, port: &CStr) -> io::Result<AddrInfoList> {
+        let hints = Hints::new()
+            .family(self.family)
+            .socktype(libc::SOCK_STREAM)
+            .build();
+
+        AddrInfoList::new(node, Some(port), &hints)
+            .map_err(|err| io::Error::other(format!("getaddrinfo: {}", err)))
+    }
+
+    fn connect_addr(&self, addr: &SocketAddr) -> io::Result<Socket> {
+        // The socket is closed on drop, so a failed candidate does not leak its fd.
+        let sock = Socket::new(AddressFamily::of(addr).as_raw(), libc::SOCK_STREAM, 0)?;
+
+        if let Some(source_addr) = self.source_addr {
+            let (sa, sa_len) = sockaddr::from_socket_addr(&source_addr);
+
+            // SAFETY: `sa` holds a valid address of `sa_len` bytes.
+            let ecode = unsafe {
+                trace::bind(
+                    sock.as_raw(),
+                    &raw const sa as *const libc::sockaddr,
+                    sa_len,
+                )
+            };
+            if ecode == -1 {
+                return Err(io::Error::last_os_error());
+            }
+        }
+
+        if let Some(max_segment) = self.max_segment {
+            sockopt::set_int(
+                sock.as_raw(),
+                libc::IPPROTO_TCP,
+                libc::TCP_MAXSEG,
+                max_segment,
+            )?;
+        }
+
+        let (sa, sa_len) = sockaddr::from_socket_addr(addr);
+        match self.timeout {
+            Some(timeout) => connect_timeout(&sock, &sa, sa_len, timeout)?,
+            None => {
+                // SAFETY: `connect()` is safe to call since `sock` is valid and `sa` holds an address of `sa_len` bytes.
+                let ecode = unsafe {
+                    trace::connect(
+                        sock.as_raw(),
+                        &raw const sa as *const libc::sockaddr,
+                        sa_len,
+                    )
+                };
+                if ecode == -1 {
+                    return Err(io::Error::last_os_error());
+                }
+            }
+        }
+
+        Ok(sock)
+    }
+}
+
+// A blocking `connect()` may take minutes to give up on an unresponsive host.
+// Instead, the socket is put into non-blocking mode, so that `connect()` returns `EINPROGRESS` right away,
+// and `poll()` waits for the socket to become writable for at most `timeout`.
+// The outcome of the connection attempt is then read from `SO_ERROR`.
+fn connect_timeout(
+    sock: &Socket,
+    sa: &libc::sockaddr_storage,
+    sa_len: libc::socklen_t,
+    timeout: Duration,
+) -> io::Result<()> {
+    fd::set_nonblocking(sock.as_raw())?;
+
+    // SAFETY: `connect()` is safe to call since `sock` is valid and `sa` holds an address of `sa_len` bytes.
+    let ecode = unsafe {
+        trace::connect(
+            sock.as_raw(),
+            sa as *const libc::sockaddr_storage as *const libc::sockaddr,
+            sa_len,
+        )
+    };
+    if ecode == -1 {
+        let err = io::Error::last_os_error();
+        if err.raw_os_error() != Some(libc::EINPROGRESS) {
+            return Err(err);
+        }
+
+        if !readiness::wait_writable(sock.as_raw(), Some(timeout))? {
+            return Err(io::Error::from(io::ErrorKind::TimedOut));
+        }
+
+        let so_error = sockopt::get_int(sock.as_raw(), libc::SOL_SOCKET, libc::SO_ERROR)?;
+        if so_error != 0 {
+            return Err(io::Error::from_raw_os_error(so_error));
+        }
+    }
+
+    // The caller expects a regular, blocking socket.
+    fd::set_blocking(sock.as_raw())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Instant;
+
+    use super::*;
+    use crate::server_builder::Server;
+
+    #[test]
+    fn reports_the_ipv4_address_when_there_is_no_ipv6_server() {
+        let listener = Server::new().bind(Some("127.0.0.1"), 0).listen().unwrap();
+        let port = listener.local_addr().port();
+
+        // Without a host, the candidates are the loopback addresses of both families, `::1` first.
+        // Nothing listens on `::1`, which is what a client sees when the server has IPv6 disabled.
+        let hints = Hints::new().socktype(libc::SOCK_STREAM).build();
+        let candidates = AddrInfoList::new(None, Some(c"3490"), &hints).unwrap();
+        assert!(candidates.iter().any(|ai| ai.ai_family == libc::AF_INET6));
+
+        let (_sock, addr) = Client::new().port(port).connect().unwrap();
+        assert_eq!(addr, listener.local_addr());
+    }
+
+    #[test]
+    fn max_segment_is_read_back_within_bounds() {
+        let listener = Server::new()
+            .bind(Some("127.0.0.1"), 0)
+            .max_segment(Some(1200))
+            .listen()
+            .unwrap();
+        let (client, _) = Client::new()
+            .host("127.0.0.1")
+            .port(listener.local_addr().port())
+            .max_segment(Some(1000))
+            .connect()
+            .unwrap();
+        let (conn, _) = listener.accept().unwrap();
+
+        // Both ends settle on the smaller of the two, and the TCP options (e.g. the timestamps) take a few bytes
+        // off of it. Without `TCP_MAXSEG`, the MTU of the loopback interface would allow ~65k.
+        for sock in [&client, &conn] {
+            let mss = sockopt::get_int(sock.as_raw(), libc::IPPROTO_TCP, libc::TCP_MAXSEG).unwrap();
+            assert!((960..=1000).contains(&mss), "TCP_MAXSEG is {}", mss);
+        }
+    }
+
+    #[test]
+    fn connects_within_the_timeout() {
+        let listener = Server::new().bind(Some("127.0.0.1"), 0).listen().unwrap();
+
+        let (sock, addr) = Client::new()
+            .host("127.0.0.1")
+            .port(listener.local_addr().port())
+            .timeout(Duration::from_secs(1))
+            .connect()
+            .unwrap();
+
+        assert_eq!(addr, listener.local_addr());
+        // The non-blocking mode of the timed connect is not left behind.
+        assert!(!fd::is_nonblocking(sock.as_raw()).unwrap());
+    }
+
+    #[test]
+    fn times_out_when_the_syn_is_dropped() {
+        // Nothing is ever accepted, so once the tiny accept queue is full, the SYNs of new connections are dropped
+        // and they stay unanswered, like the ones sent to a host that is down.
+        let listener = Server::new()
+            .bind(Some("127.0.0.1"), 0)
+            .backlog(0)
+            .listen()
+            .unwrap();
+        let client = Client::new()
+            .host("127.0.0.1")
+            .port(listener.local_addr().port())
+            .timeout(Duration::from_millis(200));
+
+        let mut queued = Vec::new();
+        for _ in 0..8 {
+            let started = Instant::now();
+            match client.connect() {
+                Ok((sock, _)) => queued.push(sock),
+                Err(ConnectError::TimedOut(_)) => {
+                    assert!(started.elapsed() < Duration::from_secs(1));
+                    return;
+                }
+                Err(err) => panic!("unexpected error: {}", err),
+            }
+        }
+        panic!(
+            "{} connections went through a full accept queue",
+            queued.len()
+        );
+    }
+
+    #[test]
+    fn binds_to_the_source_address() {
+        let listener = Server::new().bind(Some("127.0.0.1"), 0).listen().unwrap();
+
+        // The whole 127.0.0.0/8 network is on the loopback interface.
+        let source_addr: SocketAddr = "127.0.0.2:0".parse().unwrap();
+        let (_sock, _) = Client::new()
+            .host("127.0.0.1")
+            .port(listener.local_addr().port())
+            .source_addr(source_addr)
+            .connect()
+            .unwrap();
+
+        let (_, peer_addr) = listener.accept().unwrap();
+        assert_eq!(peer_addr.ip(), source_addr.ip());
+    }
+
+    #[test]
+    fn source_address_of_the_other_family_is_skipped() {
+        let listener = Server::new().bind(Some("127.0.0.1"), 0).listen().unwrap();
+
+        // 127.0.0.1 is the only candidate, and it cannot be reached from an IPv6 source.
+        let res = Client::new()
+            .host("127.0.0.1")
+            .port(listener.local_addr().port())
+            .source_addr("[::1]:0".parse().unwrap())
+            .connect();
+
+        match res {
+            Err(ConnectError::Other(err)) => {
+                assert_eq!(err.kind(), io::ErrorKind::AddrNotAvailable)
+            }
+            res => panic!("unexpected result: {:?}", res.map(|(_, addr)| addr)),
+        }
+    }
+
+    #[test]
+    fn scope_ends_up_in_the_connected_address() {
+        let listener = Server::new().bind(Some("::1"), 0).listen().unwrap();
+        // SAFETY: The interface name is a valid C string.
+        let lo_index = unsafe { libc::if_nametoindex(c"lo".as_ptr()) };
+        assert_ne!(lo_index, 0);
+
+        let (_sock, addr) = Client::new()
+            .host("::1")
+            .scope(Some("lo"))
+            .port(listener.local_addr().port())
+            .connect()
+            .unwrap();
+        match addr {
+            SocketAddr::V6(addr) => assert_eq!(addr.scope_id(), lo_index),
+            addr => panic!("unexpected address: {}", addr),
+        }
+
+        // An interface that does not exist is rejected before anything is connected.
+        let res = Client::new()
+            .host("::1")
+            .scope(Some("nosuchif0"))
+            .port(listener.local_addr().port())
+            .connect();
+        match res {
+            Err(ConnectError::Other(err)) => assert_eq!(err.kind(), io::ErrorKind::InvalidInput),
+            res => panic!("unexpected result: {:?}", res.map(|(_, addr)| addr)),
+        }
+    }
+
+    #[test]
+    fn retrying_client_waits_for_a_late_server() {
+        // The port is free once the first listener is closed, the server comes back on it a little later.
+        let port = Server::new()
+            .bind(Some("127.0.0.1"), 0)
+            .listen()
+            .unwrap()
+            .local_addr()
+            .port();
+        let server = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(300));
+            let listener = Server::new()
+                .bind(Some("127.0.0.1"), port)
+                .listen()
+                .unwrap();
+            listener.accept().unwrap();
+        });
+
+        let client = Client::new().host("127.0.0.1").port(port);
+        // Without retries, the client gives up right away.
+        assert!(matches!(
+            client.connect(),
+            Err(ConnectError::ConnectionRefused(_))
+        ));
+
+        // 100 + 200 + 400 + 800 ms of waiting covers the late start with room to spare.
+        let started_at = Instant::now();
+        let (_sock, addr) = client
+            .retry(ConnectRetry {
+                retries: 4,
+                delay: Duration::from_millis(100),
+            })
+            .connect()
+            .unwrap();
+        server.join().unwrap();
+
+        assert_eq!(addr.port(), port);
+        assert!(started_at.elapsed() >= Duration::from_millis(200));
+    }
+}
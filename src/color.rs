@@ -0,0 +1,119 @@
+use std::sync::atomic::{AtomicU8, Ordering};
+
+/// When the hexdump and trace output is colored with ANSI escape codes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColorChoice {
+    /// Only if the output goes to a terminal, pipes and files get plain text.
+    Auto,
+    Always,
+    Never,
+}
+
+static CHOICE: AtomicU8 = AtomicU8::new(ColorChoice::Auto as u8);
+
+pub const DIM: &str = "\x1b[2m";
+pub const RED: &str = "\x1b[31m";
+pub const CYAN: &str = "\x1b[36m";
+const RESET: &str = "\x1b[0m";
+
+/// Sets the color choice for the whole process, it is `Auto` by default.
+pub fn set_choice(choice: ColorChoice) {
+    CHOICE.store(choice as u8, Ordering::Relaxed);
+}
+
+/// Reports the color choice of the process.
+pub fn choice() -> ColorChoice {
+    match CHOICE.load(Ordering::Relaxed) {
+        x if x == ColorChoice::Always as u8 => ColorChoice::Always,
+        x if x == ColorChoice::Never as u8 => ColorChoice::Never,
+        _ => ColorChoice::Auto,
+    }
+}
+
+/// Whether the output written to `fd` is colored.
+pub fn enabled_for(fd: i32) -> bool {
+    match choice() {
+        ColorChoice::Always => true,
+        ColorChoice::Never => false,
+        // SAFETY: `isatty()` only inspects `fd`, it returns 0 for an invalid one.
+        ColorChoice::Auto => unsafe { libc::isatty(fd) == 1 },
+    }
+}
+
+/// Whether the output written to stdout is colored.
+pub fn stdout() -> bool {
+    enabled_for(libc::STDOUT_FILENO)
+}
+
+/// Whether the output written to stderr is colored.
+pub fn stderr() -> bool {
+    enabled_for(libc::STDERR_FILENO)
+}
+
+/// Wraps `s` in the escape code `code` if `enabled`, returns it as is otherwise.
+pub fn paint(s: &str, code: &str, enabled: bool) -> String {
+    if enabled {
+        format!("{}{}{}", code, s, RESET)
+    } else {
+        s.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+
+    use super::*;
+    use crate::{fd, hexdump};
+
+    fn pipe() -> [i32; 2] {
+        let mut fds = [0i32; 2];
+        // SAFETY: `fds` has room for the two fds that `pipe()` writes.
+        let ecode = unsafe { libc::pipe(fds.as_mut_ptr()) };
+        assert_eq!(ecode, 0, "pipe error: {}", io::Error::last_os_error());
+        fds
+    }
+
+    // The master end of a new pseudo-terminal, which is a TTY as far as `isatty()` is concerned.
+    fn pty() -> i32 {
+        // SAFETY: `posix_openpt()` does not touch any memory owned by the process.
+        let pty_fd = unsafe { libc::posix_openpt(libc::O_RDWR | libc::O_NOCTTY) };
+        assert_ne!(
+            pty_fd,
+            -1,
+            "posix_openpt error: {}",
+            io::Error::last_os_error()
+        );
+        pty_fd
+    }
+
+    // The choice is global, so every case that changes it is in this single test.
+    #[test]
+    fn color_is_only_enabled_for_a_tty_unless_it_is_never() {
+        let [read_fd, write_fd] = pipe();
+        let pty_fd = pty();
+
+        set_choice(ColorChoice::Auto);
+        assert!(!enabled_for(write_fd));
+        assert!(enabled_for(pty_fd));
+
+        set_choice(ColorChoice::Never);
+        assert!(!enabled_for(write_fd));
+        assert!(!enabled_for(pty_fd));
+
+        set_choice(ColorChoice::Auto);
+        for fd in [read_fd, write_fd, pty_fd] {
+            fd::close_fd(fd).unwrap();
+        }
+    }
+
+    #[test]
+    fn no_escape_codes_without_color() {
+        let buf: Vec<u8> = (0..=255).collect();
+
+        assert!(!hexdump::hexdump_with(&buf, false).contains('\x1b'));
+        assert!(hexdump::hexdump_with(&buf, true).contains('\x1b'));
+        assert_eq!(paint("x", RED, false), "x");
+        assert_eq!(paint("x", RED, true), "\x1b[31mx\x1b[0m");
+    }
+}
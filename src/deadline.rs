@@ -0,0 +1,61 @@
+use std::{
+    io,
+    sync::OnceLock,
+    time::{Duration, Instant},
+};
+
+use crate::readiness;
+
+// An optional deadline for the whole process, so that the single-shot examples that block on a peer
+// (`accept()`, `recv()`, `recvfrom()`) give up instead of waiting forever when nobody shows up.
+//
+// The examples call `wait_readable()` right before the blocking syscall. Without a deadline it returns
+// right away, and the syscall blocks as usual.
+
+static DEADLINE: OnceLock<(Instant, Duration)> = OnceLock::new();
+
+/// Sets the deadline of the process to `after` from now, only the first call has an effect.
+pub fn set(after: Duration) {
+    let _ = DEADLINE.set((Instant::now() + after, after));
+}
+
+/// Returns the time left until the deadline, `None` if there is no deadline.
+pub fn remaining() -> Option<Duration> {
+    DEADLINE
+        .get()
+        .map(|(deadline, _)| deadline.saturating_duration_since(Instant::now()))
+}
+
+/// Waits until `fd` is readable (a new connection for a listener), or fails with `io::ErrorKind::TimedOut`
+/// once the deadline passes. An interrupted wait fails with `io::ErrorKind::Interrupted`, like the syscall would.
+pub fn wait_readable(fd: i32) -> io::Result<()> {
+    let Some(&(_, after)) = DEADLINE.get() else {
+        return Ok(());
+    };
+    let left = remaining().unwrap_or_default();
+
+    if readiness::wait_readable(fd, Some(left))? {
+        Ok(())
+    } else {
+        Err(io::Error::new(
+            io::ErrorKind::TimedOut,
+            format!("nothing arrived before the {:?} deadline", after),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil;
+
+    // The unit tests never set a deadline, see `tests/deadline.rs` for the tests with one.
+    #[test]
+    fn without_a_deadline_the_wait_returns_right_away() {
+        let (sock, _peer) = testutil::socketpair();
+
+        assert_eq!(remaining(), None);
+        // Nothing is ever sent, the syscall after the wait is the one that would block.
+        wait_readable(sock.as_raw()).unwrap();
+    }
+}
@@ -1,8 +1,13 @@
 use core::fmt;
-use std::{
-    error,
-    ffi::{CStr, CString},
-    io, mem, ptr,
+use std::{error, ffi::CString, io};
+
+use crate::{
+    addrinfo::{AddrInfoList, Hints},
+    fd,
+    inet::{self, SendtoError},
+    socket_fd::Socket,
+    socket_kind::AddressFamily,
+    trace,
 };
 
 #[derive(Debug)]
@@ -10,7 +15,7 @@ pub enum Error {
     Getaddrinfo(String),
     Socket(io::Error),
     Close(io::Error),
-    Sendto(io::Error),
+    Sendto(SendtoError),
 }
 
 impl fmt::Display for Error {
@@ -26,48 +31,38 @@ impl fmt::Display for Error {
 
 impl error::Error for Error {}
 
-pub fn client() -> Result<(), Error> {
-    let node = ptr::null();
-    let port = CString::from(c"4950");
-
-    // SAFETY: All zero hints is a valid initialization.
-    // Required fields are set later on.
-    let mut hints: libc::addrinfo = unsafe { mem::zeroed() };
-    hints.ai_family = libc::AF_INET;
-    hints.ai_socktype = libc::SOCK_DGRAM;
-
-    let mut gai_res_ptr: *mut libc::addrinfo = ptr::null_mut();
-
-    // SAFETY: There is no uninitialized memory access. `getaddrinfo()` is safe to call.
-    let ecode = unsafe { libc::getaddrinfo(node, port.as_ptr(), &hints, &mut gai_res_ptr) };
-    match ecode {
-        0 => Ok(()),
-        _ => {
-            // SAFETY: `gai_strerror` is valid to call on a failed `getaddrinfo()` call.
-            let err = unsafe { CStr::from_ptr(libc::gai_strerror(ecode)).to_string_lossy() };
-            Err(Error::Getaddrinfo(err.into_owned()))
-        }
-    }?;
-
-    let mut sock_fd = -1;
-    while !gai_res_ptr.is_null() {
-        let gai_res = unsafe { *gai_res_ptr };
-        let next_res_ptr = gai_res.ai_next;
-
-        // SAFETY: `socket()` is safe to call since `gai_res` is valid.
-        let sock = unsafe { libc::socket(gai_res.ai_family, gai_res.ai_socktype, 0) };
-        if sock == -1 {
-            if next_res_ptr.is_null() {
-                return Err(Error::Socket(io::Error::last_os_error()));
-            } else {
-                gai_res_ptr = next_res_ptr;
-                continue;
+// EXAMPLE: A DGRAM socket talker that sends a UDP message to the listener of `bjrs dgram server`.
+// MANPAGE:
+// man 2 sendto (Linux)
+// man 3 sendto (POSIX)
+//
+// The message is sent to `port` on the loopback address of `family`, which has to match the family
+// the listener is bound to. With `Unspec` (`--family any`), the first loopback address `getaddrinfo()` returns is used.
+pub fn client(port: u16, family: AddressFamily) -> Result<(), Error> {
+    let node = None;
+    let port = CString::new(port.to_string()).unwrap();
+
+    let hints = Hints::new()
+        .family(family.as_raw())
+        .socktype(libc::SOCK_DGRAM)
+        .build();
+
+    let gai_res_list = AddrInfoList::new(node, Some(&port), &hints).map_err(Error::Getaddrinfo)?;
+
+    let mut res = Err(Error::Socket(io::Error::from(
+        io::ErrorKind::AddrNotAvailable,
+    )));
+    for gai_res in &gai_res_list {
+        match Socket::new(gai_res.ai_family, gai_res.ai_socktype, 0) {
+            Ok(sock) => {
+                res = Ok((sock, gai_res));
+                break;
             }
+            Err(err) => res = Err(Error::Socket(err)),
         }
-
-        sock_fd = sock;
-        break;
     }
+    // `sock` is closed on every early return from now on.
+    let (sock, gai_res) = res?;
 
     let msg_buf = b"Hello UDP server!";
     let len = msg_buf.len();
@@ -75,10 +70,8 @@ pub fn client() -> Result<(), Error> {
     // SAFETY: All `sendto()` arguments are initialized as desired.
     // There are no reads to uninitialized memory, therefore it is safe to call.
     let bytes = unsafe {
-        let gai_res = { *gai_res_ptr };
-
-        libc::sendto(
-            sock_fd,
+        trace::sendto(
+            sock.as_raw(),
             msg_buf.as_ptr() as *const libc::c_void,
             len,
             0,
@@ -86,23 +79,10 @@ pub fn client() -> Result<(), Error> {
             gai_res.ai_addrlen,
         )
     };
-    match bytes {
-        v if v > 0 => Ok(()),
-        _ => Err(Error::Sendto(io::Error::last_os_error())),
-    }?;
-
-    // SAFETY: `gai_res` is no longer needed and its pointer points to a valid `addrinfo` struct at this point. It can be freed safely.
-    unsafe {
-        libc::freeaddrinfo(gai_res_ptr);
-    }
+    let bytes = inet::check_sendto(bytes, len).map_err(Error::Sendto)?;
 
     println!("talker: sent {} bytes", bytes);
 
-    // SAFETY: `sock_fd` is not needed from now on.
-    // It is safe to call `close()`.
-    let ecode = unsafe { libc::close(sock_fd) };
-    match ecode {
-        -1 => Err(Error::Close(io::Error::last_os_error())),
-        _ => Ok(()),
-    }
+    // `sock` is not needed from now on, it is closed explicitly to report a failing `close()`.
+    fd::close_fd(sock.into_raw()).map_err(Error::Close)
 }
@@ -1,5 +1,7 @@
 mod client;
+mod roundtrip;
 mod server;
 
 pub use client::client;
+pub use roundtrip::roundtrip;
 pub use server::server;
@@ -0,0 +1,121 @@
+use std::{error, ffi::CStr, fmt, io, net::SocketAddr};
+
+use crate::{fd, udp};
+
+#[derive(Debug)]
+pub enum Error {
+    Udp(udp::Error),
+    AddrMismatch {
+        expected: SocketAddr,
+        got: SocketAddr,
+    },
+    Close(io::Error),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Udp(err) => write!(f, "udp error: {}", err),
+            Error::AddrMismatch { expected, got } => {
+                write!(f, "reply came from {}, expected {}", got, expected)
+            }
+            Error::Close(err) => write!(f, "close error: {}", err),
+        }
+    }
+}
+
+impl error::Error for Error {}
+
+impl From<udp::Error> for Error {
+    fn from(value: udp::Error) -> Self {
+        Self::Udp(value)
+    }
+}
+
+// EXAMPLE: A full UDP addressing round-trip on the loopback interface.
+// The client `sendto()`s the server, the server `recvfrom()`s the message and replies with `sendto()`
+// to the address captured by `recvfrom()`. Then, the client `recvfrom()`s the reply and checks that
+// it came from the server's address.
+// If the port or the address were converted to the wrong byte order anywhere along the way,
+// the reply would either get lost or come from an unexpected address.
+// The round-trip is run for both IPv4 and IPv6.
+// MANPAGE:
+// man 2 sendto (Linux)
+// man 2 recvfrom (Linux)
+// man 2 getsockname (Linux)
+pub fn roundtrip() -> Result<(), Error> {
+    for node in [c"127.0.0.1", c"::1"] {
+        roundtrip_on(node)?;
+    }
+
+    Ok(())
+}
+
+fn roundtrip_on(node: &CStr) -> Result<(), Error> {
+    let (server_fd, server_addr) = udp::bind_udp(Some(node), c"0")?;
+    println!("roundtrip: server is bound to {}", server_addr);
+
+    let family = match server_addr {
+        SocketAddr::V4(_) => libc::AF_INET,
+        SocketAddr::V6(_) => libc::AF_INET6,
+    };
+    let client_fd = udp::udp_socket(family)?;
+
+    let res = exchange(server_fd, server_addr, client_fd);
+
+    // Both sockets are closed regardless of the result, they are not used after the exchange.
+    fd::close_fd(client_fd).map_err(Error::Close)?;
+    fd::close_fd(server_fd).map_err(Error::Close)?;
+
+    res
+}
+
+fn exchange(server_fd: i32, server_addr: SocketAddr, client_fd: i32) -> Result<(), Error> {
+    let mut buf = [0u8; 100];
+
+    let sbytes = udp::send_to(client_fd, b"ping", &server_addr)?;
+    println!("roundtrip: client sent {} bytes to {}", sbytes, server_addr);
+
+    let (rbytes, client_addr) = udp::recv_from(server_fd, &mut buf)?;
+    println!(
+        "roundtrip: server got {:?} from {}",
+        String::from_utf8_lossy(&buf[..rbytes]),
+        client_addr
+    );
+
+    // The client never called `bind()`, its address is known only through `recvfrom()`.
+    udp::send_to(server_fd, b"pong", &client_addr)?;
+
+    let (rbytes, reply_addr) = udp::recv_from(client_fd, &mut buf)?;
+    println!(
+        "roundtrip: client got {:?} from {}",
+        String::from_utf8_lossy(&buf[..rbytes]),
+        reply_addr
+    );
+
+    if reply_addr != server_addr {
+        return Err(Error::AddrMismatch {
+            expected: server_addr,
+            got: reply_addr,
+        });
+    }
+
+    println!("roundtrip: reply address matches the server address");
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reply_comes_from_the_server_over_ipv4() {
+        roundtrip_on(c"127.0.0.1").unwrap();
+    }
+
+    #[test]
+    fn reply_comes_from_the_server_over_ipv6() {
+        roundtrip_on(c"::1").unwrap();
+    }
+}
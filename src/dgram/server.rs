@@ -1,11 +1,20 @@
 use std::{
     error,
-    ffi::{CStr, CString},
+    ffi::CString,
     fmt,
     io::{self, Write},
     mem,
-    net::Ipv4Addr,
-    ptr,
+    net::SocketAddr,
+};
+
+use crate::{
+    addrinfo::{AddrInfoList, Hints},
+    cidr::SourceFilter,
+    fd, sockaddr,
+    socket_fd::Socket,
+    socket_kind::AddressFamily,
+    stats::ReceiveSummary,
+    trace, udp,
 };
 
 #[derive(Debug)]
@@ -16,6 +25,7 @@ pub enum Error {
     Recvfrom(io::Error),
     InvalidAddrFamily(i32),
     Close(io::Error),
+    Udp(udp::Error),
 }
 
 impl fmt::Display for Error {
@@ -27,6 +37,7 @@ impl fmt::Display for Error {
             Error::Recvfrom(err) => write!(f, "recvfrom error: {}", err),
             Error::InvalidAddrFamily(af) => write!(f, "recvfrom error: invalid addr family {}", af),
             Error::Close(err) => write!(f, "close error: {}", err),
+            Error::Udp(err) => write!(f, "udp error: {}", err),
         }
     }
 }
@@ -38,126 +49,232 @@ impl error::Error for Error {}
 // man 2 recvfrom (Linux)
 // man 2 recvfrom (POSIX)
 // man errno
-pub fn server() -> Result<(), Error> {
-    let node = ptr::null();
-    let port = CString::from(c"4950");
-
-    // SAFETY: All zero hints is a valid initialization.
-    // Required fields are set later on.
-    let mut hints: libc::addrinfo = unsafe { mem::zeroed() };
-    hints.ai_family = libc::AF_INET;
-    hints.ai_socktype = libc::SOCK_DGRAM;
-    hints.ai_flags = libc::AI_PASSIVE;
-
-    let mut gai_res_ptr: *mut libc::addrinfo = ptr::null_mut();
-
-    // SAFETY: There is no uninitialized memory access. `getaddrinfo()` is safe to call.
-    let ecode = unsafe { libc::getaddrinfo(node, port.as_ptr(), &hints, &mut gai_res_ptr) };
-    match ecode {
-        0 => Ok(()),
-        _ => {
-            // SAFETY: `gai_strerror` is valid to call on a failed `getaddrinfo()` call.
-            let err = unsafe { CStr::from_ptr(libc::gai_strerror(ecode)).to_string_lossy() };
-            Err(Error::Getaddrinfo(err.into_owned()))
+//
+// A datagram that is longer than `max_datagram` bytes is truncated by `recvfrom()`, the rest of it is lost.
+// If `probe_size` is set, the size of the datagram is peeked first (see `udp::peek_datagram_size()`),
+// and the buffer is allocated for exactly that size. `max_datagram` is only used where the size cannot be peeked.
+//
+// The datagrams from the sources that `filter` does not permit are dropped, the listener keeps waiting
+// until a permitted one arrives.
+//
+// The listener is bound to the first candidate of `family` that `getaddrinfo()` returns.
+// With `Unspec` (`--family any`), which family comes first depends on the system (see `gai.conf` on glibc), so the bound address is read back
+// with `getsockname()` and reported. An IPv6 listener receives the IPv4 datagrams as well on most systems,
+// from IPv4-mapped addresses (`::ffff:a.b.c.d`).
+//
+// The source of each datagram is decoded with `sockaddr::to_socket_addr()`, and printed with its port
+// (converted from network byte order), since a reply has to be sent to that port as well.
+//
+// Returns what was received. With `summary`, it is also printed as a trailing line, see `ReceiveSummary`.
+pub fn server(
+    port: u16,
+    max_datagram: usize,
+    probe_size: bool,
+    filter: &SourceFilter,
+    family: AddressFamily,
+    summary: bool,
+) -> Result<ReceiveSummary, Error> {
+    let (sock, bound_addr) = bind_first(port, family)?;
+    println!(
+        "listener: bound to {} ({})",
+        bound_addr,
+        family_label(&bound_addr)
+    );
+
+    println!("listener: waiting to recvfrom...");
+
+    let (mut recv_buf, bytes, from_addr) = recv_permitted(&sock, max_datagram, probe_size, filter)?;
+
+    // The source port is printed along with the address, the pair is what a reply would be sent to.
+    println!("listener: got packet from {}", from_addr);
+    println!("listener: packet is {} bytes long", bytes);
+
+    recv_buf[bytes] = b'\0';
+
+    let msg = [b"listener: packet contains ", &recv_buf[..=bytes]].concat();
+    io::stdout()
+        .write_all(&msg)
+        .expect("message to be written to stdout");
+
+    // The communication has ended. It is safe to close the socket.
+    // It is closed explicitly instead of on drop, so that a `close()` error is reported.
+    fd::close_fd(sock.into_raw()).map_err(Error::Close)?;
+
+    let mut res = ReceiveSummary::new("datagram");
+    res.on_recv(bytes, from_addr);
+    if summary {
+        // The packet is written as is along with its null terminator, it may not end with a newline.
+        if !recv_buf[..bytes].ends_with(b"\n") {
+            println!();
+        }
+        println!("listener: summary: {}", res);
+    }
+
+    Ok(res)
+}
+
+// Receives datagrams on `sock` until one comes from a source that `filter` permits, the rest are dropped.
+// Returns the buffer (with room for a null terminator), the size of the datagram and its source.
+fn recv_permitted(
+    sock: &Socket,
+    max_datagram: usize,
+    probe_size: bool,
+    filter: &SourceFilter,
+) -> Result<(Vec<u8>, usize, SocketAddr), Error> {
+    let sock_fd = sock.as_raw();
+
+    loop {
+        let len = match probe_size {
+            true => udp::peek_datagram_size(sock_fd)
+                .map_err(Error::Udp)?
+                .unwrap_or(max_datagram),
+            false => max_datagram,
+        };
+        // One more byte for the null terminator.
+        let mut recv_buf = vec![0; len + 1];
+
+        // SAFETY: All zero `sockaddr_storage` is a valid initialization.
+        // Read will happen after it is written by `recvfrom()`.
+        let mut sockaddr: libc::sockaddr_storage = unsafe { mem::zeroed() };
+        let mut sa_len = mem::size_of_val(&sockaddr) as u32;
+
+        // SAFETY:
+        // 1 - `sock_fd` is a valid socket.
+        // 2 - The buf is initialized as desired.
+        // 3 - Casting `sockaddr_storage` to `sockaddr` is valid and expected.
+        let bytes = unsafe {
+            trace::recvfrom(
+                sock_fd,
+                recv_buf.as_mut_ptr() as *mut libc::c_void,
+                len,
+                0,
+                &raw mut sockaddr as *mut libc::sockaddr,
+                &raw mut sa_len,
+            )
+        };
+        match bytes {
+            -1 => Err(Error::Recvfrom(io::Error::last_os_error())),
+            _ => Ok(()),
+        }?;
+
+        // `sockaddr` is filled by a valid `recvfrom()` call.
+        let from_addr = sockaddr::to_socket_addr(&sockaddr)
+            .ok_or(Error::InvalidAddrFamily(sockaddr.ss_family as i32))?;
+
+        if filter.permits(from_addr.ip()) {
+            return Ok((recv_buf, bytes as usize, from_addr));
         }
-    }?;
-
-    let mut sock_fd = -1;
-    while !gai_res_ptr.is_null() {
-        // SAFETY: `gai_res_ptr` is guaranteed to point atleast one valid addrinfo struct on a successful `getaddrinfo()` call.
-        let gai_res = unsafe { *gai_res_ptr };
-        let next_res_ptr = gai_res.ai_next;
-
-        // SAFETY: `socket()` is safe to call since `gai_res` is valid.
-        let sock = unsafe { libc::socket(gai_res.ai_family, gai_res.ai_socktype, 0) };
-        if sock == -1 {
-            if next_res_ptr.is_null() {
-                return Err(Error::Socket(io::Error::last_os_error()));
-            } else {
-                gai_res_ptr = next_res_ptr;
+        println!(
+            "listener: dropped a {} byte packet from {}, the source is not allowed",
+            bytes, from_addr
+        );
+    }
+}
+
+// Binds a socket to the first candidate of `family` that `getaddrinfo()` returns for `port`,
+// and reads back the address it is bound to with `getsockname()`.
+fn bind_first(port: u16, family: AddressFamily) -> Result<(Socket, SocketAddr), Error> {
+    let node = None;
+    let port = CString::new(port.to_string()).unwrap();
+
+    let hints = Hints::new()
+        .family(family.as_raw())
+        .socktype(libc::SOCK_DGRAM)
+        .passive(true)
+        .build();
+
+    let gai_res_list = AddrInfoList::new(node, Some(&port), &hints).map_err(Error::Getaddrinfo)?;
+
+    let mut res = Err(Error::Socket(io::Error::from(
+        io::ErrorKind::AddrNotAvailable,
+    )));
+    for gai_res in &gai_res_list {
+        let sock = match Socket::new(gai_res.ai_family, gai_res.ai_socktype, 0) {
+            Ok(sock) => sock,
+            Err(err) => {
+                res = Err(Error::Socket(err));
                 continue;
             }
-        }
+        };
 
         // SAFETY: `bind()` is safe to call since `sock` and `gai_res` are valid.
-        let ecode = unsafe { libc::bind(sock, gai_res.ai_addr, gai_res.ai_addrlen) };
+        let ecode = unsafe { trace::bind(sock.as_raw(), gai_res.ai_addr, gai_res.ai_addrlen) };
         if ecode == -1 {
-            if next_res_ptr.is_null() {
-                return Err(Error::Bind(io::Error::last_os_error()));
-            } else {
-                gai_res_ptr = next_res_ptr;
-                continue;
-            }
+            // A failed candidate is closed when `sock` is dropped, so that it does not leak its fd.
+            res = Err(Error::Bind(io::Error::last_os_error()));
+            continue;
         }
 
-        sock_fd = sock;
+        res = Ok(sock);
         break;
     }
+    // The socket is closed on the error paths of the caller as well.
+    let sock = res?;
+    let bound_addr = udp::local_addr(sock.as_raw()).map_err(Error::Udp)?;
 
-    // SAFETY: `gai_res` is no longer needed and its pointer points to a valid `addrinfo` struct at this point. It can be freed safely.
-    unsafe {
-        libc::freeaddrinfo(gai_res_ptr);
+    Ok((sock, bound_addr))
+}
+
+// The family `addr` is reported by.
+fn family_label(addr: &SocketAddr) -> &'static str {
+    match AddressFamily::of(addr) {
+        AddressFamily::V6 => "IPv6",
+        _ => "IPv4",
     }
+}
 
-    println!("listener: waiting to recvfrom...");
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-    const MAXBUFLEN: usize = 100;
-    let mut recv_buf = vec![0; MAXBUFLEN];
-    let len = recv_buf.len();
-
-    // SAFETY: All zero `sockaddr_storage` is a valid initialization.
-    // Read will happen after it is written by `recvfrom()`.
-    let mut sockaddr: libc::sockaddr_storage = unsafe { mem::zeroed() };
-    let mut sa_len = mem::size_of_val(&sockaddr) as u32;
-
-    // SAFETY:
-    // 1 - `sock_fd` is a valid socket.
-    // 2 - The buf is initialized as desired.
-    // 3 - Casting `sockaddr_storage` to `sockaddr` is valid and expected.
-    let bytes = unsafe {
-        libc::recvfrom(
-            sock_fd,
-            recv_buf.as_mut_ptr() as *mut libc::c_void,
-            len,
+    // The family of the address `sock_fd` is bound to, as `getsockname()` reports it.
+    fn raw_family(sock_fd: i32) -> i32 {
+        // SAFETY: All zero `sockaddr_storage` is a valid initialization.
+        let mut sa: libc::sockaddr_storage = unsafe { mem::zeroed() };
+        let mut sa_len = mem::size_of_val(&sa) as libc::socklen_t;
+        // SAFETY: `sa` is big enough for any address family, and `sa_len` holds its size.
+        let ecode = unsafe {
+            libc::getsockname(sock_fd, &raw mut sa as *mut libc::sockaddr, &raw mut sa_len)
+        };
+        assert_eq!(
+            ecode,
             0,
-            &raw mut sockaddr as *mut libc::sockaddr,
-            &raw mut sa_len,
-        )
-    };
-    match bytes {
-        -1 => Err(Error::Recvfrom(io::Error::last_os_error())),
-        _ => Ok(()),
-    }?;
-
-    let sockaddr = match sockaddr.ss_family as i32 {
-        libc::AF_INET => {
-            // SAFETY: If `ss_family` is INET4, and we know it is due to `getaddrinfo()`, then `sockaddr_storage` can be casted safely to `sockaddr_in` to access the data written by `recvfrom()`.
-            let sockaddr_in = unsafe { *(&raw const sockaddr as *const libc::sockaddr_in) };
-            Ok(sockaddr_in)
-        }
-        af => Err(Error::InvalidAddrFamily(af)),
-    }?;
-    let ip_addr = {
-        let bits = u32::from_be(sockaddr.sin_addr.s_addr);
-        Ipv4Addr::from_bits(bits)
-    };
-
-    println!("listener: got packet from {}", ip_addr);
-    println!("listener: packet is {} bytes long", bytes);
+            "getsockname error: {}",
+            io::Error::last_os_error()
+        );
+        sa.ss_family as i32
+    }
 
-    recv_buf[bytes as usize] = b'\0';
+    #[test]
+    fn reported_family_matches_the_bound_socket() {
+        for family in [AddressFamily::Unspec, AddressFamily::V4, AddressFamily::V6] {
+            let (sock, addr) = bind_first(0, family).unwrap();
+            let expected = match raw_family(sock.as_raw()) {
+                libc::AF_INET => "IPv4",
+                libc::AF_INET6 => "IPv6",
+                af => panic!("bound to an unexpected family {}", af),
+            };
 
-    let msg = [b"listener: packet contains ", &recv_buf[..]].concat();
-    io::stdout()
-        .write_all(&msg)
-        .expect("message to be written to stdout");
+            assert_eq!(family_label(&addr), expected, "with {:?}", family);
+            if family != AddressFamily::Unspec {
+                assert_eq!(AddressFamily::of(&addr), family);
+            }
+        }
+    }
+
+    #[test]
+    fn reported_source_is_the_bound_address_of_the_sender() {
+        let (sock, addr) = bind_first(0, AddressFamily::V4).unwrap();
+        let (sender_fd, sender_addr) = udp::bind_udp(Some(c"127.0.0.1"), c"0").unwrap();
+        let sender = Socket::from_raw(sender_fd);
 
-    // SAFETY: The communication has ended. It is safe to close the socket.
-    let ecode = unsafe { libc::close(sock_fd) };
-    match ecode {
-        -1 => Err(Error::Close(io::Error::last_os_error())),
-        _ => Ok(()),
-    }?;
+        let dest = SocketAddr::from(([127, 0, 0, 1], addr.port()));
+        udp::send_to(sender.as_raw(), b"hello", &dest).unwrap();
+        let (recv_buf, bytes, from_addr) =
+            recv_permitted(&sock, 100, false, &SourceFilter::default()).unwrap();
 
-    Ok(())
+        assert_eq!(&recv_buf[..bytes], b"hello");
+        // The port is decoded to host byte order along with the address.
+        assert_eq!(from_addr, sender_addr);
+    }
 }
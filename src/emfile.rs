@@ -0,0 +1,78 @@
+use std::{io, ptr};
+
+use crate::{fd, trace};
+
+/// A spare fd that a server keeps open, so that it can still turn away a connection once it runs out of fds.
+///
+/// When the process hits `RLIMIT_NOFILE`, `accept()` fails with `EMFILE` (`ENFILE` for the system-wide limit),
+/// but the connection it was supposed to return stays in the accept queue. The listener is still readable,
+/// so the next `poll()`/`select()` returns right away, `accept()` fails again, and the server spins
+/// at 100% CPU without making any progress, until some client happens to leave on its own.
+///
+/// The way out is to make room for one more fd on purpose:
+///
+/// 1. An fd (here, `/dev/null`) is opened up front, while there is still room for it.
+/// 2. On `EMFILE`, that fd is closed, which frees exactly one slot.
+/// 3. `accept()` now succeeds and takes the pending connection out of the queue.
+/// 4. The connection is closed right away, the client sees it as an immediate EOF (or a reset).
+/// 5. The guard fd is opened again, so that the next `EMFILE` can be handled the same way.
+///
+/// The client is not served, but it is not left hanging either, and the server keeps going.
+/// In a multithreaded process, another thread may grab the freed slot between the steps,
+/// in which case `accept()` or the reopen fails again and the guard is simply retried on the next `EMFILE`.
+#[derive(Debug)]
+pub struct EmfileGuard {
+    fd: Option<i32>,
+}
+
+impl EmfileGuard {
+    /// Reserves the spare fd, which must be done before the process runs out of fds.
+    pub fn new() -> io::Result<EmfileGuard> {
+        Ok(EmfileGuard {
+            fd: Some(open_spare()?),
+        })
+    }
+
+    /// Whether `err` is the `accept()` error that the guard is for.
+    pub fn is_fd_limit(err: &io::Error) -> bool {
+        matches!(err.raw_os_error(), Some(libc::EMFILE | libc::ENFILE))
+    }
+
+    /// Turns away the pending connection on `listener_fd` by spending the spare fd on it, see `EmfileGuard`.
+    pub fn shed(&mut self, listener_fd: i32) -> io::Result<()> {
+        if let Some(spare_fd) = self.fd.take() {
+            fd::close_fd(spare_fd)?;
+        }
+
+        // SAFETY: The address of the peer is not needed, null `addr` and `addrlen` are allowed by `accept()`.
+        let conn_sock_fd = unsafe { trace::accept(listener_fd, ptr::null_mut(), ptr::null_mut()) };
+        let res = if conn_sock_fd == -1 {
+            Err(io::Error::last_os_error())
+        } else {
+            // The connection is never used, closing it is the whole point.
+            fd::close_fd(conn_sock_fd)
+        };
+
+        self.fd = open_spare().ok();
+
+        res
+    }
+}
+
+impl Drop for EmfileGuard {
+    fn drop(&mut self) {
+        if let Some(spare_fd) = self.fd.take() {
+            let _ = fd::close_fd(spare_fd);
+        }
+    }
+}
+
+fn open_spare() -> io::Result<i32> {
+    // SAFETY: The path is a valid, null terminated string.
+    let spare_fd = unsafe { libc::open(c"/dev/null".as_ptr(), libc::O_RDONLY | libc::O_CLOEXEC) };
+    if spare_fd == -1 {
+        return Err(io::Error::last_os_error());
+    }
+
+    Ok(spare_fd)
+}
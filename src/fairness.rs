@@ -0,0 +1,81 @@
+use std::num::NonZeroUsize;
+
+/// Decides which of the ready fds are served in a single iteration of a server loop.
+///
+/// Scanning the ready fds from the lowest one every time favors the low fds: under sustained load,
+/// with a cap on the events per iteration, the high fds may never get their turn.
+/// Instead, each scan starts right after the last fd that was served in the previous iteration,
+/// and wraps around.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RoundRobin {
+    next_fd: i32,
+    max_events: Option<NonZeroUsize>,
+}
+
+impl RoundRobin {
+    /// Creates a scan that serves at most `max_events` fds per iteration, or all of them if `None`.
+    pub fn new(max_events: Option<NonZeroUsize>) -> Self {
+        Self {
+            next_fd: 0,
+            max_events,
+        }
+    }
+
+    /// Returns the fds to serve in this iteration, in the order they should be served.
+    pub fn pick(&mut self, ready_fds: impl Iterator<Item = i32>) -> Vec<i32> {
+        let mut ready_fds: Vec<i32> = ready_fds.collect();
+        ready_fds.sort_unstable();
+
+        let start = ready_fds.partition_point(|fd| *fd < self.next_fd);
+        ready_fds.rotate_left(start);
+
+        if let Some(max_events) = self.max_events {
+            ready_fds.truncate(max_events.get());
+        }
+
+        if let Some(last_fd) = ready_fds.last() {
+            self.next_fd = last_fd + 1;
+        }
+
+        ready_fds
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::iter;
+
+    use super::*;
+
+    #[test]
+    fn without_a_cap_every_ready_fd_is_served_in_order() {
+        let mut round_robin = RoundRobin::new(None);
+
+        assert_eq!(round_robin.pick([7, 3, 5].into_iter()), [3, 5, 7]);
+        // The next scan starts after 7, which wraps around to the lowest fd.
+        assert_eq!(round_robin.pick([7, 3, 5].into_iter()), [3, 5, 7]);
+    }
+
+    #[test]
+    fn the_scan_continues_after_the_last_served_fd() {
+        let mut round_robin = RoundRobin::new(NonZeroUsize::new(2));
+        let ready = [3, 4, 5, 6, 7];
+
+        assert_eq!(round_robin.pick(ready.into_iter()), [3, 4]);
+        assert_eq!(round_robin.pick(ready.into_iter()), [5, 6]);
+        assert_eq!(round_robin.pick(ready.into_iter()), [7, 3]);
+        assert_eq!(round_robin.pick(ready.into_iter()), [4, 5]);
+    }
+
+    #[test]
+    fn an_fd_that_is_not_ready_is_skipped() {
+        let mut round_robin = RoundRobin::new(NonZeroUsize::new(1));
+
+        assert_eq!(round_robin.pick([3, 4, 5].into_iter()), [3]);
+        // 4 is not ready anymore, its turn goes to the next ready fd.
+        assert_eq!(round_robin.pick([3, 5].into_iter()), [5]);
+        assert_eq!(round_robin.pick([3, 4, 5].into_iter()), [3]);
+        assert!(round_robin.pick(iter::empty()).is_empty());
+        assert_eq!(round_robin.pick([3, 4, 5].into_iter()), [4]);
+    }
+}
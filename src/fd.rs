@@ -0,0 +1,122 @@
+use std::io;
+
+use crate::trace;
+
+/// Closes `fd`, handling `EINTR` the way the platform expects.
+///
+/// `close()` may be interrupted by a signal and return `EINTR`, but whether the fd
+/// is still open at that point depends on the OS:
+///
+/// - On Linux, the fd is always released before `close()` returns, even on `EINTR`.
+///   Retrying is a bug: in a multithreaded process, the same fd number may have already
+///   been handed out to an unrelated `open()`/`socket()`/`accept()`, and the retry would close it.
+///   Therefore, `EINTR` is treated as success.
+/// - POSIX leaves the state of the fd unspecified after `EINTR`.
+///   Since retrying is unsafe on most platforms, the error is never retried.
+///   It is reported to the caller instead, so that it can decide what to do.
+///
+/// Any other error (e.g. `EBADF`, `EIO`) is returned as is.
+pub fn close_fd(fd: i32) -> io::Result<()> {
+    // SAFETY: `close()` does not touch any memory owned by the process.
+    // Callers are expected to not use `fd` after this call.
+    let ecode = unsafe { trace::close(fd) };
+    if ecode == 0 {
+        return Ok(());
+    }
+
+    let err = io::Error::last_os_error();
+    match err.raw_os_error() {
+        #[cfg(any(target_os = "linux", target_os = "android"))]
+        Some(libc::EINTR) => Ok(()),
+        _ => Err(err),
+    }
+}
+
+/// Calls `f` again as long as `failed` reports its result as a failure and `errno` is `EINTR`.
+///
+/// A blocking syscall returns `-1` with `EINTR` when a signal with a handler (e.g. `SIGWINCH`, `SIGCHLD`)
+/// arrives in the middle of it, even though nothing is wrong with the fd. Any other error is returned right away.
+///
+/// It is not meant for `close()` (see `close_fd()`), nor for a `poll()`/`select()` loop that checks
+/// `signal::shutdown_requested()` on `EINTR`, since retrying would hide the shutdown request.
+pub fn retry_on_eintr<T>(mut f: impl FnMut() -> T, failed: impl Fn(&T) -> bool) -> T {
+    loop {
+        let res = f();
+        if !failed(&res) || io::Error::last_os_error().raw_os_error() != Some(libc::EINTR) {
+            return res;
+        }
+    }
+}
+
+/// Puts `fd` into non-blocking mode by setting `O_NONBLOCK`, the other file status flags are kept.
+pub fn set_nonblocking(fd: i32) -> io::Result<()> {
+    set_status_flag(fd, libc::O_NONBLOCK, true)
+}
+
+/// Puts `fd` back into blocking mode by clearing `O_NONBLOCK`, the other file status flags are kept.
+pub fn set_blocking(fd: i32) -> io::Result<()> {
+    set_status_flag(fd, libc::O_NONBLOCK, false)
+}
+
+/// Reports whether `O_NONBLOCK` is set on `fd`.
+pub fn is_nonblocking(fd: i32) -> io::Result<bool> {
+    Ok(get_status_flags(fd)? & libc::O_NONBLOCK != 0)
+}
+
+fn get_status_flags(fd: i32) -> io::Result<i32> {
+    // SAFETY: `F_GETFL` does not take any pointers, `fcntl()` is safe to call.
+    let flags = unsafe { libc::fcntl(fd, libc::F_GETFL) };
+    match flags {
+        -1 => Err(io::Error::last_os_error()),
+        _ => Ok(flags),
+    }
+}
+
+fn set_status_flag(fd: i32, flag: i32, on: bool) -> io::Result<()> {
+    // `F_SETFL` replaces all of the status flags, so the current ones are read first.
+    // Otherwise, setting `O_NONBLOCK` alone would silently clear e.g. `O_APPEND`.
+    let flags = get_status_flags(fd)?;
+    let flags = if on { flags | flag } else { flags & !flag };
+
+    // SAFETY: `F_SETFL` does not take any pointers, `fcntl()` is safe to call.
+    let ecode = unsafe { libc::fcntl(fd, libc::F_SETFL, flags) };
+    match ecode {
+        -1 => Err(io::Error::last_os_error()),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::socketpair;
+
+    #[test]
+    fn toggles_o_nonblock() {
+        let (sock, _peer) = socketpair();
+        let flags = get_status_flags(sock.as_raw()).unwrap();
+        assert_eq!(flags & libc::O_NONBLOCK, 0);
+
+        set_nonblocking(sock.as_raw()).unwrap();
+        assert!(is_nonblocking(sock.as_raw()).unwrap());
+        assert_eq!(
+            get_status_flags(sock.as_raw()).unwrap(),
+            flags | libc::O_NONBLOCK
+        );
+
+        set_blocking(sock.as_raw()).unwrap();
+        assert!(!is_nonblocking(sock.as_raw()).unwrap());
+        assert_eq!(get_status_flags(sock.as_raw()).unwrap(), flags);
+    }
+
+    #[test]
+    fn keeps_the_other_status_flags() {
+        let (sock, _peer) = socketpair();
+        set_status_flag(sock.as_raw(), libc::O_APPEND, true).unwrap();
+
+        set_nonblocking(sock.as_raw()).unwrap();
+        set_blocking(sock.as_raw()).unwrap();
+
+        assert_ne!(get_status_flags(sock.as_raw()).unwrap() & libc::O_APPEND, 0);
+    }
+}
@@ -0,0 +1,91 @@
+use std::fmt::Write;
+
+use crate::color;
+
+const BYTES_PER_LINE: usize = 16;
+
+/// Formats `buf` the way `hexdump -C` does: the offset, 16 bytes in hex, and the same bytes as ASCII.
+///
+/// Non-printable bytes are shown as `.` in the ASCII column. Every line ends with a newline.
+pub fn hexdump(buf: &[u8]) -> String {
+    hexdump_with(buf, false)
+}
+
+/// Same as `hexdump()`, but if `color` is set, the offsets are colored and the non-printable bytes are dimmed
+/// with ANSI escape codes. See `color::stdout()` to decide it for the output the dump is printed to.
+pub fn hexdump_with(buf: &[u8], color: bool) -> String {
+    let mut out = String::new();
+
+    for (i, line) in buf.chunks(BYTES_PER_LINE).enumerate() {
+        let offset = format!("{:08x}", i * BYTES_PER_LINE);
+        let _ = write!(out, "{} ", color::paint(&offset, color::CYAN, color));
+
+        for col in 0..BYTES_PER_LINE {
+            // An extra space splits the 16 bytes into two groups of 8.
+            if col % 8 == 0 {
+                out.push(' ');
+            }
+            match line.get(col) {
+                Some(b) => {
+                    let hex = format!("{:02x}", b);
+                    let _ = write!(out, "{} ", dim_unprintable(&hex, *b, color));
+                }
+                None => out.push_str("   "),
+            }
+        }
+
+        out.push_str(" |");
+        for b in line {
+            if is_printable(*b) {
+                out.push(*b as char);
+            } else {
+                out.push_str(&dim_unprintable(".", *b, color));
+            }
+        }
+        out.push_str("|\n");
+    }
+
+    out
+}
+
+fn is_printable(b: u8) -> bool {
+    b.is_ascii_graphic() || b == b' '
+}
+
+fn dim_unprintable(s: &str, b: u8, color: bool) -> String {
+    color::paint(s, color::DIM, color && !is_printable(b))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn matches_the_hexdump_c_layout() {
+        assert_eq!(
+            hexdump(b"hello, world!\n\x00\xff\x7fbeej"),
+            concat!(
+                "00000000  68 65 6c 6c 6f 2c 20 77  6f 72 6c 64 21 0a 00 ff  |hello, world!...|\n",
+                "00000010  7f 62 65 65 6a                                    |.beej|\n",
+            )
+        );
+    }
+
+    #[test]
+    fn empty_buffer_has_no_lines() {
+        assert_eq!(hexdump(b""), "");
+    }
+
+    #[test]
+    fn color_only_adds_escape_codes() {
+        let buf: Vec<u8> = (0..=255).collect();
+
+        let colored = hexdump_with(&buf, true);
+        let stripped = colored
+            .replace(color::CYAN, "")
+            .replace(color::DIM, "")
+            .replace("\x1b[0m", "");
+
+        assert_eq!(stripped, hexdump(&buf));
+    }
+}
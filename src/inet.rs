@@ -0,0 +1,410 @@
+use std::{
+    error,
+    ffi::CString,
+    fmt, io,
+    net::{IpAddr, SocketAddr, SocketAddrV6},
+};
+
+use crate::{fd, sockaddr, socket_kind::AddressFamily, trace};
+
+#[derive(Debug)]
+pub enum Error {
+    Socket(io::Error),
+    Connect(ConnectError),
+    Sendto(SendtoError),
+    Close(io::Error),
+    InvalidZone(String, io::Error),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Socket(err) => write!(f, "socket error: {}", err),
+            Error::Connect(err) => write!(f, "connect error: {}", err),
+            Error::Sendto(err) => write!(f, "sendto error: {}", err),
+            Error::Close(err) => write!(f, "close error: {}", err),
+            Error::InvalidZone(zone, err) => write!(f, "invalid zone {}: {}", zone, err),
+        }
+    }
+}
+
+impl error::Error for Error {}
+
+/// A failed `connect()`, told apart by `errno` so that the message says what is actually wrong.
+#[derive(Debug)]
+pub enum ConnectError {
+    /// `ECONNREFUSED`: The host is up, but nothing listens on the port.
+    ConnectionRefused(io::Error),
+    /// `ETIMEDOUT`: The host did not answer at all.
+    TimedOut(io::Error),
+    /// `EHOSTUNREACH`: There is no route to the host, even though its network is reachable.
+    HostUnreachable(io::Error),
+    /// `ENETUNREACH`: There is no route to the network of the host.
+    NetworkUnreachable(io::Error),
+    Other(io::Error),
+}
+
+impl fmt::Display for ConnectError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConnectError::ConnectionRefused(err) => write!(
+                f,
+                "{} (the host is up, but nothing listens on the port)",
+                err
+            ),
+            ConnectError::TimedOut(err) => write!(
+                f,
+                "{} (the host did not answer, it may be down or behind a firewall)",
+                err
+            ),
+            ConnectError::HostUnreachable(err) => {
+                write!(f, "{} (there is no route to the host)", err)
+            }
+            ConnectError::NetworkUnreachable(err) => {
+                write!(f, "{} (there is no route to the network of the host)", err)
+            }
+            ConnectError::Other(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+impl error::Error for ConnectError {}
+
+impl From<io::Error> for ConnectError {
+    fn from(value: io::Error) -> Self {
+        match value.raw_os_error() {
+            Some(libc::ECONNREFUSED) => Self::ConnectionRefused(value),
+            Some(libc::ETIMEDOUT) => Self::TimedOut(value),
+            Some(libc::EHOSTUNREACH) => Self::HostUnreachable(value),
+            Some(libc::ENETUNREACH) => Self::NetworkUnreachable(value),
+            // A timeout that is enforced by the process itself (e.g. with `poll()`) has no `errno`.
+            None if value.kind() == io::ErrorKind::TimedOut => Self::TimedOut(value),
+            _ => Self::Other(value),
+        }
+    }
+}
+
+/// A failed `sendto()` of a single datagram.
+#[derive(Debug)]
+pub enum SendtoError {
+    /// `EMSGSIZE`: The datagram of the given size is too large to be sent in one piece.
+    TooLarge(usize, io::Error),
+    /// Fewer bytes than the size of the datagram are sent, the rest of the message is lost.
+    Partial(usize, usize),
+    Other(io::Error),
+}
+
+impl fmt::Display for SendtoError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SendtoError::TooLarge(len, err) => write!(
+                f,
+                "{} (the datagram of {} bytes is too large, try a smaller payload)",
+                err, len
+            ),
+            SendtoError::Partial(sent, len) => write!(
+                f,
+                "only {} of {} bytes are sent (the datagram is truncated, try a smaller payload)",
+                sent, len
+            ),
+            SendtoError::Other(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+impl error::Error for SendtoError {}
+
+/// Checks what `sendto()` returned for a datagram of `len` bytes.
+///
+/// Unlike a stream, a datagram cannot be sent in parts: a short count means that the rest of it is lost,
+/// so it is reported as an error instead of being retried.
+/// `errno` is read on failure, so this must be called right after `sendto()`.
+pub fn check_sendto(ret: isize, len: usize) -> Result<usize, SendtoError> {
+    if ret == -1 {
+        let err = io::Error::last_os_error();
+        return match err.raw_os_error() {
+            Some(libc::EMSGSIZE) => Err(SendtoError::TooLarge(len, err)),
+            _ => Err(SendtoError::Other(err)),
+        };
+    }
+
+    let sent = ret as usize;
+    if sent < len {
+        return Err(SendtoError::Partial(sent, len));
+    }
+
+    Ok(sent)
+}
+
+/// Resolves the zone of a scoped IPv6 address into an interface index.
+///
+/// `zone` is either an interface name (e.g. `eth0`), which is resolved with `if_nametoindex()`, or an index (e.g. `2`).
+pub fn interface_index(zone: &str) -> io::Result<u32> {
+    if let Ok(index) = zone.parse::<u32>() {
+        return Ok(index);
+    }
+
+    let name = CString::new(zone).map_err(io::Error::other)?;
+
+    // SAFETY: `name` is a valid, null terminated string.
+    let index = unsafe { libc::if_nametoindex(name.as_ptr()) };
+    match index {
+        0 => Err(io::Error::last_os_error()),
+        _ => Ok(index),
+    }
+}
+
+/// Parses `host` into a `SocketAddr` with `port`, if `host` is an IP address.
+///
+/// An IPv6 address may carry a zone (e.g. `fe80::1%eth0` or `fe80::1%2`), which is required to reach a link-local peer:
+/// every interface has its own `fe80::/10` network, and the zone tells which one of them to use.
+/// It is stored in `sin6_scope_id` once the address is converted by `sockaddr::from_socket_addr()`.
+///
+/// Returns `None` if `host` is not an IP address (e.g. a host name).
+pub fn parse_ip_addr(host: &str, port: u16) -> Option<Result<SocketAddr, Error>> {
+    let (ip_addr, zone) = match host.split_once('%') {
+        Some((ip_addr, zone)) => (ip_addr, Some(zone)),
+        None => (host, None),
+    };
+    let ip_addr = ip_addr.parse::<IpAddr>().ok()?;
+
+    let addr = match (ip_addr, zone) {
+        (ip_addr, None) => Ok(SocketAddr::new(ip_addr, port)),
+        (IpAddr::V6(ip_addr), Some(zone)) => interface_index(zone)
+            .map(|index| SocketAddr::V6(SocketAddrV6::new(ip_addr, port, 0, index)))
+            .map_err(|err| Error::InvalidZone(zone.to_string(), err)),
+        // Only IPv6 addresses are scoped.
+        (IpAddr::V4(_), Some(zone)) => Err(Error::InvalidZone(
+            zone.to_string(),
+            io::Error::from(io::ErrorKind::InvalidInput),
+        )),
+    };
+
+    Some(addr)
+}
+
+/// Connects a `SOCK_STREAM` socket to an already resolved `addr`.
+///
+/// Unlike the examples that start from a host name, `getaddrinfo()` is skipped entirely:
+/// the `sockaddr_in`/`sockaddr_in6` is filled directly from `addr`.
+///
+/// Returns the connected sock fd.
+pub fn connect_to(addr: &SocketAddr) -> Result<i32, Error> {
+    // SAFETY: `socket()` does not touch any memory owned by the process.
+    let sock_fd = unsafe { trace::socket(AddressFamily::of(addr).as_raw(), libc::SOCK_STREAM, 0) };
+    if sock_fd == -1 {
+        return Err(Error::Socket(io::Error::last_os_error()));
+    }
+
+    let (sa, sa_len) = sockaddr::from_socket_addr(addr);
+
+    // SAFETY: `sa` holds a valid address of `sa_len` bytes, and `sock_fd` is a valid socket.
+    let ecode = unsafe { trace::connect(sock_fd, &raw const sa as *const libc::sockaddr, sa_len) };
+    if ecode == -1 {
+        let err = io::Error::last_os_error();
+        let _ = fd::close_fd(sock_fd);
+        return Err(Error::Connect(err.into()));
+    }
+
+    Ok(sock_fd)
+}
+
+/// Sends `msg` as a single datagram to an already resolved `addr`, without calling `getaddrinfo()`.
+///
+/// A new `SOCK_DGRAM` socket is created for the message and it is closed afterwards.
+///
+/// Returns the number of bytes sent.
+pub fn sendto_addr(addr: &SocketAddr, msg: &[u8]) -> Result<usize, Error> {
+    // SAFETY: `socket()` does not touch any memory owned by the process.
+    let sock_fd = unsafe { trace::socket(AddressFamily::of(addr).as_raw(), libc::SOCK_DGRAM, 0) };
+    if sock_fd == -1 {
+        return Err(Error::Socket(io::Error::last_os_error()));
+    }
+
+    let (sa, sa_len) = sockaddr::from_socket_addr(addr);
+
+    // SAFETY: `msg` is a valid, initialized slice and `sa` holds a valid address of `sa_len` bytes.
+    let bytes = unsafe {
+        trace::sendto(
+            sock_fd,
+            msg.as_ptr() as *const libc::c_void,
+            msg.len(),
+            0,
+            &raw const sa as *const libc::sockaddr,
+            sa_len,
+        )
+    };
+    let res = check_sendto(bytes, msg.len()).map_err(Error::Sendto);
+
+    // The message is sent, `sock_fd` is not used after this call.
+    fd::close_fd(sock_fd).map_err(Error::Close)?;
+
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ffi::CStr;
+
+    use super::*;
+    use crate::{listener, server_builder::Server, socket_fd::Socket, udp};
+
+    fn connects_to(host: &str) {
+        let listener = Server::new().bind(Some(host), 0).listen().unwrap();
+
+        let sock = Socket::from_raw(connect_to(&listener.local_addr()).unwrap());
+        let (_, peer_addr) = listener.accept().unwrap();
+
+        assert_eq!(peer_addr, listener::local_addr(sock.as_raw()).unwrap());
+    }
+
+    fn sends_to(node: &CStr) {
+        let (sock_fd, addr) = udp::bind_udp(Some(node), c"0").unwrap();
+        let sock = Socket::from_raw(sock_fd);
+
+        assert_eq!(sendto_addr(&addr, b"hello").unwrap(), 5);
+
+        let mut buf = [0u8; 16];
+        let (rbytes, _) = udp::recv_from(sock.as_raw(), &mut buf).unwrap();
+        assert_eq!(&buf[..rbytes], b"hello");
+    }
+
+    #[test]
+    fn oversized_datagram_on_a_connected_socket_is_too_large() {
+        let (receiver_fd, receiver_addr) = udp::bind_udp(Some(c"127.0.0.1"), c"0").unwrap();
+        let _receiver = Socket::from_raw(receiver_fd);
+        let sock = Socket::new(libc::AF_INET, libc::SOCK_DGRAM, 0).unwrap();
+
+        let (sa, sa_len) = sockaddr::from_socket_addr(&receiver_addr);
+        // SAFETY: `sa` holds a valid address of `sa_len` bytes.
+        let ecode = unsafe {
+            libc::connect(
+                sock.as_raw(),
+                &raw const sa as *const libc::sockaddr,
+                sa_len,
+            )
+        };
+        assert_eq!(ecode, 0, "connect error: {}", io::Error::last_os_error());
+
+        // More than the 65507 bytes that fit into the payload of a single IPv4 UDP datagram.
+        let buf = vec![0u8; 70_000];
+        // SAFETY: `buf` is valid for `buf.len()` bytes for the entire `send()` call.
+        let ret = unsafe {
+            libc::send(
+                sock.as_raw(),
+                buf.as_ptr() as *const libc::c_void,
+                buf.len(),
+                0,
+            )
+        };
+
+        match check_sendto(ret, buf.len()) {
+            Err(err @ SendtoError::TooLarge(70_000, _)) => {
+                assert!(err.to_string().contains("try a smaller payload"), "{}", err)
+            }
+            res => panic!("unexpected result: {:?}", res),
+        }
+    }
+
+    #[test]
+    fn connect_to_ipv4() {
+        connects_to("127.0.0.1");
+    }
+
+    #[test]
+    fn connect_to_ipv6() {
+        connects_to("::1");
+    }
+
+    #[test]
+    fn sendto_addr_ipv4() {
+        sends_to(c"127.0.0.1");
+    }
+
+    #[test]
+    fn sendto_addr_ipv6() {
+        sends_to(c"::1");
+    }
+
+    #[test]
+    fn closed_loopback_port_is_refused() {
+        // The port is free once the listener is closed, and nothing else listens on it.
+        let addr = Server::new()
+            .bind(Some("127.0.0.1"), 0)
+            .listen()
+            .unwrap()
+            .local_addr();
+
+        match connect_to(&addr) {
+            Err(Error::Connect(ConnectError::ConnectionRefused(_))) => {}
+            res => panic!("unexpected result: {:?}", res),
+        }
+    }
+
+    #[test]
+    fn connect_errors_are_told_apart_by_errno() {
+        let classify = |errno| ConnectError::from(io::Error::from_raw_os_error(errno));
+
+        assert!(matches!(
+            classify(libc::ECONNREFUSED),
+            ConnectError::ConnectionRefused(_)
+        ));
+        assert!(matches!(
+            classify(libc::ETIMEDOUT),
+            ConnectError::TimedOut(_)
+        ));
+        assert!(matches!(
+            classify(libc::EHOSTUNREACH),
+            ConnectError::HostUnreachable(_)
+        ));
+        assert!(matches!(
+            classify(libc::ENETUNREACH),
+            ConnectError::NetworkUnreachable(_)
+        ));
+        assert!(matches!(classify(libc::EACCES), ConnectError::Other(_)));
+
+        // A timeout enforced with `poll()` has no errno.
+        let err = ConnectError::from(io::Error::from(io::ErrorKind::TimedOut));
+        assert!(matches!(err, ConnectError::TimedOut(_)));
+    }
+
+    #[test]
+    fn ip_addr_without_a_zone() {
+        let addr = parse_ip_addr("127.0.0.1", 3490).unwrap().unwrap();
+        assert_eq!(addr, "127.0.0.1:3490".parse().unwrap());
+
+        let addr = parse_ip_addr("fe80::1", 3490).unwrap().unwrap();
+        assert_eq!(addr, "[fe80::1]:3490".parse().unwrap());
+    }
+
+    #[test]
+    fn ipv6_zone_is_stored_as_the_scope_id() {
+        let SocketAddr::V6(addr) = parse_ip_addr("fe80::1%2", 3490).unwrap().unwrap() else {
+            panic!("not an IPv6 address");
+        };
+        assert_eq!(addr.scope_id(), 2);
+
+        // The loopback interface is there on every system, though its index varies.
+        let SocketAddr::V6(addr) = parse_ip_addr("fe80::1%lo", 3490).unwrap().unwrap() else {
+            panic!("not an IPv6 address");
+        };
+        assert_eq!(addr.scope_id(), interface_index("lo").unwrap());
+        assert_ne!(addr.scope_id(), 0);
+    }
+
+    #[test]
+    fn invalid_zones_are_rejected() {
+        for host in ["fe80::1%no-such-interface", "127.0.0.1%1"] {
+            match parse_ip_addr(host, 3490) {
+                Some(Err(Error::InvalidZone(..))) => {}
+                res => panic!("{} is not rejected: {:?}", host, res),
+            }
+        }
+    }
+
+    #[test]
+    fn host_names_are_not_ip_addresses() {
+        assert!(parse_ip_addr("localhost", 3490).is_none());
+        assert!(parse_ip_addr("localhost%1", 3490).is_none());
+    }
+}
@@ -1,4 +1,34 @@
+pub mod addrinfo;
+pub mod chat;
+pub mod cidr;
+pub mod client_builder;
+pub mod color;
+pub mod deadline;
 pub mod dgram;
+pub mod emfile;
+pub mod fairness;
+pub mod fd;
+pub mod hexdump;
+pub mod inet;
+pub mod listener;
+pub mod payload;
+pub mod privilege;
+pub mod reactor;
+pub mod readiness;
+pub mod resolve;
+pub mod serialize;
+pub mod server_builder;
+pub mod signal;
+pub mod sockaddr;
+pub mod socket_fd;
+pub mod socket_kind;
+pub mod sockopt;
+pub mod stats;
 pub mod stream;
+pub mod stream_io;
 pub mod syscall;
 pub mod techniques;
+#[cfg(test)]
+mod testutil;
+pub mod trace;
+pub mod udp;
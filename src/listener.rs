@@ -0,0 +1,428 @@
+use std::{
+    error,
+    ffi::CStr,
+    fmt, io, mem,
+    net::SocketAddr,
+    ptr, thread,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    addrinfo::{AddrInfoList, Hints},
+    fd, readiness, sockaddr,
+    socket_fd::Socket,
+    sockopt, stream_io, trace,
+};
+
+#[derive(Debug)]
+pub enum Error {
+    Getaddrinfo(String),
+    Socket(io::Error),
+    Setsockopt(io::Error),
+    Bind(io::Error),
+    Listen(io::Error),
+    InvalidAddrFamily(i32),
+    Getsockopt(io::Error),
+    Getsockname(io::Error),
+    NotListening(i32),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Getaddrinfo(err) => write!(f, "getaddrinfo error: {}", err),
+            Error::Socket(err) => write!(f, "socket error: {}", err),
+            Error::Setsockopt(err) => write!(f, "setsockopt error: {}", err),
+            Error::Bind(err) => write!(f, "bind error: {}", err),
+            Error::Listen(err) => write!(f, "listen error: {}", err),
+            Error::InvalidAddrFamily(af) => write!(f, "invalid address family {}", af),
+            Error::Getsockopt(err) => write!(f, "getsockopt error: {}", err),
+            Error::Getsockname(err) => write!(f, "getsockname error: {}", err),
+            Error::NotListening(fd) => write!(f, "fd {} is not a listening socket", fd),
+        }
+    }
+}
+
+impl error::Error for Error {}
+
+/// How `bind_listener()` retries a `bind()` that fails with `EADDRINUSE`.
+///
+/// A server that is restarted quickly may find its port still held by connections in `TIME_WAIT`
+/// (or by the previous process that has not exited yet). Instead of failing right away,
+/// `bind()` is retried `retries` times, doubling the delay after each attempt.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BindRetry {
+    pub retries: u32,
+    pub delay: Duration,
+}
+
+impl Default for BindRetry {
+    fn default() -> Self {
+        Self {
+            retries: 0,
+            delay: Duration::from_millis(100),
+        }
+    }
+}
+
+const BACKLOG: i32 = 10;
+
+/// How `bind_listener_with()` sets up the listener socket.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ListenerOptions {
+    /// Whether `SO_REUSEADDR` is set before `bind()`.
+    pub reuseaddr: bool,
+    /// The `backlog` passed to `listen()`.
+    pub backlog: i32,
+    pub retry: BindRetry,
+    /// The `TCP_MAXSEG` set before `bind()`, the accepted connections inherit it.
+    pub max_segment: Option<i32>,
+    /// Whether an `AF_INET6` listener sets `IPV6_V6ONLY`, so that it leaves the IPv4 clients to an `AF_INET` one
+    /// on the same port. Otherwise, the system default decides (dual-stack on most systems).
+    pub v6only: bool,
+    /// The `TCP_DEFER_ACCEPT` set before `listen()` (Linux only), in seconds: a connection is only handed to
+    /// `accept()` once its first data arrives, or once the kernel gives up waiting for it.
+    pub defer_accept: Option<i32>,
+}
+
+impl Default for ListenerOptions {
+    fn default() -> Self {
+        Self {
+            reuseaddr: true,
+            backlog: BACKLOG,
+            retry: BindRetry::default(),
+            max_segment: None,
+            v6only: false,
+            defer_accept: None,
+        }
+    }
+}
+
+/// Creates a `SOCK_STREAM` socket that listens on `port` on all local addresses of `family`.
+///
+/// Each address returned by `getaddrinfo()` is tried in order until one of them can be bound.
+/// `SO_REUSEADDR` is set on the socket before `bind()`.
+///
+/// Returns the listener sock fd and the address it is bound to.
+pub fn bind_listener(
+    family: i32,
+    port: &CStr,
+    retry: &BindRetry,
+) -> Result<(i32, SocketAddr), Error> {
+    let opts = ListenerOptions {
+        retry: *retry,
+        ..Default::default()
+    };
+    bind_listener_with(None, family, port, &opts)
+}
+
+/// Same as `bind_listener()`, but listens on `node` instead of all local addresses if it is set,
+/// and the socket is set up according to `opts`.
+pub fn bind_listener_with(
+    node: Option<&CStr>,
+    family: i32,
+    port: &CStr,
+    opts: &ListenerOptions,
+) -> Result<(i32, SocketAddr), Error> {
+    let hints = Hints::new()
+        .family(family)
+        .socktype(libc::SOCK_STREAM)
+        .passive(true)
+        .build();
+
+    let ai_list = AddrInfoList::new(node, Some(port), &hints).map_err(Error::Getaddrinfo)?;
+
+    let mut res = Err(Error::Socket(io::Error::from(
+        io::ErrorKind::AddrNotAvailable,
+    )));
+
+    for ai in &ai_list {
+        res = bind_addrinfo(ai, opts);
+        if res.is_ok() {
+            break;
+        }
+    }
+
+    let (sock_fd, addr) = res?;
+
+    // SAFETY: `listen()` is safe to use on a valid, bound `sock_fd`.
+    let ecode = unsafe { trace::listen(sock_fd, opts.backlog) };
+    if ecode == -1 {
+        let err = io::Error::last_os_error();
+        let _ = fd::close_fd(sock_fd);
+        return Err(Error::Listen(err));
+    }
+
+    Ok((sock_fd, addr))
+}
+
+fn bind_addrinfo(ai: &libc::addrinfo, opts: &ListenerOptions) -> Result<(i32, SocketAddr), Error> {
+    // SAFETY: `socket()` is safe to call since `ai` is valid.
+    let sock_fd = unsafe { trace::socket(ai.ai_family, ai.ai_socktype, ai.ai_protocol) };
+    if sock_fd == -1 {
+        return Err(Error::Socket(io::Error::last_os_error()));
+    }
+
+    // The socket is closed on every error path below, so a failed candidate does not leak its fd.
+    let res = setup_socket(sock_fd, ai, opts);
+    if res.is_err() {
+        let _ = fd::close_fd(sock_fd);
+    }
+
+    res.map(|addr| (sock_fd, addr))
+}
+
+fn setup_socket(
+    sock_fd: i32,
+    ai: &libc::addrinfo,
+    opts: &ListenerOptions,
+) -> Result<SocketAddr, Error> {
+    if opts.reuseaddr {
+        sockopt::set_int(sock_fd, libc::SOL_SOCKET, libc::SO_REUSEADDR, 1)
+            .map_err(Error::Setsockopt)?;
+    }
+    if opts.v6only && ai.ai_family == libc::AF_INET6 {
+        sockopt::set_int(sock_fd, libc::IPPROTO_IPV6, libc::IPV6_V6ONLY, 1)
+            .map_err(Error::Setsockopt)?;
+    }
+    if let Some(max_segment) = opts.max_segment {
+        sockopt::set_int(sock_fd, libc::IPPROTO_TCP, libc::TCP_MAXSEG, max_segment)
+            .map_err(Error::Setsockopt)?;
+    }
+    #[cfg(target_os = "linux")]
+    if let Some(secs) = opts.defer_accept {
+        sockopt::set_int(sock_fd, libc::IPPROTO_TCP, libc::TCP_DEFER_ACCEPT, secs)
+            .map_err(Error::Setsockopt)?;
+    }
+
+    let retry = &opts.retry;
+    let mut delay = retry.delay;
+    let mut attempt = 0;
+    loop {
+        // SAFETY: `bind()` is safe to call since `sock_fd` and `ai` are valid.
+        let ecode = unsafe { trace::bind(sock_fd, ai.ai_addr, ai.ai_addrlen) };
+        if ecode == 0 {
+            break;
+        }
+
+        let err = io::Error::last_os_error();
+        // Only a busy address is worth waiting for, anything else (e.g. EACCES) will not go away by itself.
+        if err.raw_os_error() != Some(libc::EADDRINUSE) || attempt == retry.retries {
+            return Err(Error::Bind(err));
+        }
+
+        attempt += 1;
+        eprintln!(
+            "bind: address in use, retrying in {:?} ({}/{})",
+            delay, attempt, retry.retries
+        );
+        thread::sleep(delay);
+        delay *= 2;
+    }
+
+    // The kernel picks the port if it is 0, therefore the address is read back from the socket.
+    local_addr(sock_fd)
+}
+
+/// Reports whether `fd` is a socket that `listen()` was called on, by using `getsockopt(SO_ACCEPTCONN)`.
+///
+/// Fails with `ENOTSOCK` if `fd` is not a socket, and with `EBADF` if it is not open at all.
+pub fn is_listening(fd: i32) -> io::Result<bool> {
+    let accept_conn = sockopt::get_int(fd, libc::SOL_SOCKET, libc::SO_ACCEPTCONN)?;
+    Ok(accept_conn != 0)
+}
+
+/// Reads `TCP_DEFER_ACCEPT` back from the listener `fd`, in seconds.
+///
+/// The kernel keeps the value as a number of SYN-ACK retransmissions, so the value read back is the requested one
+/// rounded up to the end of a retransmission interval (e.g. 5 becomes 7, with the default 1 second initial timeout).
+#[cfg(target_os = "linux")]
+pub fn defer_accept(fd: i32) -> io::Result<i32> {
+    sockopt::get_int(fd, libc::IPPROTO_TCP, libc::TCP_DEFER_ACCEPT)
+}
+
+/// Takes over a listener socket that is inherited from the parent process (e.g. a socket activation manager).
+///
+/// `fd` is rejected unless it is a listening socket, otherwise the first `accept()` would fail
+/// with a confusing `EINVAL` or `ENOTSOCK` instead.
+///
+/// Returns `fd` and the address it is bound to.
+pub fn inherit_listener(fd: i32) -> Result<(i32, SocketAddr), Error> {
+    if !is_listening(fd).map_err(Error::Getsockopt)? {
+        return Err(Error::NotListening(fd));
+    }
+
+    Ok((fd, local_addr(fd)?))
+}
+
+/// Returns the address `sock_fd` is bound to by using `getsockname()`.
+pub fn local_addr(sock_fd: i32) -> Result<SocketAddr, Error> {
+    // SAFETY: All zero `sockaddr_storage` is a valid initialization.
+    // Read will happen after it is written by `getsockname()`.
+    let mut sa: libc::sockaddr_storage = unsafe { mem::zeroed() };
+    let mut sa_len = mem::size_of_val(&sa) as libc::socklen_t;
+
+    // SAFETY: `sa` is big enough for any address family, and `sa_len` holds its size.
+    let ecode =
+        unsafe { libc::getsockname(sock_fd, &raw mut sa as *mut libc::sockaddr, &raw mut sa_len) };
+    if ecode == -1 {
+        return Err(Error::Getsockname(io::Error::last_os_error()));
+    }
+
+    sockaddr::to_socket_addr(&sa).ok_or(Error::InvalidAddrFamily(sa.ss_family as i32))
+}
+
+/// The notice the servers send to the connections they drain on exit, see `drain_pending()`.
+pub const SHUTDOWN_NOTICE: &[u8] = b"server shutting down\n";
+
+/// Accepts the connections that are still waiting in the accept queue of `listener_fd`, sends `notice` to each
+/// of them and closes them, so that they learn why they are dropped instead of being reset by `close()`.
+///
+/// The kernel completes the handshakes on its own, so a client may already be connected (and sending) even though
+/// the server never called `accept()` for it. Closing the listener resets those connections.
+///
+/// Draining stops once the queue is empty or `budget` runs out, whichever comes first. The notices are sent
+/// on non-blocking sockets, so a client that does not read cannot hold up the shutdown either.
+///
+/// Returns the number of connections that were drained.
+pub fn drain_pending(listener_fd: i32, notice: &[u8], budget: Duration) -> io::Result<usize> {
+    let deadline = Instant::now() + budget;
+    let mut drained = 0;
+
+    while Instant::now() < deadline {
+        // Only the connections that are already queued are drained, new ones are not waited for.
+        if !readiness::wait_readable(listener_fd, Some(Duration::ZERO))? {
+            break;
+        }
+
+        // SAFETY: The peer address is not needed, passing null pointers to `accept()` is valid.
+        let sock_fd = unsafe { trace::accept(listener_fd, ptr::null_mut(), ptr::null_mut()) };
+        if sock_fd == -1 {
+            let err = io::Error::last_os_error();
+            if err.kind() == io::ErrorKind::Interrupted {
+                continue;
+            }
+            return Err(err);
+        }
+        let sock = Socket::from_raw(sock_fd);
+
+        // The client may be gone already, it is closed either way.
+        let _ = fd::set_nonblocking(sock.as_raw())
+            .and_then(|()| Ok(stream_io::sendall(sock.as_raw(), notice)?));
+        drained += 1;
+    }
+
+    Ok(drained)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ffi::CString;
+
+    use super::*;
+
+    fn listen_on(port: &CStr, retry: BindRetry) -> Result<(i32, SocketAddr), Error> {
+        let opts = ListenerOptions {
+            retry,
+            ..Default::default()
+        };
+        bind_listener_with(Some(c"127.0.0.1"), libc::AF_INET, port, &opts)
+    }
+
+    #[test]
+    fn bind_retries_until_the_port_is_released() {
+        let (occupant, addr) = listen_on(c"0", BindRetry::default()).unwrap();
+        let port = CString::new(addr.port().to_string()).unwrap();
+
+        // Without retries, the busy port fails right away.
+        let err = listen_on(&port, BindRetry::default()).unwrap_err();
+        assert!(
+            matches!(&err, Error::Bind(err) if err.raw_os_error() == Some(libc::EADDRINUSE)),
+            "unexpected error: {}",
+            err
+        );
+
+        let releaser = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(300));
+            fd::close_fd(occupant).unwrap();
+        });
+
+        // 100 + 200 + 400 + 800 ms of waiting covers the release with room to spare.
+        let retry = BindRetry {
+            retries: 4,
+            delay: Duration::from_millis(100),
+        };
+        let (sock_fd, rebound) = listen_on(&port, retry).unwrap();
+        releaser.join().unwrap();
+
+        assert_eq!(rebound, addr);
+        fd::close_fd(sock_fd).unwrap();
+    }
+
+    #[test]
+    fn only_a_listened_socket_is_listening() {
+        let (listener_fd, _) = listen_on(c"0", BindRetry::default()).unwrap();
+        let fresh = Socket::new(libc::AF_INET, libc::SOCK_STREAM, 0).unwrap();
+
+        assert!(is_listening(listener_fd).unwrap());
+        assert!(!is_listening(fresh.as_raw()).unwrap());
+
+        assert_eq!(inherit_listener(listener_fd).unwrap().0, listener_fd);
+        assert!(matches!(
+            inherit_listener(fresh.as_raw()),
+            Err(Error::NotListening(fd)) if fd == fresh.as_raw()
+        ));
+
+        fd::close_fd(listener_fd).unwrap();
+    }
+
+    #[test]
+    fn a_non_socket_is_rejected() {
+        let mut fds = [0i32; 2];
+        // SAFETY: `fds` has room for the two fds that `pipe()` writes.
+        assert_eq!(unsafe { libc::pipe(fds.as_mut_ptr()) }, 0);
+
+        let err = is_listening(fds[0]).unwrap_err();
+        assert_eq!(err.raw_os_error(), Some(libc::ENOTSOCK));
+
+        fd::close_fd(fds[0]).unwrap();
+        fd::close_fd(fds[1]).unwrap();
+    }
+
+    #[test]
+    fn a_pending_connection_receives_the_notice() {
+        let (listener_fd, addr) = listen_on(c"0", BindRetry::default()).unwrap();
+
+        // The handshake completes in the backlog, the client is never accepted before the drain.
+        let client = Socket::new(libc::AF_INET, libc::SOCK_STREAM, 0).unwrap();
+        let (sa, sa_len) = sockaddr::from_socket_addr(&addr);
+        // SAFETY: `sa` holds a valid address of `sa_len` bytes.
+        let ecode = unsafe {
+            libc::connect(
+                client.as_raw(),
+                &raw const sa as *const libc::sockaddr,
+                sa_len,
+            )
+        };
+        assert_eq!(ecode, 0, "connect error: {}", io::Error::last_os_error());
+
+        let drained = drain_pending(
+            listener_fd,
+            b"server shutting down\n",
+            Duration::from_secs(1),
+        )
+        .unwrap();
+        assert_eq!(drained, 1);
+        assert_eq!(
+            crate::testutil::recv_to_end(&client),
+            b"server shutting down\n"
+        );
+
+        // Nothing else is queued, so a second drain returns right away.
+        assert_eq!(
+            drain_pending(listener_fd, b"", Duration::from_secs(1)).unwrap(),
+            0
+        );
+        fd::close_fd(listener_fd).unwrap();
+    }
+}
@@ -1,23 +1,68 @@
-use std::{error, process::ExitCode};
+use std::{error, iter, num::NonZeroUsize, process::ExitCode, time::Duration};
 
-use clap::{Parser, Subcommand, command};
+use clap::{Arg, Args, Command, CommandFactory, FromArgMatches, Parser, Subcommand, ValueEnum};
+
+use bjrs::socket_kind::{AddressFamily, SocketType};
 
 fn main() -> ExitCode {
     match run() {
         Ok(_) => ExitCode::SUCCESS,
         Err(err) => {
             eprintln!("{}", err);
-            ExitCode::FAILURE
+
+            // A missing privilege is not a bug of the example, it is reported with its own exit code.
+            // The privilege error may be wrapped by the error of the example, hence the whole chain is checked.
+            let denied = iter::successors(Some(err.as_ref()), |err| err.source()).any(|err| {
+                matches!(
+                    err.downcast_ref::<bjrs::privilege::Error>(),
+                    Some(bjrs::privilege::Error::PermissionDenied { .. })
+                )
+            });
+            if denied {
+                ExitCode::from(bjrs::privilege::EXIT_NOPERM)
+            } else {
+                ExitCode::FAILURE
+            }
         }
     }
 }
 
 fn run() -> Result<(), Box<dyn error::Error>> {
-    let cli = Cli::parse();
+    let matches = with_examples(Cli::command(), None).get_matches();
+    let cli = Cli::from_arg_matches(&matches).unwrap_or_else(|err| err.exit());
+    bjrs::trace::set_enabled(cli.protocol_trace);
+    bjrs::color::set_choice(cli.color.into());
+    if let Some(secs) = cli.deadline {
+        bjrs::deadline::set(Duration::from_secs(secs));
+    }
 
     match cli.example {
         Example::Syscall { cmd } => match cmd {
-            SyscallCommand::Getaddrinfo { host } => bjrs::syscall::getaddrinfo(&host)?,
+            SyscallCommand::Getaddrinfo {
+                host,
+                service,
+                family,
+                socktype,
+                addrconfig,
+                v4mapped,
+                raw,
+            } => bjrs::syscall::getaddrinfo(
+                &host,
+                service.as_deref(),
+                family,
+                socktype,
+                addrconfig,
+                v4mapped,
+                raw,
+            )?,
+            SyscallCommand::Getnameinfo {
+                host,
+                service,
+                numeric_host,
+                name_required,
+            } => {
+                bjrs::syscall::getnameinfo(&host, service.as_deref(), numeric_host, name_required)?
+            }
             SyscallCommand::Socket => bjrs::syscall::socket()?,
             SyscallCommand::Bind { reuse_port } => {
                 if reuse_port {
@@ -26,48 +71,391 @@ fn run() -> Result<(), Box<dyn error::Error>> {
                     bjrs::syscall::bind()
                 }?
             }
-            SyscallCommand::Connect => bjrs::syscall::connect()?,
+            SyscallCommand::Connect { host, scope } => {
+                bjrs::syscall::connect(&host, scope.as_deref())?
+            }
             SyscallCommand::Listen => bjrs::syscall::listen()?,
             SyscallCommand::Accept => {
                 let _ = bjrs::syscall::accept()?;
             }
-            SyscallCommand::Send => bjrs::syscall::send()?,
-            SyscallCommand::Recv => bjrs::syscall::recv()?,
+            SyscallCommand::Send { write_timeout } => {
+                bjrs::syscall::send(write_timeout.map(Duration::from_millis))?
+            }
+            SyscallCommand::Recv { summary } => {
+                bjrs::syscall::recv(summary)?;
+            }
             SyscallCommand::Sendto => bjrs::syscall::sendto()?,
-            SyscallCommand::Recvfrom => bjrs::syscall::recvfrom()?,
+            SyscallCommand::Recvfrom {
+                count,
+                r#loop,
+                summary,
+                filter,
+            } => {
+                let count = if r#loop { None } else { Some(count) };
+                bjrs::syscall::recvfrom(count, &filter.into(), summary)?;
+            }
             SyscallCommand::Close => bjrs::syscall::close()?,
             SyscallCommand::Shutdown => bjrs::syscall::shutdown()?,
-            SyscallCommand::Getpeername => bjrs::syscall::getpeername()?,
+            SyscallCommand::Getpeername {
+                reply_with_peer,
+                loopback,
+            } => {
+                if loopback {
+                    bjrs::syscall::getpeername_loopback()?
+                } else {
+                    bjrs::syscall::getpeername(reply_with_peer)?
+                }
+            }
+            SyscallCommand::Getsockname => bjrs::syscall::getsockname()?,
             SyscallCommand::Gethostname => bjrs::syscall::gethostname()?,
         },
         Example::Stream { cmd } => match cmd {
-            StreamCommand::Server => bjrs::stream::server()?,
-            StreamCommand::Client => bjrs::stream::client()?,
+            StreamCommand::Server {
+                port,
+                listen_fd,
+                emfile_guard,
+                max_segment,
+                write_timeout,
+                defer_accept,
+                drain_on_exit,
+                drain_budget,
+                message,
+                bind,
+            } => {
+                let opts = bjrs::stream::ServerOptions {
+                    max_segment,
+                    write_timeout: write_timeout.map(Duration::from_millis),
+                    defer_accept,
+                    drain_on_exit: drain_on_exit.then(|| Duration::from_millis(drain_budget)),
+                };
+                bjrs::stream::server(
+                    port,
+                    &bind.into(),
+                    listen_fd,
+                    emfile_guard,
+                    &opts,
+                    message.as_deref().map(str::as_bytes),
+                )?
+            }
+            StreamCommand::Client {
+                host,
+                scope,
+                port,
+                drain,
+                max_bytes,
+                exact,
+                waitall,
+                max_segment,
+                connect,
+            } => {
+                let mode = match exact {
+                    Some(len) => bjrs::stream::ReadMode::Exact { len, waitall },
+                    None if drain => bjrs::stream::ReadMode::Drain { max_bytes },
+                    None => bjrs::stream::ReadMode::Once,
+                };
+                bjrs::stream::client(
+                    host.as_deref(),
+                    scope.as_deref(),
+                    port,
+                    mode,
+                    max_segment,
+                    connect.into(),
+                )?
+            }
         },
         Example::Dgram { cmd } => match cmd {
-            DgramCommand::Server => bjrs::dgram::server()?,
-            DgramCommand::Client => bjrs::dgram::client()?,
+            DgramCommand::Server {
+                port,
+                max_datagram,
+                probe_size,
+                filter,
+                family,
+                summary,
+            } => {
+                bjrs::dgram::server(
+                    port,
+                    max_datagram,
+                    probe_size,
+                    &filter.into(),
+                    family,
+                    summary,
+                )?;
+            }
+            DgramCommand::Client { port, family } => bjrs::dgram::client(port, family)?,
+            DgramCommand::Roundtrip => bjrs::dgram::roundtrip()?,
         },
         Example::Techniques { cmd } => match cmd {
             TechniquesCommand::Blocking => bjrs::techniques::blocking()?,
             TechniquesCommand::Poll => bjrs::techniques::poll()?,
-            TechniquesCommand::Pollserver => bjrs::techniques::pollserver()?,
+            TechniquesCommand::Pollserver {
+                stats,
+                idle_timeout,
+                max_events,
+                count,
+                once,
+                emfile_guard,
+                family,
+                motd,
+                drain_on_exit,
+                drain_budget,
+                defer_accept,
+                bind,
+            } => {
+                let opts = bjrs::techniques::PollserverOptions {
+                    idle_timeout: idle_timeout.map(Duration::from_secs),
+                    max_events,
+                    max_messages: if once { Some(1) } else { count },
+                    emfile_guard,
+                    motd: motd.read()?,
+                    drain_on_exit: drain_on_exit.then(|| Duration::from_millis(drain_budget)),
+                    defer_accept,
+                };
+                let summary = bjrs::techniques::pollserver(&bind.into(), family, &opts)?;
+                if stats {
+                    println!("pollserver: {}", summary);
+                }
+            }
             TechniquesCommand::Select => bjrs::techniques::select()?,
-            TechniquesCommand::Selectserver => bjrs::techniques::selectserver()?,
-            TechniquesCommand::Broadcaster { host, msg } => {
-                bjrs::techniques::broadcaster(&host, &msg)?
+            TechniquesCommand::Selectserver {
+                stats,
+                idle_timeout,
+                max_events,
+                max_broadcasts,
+                duration,
+                emfile_guard,
+                motd,
+                drain_on_exit,
+                drain_budget,
+                defer_accept,
+                bind,
+            } => {
+                let opts = bjrs::techniques::SelectserverOptions {
+                    idle_timeout: idle_timeout.map(Duration::from_secs),
+                    max_events,
+                    bounds: bjrs::techniques::Bounds {
+                        broadcasts: max_broadcasts,
+                        duration: duration.map(Duration::from_secs),
+                    },
+                    emfile_guard,
+                    motd: motd.read()?,
+                    drain_on_exit: drain_on_exit.then(|| Duration::from_millis(drain_budget)),
+                    defer_accept,
+                };
+                let summary = bjrs::techniques::selectserver(&bind.into(), &opts)?;
+                if stats {
+                    println!("selectserver: {}", summary);
+                }
+            }
+            TechniquesCommand::Broadcaster {
+                host,
+                msg,
+                port,
+                count,
+                interval,
+            } => bjrs::techniques::broadcaster(
+                &host,
+                port,
+                &msg,
+                count,
+                Duration::from_millis(interval),
+            )?,
+            TechniquesCommand::Echoserver {
+                max_bytes,
+                hexdump_both,
+                bind,
+            } => bjrs::techniques::echoserver(&bind.into(), max_bytes, hexdump_both)?,
+            TechniquesCommand::Echoclient {
+                host,
+                scope,
+                full_duplex,
+                threads,
+                ..
+            } => {
+                let duplex = if full_duplex {
+                    bjrs::techniques::Duplex::Full
+                } else if threads {
+                    bjrs::techniques::Duplex::Threads
+                } else {
+                    bjrs::techniques::Duplex::Half
+                };
+                bjrs::techniques::echoclient(&host, scope.as_deref(), duplex)?
+            }
+            TechniquesCommand::Lineserver { checksum, bind } => {
+                bjrs::techniques::lineserver(&bind.into(), checksum)?
+            }
+            TechniquesCommand::Sockinfo {
+                so_debug,
+                max_segment,
+            } => bjrs::techniques::sockinfo(so_debug, max_segment)?,
+            TechniquesCommand::FlowControl {
+                rcvbuf,
+                total,
+                reader_delay,
+            } => {
+                bjrs::techniques::flow_control(rcvbuf, total, Duration::from_millis(reader_delay))?
+            }
+            TechniquesCommand::BacklogProbe { requested } => {
+                bjrs::techniques::backlog_probe(requested)?
             }
+            TechniquesCommand::Heartbeatserver { port } => bjrs::techniques::heartbeatserver(port)?,
+            TechniquesCommand::Heartbeat {
+                host,
+                port,
+                interval,
+                count,
+                payload,
+                summary_json,
+            } => {
+                let interval = Duration::from_millis(interval);
+                bjrs::techniques::heartbeat(
+                    &host,
+                    port,
+                    interval,
+                    count,
+                    payload.payload_size,
+                    payload.into(),
+                    summary_json,
+                )?
+            }
+            TechniquesCommand::PipeSocket {
+                port,
+                interval,
+                ticks,
+            } => bjrs::techniques::pipe_and_socket(port, Duration::from_millis(interval), ticks)?,
+            TechniquesCommand::SocksConnect { proxy, target } => {
+                bjrs::techniques::socks_connect(&proxy, &target)?
+            }
+            #[cfg(target_os = "linux")]
+            TechniquesCommand::TcpInfo { host, port } => bjrs::techniques::tcpinfo(&host, port)?,
+            #[cfg(target_os = "linux")]
+            TechniquesCommand::UdpErrqueue { host, port } => {
+                bjrs::techniques::udp_errqueue(&host, port)?
+            }
+            #[cfg(target_os = "linux")]
+            TechniquesCommand::Fastopen { connections } => bjrs::techniques::fastopen(connections)?,
+            TechniquesCommand::Omniserver { port, unix } => {
+                bjrs::techniques::omniserver(port, unix.as_deref())?
+            }
+            TechniquesCommand::TimeWait => bjrs::techniques::time_wait()?,
+            TechniquesCommand::Starvation {
+                clients,
+                max_events,
+                iterations,
+            } => bjrs::techniques::starvation(clients, max_events, iterations)?,
+            TechniquesCommand::UdpProbeReceiver { port } => {
+                bjrs::techniques::udp_probe_receiver(port)?
+            }
+            TechniquesCommand::UdpProbe { host, port, count } => {
+                bjrs::techniques::udp_probe(&host, port, count)?
+            }
+            TechniquesCommand::ReliableReceiver { port, drop_acks } => {
+                bjrs::techniques::reliable_receiver(port, drop_acks)?
+            }
+            TechniquesCommand::ReliableSender {
+                host,
+                port,
+                timeout,
+                max_retries,
+                msgs,
+                payload,
+            } => bjrs::techniques::reliable_sender(
+                &host,
+                port,
+                Duration::from_millis(timeout),
+                max_retries,
+                &msgs,
+                payload.payload_size,
+                payload.into(),
+            )?,
         },
     }
 
     Ok(())
 }
 
+// Appends a concrete invocation to the help of every example, which is built from its actual arguments.
+// The test instructions in the doc comments are prose and they may drift as the flags change, this part cannot.
+fn with_examples(cmd: Command, parent: Option<&str>) -> Command {
+    let path = match parent {
+        Some(parent) => format!("{} {}", parent, cmd.get_name()),
+        None => cmd.get_name().to_string(),
+    };
+
+    if cmd.has_subcommands() {
+        let names: Vec<String> = cmd
+            .get_subcommands()
+            .map(|sub| sub.get_name().to_string())
+            .collect();
+        return names.iter().fold(cmd, |cmd, name| {
+            cmd.mut_subcommand(name, |sub| with_examples(sub, Some(&path)))
+        });
+    }
+
+    let invocation: Vec<String> = iter::once(path)
+        .chain(cmd.get_arguments().filter_map(example_arg))
+        .collect();
+    cmd.after_help(format!(
+        "Example, with every option at its default:\n  {}",
+        invocation.join(" ")
+    ))
+}
+
+// Positionals are shown as placeholders and options with a default value are spelled out.
+// The flags and the options without a default are left out, since they change what the example does.
+fn example_arg(arg: &Arg) -> Option<String> {
+    if arg.is_hide_set() {
+        return None;
+    }
+
+    if arg.is_positional() {
+        let value_name = arg
+            .get_value_names()
+            .and_then(|names| names.first())
+            .map_or_else(
+                || arg.get_id().as_str().to_uppercase(),
+                |name| name.to_string(),
+            );
+        let repeated = arg
+            .get_num_args()
+            .is_some_and(|num_args| num_args.max_values() > 1);
+
+        return Some(match (arg.is_required_set(), repeated) {
+            (true, false) => format!("<{}>", value_name),
+            (true, true) => format!("<{}>...", value_name),
+            (false, _) => format!("[{}]", value_name),
+        });
+    }
+
+    let default = arg.get_default_values().first()?;
+    if !arg.get_action().takes_values() {
+        return None;
+    }
+
+    Some(format!(
+        "--{} {}",
+        arg.get_long()?,
+        default.to_string_lossy()
+    ))
+}
+
 #[derive(Parser)]
 #[command(version, about, long_about = None)]
 pub struct Cli {
     #[command(subcommand)]
     example: Example,
+
+    /// Print every socket syscall with its arguments and its result to stderr, like a tiny `strace`.
+    #[arg(long, global = true, default_value_t = false)]
+    protocol_trace: bool,
+
+    /// Color the hexdump and trace output, `auto` only colors it if it goes to a terminal.
+    #[arg(long, global = true, value_enum, default_value_t = ColorArg::Auto)]
+    color: ColorArg,
+
+    /// Give up with a timeout error if `accept()`, `recv()` or `recvfrom()` of a syscall example is still waiting
+    /// for a peer SECS seconds after the start.
+    #[arg(long, global = true, value_name = "SECS")]
+    deadline: Option<u64>,
 }
 
 #[derive(Subcommand)]
@@ -101,7 +489,53 @@ enum Example {
 #[derive(Subcommand)]
 enum SyscallCommand {
     /// Section 5.1 - `getaddrinfo()` - Prepare to Launch!
-    Getaddrinfo { host: String },
+    Getaddrinfo {
+        host: String,
+
+        /// Also resolve this service, a port number or a name like `http`, and print the ports.
+        #[arg(long)]
+        service: Option<String>,
+
+        /// Only return the addresses of this family.
+        #[arg(long, value_enum, default_value_t = AddressFamily::Unspec)]
+        family: AddressFamily,
+
+        /// Only return the addresses for this socket type.
+        #[arg(long, value_enum, default_value_t = SocketType::Stream)]
+        socktype: SocketType,
+
+        /// Sets `AI_ADDRCONFIG`, only the families that the host has an address configured for are returned.
+        #[arg(long, default_value_t = false)]
+        addrconfig: bool,
+
+        /// Asks for IPv6 only and sets `AI_V4MAPPED`, IPv4 addresses are returned as `::ffff:a.b.c.d` if there is no IPv6 one.
+        #[arg(long, default_value_t = false, conflicts_with = "family")]
+        v4mapped: bool,
+
+        /// Also dump the raw bytes of each `sockaddr`, to see its layout and byte order.
+        #[arg(long, default_value_t = false)]
+        raw: bool,
+    },
+
+    /// Section 5.1 - `getnameinfo()` - The reverse of `getaddrinfo()`
+    ///
+    /// Run it with a name (e.g. `localhost`) and with an address that has no name (e.g. `192.0.2.1`).
+    /// Observe that the address without a name is returned as is, and that `--name-required` turns it into an error.
+    Getnameinfo {
+        host: String,
+
+        /// Also look up this service, a port number or a name like `http`, and print its name.
+        #[arg(long)]
+        service: Option<String>,
+
+        /// Sets `NI_NUMERICHOST`, the address is returned in its numeric form without a reverse lookup.
+        #[arg(long, default_value_t = false)]
+        numeric_host: bool,
+
+        /// Sets `NI_NAMEREQD`, an address without a name is an error instead of being returned in its numeric form.
+        #[arg(long, default_value_t = false, conflicts_with = "numeric_host")]
+        name_required: bool,
+    },
 
     /// Section 5.2 - `socket()` - Get the File Descriptor!
     Socket,
@@ -114,7 +548,15 @@ enum SyscallCommand {
     },
 
     /// Section 5.4 - `connect()` - Hey, you!
-    Connect,
+    Connect {
+        /// The host to connect to, on port 3490.
+        #[arg(default_value = "www.example.com")]
+        host: String,
+
+        /// The interface (name or index) to reach a link-local IPv6 host through, same as a `%zone` suffix.
+        #[arg(long, value_name = "IFACE", visible_alias = "interface-index")]
+        scope: Option<String>,
+    },
 
     /// Section 5.5 - `listen()` - Will Somebody Please Call Me?
     Listen,
@@ -129,7 +571,11 @@ enum SyscallCommand {
     /// Run this command in the background.
     /// Find out the listened IP address (IP or IPv6) via `lsof -niTCP:3490` or via any command you prefer.
     /// Initiate a connection to see the sent data. The easiest would probably be `ncat <IP_ADDR> 3490`.
-    Send,
+    Send {
+        /// Give up on a `send()` that cannot complete within MS milliseconds, by setting `SO_SNDTIMEO`.
+        #[arg(long, value_name = "MS")]
+        write_timeout: Option<u64>,
+    },
 
     /// Section 5.7 - `send() and recv()` - Talk to me, baby!
     ///
@@ -138,7 +584,12 @@ enum SyscallCommand {
     /// Run this command in the background.
     /// Find out the listened IP address (IP or IPv6) via `lsof -niTCP:3490` or via any command you prefer.
     /// Initiate a connection and send a message to the process. The easiest would be `ncat <IP_ADDR> 3490 <<< "string message"`.
-    Recv,
+    /// Run it with `--summary` to get a trailing line with the byte count and the source, e.g. for scripts.
+    Recv {
+        /// Print a trailing summary line with the received bytes, the number of segments and the source address.
+        #[arg(long, default_value_t = false)]
+        summary: bool,
+    },
 
     /// Section 5.8 - `sendto() and recvfrom()` - Talk to me, DGRAM-style
     ///
@@ -155,8 +606,24 @@ enum SyscallCommand {
     ///
     /// Run this command to start our "UDP server".
     /// Send a UDP message from a separate terminal session by using `ncat -u 127.0.0.1 3490 <<< "hello UDP message!"` or via any command you prefer.
-    /// Observe that the message "hello UDP message!" appears on our process' terminal session.
-    Recvfrom,
+    /// Observe that the message "hello UDP message!" appears on our process' terminal session, along with its source address.
+    /// Pass `--count N` to receive N datagrams, or `--loop` to keep receiving until Ctrl-C.
+    Recvfrom {
+        /// The number of datagrams to receive before exiting.
+        #[arg(long, default_value_t = 1)]
+        count: usize,
+
+        /// Keep receiving datagrams until SIGINT.
+        #[arg(long, default_value_t = false, conflicts_with = "count")]
+        r#loop: bool,
+
+        /// Print a trailing summary line with the received bytes, the number of datagrams and the source addresses.
+        #[arg(long, default_value_t = false)]
+        summary: bool,
+
+        #[command(flatten)]
+        filter: SourceFilterArgs,
+    },
 
     /// Section 5.9 - `close() and shutdown()` - Get outta my face!
     Close,
@@ -177,7 +644,25 @@ enum SyscallCommand {
     /// Run this command to start our "TCP" server.
     /// Connect to this server in a separate terminal session by using `ncat 127.0.0.1 3490` or via any command you prefer.
     /// Observe that our server writes the source IP address and it's port to the stdout.
-    Getpeername,
+    /// Run it with `--reply-with-peer` to receive the same address in the client as well.
+    ///
+    /// Run it with `--loopback` instead to connect to an ephemeral port from a thread, without `ncat`.
+    /// Observe that the address the server gets from `getpeername()` is the one the client gets from `getsockname()`.
+    Getpeername {
+        /// Send the observed address of the peer back to it.
+        #[arg(long, default_value_t = false)]
+        reply_with_peer: bool,
+
+        /// Connect from a thread of the same process and compare both ends of the connection.
+        #[arg(long, default_value_t = false, conflicts_with = "reply_with_peer")]
+        loopback: bool,
+    },
+
+    /// Section 5.3 - `bind()` - What Port Am I On?
+    ///
+    /// Run this command to bind a socket to port 0, and observe the port the kernel picked for it.
+    /// Observe that the socket reports the wildcard address with port 0 before `bind()`.
+    Getsockname,
 
     /// Section 5.11 - `gethostname()` - Who am I?
     Gethostname,
@@ -192,13 +677,242 @@ pub enum StreamCommand {
     /// Run this command to start our "TCP" server.
     /// In a separate terminal session, run the client command `bjrs stream client`.
     /// Observe that the server sends the message "Hello world!" to the client.
-    Server,
+    /// Run it with `--message pong` to send "pong" instead, e.g. for scripted tests.
+    ///
+    /// Run with `--listen-fd FD` to accept on a listener socket inherited from the parent process.
+    /// Passing an fd that is not a listening socket (e.g. `--listen-fd 0`) is rejected.
+    ///
+    /// Run with `--drain-on-exit`, suspend the server with `kill -STOP`, and connect a client that the server cannot accept yet.
+    /// Then send `kill -INT` and `kill -CONT` to observe that the client receives "server shutting down" instead of a reset.
+    Server {
+        /// The port to listen on.
+        #[arg(long, default_value_t = 3490)]
+        port: u16,
+
+        /// Use the inherited listener socket FD instead of binding a new one.
+        #[arg(long, value_name = "FD")]
+        listen_fd: Option<i32>,
+
+        /// Keep a spare fd to turn away new connections once the server runs out of fds.
+        #[arg(long, default_value_t = false)]
+        emfile_guard: bool,
+
+        /// Set `TCP_MAXSEG` (the MSS) on the listener and print the value each connection ends up with.
+        /// An inherited listener is already set up, so it cannot be used with `--listen-fd`.
+        #[arg(long, value_name = "BYTES", conflicts_with = "listen_fd")]
+        max_segment: Option<i32>,
+
+        /// Give up on sending to a client that does not read within MS milliseconds, by setting `SO_SNDTIMEO`.
+        #[arg(long, value_name = "MS")]
+        write_timeout: Option<u64>,
+
+        /// Do not accept a connection until the client sends something, or for about SECS seconds,
+        /// by setting `TCP_DEFER_ACCEPT` (Linux only). The client of this server never sends, so it is served late.
+        /// It cannot be used with `--listen-fd` either.
+        #[arg(long, value_name = "SECS", conflicts_with = "listen_fd")]
+        defer_accept: Option<i32>,
+
+        /// Stop on Ctrl-C or SIGTERM, and tell the connections that are not accepted yet that the server is shutting down.
+        #[arg(long, default_value_t = false)]
+        drain_on_exit: bool,
+
+        /// The time budget of `--drain-on-exit` in milliseconds.
+        #[arg(
+            long,
+            value_name = "MS",
+            default_value_t = 1000,
+            requires = "drain_on_exit"
+        )]
+        drain_budget: u64,
+
+        /// Send MESSAGE instead of "Hello world!", as is. Include a newline in it if the client expects one.
+        #[arg(long)]
+        message: Option<String>,
+
+        #[command(flatten)]
+        bind: BindArgs,
+    },
 
     /// Section 6.2 - A Simple Stream Client
     ///
     /// To test this example, check out `bjrs help stream server`.
     /// You can also observe ECONNREFUSED error by running this command first before the server command.
-    Client,
+    /// Run it with `--connect-retries 5` instead to observe it waiting for the server to start.
+    ///
+    /// Run with `--drain` to read until the server closes the connection, and add `--max-bytes N` to stop after N bytes.
+    ///
+    /// Run with `--exact N` to read exactly N bytes with a `recv()` loop, and add `--waitall` to use a single `recv(MSG_WAITALL)` call instead.
+    /// Since the server sends 13 bytes and closes the connection, `--exact 5` reads 5 bytes, and `--exact 100` stops at 13 in both modes.
+    Client {
+        /// The host of the server, the loopback addresses by default.
+        #[arg(long)]
+        host: Option<String>,
+
+        /// The interface (name or index) to reach a link-local IPv6 host through, same as a `%zone` suffix.
+        #[arg(long, value_name = "IFACE", visible_alias = "interface-index")]
+        scope: Option<String>,
+
+        /// The port of the server.
+        #[arg(long, default_value_t = 3490)]
+        port: u16,
+
+        /// Keep reading until the server closes the connection.
+        #[arg(long, default_value_t = false)]
+        drain: bool,
+
+        /// Stop draining after N bytes and close the connection.
+        #[arg(long, value_name = "N", requires = "drain")]
+        max_bytes: Option<usize>,
+
+        /// Read exactly N bytes from the server.
+        #[arg(long, value_name = "N", conflicts_with = "drain")]
+        exact: Option<usize>,
+
+        /// Read the `--exact` bytes with `MSG_WAITALL` instead of a `recv()` loop.
+        #[arg(long, default_value_t = false, requires = "exact")]
+        waitall: bool,
+
+        /// Set `TCP_MAXSEG` (the MSS) before connecting and print the value read back after the handshake.
+        /// The kernel clamps it to the path MTU.
+        #[arg(long, value_name = "BYTES")]
+        max_segment: Option<i32>,
+
+        #[command(flatten)]
+        connect: ConnectArgs,
+    },
+}
+
+#[derive(Args)]
+pub struct BindArgs {
+    /// Retry `bind()` this many times if the address is still in use.
+    #[arg(long, default_value_t = 0)]
+    bind_retries: u32,
+
+    /// The delay before the first `bind()` retry in milliseconds, doubled after each retry.
+    #[arg(long, default_value_t = 100)]
+    bind_retry_delay: u64,
+}
+
+#[derive(Args)]
+pub struct ConnectArgs {
+    /// Retry `connect()` this many times if it is refused or times out, e.g. while the server is starting.
+    #[arg(long, default_value_t = 0)]
+    connect_retries: u32,
+
+    /// The delay before the first `connect()` retry in milliseconds, doubled after each retry.
+    #[arg(long, default_value_t = 100)]
+    connect_retry_delay: u64,
+}
+
+#[derive(Args)]
+pub struct SourceFilterArgs {
+    /// Only process the datagrams from these sources, e.g. `127.0.0.0/8,::1`. Can be repeated.
+    #[arg(long, value_name = "CIDR", value_delimiter = ',')]
+    allow: Vec<bjrs::cidr::Cidr>,
+
+    /// Drop the datagrams from these sources, even if they are allowed. Can be repeated.
+    #[arg(long, value_name = "CIDR", value_delimiter = ',')]
+    deny: Vec<bjrs::cidr::Cidr>,
+}
+
+impl From<SourceFilterArgs> for bjrs::cidr::SourceFilter {
+    fn from(value: SourceFilterArgs) -> Self {
+        Self {
+            allow: value.allow,
+            deny: value.deny,
+        }
+    }
+}
+
+#[derive(Args)]
+pub struct MotdArgs {
+    /// Send TEXT as a message of the day to every new client, followed by a newline.
+    #[arg(long, value_name = "TEXT", conflicts_with = "motd_file")]
+    motd: Option<String>,
+
+    /// Send the contents of PATH as a message of the day to every new client.
+    #[arg(long, value_name = "PATH")]
+    motd_file: Option<std::path::PathBuf>,
+}
+
+impl MotdArgs {
+    fn read(self) -> std::io::Result<Option<Vec<u8>>> {
+        match (self.motd, self.motd_file) {
+            (Some(text), _) => Ok(Some(format!("{}\n", text).into_bytes())),
+            (None, Some(path)) => std::fs::read(&path).map(Some).map_err(|err| {
+                std::io::Error::new(
+                    err.kind(),
+                    format!("failed to read the MOTD from {}: {}", path.display(), err),
+                )
+            }),
+            (None, None) => Ok(None),
+        }
+    }
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum ColorArg {
+    Auto,
+    Always,
+    Never,
+}
+
+impl From<ColorArg> for bjrs::color::ColorChoice {
+    fn from(value: ColorArg) -> Self {
+        match value {
+            ColorArg::Auto => Self::Auto,
+            ColorArg::Always => Self::Always,
+            ColorArg::Never => Self::Never,
+        }
+    }
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum PayloadKindArg {
+    Pattern,
+    Random,
+}
+
+#[derive(Args)]
+pub struct PayloadArgs {
+    /// The number of payload bytes to send in each message.
+    #[arg(long, value_name = "N", default_value_t = 0)]
+    payload_size: usize,
+
+    /// How the payload bytes are generated.
+    #[arg(long, value_enum, default_value_t = PayloadKindArg::Pattern)]
+    payload: PayloadKindArg,
+
+    /// The seed of the `random` payload, the same seed always generates the same bytes.
+    #[arg(long, default_value_t = 1)]
+    seed: u64,
+}
+
+impl From<PayloadArgs> for bjrs::payload::PayloadKind {
+    fn from(value: PayloadArgs) -> Self {
+        match value.payload {
+            PayloadKindArg::Pattern => Self::Pattern,
+            PayloadKindArg::Random => Self::Random { seed: value.seed },
+        }
+    }
+}
+
+impl From<BindArgs> for bjrs::listener::BindRetry {
+    fn from(value: BindArgs) -> Self {
+        Self {
+            retries: value.bind_retries,
+            delay: Duration::from_millis(value.bind_retry_delay),
+        }
+    }
+}
+
+impl From<ConnectArgs> for bjrs::client_builder::ConnectRetry {
+    fn from(value: ConnectArgs) -> Self {
+        Self {
+            retries: value.connect_retries,
+            delay: Duration::from_millis(value.connect_retry_delay),
+        }
+    }
 }
 
 #[derive(Subcommand)]
@@ -210,7 +924,33 @@ pub enum DgramCommand {
     /// Run this command to start our "UDP" server.
     /// In a separate terminal session, run the client command `bjrs dgram client`.
     /// Observe that the server receives the message "Hello UDP server!" from the client.
-    Server,
+    /// Run it with `--max-datagram 5` to observe the message being truncated, then add `--probe-size` to receive it whole.
+    /// Run it with `--deny 127.0.0.0/8` to observe the message being dropped.
+    /// Run both commands with the same `--port` and `--family` to use another port or IPv6.
+    Server {
+        /// The port to receive the datagrams on.
+        #[arg(long, default_value_t = 4950)]
+        port: u16,
+
+        /// The size of the receive buffer in bytes, a longer datagram is truncated.
+        #[arg(long, default_value_t = 100)]
+        max_datagram: usize,
+
+        /// Peek the size of the datagram first and receive it into an exactly sized buffer (Linux only, otherwise `--max-datagram` is used).
+        #[arg(long, default_value_t = false)]
+        probe_size: bool,
+
+        /// Bind to IPv4, IPv6, or whichever of them `getaddrinfo()` returns first (the bound one is reported).
+        #[arg(long, value_enum, default_value_t = AddressFamily::V4)]
+        family: AddressFamily,
+
+        /// Print a trailing summary line with the received bytes, the number of datagrams and the source address.
+        #[arg(long, default_value_t = false)]
+        summary: bool,
+
+        #[command(flatten)]
+        filter: SourceFilterArgs,
+    },
 
     /// Section 6.3 - Datagram Sockets
     ///
@@ -218,7 +958,24 @@ pub enum DgramCommand {
     /// You can also observe the nature of UDP packets by just running this command without the server. You will see that the packets will be sent without any errors.
     ///
     /// That's the gist with datagram sockets, the data sent through them is not guaranteed to arrive at the destination!
-    Client,
+    Client {
+        /// The port of the server.
+        #[arg(long, default_value_t = 4950)]
+        port: u16,
+
+        /// Send to the IPv4 or the IPv6 loopback address, or to whichever of them `getaddrinfo()` returns first.
+        #[arg(long, value_enum, default_value_t = AddressFamily::V4)]
+        family: AddressFamily,
+    },
+
+    /// `sendto()` & `recvfrom()` round-trip - Address symmetry
+    ///
+    /// To test this example:
+    ///
+    /// Run this command, it does not need a separate server.
+    /// Observe that the server replies to the address captured by `recvfrom()`, and that the reply comes from the server's address.
+    /// The round-trip is run on both IPv4 and IPv6 loopback.
+    Roundtrip,
 }
 
 #[derive(Subcommand)]
@@ -238,7 +995,65 @@ enum TechniquesCommand {
     /// Send messages from each terminal session to observe the server sending each message to all other clients.
     /// Close a client connection to observe that our server acknowleges it.
     /// Send messages from remaining connections to see that server does not try to send each message to the closed connections.
-    Pollserver,
+    /// Hit Ctrl-C to stop the server.
+    ///
+    /// Run with `--idle-timeout 10` and leave a client silent to observe that the server closes it and notifies the others.
+    ///
+    /// Run with `--drain-on-exit`, suspend the server with `kill -STOP`, and connect a client that the server cannot accept yet.
+    /// Then send `kill -INT` and `kill -CONT` to observe that the client receives "server shutting down" instead of a reset.
+    Pollserver {
+        /// Print a summary of connections, bytes and peak clients on exit.
+        #[arg(long, default_value_t = false)]
+        stats: bool,
+
+        /// Close the clients that do not send anything for SECS seconds.
+        #[arg(long, value_name = "SECS")]
+        idle_timeout: Option<u64>,
+
+        /// Serve at most N ready sockets per `poll()` call, in round-robin order.
+        #[arg(long, value_name = "N")]
+        max_events: Option<NonZeroUsize>,
+
+        /// Stop after relaying N messages.
+        #[arg(long, value_name = "N")]
+        count: Option<u64>,
+
+        /// Stop after relaying the first message, same as `--count 1`.
+        #[arg(long, default_value_t = false, conflicts_with = "count")]
+        once: bool,
+
+        /// Keep a spare fd to turn away new connections once the server runs out of fds.
+        #[arg(long, default_value_t = false)]
+        emfile_guard: bool,
+
+        /// Listen on IPv4 or on IPv6, an IPv6 listener accepts IPv4 clients as well on most systems.
+        #[arg(long, value_enum, default_value_t = AddressFamily::V4)]
+        family: AddressFamily,
+
+        #[command(flatten)]
+        motd: MotdArgs,
+
+        /// On exit, tell the connections that are not accepted yet that the server is shutting down.
+        #[arg(long, default_value_t = false)]
+        drain_on_exit: bool,
+
+        /// The time budget of `--drain-on-exit` in milliseconds.
+        #[arg(
+            long,
+            value_name = "MS",
+            default_value_t = 1000,
+            requires = "drain_on_exit"
+        )]
+        drain_budget: u64,
+
+        /// Do not accept a client until it sends its first message, or for about SECS seconds,
+        /// by setting `TCP_DEFER_ACCEPT` (Linux only).
+        #[arg(long, value_name = "SECS")]
+        defer_accept: Option<i32>,
+
+        #[command(flatten)]
+        bind: BindArgs,
+    },
 
     /// Section 7.3 - `select()` - Synchronous I/O Multiplexing, Old School
     Select,
@@ -252,7 +1067,61 @@ enum TechniquesCommand {
     /// Send messages from each terminal session to observe the server sending each message to all other clients.
     /// Close a client connection to observe that our server acknowleges it.
     /// Send messages from remaining connections to see that server does not try to send each message to the closed connections.
-    Selectserver,
+    /// Hit Ctrl-C to stop the server.
+    ///
+    /// Run with `--idle-timeout 10` and leave a client silent to observe that the server closes it and notifies the others.
+    ///
+    /// Run with `--drain-on-exit`, suspend the server with `kill -STOP`, and connect a client that the server cannot accept yet.
+    /// Then send `kill -INT` and `kill -CONT` to observe that the client receives "server shutting down" instead of a reset.
+    Selectserver {
+        /// Print a summary of connections, bytes and peak clients on exit.
+        #[arg(long, default_value_t = false)]
+        stats: bool,
+
+        /// Close the clients that do not send anything for SECS seconds.
+        #[arg(long, value_name = "SECS")]
+        idle_timeout: Option<u64>,
+
+        /// Serve at most N ready sockets per `select()` call, in round-robin order.
+        #[arg(long, value_name = "N")]
+        max_events: Option<NonZeroUsize>,
+
+        /// Exit after relaying N messages.
+        #[arg(long, value_name = "N")]
+        max_broadcasts: Option<u64>,
+
+        /// Exit after running for SECS seconds.
+        #[arg(long, value_name = "SECS")]
+        duration: Option<u64>,
+
+        /// Keep a spare fd to turn away new connections once the server runs out of fds.
+        #[arg(long, default_value_t = false)]
+        emfile_guard: bool,
+
+        #[command(flatten)]
+        motd: MotdArgs,
+
+        /// On exit, tell the connections that are not accepted yet that the server is shutting down.
+        #[arg(long, default_value_t = false)]
+        drain_on_exit: bool,
+
+        /// The time budget of `--drain-on-exit` in milliseconds.
+        #[arg(
+            long,
+            value_name = "MS",
+            default_value_t = 1000,
+            requires = "drain_on_exit"
+        )]
+        drain_budget: u64,
+
+        /// Do not accept a client until it sends its first message, or for about SECS seconds,
+        /// by setting `TCP_DEFER_ACCEPT` (Linux only).
+        #[arg(long, value_name = "SECS")]
+        defer_accept: Option<i32>,
+
+        #[command(flatten)]
+        bind: BindArgs,
+    },
 
     /// Section 7.7 - Broadcast Packets - Hello, World!
     ///
@@ -264,11 +1133,391 @@ enum TechniquesCommand {
     ///
     /// Observe that the server can receive the broadcast messages.
     /// Since the UDP server is implemented to recv a single message only, you will need to restart the server while trying different addresses.
+    ///
+    /// Run it with `--count 5 --interval 500` to send the message as a periodic beacon,
+    /// and observe all of them arriving on a receiver that keeps listening, e.g. `ncat -ulk 4950`.
+    /// Use `--port` along with `bjrs dgram server --port` to try another port.
     Broadcaster {
         /// The host address to send the message.
         host: String,
 
         /// The message to send.
         msg: String,
+
+        /// The port to send the message to, `bjrs dgram server` listens on 4950 by default.
+        #[arg(long, default_value_t = 4950)]
+        port: u16,
+
+        /// The number of times to send the message.
+        #[arg(long, default_value_t = 1)]
+        count: u32,
+
+        /// The delay between two messages in milliseconds.
+        #[arg(long, value_name = "MS", default_value_t = 1000)]
+        interval: u64,
+    },
+    /// Echo server - A server that sends back everything it receives
+    ///
+    /// To test this example:
+    ///
+    /// Run this command to start our "TCP" echo server.
+    /// In a separate terminal session, run the client command `bjrs techniques echoclient 127.0.0.1`.
+    /// Type a few lines and observe that the server sends each of them back.
+    /// Run with `--max-bytes N` to observe that the server closes a client after reading N bytes from it.
+    Echoserver {
+        /// Close a client after reading N bytes from it.
+        #[arg(long, value_name = "N")]
+        max_bytes: Option<usize>,
+
+        /// Hexdump the bytes that are received and the bytes that are sent back.
+        #[arg(long, default_value_t = false)]
+        hexdump_both: bool,
+
+        #[command(flatten)]
+        bind: BindArgs,
+    },
+
+    /// Echo client - Half-duplex vs full-duplex communication
+    ///
+    /// To test this example, check out `bjrs help techniques echoserver`.
+    ///
+    /// In half-duplex mode (the default), the client sends a line and then waits for the whole echo before reading the next one from stdin.
+    /// In full-duplex mode, the client uses `poll()` to send and receive at the same time.
+    /// Pipe a large file into both modes (e.g. `bjrs techniques echoclient 127.0.0.1 --full-duplex < /etc/services`) to observe the difference.
+    Echoclient {
+        /// The host address of the echo server.
+        host: String,
+
+        /// The interface (name or index) to reach a link-local IPv6 host through, same as a `%zone` suffix.
+        #[arg(long, value_name = "IFACE", visible_alias = "interface-index")]
+        scope: Option<String>,
+
+        /// Send and receive one after the other (default).
+        #[arg(long, default_value_t = false, conflicts_with_all = ["full_duplex", "threads"])]
+        half_duplex: bool,
+
+        /// Send and receive at the same time by polling stdin and the socket together.
+        #[arg(long, default_value_t = false, conflicts_with = "threads")]
+        full_duplex: bool,
+
+        /// Send and receive at the same time from two threads, each with its own half of the socket.
+        #[arg(long, default_value_t = false)]
+        threads: bool,
+    },
+
+    /// Line server - A newline-delimited command protocol
+    ///
+    /// To test this example:
+    ///
+    /// Run this command to start our "TCP" server.
+    /// Connect to this server in a separate terminal session by using `ncat 127.0.0.1 3490` or via any command you prefer.
+    /// Send `PING`, `ECHO <text>` and `QUIT` lines, and observe the replies.
+    /// Observe that the server closes the connection after `QUIT`.
+    ///
+    /// Run with `--checksum` to require a trailing CRC32 on each line, e.g. `PING 1340d049`.
+    /// Change a single character of a line to observe that the server rejects it with `ERR bad checksum`.
+    Lineserver {
+        /// Append a CRC32 to every reply and reject the lines whose CRC32 does not match.
+        #[arg(long, default_value_t = false)]
+        checksum: bool,
+
+        #[command(flatten)]
+        bind: BindArgs,
+    },
+
+    /// Socket options - Prints the `SOL_SOCKET` options of a fresh TCP socket
+    ///
+    /// To test this example:
+    ///
+    /// Run this command to see the defaults of the host.
+    /// Run it with `--so-debug` as a regular user to see the `EACCES` error, then as root to see `SO_DEBUG = 1`.
+    /// Run it with `--max-segment 1000` to set `TCP_MAXSEG` and read it back, the MSS of an actual connection
+    /// is shown by `bjrs stream client --max-segment`.
+    Sockinfo {
+        /// Turn on `SO_DEBUG` before reading the options back (requires `CAP_NET_ADMIN`).
+        #[arg(long, default_value_t = false)]
+        so_debug: bool,
+
+        /// Set `TCP_MAXSEG` (the MSS) and read it back.
+        #[arg(long, value_name = "BYTES")]
+        max_segment: Option<i32>,
+    },
+
+    /// TCP flow control - A slow receiver with a tiny `SO_RCVBUF` slows the sender down
+    ///
+    /// To test this example:
+    ///
+    /// Run this command, both the sender and the receiver run in the same process over the loopback interface.
+    /// Observe that `send()` would block long before all bytes are sent, and how long the sender waits for the receiver.
+    /// Run it again with a larger `--rcvbuf` to see the sender wait less.
+    FlowControl {
+        /// The `SO_RCVBUF` of the receiver, in bytes.
+        #[arg(long, default_value_t = 4096)]
+        rcvbuf: i32,
+
+        /// The number of bytes to send.
+        #[arg(long, default_value_t = 4 * 1024 * 1024)]
+        total: usize,
+
+        /// How long the receiver waits before it starts reading, in milliseconds.
+        #[arg(long, default_value_t = 500)]
+        reader_delay: u64,
+    },
+
+    /// Listen backlog probe - Prints the accept queue length the kernel uses for a `listen()` backlog
+    ///
+    /// To test this example:
+    ///
+    /// Run this command with a small backlog (e.g. 10) and with a huge one (e.g. 100000).
+    /// Observe that the huge one is capped to `net.core.somaxconn` on Linux, without `listen()` failing.
+    BacklogProbe {
+        /// The backlog to pass to `listen()`.
+        #[arg(allow_negative_numbers = true)]
+        requested: i32,
+    },
+
+    /// UDP heartbeat server - Echoes every heartbeat back to its sender
+    ///
+    /// To test this example:
+    ///
+    /// Run this command to start our "UDP" heartbeat server.
+    /// In a separate terminal session, run the client command `bjrs techniques heartbeat 127.0.0.1`.
+    Heartbeatserver {
+        /// The port to listen on.
+        #[arg(long, default_value_t = 4951)]
+        port: u16,
+    },
+
+    /// UDP heartbeat client - RTT, jitter and packet loss
+    ///
+    /// To test this example, check out `bjrs help techniques heartbeatserver`.
+    ///
+    /// Observe the RTT and the jitter of each heartbeat, and the summary at the end.
+    /// Stop the server in the middle of a run (or run the client without a server) to observe that the missing echoes are counted as lost.
+    Heartbeat {
+        /// The host address of the heartbeat server.
+        host: String,
+
+        /// The port of the heartbeat server.
+        #[arg(long, default_value_t = 4951)]
+        port: u16,
+
+        /// The time between two heartbeats in milliseconds, it is also the time to wait for an echo.
+        #[arg(long, default_value_t = 1000)]
+        interval: u64,
+
+        /// The number of heartbeats to send.
+        #[arg(long, default_value_t = 10)]
+        count: u64,
+
+        #[command(flatten)]
+        payload: PayloadArgs,
+
+        /// Print only the summary, as a single JSON object for scripts.
+        #[arg(long, default_value_t = false)]
+        summary_json: bool,
+    },
+
+    /// Polling a pipe and a socket together - `poll()` works on any fd
+    ///
+    /// To test this example:
+    ///
+    /// Run this command and observe the pipe becoming ready every `--interval` milliseconds.
+    /// Run it with `--port 4950`, and in a separate terminal session, send datagrams via `bjrs techniques broadcaster 127.0.0.1 hello` to observe the socket becoming ready in between.
+    /// The example ends when the pipe reaches EOF after `--ticks` lines.
+    PipeSocket {
+        /// The port of the UDP socket on 127.0.0.1.
+        #[arg(long, default_value_t = 4952)]
+        port: u16,
+
+        /// The time between two lines written to the pipe in milliseconds.
+        #[arg(long, default_value_t = 1000)]
+        interval: u64,
+
+        /// The number of lines to write to the pipe before closing it.
+        #[arg(long, default_value_t = 5)]
+        ticks: u32,
+    },
+
+    /// SOCKS5 client - Connecting to a host through a proxy
+    ///
+    /// To test this example:
+    ///
+    /// Start a SOCKS5 proxy without authentication (e.g. `ssh -N -D 1080 localhost`).
+    /// Run `bjrs stream server` in a separate terminal session, then run `bjrs techniques socks-connect 127.0.0.1:1080 127.0.0.1:3490` to receive its message through the proxy.
+    /// Stop the stream server and run the command again to observe the error reply of the proxy.
+    SocksConnect {
+        /// The address of the SOCKS5 proxy as HOST:PORT.
+        proxy: String,
+
+        /// The address to connect to through the proxy as HOST:PORT, the host may be a domain name.
+        target: String,
+    },
+
+    /// TCP_INFO - Prints what the kernel knows about a TCP connection (Linux only)
+    ///
+    /// To test this example:
+    ///
+    /// Run `bjrs stream server` in a separate terminal session, then run `bjrs techniques tcp-info 127.0.0.1`.
+    /// Run it against a remote host to see a larger RTT.
+    #[cfg(target_os = "linux")]
+    TcpInfo {
+        /// The host to connect to.
+        host: String,
+
+        /// The port to connect to.
+        #[arg(long, default_value_t = 3490)]
+        port: u16,
+    },
+
+    /// UDP error queue - Reads the ICMP error caused by a datagram with `IP_RECVERR` on Linux
+    ///
+    /// To test this example:
+    ///
+    /// Run this command against a port that nothing listens on, e.g. `bjrs techniques udp-errqueue 127.0.0.1 --port 9`.
+    /// Observe the "port unreachable" error (ICMP type 3, code 3) and the host that reported it.
+    /// Run `bjrs dgram server` and point this command to port 4950 to see that no error is queued.
+    #[cfg(target_os = "linux")]
+    UdpErrqueue {
+        /// The host to send the datagram to.
+        host: String,
+
+        /// The port to send the datagram to.
+        #[arg(long, default_value_t = 9)]
+        port: u16,
+    },
+
+    /// TCP Fast Open - Sends data in the SYN with `MSG_FASTOPEN` on Linux
+    ///
+    /// To test this example:
+    ///
+    /// Enable TCP Fast Open on both sides with `sysctl -w net.ipv4.tcp_fastopen=3`, then run this command.
+    /// Observe that the first connection only gets a cookie, and the rest send their data in the SYN.
+    /// Run it with the sysctl set to 1 (the default) to observe every connection falling back to a regular handshake.
+    #[cfg(target_os = "linux")]
+    Fastopen {
+        /// The number of connections to make, the first one only gets the cookie.
+        #[arg(long, default_value_t = 3)]
+        connections: u32,
+    },
+
+    /// Omniserver - An echo server on TCP over IPv4 and IPv6, and on a Unix domain socket, from one `poll()` loop
+    ///
+    /// To test this example:
+    ///
+    /// Run this command with `--unix /tmp/bjrs.sock` to start the server.
+    /// Connect to it in separate terminal sessions with `ncat 127.0.0.1 3490`, `ncat ::1 3490` and `ncat -U /tmp/bjrs.sock`.
+    /// Observe that every client is labeled by its transport, and that all of them get their lines echoed back.
+    Omniserver {
+        /// The TCP port to listen on, over both IPv4 and IPv6.
+        #[arg(long, default_value_t = 3490)]
+        port: u16,
+
+        /// Also listen on a Unix domain socket at this path, or at `@NAME` in the abstract namespace on Linux.
+        #[arg(long, value_name = "PATH")]
+        unix: Option<std::path::PathBuf>,
+    },
+
+    /// TIME_WAIT - Rebinding the address of an actively closed connection without and with `SO_REUSEADDR`
+    ///
+    /// To test this example:
+    ///
+    /// Run this command, and observe that binding the address again fails with `EADDRINUSE` without `SO_REUSEADDR`,
+    /// and succeeds with it. Run `ss -tan state time-wait` right after to observe the connection in TIME_WAIT.
+    TimeWait,
+
+    /// Starvation - High fds never served by a naive scan, and served in turn by a round-robin one
+    ///
+    /// To test this example:
+    ///
+    /// Run this command, and observe that the naive scan serves the same `--max-events` fds on every iteration
+    /// while the rest starve, and that the round-robin scan serves every fd about as often.
+    Starvation {
+        /// The number of always-ready fds.
+        #[arg(long, default_value_t = 8)]
+        clients: usize,
+
+        /// The number of fds served per iteration.
+        #[arg(long, default_value_t = NonZeroUsize::new(2).unwrap())]
+        max_events: NonZeroUsize,
+
+        /// The number of `poll()` iterations to run for each scan.
+        #[arg(long, default_value_t = 1000)]
+        iterations: usize,
+    },
+
+    /// UDP integrity probe receiver - Loss, reordering and corruption
+    ///
+    /// To test this example:
+    ///
+    /// Run this command, and in a separate terminal session, run `bjrs techniques udp-probe 127.0.0.1 --count 1000`.
+    /// Observe the summary of the run, a loopback run should have no loss and no corruption.
+    /// Raise `--count` until the receive buffer overflows to observe the loss.
+    UdpProbeReceiver {
+        /// The port to receive the probes on.
+        #[arg(long, default_value_t = 4954)]
+        port: u16,
+    },
+
+    /// UDP integrity probe - Sends datagrams with a sequence number and a CRC32
+    ///
+    /// To test this example, check out `bjrs help techniques udp-probe-receiver`.
+    UdpProbe {
+        /// The host address of the probe receiver.
+        host: String,
+
+        /// The port of the probe receiver.
+        #[arg(long, default_value_t = 4954)]
+        port: u16,
+
+        /// The number of probes to send.
+        #[arg(long, default_value_t = 100)]
+        count: u32,
+    },
+
+    /// Reliable UDP receiver - Acknowledging every datagram
+    ///
+    /// To test this example:
+    ///
+    /// Run this command with `--drop-acks 2`, and in a separate terminal session, run `bjrs techniques reliable-sender 127.0.0.1 hello world`.
+    /// Observe the sender retransmitting the first message until its ACK is not dropped anymore.
+    ReliableReceiver {
+        /// The port to receive the messages on.
+        #[arg(long, default_value_t = 4953)]
+        port: u16,
+
+        /// The number of ACKs to drop before acknowledging the messages.
+        #[arg(long, default_value_t = 0)]
+        drop_acks: u32,
+    },
+
+    /// Reliable UDP sender - Retransmitting datagrams until they are acknowledged
+    ///
+    /// To test this example:
+    ///
+    /// Run `bjrs techniques reliable-receiver` in a separate terminal session, then run this command with a few messages.
+    /// Run it again without the receiver to observe it retransmitting each message `--max-retries` times before giving up.
+    ReliableSender {
+        /// The host address of the receiver.
+        host: String,
+
+        /// The messages to send, one datagram each.
+        #[arg(required = true)]
+        msgs: Vec<String>,
+
+        /// The port of the receiver.
+        #[arg(long, default_value_t = 4953)]
+        port: u16,
+
+        /// The time to wait for an ACK in milliseconds.
+        #[arg(long, default_value_t = 500)]
+        timeout: u64,
+
+        /// The number of retransmissions of a message before giving up.
+        #[arg(long, default_value_t = 3)]
+        max_retries: u32,
+
+        #[command(flatten)]
+        payload: PayloadArgs,
     },
 }
@@ -0,0 +1,85 @@
+/// How the bytes of a generated payload are chosen.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PayloadKind {
+    /// The lowercase alphabet repeated over and over, easy to recognize in a dump.
+    Pattern,
+    /// Pseudo-random bytes, the same `seed` always generates the same payload.
+    Random { seed: u64 },
+}
+
+/// A small xorshift64* pseudo-random number generator.
+///
+/// It is nowhere near cryptographically secure, but it is fast, seedable and good enough
+/// to produce payloads that do not compress or repeat.
+#[derive(Debug, Clone)]
+pub struct Prng(u64);
+
+impl Prng {
+    pub fn new(seed: u64) -> Self {
+        // The all zero state never changes, it is replaced with an arbitrary odd constant.
+        match seed {
+            0 => Self(0x9E37_79B9_7F4A_7C15),
+            seed => Self(seed),
+        }
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        self.0.wrapping_mul(0x2545_F491_4F6C_DD1D)
+    }
+
+    pub fn fill(&mut self, buf: &mut [u8]) {
+        for chunk in buf.chunks_mut(8) {
+            let bytes = self.next_u64().to_le_bytes();
+            chunk.copy_from_slice(&bytes[..chunk.len()]);
+        }
+    }
+}
+
+/// Fills `buf` with the bytes of a `kind` payload.
+pub fn fill(buf: &mut [u8], kind: PayloadKind) {
+    match kind {
+        PayloadKind::Pattern => {
+            for (b, p) in buf.iter_mut().zip((b'a'..=b'z').cycle()) {
+                *b = p;
+            }
+        }
+        PayloadKind::Random { seed } => Prng::new(seed).fill(buf),
+    }
+}
+
+/// Returns a new `size` byte payload of `kind`.
+pub fn generate(size: usize, kind: PayloadKind) -> Vec<u8> {
+    let mut buf = vec![0; size];
+    fill(&mut buf, kind);
+    buf
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pattern_repeats_the_alphabet() {
+        let payload = generate(30, PayloadKind::Pattern);
+        assert_eq!(payload, b"abcdefghijklmnopqrstuvwxyzabcd");
+    }
+
+    #[test]
+    fn the_same_seed_generates_the_same_payload() {
+        let payload = generate(1000, PayloadKind::Random { seed: 7 });
+
+        assert_eq!(payload, generate(1000, PayloadKind::Random { seed: 7 }));
+        assert_ne!(payload, generate(1000, PayloadKind::Random { seed: 8 }));
+        // A shorter payload is a prefix of a longer one with the same seed.
+        assert_eq!(generate(13, PayloadKind::Random { seed: 7 }), payload[..13]);
+    }
+
+    #[test]
+    fn seed_zero_does_not_get_stuck() {
+        let payload = generate(64, PayloadKind::Random { seed: 0 });
+        assert!(payload.iter().any(|b| *b != 0));
+    }
+}
@@ -0,0 +1,105 @@
+use std::{error, fmt, io};
+
+use crate::trace;
+
+/// The exit code of the process when an example lacks the privileges it needs (`EX_NOPERM` in `sysexits.h`).
+pub const EXIT_NOPERM: u8 = 77;
+
+#[derive(Debug)]
+pub enum Error {
+    /// The operation failed with `EPERM` or `EACCES`.
+    PermissionDenied {
+        what: &'static str,
+        capability: &'static str,
+        err: io::Error,
+    },
+    Other(io::Error),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::PermissionDenied {
+                what,
+                capability,
+                err,
+            } => write!(
+                f,
+                "{} requires privileges ({}): run as root, or grant the capability with `sudo setcap {}+ep <path to bjrs>`",
+                what, err, capability
+            ),
+            Error::Other(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+impl error::Error for Error {}
+
+/// Reports whether `err` means that the process is not allowed to do something, rather than it going wrong.
+pub fn is_permission_error(err: &io::Error) -> bool {
+    matches!(err.raw_os_error(), Some(libc::EPERM) | Some(libc::EACCES))
+}
+
+/// Turns the `err` of a privileged operation into an `Error`.
+///
+/// `what` names the operation and `capability` is the Linux capability that allows it (e.g. `cap_net_raw`),
+/// both are used to print an actionable message instead of a bare errno.
+pub fn classify(what: &'static str, capability: &'static str, err: io::Error) -> Error {
+    if is_permission_error(&err) {
+        Error::PermissionDenied {
+            what,
+            capability,
+            err,
+        }
+    } else {
+        Error::Other(err)
+    }
+}
+
+/// Creates a `SOCK_RAW` socket, which requires `CAP_NET_RAW` (or root) on Linux.
+pub fn raw_socket(domain: i32, protocol: i32) -> Result<i32, Error> {
+    // SAFETY: `socket()` does not touch any memory owned by the process.
+    let sock_fd = unsafe { trace::socket(domain, libc::SOCK_RAW, protocol) };
+    match sock_fd {
+        -1 => Err(classify(
+            "a raw socket",
+            "cap_net_raw",
+            io::Error::last_os_error(),
+        )),
+        _ => Ok(sock_fd),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn eperm_and_eacces_are_permission_errors() {
+        for errno in [libc::EPERM, libc::EACCES] {
+            let err = classify(
+                "a raw socket",
+                "cap_net_raw",
+                io::Error::from_raw_os_error(errno),
+            );
+
+            let msg = err.to_string();
+            assert!(matches!(err, Error::PermissionDenied { .. }), "{}", msg);
+            assert!(msg.contains("setcap cap_net_raw+ep"), "{}", msg);
+        }
+    }
+
+    #[test]
+    fn other_errors_are_kept_as_is() {
+        let err = classify(
+            "a raw socket",
+            "cap_net_raw",
+            io::Error::from_raw_os_error(libc::EPROTONOSUPPORT),
+        );
+
+        match err {
+            Error::Other(err) => assert_eq!(err.raw_os_error(), Some(libc::EPROTONOSUPPORT)),
+            err => panic!("unexpected error: {}", err),
+        }
+    }
+}
@@ -0,0 +1,224 @@
+use std::vec;
+
+use crate::fairness::RoundRobin;
+
+/// What a socket is watched for when it is registered with a server loop.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Interest {
+    /// Something to read, a new connection for a listener.
+    Read,
+    /// Room in the send buffer, e.g. to resume a send that would block.
+    Write,
+    /// Either of them.
+    Both,
+}
+
+impl Interest {
+    /// The `events` of a `pollfd` that watches for this interest.
+    ///
+    /// `POLLHUP` and `POLLERR` are not part of it, `poll()` always reports them.
+    pub fn poll_events(self) -> libc::c_short {
+        match self {
+            Interest::Read => libc::POLLIN,
+            Interest::Write => libc::POLLOUT,
+            Interest::Both => libc::POLLIN | libc::POLLOUT,
+        }
+    }
+}
+
+/// How a socket was reported ready.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReadyKind {
+    /// There is something to read, a new connection for a listener.
+    Readable,
+    /// There is room in the send buffer, only reported for the sockets that are watched for writing.
+    Writable,
+    /// The peer hung up, there may still be data left to read before EOF.
+    Hangup,
+    /// There is a pending error on the socket, e.g. a reset connection.
+    Error,
+}
+
+impl ReadyKind {
+    /// Reads the kind from the `revents` of a `pollfd`, `None` if the fd is not ready.
+    ///
+    /// `POLLHUP` and `POLLERR` are reported even though they are not asked for, and often along with `POLLIN`.
+    /// They still count as ready: the next `recv()` is what reports the EOF or the error.
+    /// A socket that is both readable and writable is reported as readable.
+    pub fn from_revents(revents: libc::c_short) -> Option<ReadyKind> {
+        if revents & libc::POLLERR != 0 {
+            Some(ReadyKind::Error)
+        } else if revents & libc::POLLHUP != 0 {
+            Some(ReadyKind::Hangup)
+        } else if revents & libc::POLLIN != 0 {
+            Some(ReadyKind::Readable)
+        } else if revents & libc::POLLOUT != 0 {
+            Some(ReadyKind::Writable)
+        } else {
+            None
+        }
+    }
+}
+
+/// A single ready socket, as yielded by `ReadyEvents`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ReadyEvent {
+    pub fd: i32,
+    pub is_listener: bool,
+    pub kind: ReadyKind,
+}
+
+/// The sockets reported ready by a single wait of a server loop, in the order they should be served.
+#[derive(Debug)]
+pub struct ReadyEvents {
+    listener_fds: Vec<i32>,
+    events: vec::IntoIter<(i32, ReadyKind)>,
+}
+
+impl ReadyEvents {
+    /// Orders the `ready` sockets with `round_robin`, the ones it leaves out for this iteration are dropped.
+    pub fn new(
+        listener_fds: &[i32],
+        ready: Vec<(i32, ReadyKind)>,
+        round_robin: &mut RoundRobin,
+    ) -> Self {
+        let events: Vec<(i32, ReadyKind)> = round_robin
+            .pick(ready.iter().map(|(fd, _)| *fd))
+            .into_iter()
+            .filter_map(|fd| ready.iter().find(|(ready_fd, _)| *ready_fd == fd).copied())
+            .collect();
+
+        Self {
+            listener_fds: listener_fds.to_vec(),
+            events: events.into_iter(),
+        }
+    }
+}
+
+impl Iterator for ReadyEvents {
+    type Item = ReadyEvent;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.events.next().map(|(fd, kind)| ReadyEvent {
+            fd,
+            is_listener: self.listener_fds.contains(&fd),
+            kind,
+        })
+    }
+}
+
+/// The fd set that a server loop waits on, e.g. the `pollfd` list of `poll()` or the `fd_set` of `select()`.
+pub trait Reactor {
+    /// The listening sockets, which are watched along with the clients.
+    /// A server usually has a single one, a server that listens on several transports has one for each.
+    fn listener_fds(&self) -> &[i32];
+
+    /// The sockets that the last wait reported ready, along with how they are ready.
+    fn ready(&self) -> Vec<(i32, ReadyKind)>;
+
+    /// The sockets to serve after the last wait, see `ReadyEvents`.
+    fn events(&self, round_robin: &mut RoundRobin) -> ReadyEvents {
+        ReadyEvents::new(self.listener_fds(), self.ready(), round_robin)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::num::NonZeroUsize;
+
+    use super::*;
+
+    // A reactor whose last wait reported a fixed set of sockets.
+    struct Crafted {
+        listener_fds: Vec<i32>,
+        ready: Vec<(i32, ReadyKind)>,
+    }
+
+    impl Reactor for Crafted {
+        fn listener_fds(&self) -> &[i32] {
+            &self.listener_fds
+        }
+
+        fn ready(&self) -> Vec<(i32, ReadyKind)> {
+            self.ready.clone()
+        }
+    }
+
+    fn event(fd: i32, is_listener: bool, kind: ReadyKind) -> ReadyEvent {
+        ReadyEvent {
+            fd,
+            is_listener,
+            kind,
+        }
+    }
+
+    #[test]
+    fn events_are_yielded_in_fd_order_with_their_kinds() {
+        let reactor = Crafted {
+            listener_fds: vec![3, 4],
+            ready: vec![
+                (9, ReadyKind::Hangup),
+                (4, ReadyKind::Readable),
+                (6, ReadyKind::Error),
+                (5, ReadyKind::Readable),
+            ],
+        };
+
+        let events: Vec<ReadyEvent> = reactor.events(&mut RoundRobin::new(None)).collect();
+
+        assert_eq!(
+            events,
+            [
+                event(4, true, ReadyKind::Readable),
+                event(5, false, ReadyKind::Readable),
+                event(6, false, ReadyKind::Error),
+                event(9, false, ReadyKind::Hangup),
+            ]
+        );
+    }
+
+    #[test]
+    fn events_left_out_by_the_round_robin_are_dropped() {
+        let reactor = Crafted {
+            listener_fds: vec![3],
+            ready: vec![
+                (3, ReadyKind::Readable),
+                (5, ReadyKind::Readable),
+                (7, ReadyKind::Writable),
+            ],
+        };
+        let mut round_robin = RoundRobin::new(NonZeroUsize::new(2));
+
+        let fds = |events: ReadyEvents| events.map(|event| event.fd).collect::<Vec<_>>();
+        assert_eq!(fds(reactor.events(&mut round_robin)), [3, 5]);
+        // The next wait picks up where this one stopped.
+        assert_eq!(fds(reactor.events(&mut round_robin)), [7, 3]);
+    }
+
+    #[test]
+    fn nothing_ready_yields_nothing() {
+        let reactor = Crafted {
+            listener_fds: vec![3],
+            ready: vec![],
+        };
+
+        assert_eq!(reactor.events(&mut RoundRobin::new(None)).count(), 0);
+    }
+
+    #[test]
+    fn a_hangup_alone_is_ready() {
+        assert_eq!(
+            ReadyKind::from_revents(libc::POLLHUP),
+            Some(ReadyKind::Hangup)
+        );
+        assert_eq!(
+            ReadyKind::from_revents(libc::POLLIN | libc::POLLHUP),
+            Some(ReadyKind::Hangup)
+        );
+        assert_eq!(
+            ReadyKind::from_revents(libc::POLLIN),
+            Some(ReadyKind::Readable)
+        );
+        assert_eq!(ReadyKind::from_revents(0), None);
+    }
+}
@@ -0,0 +1,77 @@
+use std::{io, time::Duration};
+
+/// Waits until `fd` is readable (a new connection for a listener) for at most `timeout`, or indefinitely if `None`.
+///
+/// Returns `Ok(true)` once the fd is ready, and `Ok(false)` if the timeout expires first.
+/// A hangup or a pending error also counts as ready, since the next call on the fd does not block either.
+/// An interrupted wait fails with `io::ErrorKind::Interrupted`, it is up to the caller to retry it.
+pub fn wait_readable(fd: i32, timeout: Option<Duration>) -> io::Result<bool> {
+    wait(fd, libc::POLLIN, timeout)
+}
+
+/// Same as `wait_readable()`, but waits until `fd` is writable, e.g. a non-blocking `connect()` is done.
+pub fn wait_writable(fd: i32, timeout: Option<Duration>) -> io::Result<bool> {
+    wait(fd, libc::POLLOUT, timeout)
+}
+
+fn wait(fd: i32, events: libc::c_short, timeout: Option<Duration>) -> io::Result<bool> {
+    let mut pfd = libc::pollfd {
+        fd,
+        events,
+        revents: 0,
+    };
+    // Round up, otherwise `poll()` returns a little before the timeout.
+    let timeout_ms = timeout.map_or(-1, |timeout| {
+        timeout.as_micros().div_ceil(1000).min(i32::MAX as u128) as i32
+    });
+
+    // SAFETY: `pfd` is an initialized `pollfd`, and it is the only element that is passed.
+    let nfds = unsafe { libc::poll(&mut pfd, 1, timeout_ms) };
+    match nfds {
+        -1 => Err(io::Error::last_os_error()),
+        0 => Ok(false),
+        _ => Ok(true),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Instant;
+
+    use super::*;
+    use crate::{stream_io, testutil};
+
+    const TIMEOUT: Duration = Duration::from_millis(100);
+
+    #[test]
+    fn readable_once_the_peer_sends() {
+        let (client, server) = testutil::loopback_pair();
+        stream_io::sendall(client.as_raw(), b"hello").unwrap();
+
+        assert!(wait_readable(server.as_raw(), Some(TIMEOUT)).unwrap());
+    }
+
+    #[test]
+    fn readable_times_out_when_nothing_is_sent() {
+        let (_client, server) = testutil::loopback_pair();
+        let started_at = Instant::now();
+
+        assert!(!wait_readable(server.as_raw(), Some(TIMEOUT)).unwrap());
+        assert!(started_at.elapsed() >= TIMEOUT);
+    }
+
+    #[test]
+    fn readable_once_the_peer_hangs_up() {
+        let (client, server) = testutil::loopback_pair();
+        drop(client);
+
+        assert!(wait_readable(server.as_raw(), Some(TIMEOUT)).unwrap());
+    }
+
+    #[test]
+    fn writable_with_room_in_the_send_buffer() {
+        let (client, _server) = testutil::loopback_pair();
+
+        assert!(wait_writable(client.as_raw(), Some(TIMEOUT)).unwrap());
+    }
+}
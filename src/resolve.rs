@@ -0,0 +1,71 @@
+use std::{error, ffi::CString, fmt, net::SocketAddr};
+
+use crate::{
+    addrinfo::{AddrInfoList, Hints},
+    sockaddr,
+};
+
+#[derive(Debug)]
+pub enum Error {
+    InvalidHost(String),
+    InvalidService(String),
+    Getaddrinfo(String),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::InvalidHost(host) => write!(f, "invalid host {:?}", host),
+            Error::InvalidService(service) => write!(f, "invalid service {:?}", service),
+            Error::Getaddrinfo(err) => write!(f, "getaddrinfo error: {}", err),
+        }
+    }
+}
+
+impl error::Error for Error {}
+
+/// Resolves `host` and `service` into the addresses of every family, in the order `getaddrinfo()` returns them.
+///
+/// `host` can be a hostname or an IP address, and `service` can be a port number or a service name
+/// from `/etc/services` (e.g. `http` for port 80). Without a `service`, the ports are 0.
+///
+/// Only the `SOCK_STREAM` entries are asked for, otherwise every address would be returned once per socket type.
+pub fn resolve(host: &str, service: Option<&str>) -> Result<Vec<SocketAddr>, Error> {
+    let hints = Hints::new()
+        .family(libc::AF_UNSPEC)
+        .socktype(libc::SOCK_STREAM)
+        .build();
+
+    resolve_with(host, service, &hints)
+}
+
+/// Same as `resolve()`, but `getaddrinfo()` is called with `hints` (see `Hints`).
+///
+/// The entries of any family other than `AF_INET` and `AF_INET6` are skipped.
+pub fn resolve_with(
+    host: &str,
+    service: Option<&str>,
+    hints: &libc::addrinfo,
+) -> Result<Vec<SocketAddr>, Error> {
+    let node = CString::new(host).map_err(|_| Error::InvalidHost(host.to_string()))?;
+    let service = service
+        .map(|service| {
+            CString::new(service).map_err(|_| Error::InvalidService(service.to_string()))
+        })
+        .transpose()?;
+
+    // The list is freed by `freeaddrinfo()` when `ai_list` goes out of scope.
+    let ai_list =
+        AddrInfoList::new(Some(&node), service.as_deref(), hints).map_err(Error::Getaddrinfo)?;
+
+    let addrs = ai_list
+        .iter()
+        .filter_map(|ai| {
+            // SAFETY: `ai.ai_addr` is filled by `getaddrinfo()` and it is valid for `ai.ai_addrlen` bytes.
+            let sa = unsafe { sockaddr::copy_to_storage(ai.ai_addr, ai.ai_addrlen) };
+            sockaddr::to_socket_addr(&sa)
+        })
+        .collect();
+
+    Ok(addrs)
+}
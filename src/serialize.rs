@@ -0,0 +1,159 @@
+/// The reflected CRC-32 polynomial used by Ethernet, zlib, PNG etc. (a.k.a. CRC-32/ISO-HDLC).
+const CRC32_POLY: u32 = 0xEDB8_8320;
+
+/// Lookup table for `crc32()`, one entry for each possible byte value.
+/// It is computed at compile time, so that the checksum only needs a single table lookup per byte.
+const CRC32_TABLE: [u32; 256] = {
+    let mut table = [0u32; 256];
+
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = i as u32;
+
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 == 1 {
+                (crc >> 1) ^ CRC32_POLY
+            } else {
+                crc >> 1
+            };
+            bit += 1;
+        }
+
+        table[i] = crc;
+        i += 1;
+    }
+
+    table
+};
+
+/// Computes the CRC32 checksum of `buf`.
+///
+/// The result matches the common CRC32 implementations, e.g. `crc32(b"123456789") == 0xCBF4_3926`.
+pub fn crc32(buf: &[u8]) -> u32 {
+    let crc = buf.iter().fold(!0u32, |crc, b| {
+        CRC32_TABLE[((crc ^ *b as u32) & 0xFF) as usize] ^ (crc >> 8)
+    });
+
+    !crc
+}
+
+/// Stores `i` into the first 8 bytes of `buf` in network byte order (big-endian).
+///
+/// # Panics
+///
+/// Panics if `buf` is shorter than 8 bytes.
+pub fn packi64(buf: &mut [u8], i: u64) {
+    buf[..8].copy_from_slice(&i.to_be_bytes());
+}
+
+/// Reads an unsigned 64 bit integer stored by `packi64()` from the first 8 bytes of `buf`.
+///
+/// # Panics
+///
+/// Panics if `buf` is shorter than 8 bytes.
+pub fn unpacku64(buf: &[u8]) -> u64 {
+    u64::from_be_bytes(buf[..8].try_into().unwrap())
+}
+
+/// Reads a signed 64 bit integer stored by `packi64()` from the first 8 bytes of `buf`.
+///
+/// # Panics
+///
+/// Panics if `buf` is shorter than 8 bytes.
+pub fn unpacki64(buf: &[u8]) -> i64 {
+    unpacku64(buf) as i64
+}
+
+/// Stores `i` into the first 2 bytes of `buf` in network byte order (big-endian).
+///
+/// # Panics
+///
+/// Panics if `buf` is shorter than 2 bytes.
+pub fn packi16(buf: &mut [u8], i: u16) {
+    buf[..2].copy_from_slice(&i.to_be_bytes());
+}
+
+/// Reads an unsigned 16 bit integer stored by `packi16()` from the first 2 bytes of `buf`.
+///
+/// # Panics
+///
+/// Panics if `buf` is shorter than 2 bytes.
+pub fn unpacku16(buf: &[u8]) -> u16 {
+    u16::from_be_bytes(buf[..2].try_into().unwrap())
+}
+
+/// Reads a signed 16 bit integer stored by `packi16()` from the first 2 bytes of `buf`.
+///
+/// # Panics
+///
+/// Panics if `buf` is shorter than 2 bytes.
+pub fn unpacki16(buf: &[u8]) -> i16 {
+    unpacku16(buf) as i16
+}
+
+/// Stores `i` into the first 4 bytes of `buf` in network byte order (big-endian).
+///
+/// # Panics
+///
+/// Panics if `buf` is shorter than 4 bytes.
+pub fn packi32(buf: &mut [u8], i: u32) {
+    buf[..4].copy_from_slice(&i.to_be_bytes());
+}
+
+/// Reads an unsigned 32 bit integer stored by `packi32()` from the first 4 bytes of `buf`.
+///
+/// # Panics
+///
+/// Panics if `buf` is shorter than 4 bytes.
+pub fn unpacku32(buf: &[u8]) -> u32 {
+    u32::from_be_bytes(buf[..4].try_into().unwrap())
+}
+
+/// Reads a signed 32 bit integer stored by `packi32()` from the first 4 bytes of `buf`.
+///
+/// # Panics
+///
+/// Panics if `buf` is shorter than 4 bytes.
+pub fn unpacki32(buf: &[u8]) -> i32 {
+    unpacku32(buf) as i32
+}
+
+/// Stores `f` into the first 4 bytes of `buf` as a big-endian IEEE 754 single.
+///
+/// Unlike the `pack754()` of the guide, there is no need to build the bits by hand:
+/// Rust floats are IEEE 754 on every platform, `to_bits()` only has to be put in network byte order.
+///
+/// # Panics
+///
+/// Panics if `buf` is shorter than 4 bytes.
+pub fn pack754_32(buf: &mut [u8], f: f32) {
+    packi32(buf, f.to_bits());
+}
+
+/// Reads a float stored by `pack754_32()` from the first 4 bytes of `buf`.
+///
+/// # Panics
+///
+/// Panics if `buf` is shorter than 4 bytes.
+pub fn unpack754_32(buf: &[u8]) -> f32 {
+    f32::from_bits(unpacku32(buf))
+}
+
+/// Stores `f` into the first 8 bytes of `buf` as a big-endian IEEE 754 double.
+///
+/// # Panics
+///
+/// Panics if `buf` is shorter than 8 bytes.
+pub fn pack754_64(buf: &mut [u8], f: f64) {
+    packi64(buf, f.to_bits());
+}
+
+/// Reads a float stored by `pack754_64()` from the first 8 bytes of `buf`.
+///
+/// # Panics
+///
+/// Panics if `buf` is shorter than 8 bytes.
+pub fn unpack754_64(buf: &[u8]) -> f64 {
+    f64::from_bits(unpacku64(buf))
+}
@@ -0,0 +1,363 @@
+use std::{error, ffi::CString, fmt, io, mem, net::SocketAddr, time::Duration};
+
+use crate::{
+    emfile::EmfileGuard,
+    listener::{self, BindRetry, ListenerOptions},
+    signal, sockaddr,
+    socket_fd::Socket,
+    trace,
+};
+
+#[derive(Debug)]
+pub enum Error {
+    Listener(listener::Error),
+    InvalidHost(String),
+    Accept(io::Error),
+    InvalidAddrFamily(i32),
+    Guard(io::Error),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Listener(err) => write!(f, "listener error: {}", err),
+            Error::InvalidHost(host) => write!(f, "invalid host {}", host),
+            Error::Accept(err) => write!(f, "accept error: {}", err),
+            Error::InvalidAddrFamily(af) => {
+                write!(f, "accept error: invalid address family {}", af)
+            }
+            Error::Guard(err) => write!(f, "failed to reserve the EMFILE guard fd: {}", err),
+        }
+    }
+}
+
+impl error::Error for Error {}
+
+impl From<listener::Error> for Error {
+    fn from(value: listener::Error) -> Self {
+        Self::Listener(value)
+    }
+}
+
+/// Builds a `SOCK_STREAM` server out of the shared listener code.
+///
+/// By default, the server listens on an ephemeral port on all local addresses of any family,
+/// with `SO_REUSEADDR` set.
+#[derive(Debug, Clone)]
+pub struct Server {
+    host: Option<String>,
+    port: u16,
+    family: i32,
+    opts: ListenerOptions,
+    listen_fd: Option<i32>,
+    emfile_guard: bool,
+    drain_on_exit: Option<Duration>,
+}
+
+impl Default for Server {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Server {
+    pub fn new() -> Self {
+        Self {
+            host: None,
+            port: 0,
+            family: libc::AF_UNSPEC,
+            opts: ListenerOptions::default(),
+            listen_fd: None,
+            emfile_guard: false,
+            drain_on_exit: None,
+        }
+    }
+
+    /// Listens on `host` and `port`, or on all local addresses if `host` is `None`.
+    ///
+    /// Port 0 lets the kernel pick a free port, see `Listener::local_addr()`.
+    pub fn bind(mut self, host: Option<&str>, port: u16) -> Self {
+        self.host = host.map(String::from);
+        self.port = port;
+        self
+    }
+
+    /// Restricts the listener to `family` (`AF_INET`, `AF_INET6` or `AF_UNSPEC`).
+    pub fn family(mut self, family: i32) -> Self {
+        self.family = family;
+        self
+    }
+
+    pub fn reuseaddr(mut self, reuseaddr: bool) -> Self {
+        self.opts.reuseaddr = reuseaddr;
+        self
+    }
+
+    pub fn backlog(mut self, backlog: i32) -> Self {
+        self.opts.backlog = backlog;
+        self
+    }
+
+    pub fn retry(mut self, retry: BindRetry) -> Self {
+        self.opts.retry = retry;
+        self
+    }
+
+    /// Sets `TCP_MAXSEG` on the listener, so that the accepted connections advertise `max_segment`
+    /// as their MSS in the handshake, see `stream::server()`.
+    pub fn max_segment(mut self, max_segment: Option<i32>) -> Self {
+        self.opts.max_segment = max_segment;
+        self
+    }
+
+    /// Sets `TCP_DEFER_ACCEPT` on the listener (Linux only), so that a connection is not accepted
+    /// until its peer sends something, see `ListenerOptions::defer_accept`.
+    pub fn defer_accept(mut self, secs: Option<i32>) -> Self {
+        self.opts.defer_accept = secs;
+        self
+    }
+
+    /// Accepts on an inherited listener socket instead of creating one, see `listener::inherit_listener()`.
+    ///
+    /// The other settings are ignored when it is set.
+    pub fn listen_fd(mut self, listen_fd: Option<i32>) -> Self {
+        self.listen_fd = listen_fd;
+        self
+    }
+
+    /// Keeps a spare fd, so that `Listener::serve()` turns away the connections that arrive
+    /// once the process runs out of fds instead of failing, see `EmfileGuard`.
+    pub fn emfile_guard(mut self, emfile_guard: bool) -> Self {
+        self.emfile_guard = emfile_guard;
+        self
+    }
+
+    /// Makes `Listener::serve()` spend at most `budget` turning away the connections that are queued
+    /// but not accepted yet once a shutdown is requested, see `listener::drain_pending()`.
+    pub fn drain_on_exit(mut self, budget: Option<Duration>) -> Self {
+        self.drain_on_exit = budget;
+        self
+    }
+
+    /// Creates the listener socket.
+    pub fn listen(self) -> Result<Listener, Error> {
+        let (sock_fd, addr) = match self.listen_fd {
+            Some(fd) => listener::inherit_listener(fd)?,
+            None => {
+                let node = self
+                    .host
+                    .as_deref()
+                    .map(CString::new)
+                    .transpose()
+                    .map_err(|_| Error::InvalidHost(self.host.clone().unwrap_or_default()))?;
+                let port = CString::new(self.port.to_string()).unwrap();
+
+                listener::bind_listener_with(node.as_deref(), self.family, &port, &self.opts)?
+            }
+        };
+
+        let sock = Socket::from_raw(sock_fd);
+        let guard = self
+            .emfile_guard
+            .then(EmfileGuard::new)
+            .transpose()
+            .map_err(Error::Guard)?;
+
+        Ok(Listener {
+            sock,
+            addr,
+            guard,
+            drain_on_exit: self.drain_on_exit,
+        })
+    }
+
+    /// Creates the listener socket and passes every accepted connection to `handler`, see `Listener::serve()`.
+    pub fn serve(self, handler: impl FnMut(Socket, SocketAddr)) -> Result<(), Error> {
+        self.listen()?.serve(handler)
+    }
+}
+
+/// A listening socket created by `Server::listen()`.
+#[derive(Debug)]
+pub struct Listener {
+    sock: Socket,
+    addr: SocketAddr,
+    guard: Option<EmfileGuard>,
+    drain_on_exit: Option<Duration>,
+}
+
+impl Listener {
+    /// Returns the address the listener is bound to, including the port the kernel picked.
+    pub fn local_addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    /// Returns the fd of the listener socket, e.g. to set an option that the accepted sockets inherit.
+    pub fn as_raw(&self) -> i32 {
+        self.sock.as_raw()
+    }
+
+    /// Accepts a single connection.
+    ///
+    /// Returns the connected socket and the address of the peer.
+    pub fn accept(&self) -> Result<(Socket, SocketAddr), Error> {
+        // SAFETY: All zero `sockaddr_storage` is a valid initialization.
+        // Read will happen after it is written by `accept()`.
+        let mut sa: libc::sockaddr_storage = unsafe { mem::zeroed() };
+        let mut sa_len = mem::size_of_val(&sa) as libc::socklen_t;
+
+        // SAFETY: `sa` is big enough for any address family, and `sa_len` holds its size.
+        let conn_sock_fd = unsafe {
+            trace::accept(
+                self.sock.as_raw(),
+                &raw mut sa as *mut libc::sockaddr,
+                &raw mut sa_len,
+            )
+        };
+        if conn_sock_fd == -1 {
+            return Err(Error::Accept(io::Error::last_os_error()));
+        }
+        let conn_sock = Socket::from_raw(conn_sock_fd);
+
+        let addr =
+            sockaddr::to_socket_addr(&sa).ok_or(Error::InvalidAddrFamily(sa.ss_family as i32))?;
+
+        Ok((conn_sock, addr))
+    }
+
+    /// Accepts connections forever, and passes each of them to `handler` along with the address of the peer.
+    ///
+    /// The connection is closed when `handler` drops its `Socket`.
+    /// Returns if `accept()` fails, an `accept()` that is interrupted by a signal is retried.
+    /// With `Server::emfile_guard()`, running out of fds is not a failure either.
+    ///
+    /// Also returns `Ok(())` once a shutdown is requested (see `signal::install_shutdown_handler()`),
+    /// after draining the accept queue if `Server::drain_on_exit()` is set.
+    pub fn serve(mut self, mut handler: impl FnMut(Socket, SocketAddr)) -> Result<(), Error> {
+        let mut guard = self.guard.take();
+
+        loop {
+            // An interrupted `accept()` comes back here, so a shutdown request is noticed right away.
+            if signal::shutdown_requested() {
+                println!("server: shutting down...");
+                self.drain();
+                return Ok(());
+            }
+
+            match self.accept() {
+                Ok((conn_sock, addr)) => handler(conn_sock, addr),
+                Err(Error::Accept(err)) if err.kind() == io::ErrorKind::Interrupted => continue,
+                Err(Error::Accept(err)) if EmfileGuard::is_fd_limit(&err) => {
+                    let Some(guard) = guard.as_mut() else {
+                        return Err(Error::Accept(err));
+                    };
+                    match guard.shed(self.sock.as_raw()) {
+                        Ok(()) => eprintln!("server: out of fds, turned a new connection away"),
+                        Err(err) => eprintln!("server: out of fds, failed to turn away: {}", err),
+                    }
+                }
+                Err(err) => return Err(err),
+            }
+        }
+    }
+
+    fn drain(&self) {
+        let Some(budget) = self.drain_on_exit else {
+            return;
+        };
+
+        match listener::drain_pending(self.sock.as_raw(), listener::SHUTDOWN_NOTICE, budget) {
+            Ok(drained) => println!("server: drained {} pending connections", drained),
+            Err(err) => eprintln!("server: failed to drain the pending connections: {}", err),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{thread, time::Duration};
+
+    use super::*;
+    use crate::{client_builder::Client, readiness, stream_io, testutil::recv_to_end};
+
+    // Sends back everything it receives until the peer shuts down its side.
+    fn echo(conn_sock: Socket, _: SocketAddr) {
+        let mut buf = [0u8; 1024];
+        loop {
+            // SAFETY: `buf` is valid for `buf.len()` bytes for the entire `recv()` call.
+            let rbytes = unsafe {
+                libc::recv(
+                    conn_sock.as_raw(),
+                    buf.as_mut_ptr() as *mut libc::c_void,
+                    buf.len(),
+                    0,
+                )
+            };
+            if rbytes <= 0 {
+                return;
+            }
+            stream_io::sendall(conn_sock.as_raw(), &buf[..rbytes as usize]).unwrap();
+        }
+    }
+
+    #[test]
+    fn stands_up_an_echo_server_on_an_ephemeral_port() {
+        let listener = Server::new()
+            .bind(Some("127.0.0.1"), 0)
+            .backlog(1)
+            .listen()
+            .unwrap();
+        let addr = listener.local_addr();
+        assert_ne!(addr.port(), 0);
+
+        // The server runs until the test process exits.
+        thread::spawn(move || listener.serve(echo));
+
+        for msg in [&b"hello"[..], b"world"] {
+            let (sock, _) = Client::new()
+                .host("127.0.0.1")
+                .port(addr.port())
+                .connect()
+                .unwrap();
+            stream_io::sendall(sock.as_raw(), msg).unwrap();
+            // SAFETY: `sock` is a valid connected socket.
+            assert_eq!(unsafe { libc::shutdown(sock.as_raw(), libc::SHUT_WR) }, 0);
+
+            assert_eq!(recv_to_end(&sock), msg);
+        }
+    }
+
+    #[test]
+    fn host_with_a_nul_byte_is_rejected() {
+        let res = Server::new().bind(Some("127.0.0.1\0"), 0).listen();
+        assert!(matches!(res, Err(Error::InvalidHost(_))), "{:?}", res);
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn deferred_connection_is_not_accepted_until_data_arrives() {
+        let listener = Server::new()
+            .bind(Some("127.0.0.1"), 0)
+            .defer_accept(Some(5))
+            .listen()
+            .unwrap();
+        assert!(listener::defer_accept(listener.as_raw()).unwrap() >= 5);
+
+        let (client, _) = Client::new()
+            .host("127.0.0.1")
+            .port(listener.local_addr().port())
+            .connect()
+            .unwrap();
+        // The handshake is complete on the client side, but the listener has nothing to accept yet.
+        let ready = |timeout| readiness::wait_readable(listener.as_raw(), Some(timeout)).unwrap();
+        assert!(!ready(Duration::from_millis(300)));
+
+        stream_io::sendall(client.as_raw(), b"hello").unwrap();
+        assert!(ready(Duration::from_secs(1)));
+
+        let (conn, _) = listener.accept().unwrap();
+        let mut buf = [0u8; 5];
+        assert_eq!(stream_io::recv_exact(conn.as_raw(), &mut buf).unwrap(), 5);
+        assert_eq!(&buf, b"hello");
+    }
+}
@@ -0,0 +1,43 @@
+use std::{
+    io, mem, ptr,
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+static SHUTDOWN_REQUESTED: AtomicBool = AtomicBool::new(false);
+
+extern "C" fn request_shutdown(_signum: libc::c_int) {
+    // Only async-signal-safe operations are allowed in a signal handler.
+    // Storing to an atomic is one of them, printing is not.
+    SHUTDOWN_REQUESTED.store(true, Ordering::SeqCst);
+}
+
+/// Installs a `SIGINT`/`SIGTERM` handler that asks the long-running servers to stop.
+///
+/// The handler is installed without `SA_RESTART`, so a blocking `poll()`, `select()` or `accept()`
+/// returns `-1` with `EINTR` once the signal arrives. The servers then check `shutdown_requested()`
+/// to tell a shutdown apart from a stray interruption, and leave their loop gracefully.
+pub fn install_shutdown_handler() -> io::Result<()> {
+    // SAFETY: All zero `sigaction` is a valid initialization.
+    // The handler and the signal mask are set before it is used.
+    let mut sa: libc::sigaction = unsafe { mem::zeroed() };
+    sa.sa_sigaction = request_shutdown as *const () as libc::sighandler_t;
+    sa.sa_flags = 0;
+
+    // SAFETY: `sa.sa_mask` is a valid `sigset_t` owned by `sa`.
+    unsafe { libc::sigemptyset(&mut sa.sa_mask) };
+
+    for signum in [libc::SIGINT, libc::SIGTERM] {
+        // SAFETY: `sa` is initialized properly and the old action is not needed.
+        let ecode = unsafe { libc::sigaction(signum, &sa, ptr::null_mut()) };
+        if ecode == -1 {
+            return Err(io::Error::last_os_error());
+        }
+    }
+
+    Ok(())
+}
+
+/// Returns `true` once `SIGINT` or `SIGTERM` is received after `install_shutdown_handler()`.
+pub fn shutdown_requested() -> bool {
+    SHUTDOWN_REQUESTED.load(Ordering::SeqCst)
+}
@@ -0,0 +1,280 @@
+use std::{
+    error, fmt, mem,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, SocketAddrV4, SocketAddrV6},
+    os::unix::ffi::OsStrExt,
+    path::Path,
+    ptr,
+};
+
+#[derive(Debug)]
+pub enum Error {
+    PathTooLong { len: usize, max: usize },
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::PathTooLong { len, max } => write!(
+                f,
+                "the unix socket path is {} bytes long, sun_path holds at most {}",
+                len, max
+            ),
+        }
+    }
+}
+
+impl error::Error for Error {}
+
+/// Converts an INET or INET6 `sockaddr_storage` into a `SocketAddr`.
+///
+/// The port is converted to host byte order.
+/// For INET6, `sin6_flowinfo` and `sin6_scope_id` are kept, so a link-local peer
+/// like `fe80::1` is formatted with its zone (e.g. `[fe80::1%2]:3490`).
+///
+/// Returns `None` for any other address family.
+pub fn to_socket_addr(sa: &libc::sockaddr_storage) -> Option<SocketAddr> {
+    match sa.ss_family as i32 {
+        libc::AF_INET => {
+            // SAFETY: `ss_family == AF_INET` means that it is safe to cast `sockaddr_storage` to `sockaddr_in`.
+            let sockaddr_in =
+                unsafe { *(sa as *const libc::sockaddr_storage as *const libc::sockaddr_in) };
+
+            let ip_addr = Ipv4Addr::from_bits(u32::from_be(sockaddr_in.sin_addr.s_addr));
+            let port = u16::from_be(sockaddr_in.sin_port);
+            Some(SocketAddr::V4(SocketAddrV4::new(ip_addr, port)))
+        }
+        libc::AF_INET6 => {
+            // SAFETY: `ss_family == AF_INET6` means that it is safe to cast `sockaddr_storage` to `sockaddr_in6`.
+            let sockaddr_in6 =
+                unsafe { *(sa as *const libc::sockaddr_storage as *const libc::sockaddr_in6) };
+
+            let ip_addr = Ipv6Addr::from_bits(u128::from_be_bytes(sockaddr_in6.sin6_addr.s6_addr));
+            let port = u16::from_be(sockaddr_in6.sin6_port);
+            Some(SocketAddr::V6(SocketAddrV6::new(
+                ip_addr,
+                port,
+                u32::from_be(sockaddr_in6.sin6_flowinfo),
+                sockaddr_in6.sin6_scope_id,
+            )))
+        }
+        _ => None,
+    }
+}
+
+/// Same as `to_socket_addr()`, but only the IP address is returned, e.g. when the port is not set.
+pub fn to_ip_addr(sa: &libc::sockaddr_storage) -> Option<IpAddr> {
+    to_socket_addr(sa).map(|addr| addr.ip())
+}
+
+/// Copies a raw `sockaddr` (e.g. the `ai_addr` of an `addrinfo`) into a `sockaddr_storage`.
+///
+/// Reading a `sockaddr_in6` through a plain `sockaddr` is out of bounds, since `sockaddr` is
+/// smaller than `sockaddr_in6`. Copying into a `sockaddr_storage` first is always safe to cast afterwards.
+///
+/// # Safety
+///
+/// `sa` must point to a valid, initialized address that is at least `len` bytes long.
+pub unsafe fn copy_to_storage(
+    sa: *const libc::sockaddr,
+    len: libc::socklen_t,
+) -> libc::sockaddr_storage {
+    // SAFETY: All zero `sockaddr_storage` is a valid initialization.
+    let mut storage: libc::sockaddr_storage = unsafe { mem::zeroed() };
+    let len = (len as usize).min(mem::size_of_val(&storage));
+
+    // SAFETY: `sa` is valid for `len` bytes as required by the caller, and `storage` is big enough
+    // to hold any address family, the copy is clamped to its size regardless.
+    unsafe { ptr::copy_nonoverlapping(sa as *const u8, &raw mut storage as *mut u8, len) };
+
+    storage
+}
+
+/// Converts a `SocketAddr` into a `sockaddr_storage` that can be passed to the syscalls.
+///
+/// This is the inverse of `to_socket_addr()`, the port and `sin6_flowinfo` are converted to network byte order.
+///
+/// Returns the storage and the length of the address inside of it.
+pub fn from_socket_addr(addr: &SocketAddr) -> (libc::sockaddr_storage, libc::socklen_t) {
+    // SAFETY: All zero `sockaddr_storage` is a valid initialization.
+    let mut storage: libc::sockaddr_storage = unsafe { mem::zeroed() };
+
+    let len = match addr {
+        SocketAddr::V4(addr) => {
+            // SAFETY: `sockaddr_storage` is big enough and suitably aligned to be used as a `sockaddr_in`.
+            let sockaddr_in = unsafe { &mut *(&raw mut storage as *mut libc::sockaddr_in) };
+            sockaddr_in.sin_family = libc::AF_INET as libc::sa_family_t;
+            sockaddr_in.sin_port = addr.port().to_be();
+            sockaddr_in.sin_addr.s_addr = addr.ip().to_bits().to_be();
+            mem::size_of::<libc::sockaddr_in>()
+        }
+        SocketAddr::V6(addr) => {
+            // SAFETY: `sockaddr_storage` is big enough and suitably aligned to be used as a `sockaddr_in6`.
+            let sockaddr_in6 = unsafe { &mut *(&raw mut storage as *mut libc::sockaddr_in6) };
+            sockaddr_in6.sin6_family = libc::AF_INET6 as libc::sa_family_t;
+            sockaddr_in6.sin6_port = addr.port().to_be();
+            sockaddr_in6.sin6_flowinfo = addr.flowinfo().to_be();
+            sockaddr_in6.sin6_addr.s6_addr = addr.ip().octets();
+            sockaddr_in6.sin6_scope_id = addr.scope_id();
+            mem::size_of::<libc::sockaddr_in6>()
+        }
+    };
+
+    (storage, len as libc::socklen_t)
+}
+
+/// Whether `path` names a socket in the abstract namespace of Linux instead of a file, see `fill_sockaddr_un()`.
+pub fn is_abstract_unix_path(path: &Path) -> bool {
+    cfg!(target_os = "linux") && path.as_os_str().as_bytes().starts_with(b"@")
+}
+
+/// Fills a `sockaddr_un` with `path`, to `bind()` or `connect()` a Unix domain socket.
+///
+/// `sun_path` is a small fixed array (108 bytes on Linux), a path that does not fit is rejected
+/// with `Error::PathTooLong` instead of being truncated into a different one.
+/// A regular path needs room for its null terminator as well.
+///
+/// On Linux, a path that starts with `@` names a socket in the abstract namespace, which is not a file
+/// (the `@` is how `ss` prints them). Its name goes after a leading null byte, and it is not null terminated,
+/// since the address length tells where it ends.
+///
+/// Returns the address and its length, which covers the path but not the unused rest of `sun_path`.
+pub fn fill_sockaddr_un(path: &Path) -> Result<(libc::sockaddr_un, libc::socklen_t), Error> {
+    // SAFETY: All zero `sockaddr_un` is a valid initialization, an empty path.
+    let mut sa: libc::sockaddr_un = unsafe { mem::zeroed() };
+    sa.sun_family = libc::AF_UNIX as libc::sa_family_t;
+
+    let bytes = path.as_os_str().as_bytes();
+    // Both kinds take one byte of `sun_path` besides the name: the leading null byte or the null terminator.
+    let (name, start) = match is_abstract_unix_path(path) {
+        true => (&bytes[1..], 1),
+        false => (bytes, 0),
+    };
+    let max = sa.sun_path.len() - 1;
+    if name.len() > max {
+        return Err(Error::PathTooLong {
+            len: name.len(),
+            max,
+        });
+    }
+
+    for (dst, src) in sa.sun_path[start..].iter_mut().zip(name) {
+        *dst = *src as libc::c_char;
+    }
+
+    let len = mem::offset_of!(libc::sockaddr_un, sun_path) + name.len() + 1;
+
+    Ok((sa, len as libc::socklen_t))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A `sockaddr_in6` for `[fe80::1%2]:3490`, filled by hand the way the kernel fills it.
+    fn link_local_sockaddr_in6() -> libc::sockaddr_in6 {
+        // SAFETY: All zero `sockaddr_in6` is a valid initialization.
+        let mut sa: libc::sockaddr_in6 = unsafe { mem::zeroed() };
+        sa.sin6_family = libc::AF_INET6 as libc::sa_family_t;
+        sa.sin6_port = 3490u16.to_be();
+        sa.sin6_flowinfo = 0x12345u32.to_be();
+        sa.sin6_addr.s6_addr = [0xfe, 0x80, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1];
+        // The scope id is an interface index, it is in host byte order.
+        sa.sin6_scope_id = 2;
+        sa
+    }
+
+    #[test]
+    fn link_local_peer_keeps_its_zone_and_flowinfo() {
+        let sa = link_local_sockaddr_in6();
+        // SAFETY: `sa` is a valid `sockaddr_in6`, which is exactly `size_of_val(&sa)` bytes long.
+        let storage = unsafe {
+            copy_to_storage(
+                &raw const sa as *const libc::sockaddr,
+                mem::size_of_val(&sa) as libc::socklen_t,
+            )
+        };
+
+        let SocketAddr::V6(addr) = to_socket_addr(&storage).unwrap() else {
+            panic!("not an IPv6 address");
+        };
+        assert_eq!(addr.to_string(), "[fe80::1%2]:3490");
+        assert_eq!(addr.flowinfo(), 0x12345);
+        assert_eq!(addr.scope_id(), 2);
+    }
+
+    #[test]
+    fn ipv4_port_is_converted_to_host_byte_order() {
+        // SAFETY: All zero `sockaddr_storage` is a valid initialization.
+        let mut storage: libc::sockaddr_storage = unsafe { mem::zeroed() };
+        // SAFETY: `sockaddr_storage` is big enough and suitably aligned to be used as a `sockaddr_in`.
+        let sa = unsafe { &mut *(&raw mut storage as *mut libc::sockaddr_in) };
+        sa.sin_family = libc::AF_INET as libc::sa_family_t;
+        sa.sin_port = 3490u16.to_be();
+        sa.sin_addr.s_addr = u32::from(Ipv4Addr::new(127, 0, 0, 1)).to_be();
+
+        assert_eq!(
+            to_socket_addr(&storage),
+            Some("127.0.0.1:3490".parse().unwrap())
+        );
+        assert_eq!(to_ip_addr(&storage), Some("127.0.0.1".parse().unwrap()));
+    }
+
+    #[test]
+    fn other_families_are_not_converted() {
+        // SAFETY: All zero `sockaddr_storage` is a valid initialization.
+        let mut storage: libc::sockaddr_storage = unsafe { mem::zeroed() };
+        storage.ss_family = libc::AF_UNIX as libc::sa_family_t;
+
+        assert_eq!(to_socket_addr(&storage), None);
+    }
+
+    #[test]
+    fn from_socket_addr_is_the_inverse() {
+        for addr in ["127.0.0.1:3490", "[::1]:80", "[fe80::1%2]:3490"] {
+            let addr: SocketAddr = addr.parse().unwrap();
+            let (storage, len) = from_socket_addr(&addr);
+
+            let expected = match addr {
+                SocketAddr::V4(_) => mem::size_of::<libc::sockaddr_in>(),
+                SocketAddr::V6(_) => mem::size_of::<libc::sockaddr_in6>(),
+            };
+            assert_eq!(len as usize, expected);
+            assert_eq!(to_socket_addr(&storage), Some(addr));
+        }
+    }
+
+    #[test]
+    fn over_long_unix_path_is_rejected() {
+        // 107 bytes and the null terminator fill `sun_path` on Linux.
+        let max =
+            mem::size_of::<libc::sockaddr_un>() - mem::offset_of!(libc::sockaddr_un, sun_path) - 1;
+        let fits = format!("/{}", "a".repeat(max - 1));
+        let too_long = format!("/{}", "a".repeat(max));
+
+        let (sa, len) = fill_sockaddr_un(Path::new(&fits)).unwrap();
+        assert_eq!(sa.sun_path[max], 0);
+        assert_eq!(
+            len as usize,
+            mem::offset_of!(libc::sockaddr_un, sun_path) + max + 1
+        );
+
+        assert!(matches!(
+            fill_sockaddr_un(Path::new(&too_long)),
+            Err(Error::PathTooLong { len, max: m }) if len == max + 1 && m == max
+        ));
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn abstract_unix_path_starts_with_a_null_byte() {
+        let (sa, len) = fill_sockaddr_un(Path::new("@bjrs")).unwrap();
+
+        let name: Vec<u8> = sa.sun_path[..6].iter().map(|c| *c as u8).collect();
+        assert_eq!(name, b"\0bjrs\0");
+        // The length covers the leading null byte and the name, there is no null terminator.
+        assert_eq!(
+            len as usize,
+            mem::offset_of!(libc::sockaddr_un, sun_path) + 5
+        );
+    }
+}
@@ -0,0 +1,225 @@
+use std::{io, mem};
+
+use crate::{fd, stream_io, trace};
+
+/// An owned socket fd that is closed when it goes out of scope.
+///
+/// Holding a `Socket` instead of a bare `i32` means that the fd cannot leak on an early return.
+#[derive(Debug)]
+pub struct Socket(i32);
+
+impl Socket {
+    /// Creates a new socket by calling `socket()`.
+    pub fn new(domain: i32, ty: i32, protocol: i32) -> io::Result<Socket> {
+        // SAFETY: `socket()` does not touch any memory owned by the process.
+        let sock_fd = unsafe { trace::socket(domain, ty, protocol) };
+        match sock_fd {
+            -1 => Err(io::Error::last_os_error()),
+            _ => Ok(Socket(sock_fd)),
+        }
+    }
+
+    /// Takes the ownership of `sock_fd`, e.g. the one returned by `accept()`.
+    ///
+    /// `sock_fd` must not be closed by anything else afterwards.
+    pub fn from_raw(sock_fd: i32) -> Socket {
+        Socket(sock_fd)
+    }
+
+    /// Returns the underlying fd to be passed to the syscalls, the ownership is kept.
+    pub fn as_raw(&self) -> i32 {
+        self.0
+    }
+
+    /// Duplicates the fd with `fcntl(F_DUPFD_CLOEXEC)`, e.g. to read and write a connection from different threads.
+    ///
+    /// Both `Socket`s refer to the same underlying socket: the data, the options and the file status flags
+    /// (e.g. `O_NONBLOCK`) are shared. Only the fds are separate, each `Socket` closes its own,
+    /// and the connection is only released once both of them are closed.
+    /// Use `shutdown()` to end the connection for both of them at once.
+    pub fn try_clone(&self) -> io::Result<Socket> {
+        // SAFETY: `F_DUPFD_CLOEXEC` only creates a new fd, the lowest one that is at least 0.
+        let sock_fd = unsafe { libc::fcntl(self.0, libc::F_DUPFD_CLOEXEC, 0) };
+        match sock_fd {
+            -1 => Err(io::Error::last_os_error()),
+            _ => Ok(Socket(sock_fd)),
+        }
+    }
+
+    /// Splits a connected socket into a half that can only receive and a half that can only send,
+    /// so that each of them can be moved to a different thread.
+    ///
+    /// Each half owns its own fd (see `try_clone()`), and the connection is released once both are dropped.
+    pub fn into_split(self) -> io::Result<(ReadHalf, WriteHalf)> {
+        let write_sock = self.try_clone()?;
+        Ok((ReadHalf(self), WriteHalf(write_sock)))
+    }
+
+    /// Gives up the ownership of the fd without closing it, the caller is responsible for closing it afterwards.
+    pub fn into_raw(self) -> i32 {
+        let sock_fd = self.0;
+        mem::forget(self);
+        sock_fd
+    }
+}
+
+impl Drop for Socket {
+    fn drop(&mut self) {
+        // There is nobody to report the error to, and the fd is released regardless (see `fd::close_fd()`).
+        let _ = fd::close_fd(self.0);
+    }
+}
+
+fn shutdown(sock_fd: i32, how: i32) -> io::Result<()> {
+    // SAFETY: `shutdown()` does not touch any memory owned by the process.
+    let ecode = unsafe { trace::shutdown(sock_fd, how) };
+    match ecode {
+        -1 => Err(io::Error::last_os_error()),
+        _ => Ok(()),
+    }
+}
+
+/// The receiving half of a connection, see `Socket::into_split()`.
+#[derive(Debug)]
+pub struct ReadHalf(Socket);
+
+impl ReadHalf {
+    /// Receives into `buf` with `recv()`, 0 means that the peer is done sending.
+    pub fn recv(&self, buf: &mut [u8]) -> io::Result<usize> {
+        // SAFETY: `buf` is a valid, initialized slice, and its length is passed along with it.
+        let rbytes = unsafe {
+            trace::recv(
+                self.0.as_raw(),
+                buf.as_mut_ptr() as *mut libc::c_void,
+                buf.len(),
+                0,
+            )
+        };
+        match rbytes {
+            -1 => Err(io::Error::last_os_error()),
+            _ => Ok(rbytes as usize),
+        }
+    }
+
+    /// Stops receiving with `shutdown(SHUT_RD)`, the write half keeps working.
+    pub fn shutdown(&self) -> io::Result<()> {
+        shutdown(self.0.as_raw(), libc::SHUT_RD)
+    }
+
+    pub fn as_raw(&self) -> i32 {
+        self.0.as_raw()
+    }
+}
+
+/// The sending half of a connection, see `Socket::into_split()`.
+#[derive(Debug)]
+pub struct WriteHalf(Socket);
+
+impl WriteHalf {
+    /// Sends `buf` with a single `send()`, which may send less than all of it.
+    pub fn send(&self, buf: &[u8]) -> io::Result<usize> {
+        // SAFETY: `buf` is a valid, initialized slice, and its length is passed along with it.
+        let sbytes = unsafe {
+            trace::send(
+                self.0.as_raw(),
+                buf.as_ptr() as *const libc::c_void,
+                buf.len(),
+                0,
+            )
+        };
+        match sbytes {
+            -1 => Err(io::Error::last_os_error()),
+            _ => Ok(sbytes as usize),
+        }
+    }
+
+    /// Sends the whole `buf`, see `stream_io::sendall()`.
+    pub fn send_all(&self, buf: &[u8]) -> io::Result<usize> {
+        Ok(stream_io::sendall(self.0.as_raw(), buf)?)
+    }
+
+    /// Tells the peer that nothing more is sent with `shutdown(SHUT_WR)`, the read half keeps receiving.
+    ///
+    /// Dropping the half is not enough for that, the read half still holds the connection open.
+    pub fn shutdown(&self) -> io::Result<()> {
+        shutdown(self.0.as_raw(), libc::SHUT_WR)
+    }
+
+    pub fn as_raw(&self) -> i32 {
+        self.0.as_raw()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use super::*;
+    use crate::testutil;
+
+    #[test]
+    fn data_sent_through_a_clone_reaches_the_peer() {
+        let (client, server) = testutil::loopback_pair();
+        let clone = client.try_clone().unwrap();
+        assert_ne!(clone.as_raw(), client.as_raw());
+
+        stream_io::sendall(clone.as_raw(), b"from the clone\n").unwrap();
+        let mut pending = Vec::new();
+        let line = stream_io::recv_until(server.as_raw(), &mut pending, b'\n').unwrap();
+        assert_eq!(line.unwrap(), b"from the clone");
+
+        // The connection is still open through the original after the clone is closed.
+        drop(clone);
+        stream_io::sendall(client.as_raw(), b"from the original\n").unwrap();
+        let line = stream_io::recv_until(server.as_raw(), &mut pending, b'\n').unwrap();
+        assert_eq!(line.unwrap(), b"from the original");
+    }
+
+    #[test]
+    fn clone_is_close_on_exec() {
+        let (client, _server) = testutil::loopback_pair();
+        let clone = client.try_clone().unwrap();
+
+        // SAFETY: `F_GETFD` only reads the fd flags.
+        let flags = unsafe { libc::fcntl(clone.as_raw(), libc::F_GETFD) };
+        assert_ne!(flags & libc::FD_CLOEXEC, 0);
+    }
+
+    #[test]
+    fn halves_send_and_receive_from_different_threads() {
+        let (client, server) = testutil::loopback_pair();
+        let (read_half, write_half) = client.into_split().unwrap();
+
+        let writer = thread::spawn(move || {
+            for i in 0..100 {
+                write_half
+                    .send_all(format!("line {}\n", i).as_bytes())
+                    .unwrap();
+            }
+            write_half.shutdown().unwrap();
+        });
+        let reader = thread::spawn(move || {
+            let mut received = Vec::new();
+            let mut buf = [0u8; 64];
+            loop {
+                match read_half.recv(&mut buf).unwrap() {
+                    0 => return received,
+                    n => received.extend_from_slice(&buf[..n]),
+                }
+            }
+        });
+
+        // The server echoes every line back as soon as it arrives, and hangs up once the write half is shut down.
+        let mut pending = Vec::new();
+        while let Some(line) = stream_io::recv_until(server.as_raw(), &mut pending, b'\n').unwrap()
+        {
+            stream_io::sendall(server.as_raw(), &[&line[..], b"\n"].concat()).unwrap();
+        }
+        drop(server);
+
+        writer.join().unwrap();
+        let received = reader.join().unwrap();
+        let expected: String = (0..100).map(|i| format!("line {}\n", i)).collect();
+        assert_eq!(received, expected.as_bytes());
+    }
+}
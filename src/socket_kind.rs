@@ -0,0 +1,78 @@
+use std::net::SocketAddr;
+
+use clap::ValueEnum;
+
+/// The address family of a socket, the `domain` argument of `socket()`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum AddressFamily {
+    /// `AF_INET`
+    V4,
+    /// `AF_INET6`
+    V6,
+    /// `AF_UNSPEC`, either of them. Only meaningful as a `getaddrinfo()` hint.
+    #[value(name = "any", alias = "unspec")]
+    Unspec,
+}
+
+impl AddressFamily {
+    /// The family of `addr`, which is never `Unspec`.
+    pub fn of(addr: &SocketAddr) -> Self {
+        match addr {
+            SocketAddr::V4(_) => AddressFamily::V4,
+            SocketAddr::V6(_) => AddressFamily::V6,
+        }
+    }
+
+    pub fn as_raw(self) -> i32 {
+        match self {
+            AddressFamily::V4 => libc::AF_INET,
+            AddressFamily::V6 => libc::AF_INET6,
+            AddressFamily::Unspec => libc::AF_UNSPEC,
+        }
+    }
+}
+
+/// The type of a socket, the `type` argument of `socket()`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum SocketType {
+    /// `SOCK_STREAM`, e.g. TCP.
+    Stream,
+    /// `SOCK_DGRAM`, e.g. UDP.
+    Dgram,
+}
+
+impl SocketType {
+    pub fn as_raw(self) -> i32 {
+        match self {
+            SocketType::Stream => libc::SOCK_STREAM,
+            SocketType::Dgram => libc::SOCK_DGRAM,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn families_map_to_their_libc_constants() {
+        assert_eq!(AddressFamily::V4.as_raw(), libc::AF_INET);
+        assert_eq!(AddressFamily::V6.as_raw(), libc::AF_INET6);
+        assert_eq!(AddressFamily::Unspec.as_raw(), libc::AF_UNSPEC);
+    }
+
+    #[test]
+    fn socket_types_map_to_their_libc_constants() {
+        assert_eq!(SocketType::Stream.as_raw(), libc::SOCK_STREAM);
+        assert_eq!(SocketType::Dgram.as_raw(), libc::SOCK_DGRAM);
+    }
+
+    #[test]
+    fn family_of_an_address() {
+        let v4: SocketAddr = "127.0.0.1:3490".parse().unwrap();
+        let v6: SocketAddr = "[::1]:3490".parse().unwrap();
+
+        assert_eq!(AddressFamily::of(&v4), AddressFamily::V4);
+        assert_eq!(AddressFamily::of(&v6), AddressFamily::V6);
+    }
+}
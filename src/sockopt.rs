@@ -0,0 +1,66 @@
+use std::{io, mem, time::Duration};
+
+/// Reads an `int` socket option with `getsockopt()`.
+pub fn get_int(sock_fd: i32, level: i32, name: i32) -> io::Result<i32> {
+    let mut value: i32 = 0;
+    let mut len = mem::size_of_val(&value) as libc::socklen_t;
+
+    // SAFETY: `value` is an initialized `int`, and `len` holds its size.
+    let ecode = unsafe {
+        libc::getsockopt(
+            sock_fd,
+            level,
+            name,
+            &raw mut value as *mut libc::c_void,
+            &raw mut len,
+        )
+    };
+    match ecode {
+        -1 => Err(io::Error::last_os_error()),
+        _ => Ok(value),
+    }
+}
+
+/// Sets an `int` socket option with `setsockopt()`.
+pub fn set_int(sock_fd: i32, level: i32, name: i32, value: i32) -> io::Result<()> {
+    // SAFETY: `value` is an initialized `int`, and its size is passed along with it.
+    let ecode = unsafe {
+        libc::setsockopt(
+            sock_fd,
+            level,
+            name,
+            &raw const value as *const libc::c_void,
+            mem::size_of_val(&value) as libc::socklen_t,
+        )
+    };
+    match ecode {
+        -1 => Err(io::Error::last_os_error()),
+        _ => Ok(()),
+    }
+}
+
+/// Sets a `timeval` socket option of `SOL_SOCKET` (`SO_RCVTIMEO` or `SO_SNDTIMEO`) with `setsockopt()`.
+///
+/// A blocking call that times out fails with `EAGAIN`/`EWOULDBLOCK` (`io::ErrorKind::WouldBlock`).
+/// `Duration::ZERO` turns the timeout off.
+pub fn set_timeout(sock_fd: i32, name: i32, timeout: Duration) -> io::Result<()> {
+    let tv = libc::timeval {
+        tv_sec: timeout.as_secs() as libc::time_t,
+        tv_usec: timeout.subsec_micros() as libc::suseconds_t,
+    };
+
+    // SAFETY: `tv` is an initialized `timeval`, and its size is passed along with it.
+    let ecode = unsafe {
+        libc::setsockopt(
+            sock_fd,
+            libc::SOL_SOCKET,
+            name,
+            &raw const tv as *const libc::c_void,
+            mem::size_of_val(&tv) as libc::socklen_t,
+        )
+    };
+    match ecode {
+        -1 => Err(io::Error::last_os_error()),
+        _ => Ok(()),
+    }
+}
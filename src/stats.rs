@@ -0,0 +1,179 @@
+use std::{fmt, net::SocketAddr};
+
+/// Counters collected by the long-running servers during their lifetime.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct ServerStats {
+    /// Total number of accepted connections.
+    pub connections: u64,
+    /// Total number of bytes received from the clients.
+    pub bytes_in: u64,
+    /// Total number of bytes sent to the clients.
+    pub bytes_out: u64,
+    /// The highest number of clients that were connected at the same time.
+    pub peak_clients: usize,
+    clients: usize,
+}
+
+impl ServerStats {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn on_accept(&mut self) {
+        self.connections += 1;
+        self.clients += 1;
+        self.peak_clients = self.peak_clients.max(self.clients);
+    }
+
+    pub fn on_disconnect(&mut self) {
+        self.clients = self.clients.saturating_sub(1);
+    }
+
+    pub fn on_recv(&mut self, nbytes: usize) {
+        self.bytes_in += nbytes as u64;
+    }
+
+    pub fn on_send(&mut self, nbytes: usize) {
+        self.bytes_out += nbytes as u64;
+    }
+
+    /// The number of clients that are currently connected.
+    pub fn clients(&self) -> usize {
+        self.clients
+    }
+}
+
+impl fmt::Display for ServerStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "connections: {}, bytes in: {}, bytes out: {}, peak clients: {}",
+            self.connections, self.bytes_in, self.bytes_out, self.peak_clients
+        )
+    }
+}
+
+/// What a single-shot receive example got, so that it can be checked without parsing the output.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReceiveSummary {
+    /// Total number of bytes received.
+    pub bytes: usize,
+    /// The number of datagrams, or the `recv()` calls on a stream, that carried them.
+    pub messages: usize,
+    /// The distinct source addresses, in the order they were first seen.
+    pub sources: Vec<SocketAddr>,
+    unit: &'static str,
+}
+
+impl ReceiveSummary {
+    /// `unit` names a message in the summary line, e.g. `"datagram"`.
+    pub fn new(unit: &'static str) -> Self {
+        Self {
+            bytes: 0,
+            messages: 0,
+            sources: Vec::new(),
+            unit,
+        }
+    }
+
+    pub fn on_recv(&mut self, nbytes: usize, from: SocketAddr) {
+        self.bytes += nbytes;
+        self.messages += 1;
+        if !self.sources.contains(&from) {
+            self.sources.push(from);
+        }
+    }
+}
+
+impl fmt::Display for ReceiveSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let plural = if self.messages == 1 { "" } else { "s" };
+        write!(
+            f,
+            "{} bytes in {} {}{}",
+            self.bytes, self.messages, self.unit, plural
+        )?;
+        for (i, source) in self.sources.iter().enumerate() {
+            let sep = if i == 0 { " from " } else { ", " };
+            write!(f, "{}{}", sep, source)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn peak_clients_is_the_most_at_the_same_time() {
+        let mut stats = ServerStats::new();
+
+        stats.on_accept();
+        stats.on_accept();
+        stats.on_disconnect();
+        stats.on_accept();
+        stats.on_disconnect();
+        stats.on_disconnect();
+
+        assert_eq!(stats.connections, 3);
+        assert_eq!(stats.peak_clients, 2);
+        assert_eq!(stats.clients(), 0);
+    }
+
+    #[test]
+    fn a_disconnect_without_clients_does_not_underflow() {
+        let mut stats = ServerStats::new();
+        stats.on_disconnect();
+
+        assert_eq!(stats.clients(), 0);
+    }
+
+    #[test]
+    fn bytes_add_up_in_each_direction() {
+        let mut stats = ServerStats::new();
+        stats.on_recv(6);
+        stats.on_send(6);
+        stats.on_send(6);
+
+        assert_eq!(stats.bytes_in, 6);
+        assert_eq!(stats.bytes_out, 12);
+        assert_eq!(
+            stats.to_string(),
+            "connections: 0, bytes in: 6, bytes out: 12, peak clients: 0"
+        );
+    }
+
+    #[test]
+    fn receive_summary_counts_each_source_once() {
+        let (a, b) = (
+            "127.0.0.1:4950".parse().unwrap(),
+            "[::1]:4951".parse().unwrap(),
+        );
+        let mut summary = ReceiveSummary::new("datagram");
+        summary.on_recv(10, a);
+        summary.on_recv(5, b);
+        summary.on_recv(1, a);
+
+        assert_eq!(summary.bytes, 16);
+        assert_eq!(summary.messages, 3);
+        assert_eq!(summary.sources, [a, b]);
+        assert_eq!(
+            summary.to_string(),
+            "16 bytes in 3 datagrams from 127.0.0.1:4950, [::1]:4951"
+        );
+    }
+
+    #[test]
+    fn receive_summary_of_a_single_message() {
+        let mut summary = ReceiveSummary::new("segment");
+        assert_eq!(summary.to_string(), "0 bytes in 0 segments");
+
+        summary.on_recv(12, "127.0.0.1:3490".parse().unwrap());
+        assert_eq!(
+            summary.to_string(),
+            "12 bytes in 1 segment from 127.0.0.1:3490"
+        );
+    }
+}
@@ -1,112 +1,158 @@
 use std::{
-    error,
-    ffi::{CStr, CString},
-    fmt,
+    error, fmt,
     io::{self, Write},
-    mem, ptr,
+};
+
+use crate::{
+    client_builder::{Client, ConnectRetry},
+    inet::ConnectError,
+    socket_fd::Socket,
+    sockopt, stream_io, trace,
 };
 
 #[derive(Debug)]
 pub enum Error {
-    Getaddrinfo(String),
-    Socket(io::Error),
-    Connect(io::Error),
+    Connect(ConnectError),
     Recv(io::Error),
-    Close(io::Error),
+    Getsockopt(io::Error),
 }
 
 impl fmt::Display for Error {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
-            Error::Getaddrinfo(err) => write!(f, "getaddrinfo error: {}", err),
-            Error::Socket(err) => write!(f, "socket error: {}", err),
             Error::Connect(err) => write!(f, "connect error: {}", err),
             Error::Recv(err) => write!(f, "recv error: {}", err),
-            Error::Close(err) => write!(f, "close err: {}", err),
+            Error::Getsockopt(err) => write!(f, "getsockopt TCP_MAXSEG error: {}", err),
         }
     }
 }
 
 impl error::Error for Error {}
 
-// EXAMPLE: A simple stream client that connects to the server created by `bjrs stream server` command.
+/// The way `client()` reads what the server sends.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReadMode {
+    /// A single `recv()` call, as in the guide.
+    Once,
+    /// Keep calling `recv()` until the server closes the connection, or until `max_bytes` are read.
+    Drain { max_bytes: Option<usize> },
+    /// Read exactly `len` bytes, either with a `recv()` loop or with a single `recv(MSG_WAITALL)` call.
+    Exact { len: usize, waitall: bool },
+}
+
+// EXAMPLE: A simple stream client that connects to the server created by `bjrs stream server` command, on `port`.
 // This example is a more complete version of `recv()` syscall example.
 // MANPAGE:
 // man 2 recv (Linux)
 // man 3 recv (POSIX)
 // man errno
-pub fn client() -> Result<(), Error> {
-    let node = ptr::null();
-    let port = CString::from(c"3490");
-
-    // SAFETY: All zero hints is a valid initialization.
-    // Required fields are set later on.
-    let mut hints: libc::addrinfo = unsafe { mem::zeroed() };
-    hints.ai_family = libc::AF_UNSPEC;
-    hints.ai_socktype = libc::SOCK_STREAM;
-
-    let mut gai_res_ptr = ptr::null_mut();
-
-    // SAFETY: There is no uninitialized memory access. `getaddrinfo()` is safe to call.
-    let ecode = unsafe { libc::getaddrinfo(node, port.as_ptr(), &hints, &mut gai_res_ptr) };
-    match ecode {
-        0 => Ok(()),
-        _ => {
-            // SAFETY: `gai_strerror` is valid to call on a failed `getaddrinfo()` call.
-            let err = unsafe { CStr::from_ptr(libc::gai_strerror(ecode)).to_string_lossy() };
-            Err(Error::Getaddrinfo(err.into_owned()))
-        }
-    }?;
-
-    let mut sock_fd = -1;
-    while !gai_res_ptr.is_null() {
-        // SAFETY: `gai_res_ptr` is guaranteed to point atleast one valid addrinfo struct on a successful `getaddrinfo()` call.
-        let gai_res = unsafe { *gai_res_ptr };
-        let next_res_ptr = gai_res.ai_next;
-
-        // SAFETY: `socket()` is safe to call since `gai_res` is valid.
-        let sock = unsafe {
-            let sock = libc::socket(gai_res.ai_family, gai_res.ai_socktype, 0);
-            if sock == -1 {
-                if next_res_ptr.is_null() {
-                    return Err(Error::Socket(io::Error::last_os_error()));
-                } else {
-                    gai_res_ptr = next_res_ptr;
-                    continue;
-                }
-            }
-
-            sock
-        };
+//
+// The server's message is read according to `mode`, see `ReadMode`.
+// `ReadMode::Exact` shows the two ways of doing a fixed-size read on a stream:
+// a loop that calls `recv()` until enough bytes arrive, or `MSG_WAITALL` that makes the kernel wait for them.
+// Either way, fewer bytes are read if the server closes the connection first.
+//
+// `getaddrinfo()` may return both an IPv6 and an IPv4 address for the server.
+// The address that is actually connected to is printed, so that it is clear which one of them is used.
+//
+// If `max_segment` is set, `TCP_MAXSEG` is set before `connect()`, since the MSS is advertised in the SYN.
+// The value read back after the handshake is the MSS that is actually used, which is also limited
+// by what the server advertised, and clamped by the kernel to the path MTU.
+//
+// If `host` is not set, the server is looked up on the loopback addresses. A link-local IPv6 `host` needs a zone,
+// either as a suffix (e.g. `fe80::1%eth0`) or as `scope`.
+//
+// A client that is started before the server gets `ECONNREFUSED`. With `retry`, the connection is retried
+// with a growing delay until the server is up or the retries run out.
+pub fn client(
+    host: Option<&str>,
+    scope: Option<&str>,
+    port: u16,
+    mode: ReadMode,
+    max_segment: Option<i32>,
+    retry: ConnectRetry,
+) -> Result<(), Error> {
+    let mut client = Client::new();
+    if let Some(host) = host {
+        client = client.host(host);
+    }
+    let (sock, server_addr) = client
+        .scope(scope)
+        .port(port)
+        .max_segment(max_segment)
+        .retry(retry)
+        .connect()
+        .map_err(Error::Connect)?;
+    println!("client: connected to {}", server_addr);
+
+    if max_segment.is_some() {
+        let mss = sockopt::get_int(sock.as_raw(), libc::IPPROTO_TCP, libc::TCP_MAXSEG)
+            .map_err(Error::Getsockopt)?;
+        println!("client: TCP_MAXSEG = {}", mss);
+    }
 
-        // SAFETY: `connect()` is safe to call since `sock` and `gai_res` are valid..
-        let ecode = unsafe { libc::connect(sock, gai_res.ai_addr, gai_res.ai_addrlen) };
-        if ecode == -1 {
-            if next_res_ptr.is_null() {
-                return Err(Error::Connect(io::Error::last_os_error()));
+    match mode {
+        ReadMode::Once => {}
+        ReadMode::Drain { max_bytes } => {
+            let total = drain_until_eof(&sock, max_bytes)?;
+            println!("client: drained {} bytes", total);
+            return Ok(());
+        }
+        ReadMode::Exact { len, waitall } => {
+            let mut recv_buf = vec![0; len];
+            let (how, res) = if waitall {
+                (
+                    "recv(MSG_WAITALL)",
+                    stream_io::recv_waitall(sock.as_raw(), &mut recv_buf),
+                )
             } else {
-                gai_res_ptr = next_res_ptr;
-                continue;
-            }
+                (
+                    "recv() loop",
+                    stream_io::recv_exact(sock.as_raw(), &mut recv_buf),
+                )
+            };
+            let total = res.map_err(Error::Recv)?;
+
+            println!(
+                "client: {} read {} of {} bytes: {:?}",
+                how,
+                total,
+                len,
+                String::from_utf8_lossy(&recv_buf[..total])
+            );
+            return Ok(());
         }
-
-        sock_fd = sock;
-        break;
     }
 
-    // SAFETY: `gai_res` is no longer needed and its pointer points to a valid `addrinfo` struct at this point. It can be freed safely.
-    unsafe {
-        libc::freeaddrinfo(gai_res_ptr);
-    }
+    let recv_buf = recv_once(&sock)?;
+
+    let msg = [b"client: received ", &recv_buf[..]].concat();
+    io::stdout()
+        .write_all(&msg)
+        .expect("message to be written to stdout");
+
+    Ok(())
+}
 
-    const MAXDATASIZE: usize = 100;
-    let mut recv_buf = vec![0; MAXDATASIZE];
-    let len = recv_buf.len();
+const MAXDATASIZE: usize = 100;
+
+// Receives a single reply of at most `MAXDATASIZE` bytes, followed by a null terminator.
+fn recv_once(sock: &Socket) -> Result<Vec<u8>, Error> {
+    // One more byte for the null terminator, a reply that fills `MAXDATASIZE` still leaves room for it.
+    let mut recv_buf = vec![0; MAXDATASIZE + 1];
+    let len = MAXDATASIZE;
 
     // SAFETY:
-    // 1 - `sock_fd` is a valid sock fd for server communication.
+    // 1 - `sock` is a valid sock fd for server communication.
     // 2 - `recv_buf` and its len are initialized as desired.
-    let bytes = unsafe { libc::recv(sock_fd, recv_buf.as_mut_ptr() as *mut libc::c_void, len, 0) };
+    let bytes = unsafe {
+        trace::recv(
+            sock.as_raw(),
+            recv_buf.as_mut_ptr() as *mut libc::c_void,
+            len,
+            0,
+        )
+    };
     match bytes {
         -1 => Err(Error::Recv(io::Error::last_os_error())),
         _ => Ok(()),
@@ -114,18 +160,79 @@ pub fn client() -> Result<(), Error> {
 
     recv_buf[bytes as usize] = b'\0';
 
-    let msg = [b"client: received ", &recv_buf[..]].concat();
-    io::stdout()
-        .write_all(&msg)
-        .expect("message to be written to stdout");
+    Ok(recv_buf)
+}
 
-    // SAFETY:
-    // `sock_fd` is a valid sock fd for peer communication.
-    let ecode = unsafe { libc::close(sock_fd) };
-    match ecode {
-        -1 => Err(Error::Close(io::Error::last_os_error())),
-        _ => Ok(()),
-    }?;
+fn drain_until_eof(sock: &Socket, max_bytes: Option<usize>) -> Result<usize, Error> {
+    let mut recv_buf = [0u8; 256];
+    let mut total = 0;
 
-    Ok(())
+    loop {
+        // A chatty server cannot make the client read more than `max_bytes`.
+        let len = max_bytes.map_or(recv_buf.len(), |max| recv_buf.len().min(max - total));
+        if len == 0 {
+            return Ok(total);
+        }
+
+        // SAFETY: `recv_buf` is initialized and `len` does not exceed its size.
+        let bytes = unsafe {
+            trace::recv(
+                sock.as_raw(),
+                recv_buf.as_mut_ptr() as *mut libc::c_void,
+                len,
+                0,
+            )
+        };
+        match bytes {
+            -1 => {
+                let err = io::Error::last_os_error();
+                if err.kind() != io::ErrorKind::Interrupted {
+                    return Err(Error::Recv(err));
+                }
+            }
+            0 => return Ok(total),
+            _ => {
+                io::stdout()
+                    .write_all(&recv_buf[..bytes as usize])
+                    .expect("message to be written to stdout");
+                total += bytes as usize;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::socketpair;
+
+    #[test]
+    fn drain_stops_at_max_bytes() {
+        let (client, server) = socketpair();
+        stream_io::sendall(server.as_raw(), &[b'x'; 5000]).unwrap();
+
+        assert_eq!(drain_until_eof(&client, Some(1000)).unwrap(), 1000);
+    }
+
+    #[test]
+    fn drain_reads_everything_until_eof() {
+        let (client, server) = socketpair();
+        stream_io::sendall(server.as_raw(), &[b'x'; 5000]).unwrap();
+        drop(server);
+
+        assert_eq!(drain_until_eof(&client, None).unwrap(), 5000);
+        assert_eq!(drain_until_eof(&client, Some(5000)).unwrap(), 0);
+    }
+
+    #[test]
+    fn a_reply_that_fills_the_buffer_is_still_terminated() {
+        let (client, server) = socketpair();
+        stream_io::sendall(server.as_raw(), &[b'x'; MAXDATASIZE]).unwrap();
+
+        let recv_buf = recv_once(&client).unwrap();
+
+        assert_eq!(recv_buf.len(), MAXDATASIZE + 1);
+        assert_eq!(recv_buf[..MAXDATASIZE], [b'x'; MAXDATASIZE]);
+        assert_eq!(recv_buf[MAXDATASIZE], b'\0');
+    }
 }
@@ -1,5 +1,5 @@
 mod client;
 mod server;
 
-pub use client::client;
-pub use server::server;
+pub use client::{ReadMode, client};
+pub use server::{ServerOptions, server};
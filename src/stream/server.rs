@@ -1,193 +1,158 @@
-use std::{
-    error,
-    ffi::{CStr, CString},
-    fmt, io, mem,
-    net::{IpAddr, Ipv4Addr, Ipv6Addr},
-    ptr,
+use std::{error, fmt, io, time::Duration};
+
+use crate::{
+    listener::{self, BindRetry},
+    server_builder::{self, Server},
+    signal,
+    socket_fd::Socket,
+    sockopt, stream_io,
 };
 
 #[derive(Debug)]
 pub enum Error {
-    Getaddrinfo(String),
-    Socket(io::Error),
+    Server(server_builder::Error),
+    Send(stream_io::SendallError),
+    SendTimedOut { timeout: Duration, sent: usize },
     Setsockopt(io::Error),
-    Bind(io::Error),
-    Listen(io::Error),
-    Accept(io::Error),
-    InvalidAddrFamily(i32),
-    Send(io::Error),
-    Close(io::Error),
+    Getsockopt(&'static str, io::Error),
+    Signal(io::Error),
 }
 
 impl fmt::Display for Error {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
-            Error::Getaddrinfo(err) => write!(f, "getaddrinfo error: {}", err),
-            Error::Socket(err) => write!(f, "socket error: {}", err),
-            Error::Setsockopt(err) => write!(f, "setsockopt error: {}", err),
-            Error::Bind(err) => write!(f, "bind error: {}", err),
-            Error::Listen(err) => write!(f, "listen error: {}", err),
-            Error::Accept(err) => write!(f, "accept error: {}", err),
+            Error::Server(err) => write!(f, "server error: {}", err),
             Error::Send(err) => write!(f, "send error: {}", err),
-            Error::InvalidAddrFamily(af) => {
-                write!(f, "accept error: invalid address family {}", af)
-            }
-            Error::Close(err) => write!(f, "close error: {}", err),
+            Error::SendTimedOut { timeout, sent } => write!(
+                f,
+                "send timed out after {:?} with {} bytes sent, the peer is not reading (SO_SNDTIMEO)",
+                timeout, sent
+            ),
+            Error::Setsockopt(err) => write!(f, "setsockopt SO_SNDTIMEO error: {}", err),
+            Error::Getsockopt(name, err) => write!(f, "getsockopt {} error: {}", name, err),
+            Error::Signal(err) => write!(f, "sigaction error: {}", err),
         }
     }
 }
 
 impl error::Error for Error {}
 
-// EXAMPLE: A simple stream server that sends "Hello world!" to a connected peer.
+impl From<server_builder::Error> for Error {
+    fn from(value: server_builder::Error) -> Self {
+        Self::Server(value)
+    }
+}
+
+const HELLO: &[u8] = b"Hello world!\n";
+
+/// The socket options of `server()`, the defaults leave all of them to the system.
+///
+/// An inherited listener (`listen_fd`) is already set up, so the listener options are ignored with it,
+/// and nothing is read back.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct ServerOptions {
+    /// Set `TCP_MAXSEG` on the listener before `bind()`, the accepted connections inherit it.
+    /// The value read back from each connection is the MSS that is actually used: the smaller of the two sides'
+    /// values minus the room for TCP options (e.g. 12 bytes for timestamps), further clamped to the path MTU.
+    pub max_segment: Option<i32>,
+    /// Set `SO_SNDTIMEO` on each accepted connection, so that a send to a peer that does not read
+    /// (and lets its receive buffer fill up) gives up after this long instead of blocking forever.
+    pub write_timeout: Option<Duration>,
+    /// Set `TCP_DEFER_ACCEPT` on the listener (Linux only), in seconds, and read back the value the kernel settled on.
+    ///
+    /// A connection is then only handed to `accept()` once the peer sends something, or once the kernel gives up
+    /// waiting for it (after about this many seconds, the connection is either accepted or dropped then,
+    /// depending on the kernel version). The client of this server never sends anything before it reads
+    /// the message, so it is accepted late. The option is meant for protocols where the client speaks first
+    /// (e.g. HTTP). The listener does not become readable until then either, so a `--deadline` may expire
+    /// while silent peers are already connected.
+    pub defer_accept: Option<i32>,
+    /// Stop on SIGINT (Ctrl-C) or SIGTERM, and spend at most this long turning away the connections
+    /// that are queued but not accepted yet, see `listener::drain_pending()`.
+    pub drain_on_exit: Option<Duration>,
+}
+
+// EXAMPLE: A simple stream server that sends "Hello world!" (or `message` if it is set) to a connected peer.
 // This example is a more complete version of `send()` syscall example.
 // MANPAGE:
 // man 2 send (Linux)
 // man 3 send (POSIX)
 // man errno
-pub fn server() -> Result<(), Error> {
-    let node = ptr::null();
-    let port = CString::from(c"3490");
-
-    // SAFETY: All zero hints is a valid initialization.
-    // Required fields are set later on.
-    let mut hints: libc::addrinfo = unsafe { mem::zeroed() };
-    hints.ai_family = libc::AF_UNSPEC;
-    hints.ai_socktype = libc::SOCK_STREAM;
-
-    let mut gai_res_ptr: *mut libc::addrinfo = ptr::null_mut();
-
-    // SAFETY: There is no uninitialized memory access. `getaddrinfo()` is safe to call.
-    let ecode = unsafe { libc::getaddrinfo(node, port.as_ptr(), &hints, &mut gai_res_ptr) };
-    match ecode {
-        0 => Ok(()),
-        _ => {
-            // SAFETY: `gai_strerror` is valid to call on a failed `getaddrinfo()` call.
-            let err = unsafe { CStr::from_ptr(libc::gai_strerror(ecode)).to_string_lossy() };
-            Err(Error::Getaddrinfo(err.into_owned()))
-        }
-    }?;
-
-    let mut sock_fd = -1;
-    while !gai_res_ptr.is_null() {
-        let gai_res = unsafe { *gai_res_ptr };
-        let next_res_ptr = gai_res.ai_next;
-
-        let sock = unsafe { libc::socket(gai_res.ai_family, gai_res.ai_socktype, 0) };
-        if sock == -1 {
-            if next_res_ptr.is_null() {
-                return Err(Error::Socket(io::Error::last_os_error()));
-            } else {
-                gai_res_ptr = next_res_ptr;
-                continue;
-            }
-        }
+//
+// The server listens on `port` on all local addresses, or accepts on the inherited `listen_fd` if it is set.
+// `message` is sent as is, and `emfile_guard` turns away the connections that arrive once the server runs out
+// of fds (see `EmfileGuard`). See `ServerOptions` for the rest.
+pub fn server(
+    port: u16,
+    retry: &BindRetry,
+    listen_fd: Option<i32>,
+    emfile_guard: bool,
+    opts: &ServerOptions,
+    message: Option<&[u8]>,
+) -> Result<(), Error> {
+    let msg = message.unwrap_or(HELLO);
+
+    if opts.drain_on_exit.is_some() {
+        signal::install_shutdown_handler().map_err(Error::Signal)?;
+    }
 
-        let reuse_sock = 1;
-        let size = mem::size_of_val(&reuse_sock);
-        let ecode = unsafe {
-            libc::setsockopt(
-                sock,
-                libc::SOL_SOCKET,
-                libc::SO_REUSEADDR,
-                &raw const reuse_sock as _,
-                size as libc::socklen_t,
-            )
-        };
-        if ecode == -1 {
-            return Err(Error::Setsockopt(io::Error::last_os_error()));
+    let listener = Server::new()
+        .bind(None, port)
+        .retry(*retry)
+        .listen_fd(listen_fd)
+        .emfile_guard(emfile_guard)
+        .max_segment(opts.max_segment)
+        .defer_accept(opts.defer_accept)
+        .drain_on_exit(opts.drain_on_exit)
+        .listen()?;
+
+    #[cfg(target_os = "linux")]
+    if opts.defer_accept.is_some() && listen_fd.is_none() {
+        match listener::defer_accept(listener.as_raw()) {
+            Ok(secs) => println!("server: TCP_DEFER_ACCEPT = {}", secs),
+            Err(err) => eprintln!("server: {}", Error::Getsockopt("TCP_DEFER_ACCEPT", err)),
         }
+    }
+
+    println!("server: waiting for connections...");
 
-        let ecode = unsafe { libc::bind(sock, gai_res.ai_addr, gai_res.ai_addrlen) };
-        if ecode == -1 {
-            if next_res_ptr.is_null() {
-                return Err(Error::Bind(io::Error::last_os_error()));
-            } else {
-                gai_res_ptr = next_res_ptr;
-                continue;
+    listener.serve(|conn_sock, from_addr| {
+        println!("server: got connection from {}", from_addr);
+
+        if opts.max_segment.is_some() && listen_fd.is_none() {
+            match sockopt::get_int(conn_sock.as_raw(), libc::IPPROTO_TCP, libc::TCP_MAXSEG) {
+                Ok(mss) => println!("server: TCP_MAXSEG = {}", mss),
+                Err(err) => eprintln!("server: {}", Error::Getsockopt("TCP_MAXSEG", err)),
             }
         }
 
-        sock_fd = sock;
-        break;
-    }
-
-    // SAFETY: `gai_res` is no longer needed and its pointer points to a valid `addrinfo` struct at this point. It can be freed safely.
-    unsafe {
-        libc::freeaddrinfo(gai_res_ptr);
-    }
+        if let Err(err) = send_message(&conn_sock, msg, opts.write_timeout) {
+            eprintln!("server: {}", err);
+        }
+    })?;
 
-    // SAFETY: `listen()` is safe to use on a valid `sock_fd`.
-    let ecode = unsafe { libc::listen(sock_fd, 10) };
-    match ecode {
-        -1 => Err(Error::Listen(io::Error::last_os_error())),
-        _ => Ok(()),
-    }?;
+    Ok(())
+}
 
-    println!("server: waiting for connections...");
+// A single `send()` may transmit only a part of the message, `stream_io::sendall()` sends the rest as well
+// and retries on `EINTR`. With `write_timeout`, a send that times out is told apart from the other errors.
+fn send_message(
+    conn_sock: &Socket,
+    msg: &[u8],
+    write_timeout: Option<Duration>,
+) -> Result<(), Error> {
+    if let Some(timeout) = write_timeout {
+        sockopt::set_timeout(conn_sock.as_raw(), libc::SO_SNDTIMEO, timeout)
+            .map_err(Error::Setsockopt)?;
+    }
 
-    loop {
-        // SAFETY:
-        // 1 - All zeroed `sockaddr_storage` is a valid initialization.
-        // 2 - `sock_fd` a valid socket fd.
-        let (conn_sock_fd, sockaddr) = unsafe {
-            let mut sockaddr: libc::sockaddr_storage = mem::zeroed();
-            let mut len = mem::size_of_val(&sockaddr);
-
-            let conn_sock_fd = libc::accept(
-                sock_fd,
-                &raw mut sockaddr as *mut libc::sockaddr,
-                &raw mut len as *mut _,
-            );
-
-            (conn_sock_fd, sockaddr)
-        };
-        match conn_sock_fd {
-            -1 => Err(Error::Accept(io::Error::last_os_error())),
-            _ => Ok(()),
-        }?;
-
-        // SAFETY:
-        // 1 - `sockaddr_storage` pointer points to a memory that is initialized by a successful `accept()` call.
-        // 2 - raw `sockaddr_storage` pointer is casted to INET or INET6 based on the address family filled by `accept()`.
-        let from_addr = unsafe {
-            match sockaddr.ss_family as i32 {
-                libc::AF_INET => {
-                    let sockaddr_in = *(&raw const sockaddr as *const libc::sockaddr_in);
-
-                    let bits = u32::from_be(sockaddr_in.sin_addr.s_addr);
-                    Ok(IpAddr::V4(Ipv4Addr::from_bits(bits)))
-                }
-                libc::AF_INET6 => {
-                    let sockaddr_in6 = *(&raw const sockaddr as *const libc::sockaddr_in6);
-
-                    let bits = u128::from_be_bytes(sockaddr_in6.sin6_addr.s6_addr);
-                    Ok(IpAddr::V6(Ipv6Addr::from_bits(bits)))
-                }
-                af => Err(Error::InvalidAddrFamily(af)),
-            }
-        }?;
-        println!("server: got connection from {}", from_addr);
+    stream_io::sendall(conn_sock.as_raw(), msg).map_err(|err| match write_timeout {
+        Some(timeout) if err.kind() == io::ErrorKind::WouldBlock => Error::SendTimedOut {
+            timeout,
+            sent: err.sent,
+        },
+        _ => Error::Send(err),
+    })?;
 
-        let msg = b"Hello world!\n";
-        let len = msg.len();
-
-        // SAFETY:
-        // 1 - `conn_sock_fd` is a valid sock fd for peer communication.
-        // 2 - The message and its len are initialized as desired.
-        let bytes =
-            unsafe { libc::send(conn_sock_fd, msg.as_ptr() as *const libc::c_void, len, 0) };
-        match bytes {
-            -1 => Err(Error::Send(io::Error::last_os_error())),
-            _ => Ok(()),
-        }?;
-
-        // SAFETY:
-        // `conn_sock_fd` is a valid sock fd for peer communication.
-        let ecode = unsafe { libc::close(conn_sock_fd) };
-        match ecode {
-            -1 => Err(Error::Close(io::Error::last_os_error())),
-            _ => Ok(()),
-        }?;
-    }
+    Ok(())
 }
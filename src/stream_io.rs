@@ -0,0 +1,284 @@
+use std::{error, fmt, io};
+
+use crate::trace;
+
+/// The error of `sendall()`: the failed `send()`, and the number of bytes that were sent before it.
+#[derive(Debug)]
+pub struct SendallError {
+    /// The bytes of the buffer that made it out before the error, the rest was not sent.
+    pub sent: usize,
+    pub err: io::Error,
+}
+
+impl SendallError {
+    /// The kind of the `send()` error, e.g. `WouldBlock` for a send timeout.
+    pub fn kind(&self) -> io::ErrorKind {
+        self.err.kind()
+    }
+}
+
+impl fmt::Display for SendallError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} (after sending {} bytes)", self.err, self.sent)
+    }
+}
+
+impl error::Error for SendallError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        Some(&self.err)
+    }
+}
+
+// The kind is kept, and the sent count stays in the message.
+impl From<SendallError> for io::Error {
+    fn from(value: SendallError) -> Self {
+        io::Error::new(value.kind(), value)
+    }
+}
+
+/// Sends the whole `buf` through `fd`, calling `send()` as many times as needed.
+///
+/// A single `send()` call may transmit less than requested, so the remaining bytes are
+/// sent by advancing an offset until nothing is left. `EINTR` is retried.
+///
+/// Returns the number of bytes sent, which is always `buf.len()` on success.
+/// On failure, the error carries the number of bytes that were sent before it, like Beej's `sendall()` does with `*len`.
+pub fn sendall(fd: i32, buf: &[u8]) -> Result<usize, SendallError> {
+    let mut total = 0;
+
+    while total < buf.len() {
+        let left = &buf[total..];
+
+        // SAFETY: `left` is a valid, initialized slice for the entire `send()` call.
+        let sbytes =
+            unsafe { trace::send(fd, left.as_ptr() as *const libc::c_void, left.len(), 0) };
+        if sbytes == -1 {
+            let err = io::Error::last_os_error();
+            if err.kind() == io::ErrorKind::Interrupted {
+                continue;
+            }
+
+            return Err(SendallError { sent: total, err });
+        }
+
+        total += sbytes as usize;
+    }
+
+    Ok(total)
+}
+
+/// Whether `err` means that the peer aborted the connection instead of closing it.
+///
+/// A peer that closes its socket with unread data, or with `SO_LINGER` set to zero, sends a `RST`
+/// instead of a `FIN`. The next `recv()` then fails with `ECONNRESET` instead of returning 0.
+/// For a server, this is just another way for a client to leave, not an unexpected error.
+pub fn is_reset(err: &io::Error) -> bool {
+    err.raw_os_error() == Some(libc::ECONNRESET)
+}
+
+/// Receives from `fd` until `delim` is seen and returns the bytes before it.
+///
+/// `recv()` does not know anything about message boundaries, it may return half a message or
+/// multiple messages at once. Therefore, the bytes that come after `delim` are kept in `pending`
+/// and they are consumed first on the next call. The same `pending` buffer must be passed on every
+/// call for the same `fd`.
+///
+/// Returns `Ok(None)` when the peer closes the connection before sending `delim`.
+pub fn recv_until(fd: i32, pending: &mut Vec<u8>, delim: u8) -> io::Result<Option<Vec<u8>>> {
+    let mut recv_buf = [0u8; 256];
+
+    loop {
+        if let Some(pos) = pending.iter().position(|b| *b == delim) {
+            let mut frame: Vec<u8> = pending.drain(..=pos).collect();
+            frame.pop();
+            return Ok(Some(frame));
+        }
+
+        // SAFETY: `recv_buf` is initialized, making `recv()` safe to call.
+        let rbytes = unsafe {
+            trace::recv(
+                fd,
+                recv_buf.as_mut_ptr() as *mut libc::c_void,
+                recv_buf.len(),
+                0,
+            )
+        };
+        match rbytes {
+            -1 => {
+                let err = io::Error::last_os_error();
+                if err.kind() != io::ErrorKind::Interrupted {
+                    return Err(err);
+                }
+            }
+            0 => return Ok(None),
+            n => pending.extend_from_slice(&recv_buf[..n as usize]),
+        }
+    }
+}
+
+/// Receives from `fd` until `buf` is full, calling `recv()` as many times as needed.
+///
+/// `recv()` returns whatever has arrived so far, which may be less than requested.
+/// `EINTR` is retried.
+///
+/// Returns the number of bytes received, which is less than `buf.len()` only if the peer closes the connection first.
+pub fn recv_exact(fd: i32, buf: &mut [u8]) -> io::Result<usize> {
+    let mut total = 0;
+
+    while total < buf.len() {
+        let left = &mut buf[total..];
+
+        // SAFETY: `left` is a valid, initialized slice for the entire `recv()` call.
+        let rbytes =
+            unsafe { trace::recv(fd, left.as_mut_ptr() as *mut libc::c_void, left.len(), 0) };
+        match rbytes {
+            -1 => {
+                let err = io::Error::last_os_error();
+                if err.kind() != io::ErrorKind::Interrupted {
+                    return Err(err);
+                }
+            }
+            0 => break,
+            n => total += n as usize,
+        }
+    }
+
+    Ok(total)
+}
+
+/// Receives from `fd` until `buf` is full with a single `recv(MSG_WAITALL)` call.
+///
+/// Unlike `recv_exact()`, the kernel does the waiting. However, `MSG_WAITALL` is not a guarantee:
+/// the call still returns early if the peer closes the connection, if an error occurs,
+/// or if a signal is caught after some bytes are received. Hence, the caller must check the result anyway.
+///
+/// Returns the number of bytes received.
+pub fn recv_waitall(fd: i32, buf: &mut [u8]) -> io::Result<usize> {
+    // SAFETY: `buf` is a valid, initialized slice for the entire `recv()` call.
+    let rbytes = unsafe {
+        trace::recv(
+            fd,
+            buf.as_mut_ptr() as *mut libc::c_void,
+            buf.len(),
+            libc::MSG_WAITALL,
+        )
+    };
+    match rbytes {
+        -1 => Err(io::Error::last_os_error()),
+        n => Ok(n as usize),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{thread, time::Duration};
+
+    use super::*;
+    use crate::{fd, socket_fd::Socket, testutil::socketpair};
+
+    // Sends `chunks` pieces of `msg` with a pause in between, so that they arrive in separate `recv()` calls.
+    // The socket is closed afterwards.
+    fn send_in_chunks(sock: Socket, msg: Vec<u8>, chunks: usize) -> thread::JoinHandle<()> {
+        thread::spawn(move || {
+            for chunk in msg.chunks(msg.len().div_ceil(chunks)) {
+                sendall(sock.as_raw(), chunk).unwrap();
+                thread::sleep(Duration::from_millis(20));
+            }
+        })
+    }
+
+    #[test]
+    fn recv_until_splits_the_lines_of_a_single_recv() {
+        let (a, b) = socketpair();
+        sendall(a.as_raw(), b"one\ntwo\nthree").unwrap();
+        drop(a);
+
+        let mut pending = Vec::new();
+        assert_eq!(
+            recv_until(b.as_raw(), &mut pending, b'\n')
+                .unwrap()
+                .unwrap(),
+            b"one"
+        );
+        assert_eq!(
+            recv_until(b.as_raw(), &mut pending, b'\n')
+                .unwrap()
+                .unwrap(),
+            b"two"
+        );
+        // The last line is not terminated, it is left in `pending` at EOF.
+        assert_eq!(recv_until(b.as_raw(), &mut pending, b'\n').unwrap(), None);
+        assert_eq!(pending, b"three");
+    }
+
+    #[test]
+    fn recv_until_joins_a_line_split_across_recvs() {
+        let (a, b) = socketpair();
+        let sender = send_in_chunks(a, b"hello world\n".to_vec(), 4);
+
+        let mut pending = Vec::new();
+        assert_eq!(
+            recv_until(b.as_raw(), &mut pending, b'\n')
+                .unwrap()
+                .unwrap(),
+            b"hello world"
+        );
+        assert!(pending.is_empty());
+        sender.join().unwrap();
+    }
+
+    type RecvFn = fn(i32, &mut [u8]) -> io::Result<usize>;
+
+    #[test]
+    fn waitall_and_recv_exact_both_fill_the_buffer() {
+        let msg: Vec<u8> = (0..=255).cycle().take(300).collect();
+
+        for recv in [recv_exact as RecvFn, recv_waitall] {
+            let (a, b) = socketpair();
+            let sender = send_in_chunks(a, msg.clone(), 3);
+
+            let mut buf = [0u8; 300];
+            assert_eq!(recv(b.as_raw(), &mut buf).unwrap(), 300);
+            assert_eq!(buf[..], msg[..]);
+            sender.join().unwrap();
+        }
+    }
+
+    #[test]
+    fn waitall_and_recv_exact_both_return_early_on_eof() {
+        for recv in [recv_exact as RecvFn, recv_waitall] {
+            let (a, b) = socketpair();
+            let sender = send_in_chunks(a, vec![b'x'; 100], 2);
+
+            let mut buf = [0u8; 300];
+            assert_eq!(recv(b.as_raw(), &mut buf).unwrap(), 100);
+            sender.join().unwrap();
+        }
+    }
+
+    #[test]
+    fn sendall_sends_everything() {
+        let (a, b) = socketpair();
+        let msg = b"hello world!\n";
+
+        assert_eq!(sendall(a.as_raw(), msg).unwrap(), msg.len());
+
+        let mut buf = [0u8; 13];
+        assert_eq!(recv_exact(b.as_raw(), &mut buf).unwrap(), msg.len());
+        assert_eq!(&buf, msg);
+    }
+
+    #[test]
+    fn sendall_reports_the_partial_count() {
+        let (a, _b) = socketpair();
+        fd::set_nonblocking(a.as_raw()).unwrap();
+
+        // Nobody reads on the other end, so the send buffer fills up long before this is sent.
+        let buf = vec![0u8; 16 * 1024 * 1024];
+        let err = sendall(a.as_raw(), &buf).unwrap_err();
+
+        assert_eq!(err.kind(), io::ErrorKind::WouldBlock);
+        assert!(err.sent > 0 && err.sent < buf.len(), "sent {}", err.sent);
+        assert!(err.to_string().contains(&err.sent.to_string()));
+    }
+}
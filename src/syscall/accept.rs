@@ -1,7 +1,15 @@
 use std::{
     error,
     ffi::{CStr, CString},
-    fmt, io, mem, ptr,
+    fmt, io, mem,
+    net::SocketAddr,
+};
+
+use crate::{
+    addrinfo::{AddrInfoList, Hints},
+    deadline, sockaddr,
+    socket_fd::Socket,
+    trace,
 };
 
 #[derive(Debug)]
@@ -11,6 +19,7 @@ pub enum Error {
     Bind(i32, io::Error),
     Listen(i32, io::Error),
     Accept(io::Error),
+    InvalidAddrFamily(i32),
 }
 
 impl fmt::Display for Error {
@@ -23,6 +32,7 @@ impl fmt::Display for Error {
                 write!(f, "listen error on sock fd {}: {}", sock_fd, err)
             }
             Error::Accept(err) => write!(f, "accept error: {}", err),
+            Error::InvalidAddrFamily(af) => write!(f, "accept error: invalid addr family {}", af),
         }
     }
 }
@@ -33,37 +43,33 @@ impl error::Error for Error {}
 // MANPAGES:
 // man 2 accept (Linux)
 // man 3 accept (POSIX)
-pub fn accept() -> Result<i32, Error> {
-    let node = ptr::null() as *const libc::c_char;
+//
+// Returns the accepted connection along with the address of the peer.
+pub fn accept() -> Result<(Socket, SocketAddr), Error> {
     let port = CString::from(c"3490");
 
-    // SAFETY: hints is initialized as empty, but the required fields are set later on.
-    let mut hints: libc::addrinfo = unsafe { mem::zeroed() };
-    hints.ai_family = libc::AF_UNSPEC;
-    hints.ai_socktype = libc::SOCK_STREAM;
+    let sock = listen_on(&port)?;
 
-    let mut res_ptr: *mut libc::addrinfo = ptr::null_mut();
+    println!("listening on port {}", port.to_string_lossy());
 
-    // SAFETY:
-    // All the required vars are initialized for getaddrinfo().
-    // gai_stderror() is used for error cases only.
-    unsafe {
-        let s = libc::getaddrinfo(node, port.as_ptr(), &hints, &mut res_ptr);
-        match s {
-            0 => Ok(()),
-            _ => {
-                let err = CStr::from_ptr(libc::gai_strerror(s)).to_string_lossy();
-                Err(Error::Getaddrinfo(err.into_owned()))
-            }
-        }
-    }?;
+    accept_from(&sock)
+}
+
+// Creates the listener socket on `port` of the loopback address.
+fn listen_on(port: &CStr) -> Result<Socket, Error> {
+    let hints = Hints::new()
+        .family(libc::AF_UNSPEC)
+        .socktype(libc::SOCK_STREAM)
+        .build();
+
+    // The list is freed by `freeaddrinfo()` when `res_list` goes out of scope.
+    let res_list = AddrInfoList::new(None, Some(port), &hints).map_err(Error::Getaddrinfo)?;
+    let res = res_list.first();
 
-    // SAFETY: Since we are trying to get our loopback IP address via `getaddrinfo()`, we know that `res_ptr` points to an initialized memory, making `socket()` safe to use.
+    // SAFETY: Since we are trying to get our loopback IP address via `getaddrinfo()`, we know that `res` points to an initialized memory, making `socket()` safe to use.
     // Any potential `socket()` error is checked by reading `errno` instantly after the `socket()` call. This ensures that `sock_fd` contains the fd of a successfully created socket.
     let sock_fd = unsafe {
-        let res = *res_ptr;
-
-        let fd = libc::socket(res.ai_family, res.ai_socktype, 0);
+        let fd = trace::socket(res.ai_family, res.ai_socktype, 0);
         match fd {
             -1 => {
                 let err = io::Error::last_os_error();
@@ -72,26 +78,21 @@ pub fn accept() -> Result<i32, Error> {
             fd => Ok(fd),
         }
     }?;
+    // The listener socket is closed when this function returns, on the error paths as well.
+    let sock = Socket::from_raw(sock_fd);
 
-    // SAFETY: Due to the points above, `res_ptr` and `sock_fd` are safe to use.
+    // SAFETY: Due to the points above, `res` and `sock_fd` are safe to use.
     // Any potential `bind()` error is checked by reading `errno` instantly after the `bind()` call.
     // This ensures that any errors that may happen in `bind()` are caught.
-    //
-    // Since `res_ptr` points to a valid initialized memory and will not be used after `bind()`, it is safe to free it upon a successful `bind()` call.
     unsafe {
-        let res = *res_ptr;
-        let s = libc::bind(sock_fd, res.ai_addr, res.ai_addrlen);
-        let res = match s {
+        let s = trace::bind(sock_fd, res.ai_addr, res.ai_addrlen);
+        match s {
             -1 => {
                 let err = io::Error::last_os_error();
                 Err(Error::Bind(sock_fd, err))
             }
             _ => Ok(()),
-        };
-
-        libc::freeaddrinfo(res_ptr);
-
-        res
+        }
     }?;
 
     // SAFETY: The `sock_fd` used for `listen()` is guaranteed to be valid due to the points above.
@@ -99,7 +100,7 @@ pub fn accept() -> Result<i32, Error> {
     unsafe {
         const BACKLOG: i32 = 10;
 
-        let s = libc::listen(sock_fd, BACKLOG);
+        let s = trace::listen(sock_fd, BACKLOG);
         match s {
             -1 => {
                 let err = io::Error::last_os_error();
@@ -109,19 +110,25 @@ pub fn accept() -> Result<i32, Error> {
         }
     }?;
 
-    println!("listening on port {}", port.to_string_lossy());
+    Ok(sock)
+}
 
-    // SAFETY: The uninitialized memory of `*addr_ptr` is initialized via `accept()`. This memory will hold the object regarding the accepted connection.
+// Accepts a single connection on the listener `sock`.
+fn accept_from(sock: &Socket) -> Result<(Socket, SocketAddr), Error> {
+    // Without a `--deadline`, this returns right away and `accept()` blocks until a peer connects.
+    deadline::wait_readable(sock.as_raw()).map_err(Error::Accept)?;
+
+    // SAFETY: All zero `sockaddr_storage` is a valid initialization.
+    // `addr` is filled by `accept()` with the address of the peer, and `addr_len` holds its size.
     // Any potential `accept()` error is checked by reading `errno` instantly after the `accept()` call.
     // The returned sock_fd is a valid fd created by a successful `accept()` call to interact with the accepted connection.
+    let mut addr: libc::sockaddr_storage = unsafe { mem::zeroed() };
+    let mut addr_len = mem::size_of_val(&addr) as libc::socklen_t;
     let conn_sock_fd = unsafe {
-        let addr_ptr: *mut libc::sockaddr_storage = ptr::null_mut();
-        let addr_size = mem::size_of::<libc::sockaddr_storage>();
-
-        let conn_sock_fd = libc::accept(
-            sock_fd,
-            addr_ptr as *mut libc::sockaddr,
-            addr_size as *mut u32,
+        let conn_sock_fd = trace::accept(
+            sock.as_raw(),
+            &raw mut addr as *mut libc::sockaddr,
+            &raw mut addr_len,
         );
         match conn_sock_fd {
             -1 => {
@@ -132,7 +139,34 @@ pub fn accept() -> Result<i32, Error> {
         }
     }?;
 
-    println!("sock fd of accepted connection: {}", conn_sock_fd);
+    let conn_sock = Socket::from_raw(conn_sock_fd);
+
+    let peer_addr =
+        sockaddr::to_socket_addr(&addr).ok_or(Error::InvalidAddrFamily(addr.ss_family as i32))?;
 
-    Ok(conn_sock_fd)
+    println!(
+        "sock fd of accepted connection: {}, peer: {}",
+        conn_sock.as_raw(),
+        peer_addr
+    );
+
+    Ok((conn_sock, peer_addr))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{inet, listener};
+
+    #[test]
+    fn returns_the_address_of_the_peer() {
+        let sock = listen_on(c"0").unwrap();
+        let addr = listener::local_addr(sock.as_raw()).unwrap();
+
+        // The connection completes in the backlog, before it is accepted.
+        let client = Socket::from_raw(inet::connect_to(&addr).unwrap());
+        let (_conn, peer_addr) = accept_from(&sock).unwrap();
+
+        assert_eq!(peer_addr, listener::local_addr(client.as_raw()).unwrap());
+    }
 }
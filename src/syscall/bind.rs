@@ -1,7 +1,8 @@
-use std::{
-    error,
-    ffi::{CStr, CString},
-    fmt, io, mem, ptr,
+use std::{error, ffi::CString, fmt, io, mem};
+
+use crate::{
+    addrinfo::{AddrInfoList, Hints},
+    trace,
 };
 
 #[derive(Debug)]
@@ -30,51 +31,35 @@ impl error::Error for Error {}
 // MANPAGE: man 3 bind
 pub fn bind() -> Result<(), Error> {
     // Preparing the getaddrinfo call.
-    let node_ptr = ptr::null();
-
     let service = CString::new("3490").unwrap();
-    let service_ptr = service.as_ptr();
 
-    // SAFETY: hints is initialized as empty, but the required fields are set later on.
-    let mut hints: libc::addrinfo = unsafe { mem::zeroed() };
-    hints.ai_family = libc::AF_UNSPEC;
-    hints.ai_socktype = libc::SOCK_STREAM;
-    hints.ai_flags = libc::AI_PASSIVE;
+    let hints = Hints::new()
+        .family(libc::AF_UNSPEC)
+        .socktype(libc::SOCK_STREAM)
+        .passive(true)
+        .build();
 
-    let mut res_ptr = ptr::null_mut();
+    // The list is freed by `freeaddrinfo()` when `res_list` goes out of scope, on the error paths as well.
+    let res_list = AddrInfoList::new(None, Some(&service), &hints).map_err(Error::Getaddrinfo)?;
+    let res = res_list.first();
 
     // SAFETY:
-    // All the required vars are initialized for getaddrinfo().
-    // gai_stderror() is used for error cases only.
+    // `res` is filled by a successful `getaddrinfo()` call.
     //
     // Having a one big unsafe block is just for showcase purposes.
     unsafe {
-        let s = libc::getaddrinfo(node_ptr, service_ptr, &hints, &mut res_ptr);
-        if s != 0 {
-            let err = libc::gai_strerror(s);
-            let c_err = CStr::from_ptr(err).to_string_lossy();
-            return Err(Error::Getaddrinfo(c_err.into_owned()));
-        }
-
-        // SAFETY: `res_ptr` is initialized upon a successful getaddrinfo() call.
-        // Therefore we can guarantee that there is atleast one addrinfo that `res_ptr` points to, making deref safe in the usages below.
-        let res = *res_ptr;
-
-        let sock_fd = libc::socket(res.ai_family, res.ai_socktype, 0);
+        let sock_fd = trace::socket(res.ai_family, res.ai_socktype, 0);
         if sock_fd == -1 {
             let err = io::Error::last_os_error();
             return Err(Error::Socket(err));
         }
 
         // SAFETY: `bind()` is called on a valid `sock_fd` upon a successful `socket()` call.
-        let s = libc::bind(sock_fd, res.ai_addr, res.ai_addrlen);
+        let s = trace::bind(sock_fd, res.ai_addr, res.ai_addrlen);
         if s != 0 {
             let err = io::Error::last_os_error();
             return Err(Error::Bind(sock_fd, err));
         }
-
-        // SAFETY: `res_ptr` will not be used after this call, therefore it is safe to free it.
-        libc::freeaddrinfo(res_ptr);
     }
 
     Ok(())
@@ -89,36 +74,24 @@ pub fn bind() -> Result<(), Error> {
 // - man 7 socket
 pub fn reuse_port() -> Result<(), Error> {
     // Preparing the getaddrinfo call.
-    let node_ptr = ptr::null();
-
     let service = CString::new("3490").unwrap();
-    let service_ptr = service.as_ptr();
 
-    // SAFETY: hints is initialized as empty, but the required fields are set later on.
-    let mut hints: libc::addrinfo = unsafe { mem::zeroed() };
-    hints.ai_family = libc::AF_UNSPEC;
-    hints.ai_flags = libc::AI_PASSIVE;
-    hints.ai_socktype = libc::SOCK_STREAM;
+    let hints = Hints::new()
+        .family(libc::AF_UNSPEC)
+        .socktype(libc::SOCK_STREAM)
+        .passive(true)
+        .build();
 
-    let mut res_ptr = ptr::null_mut();
+    // The list is freed by `freeaddrinfo()` when `res_list` goes out of scope, on the error paths as well.
+    let res_list = AddrInfoList::new(None, Some(&service), &hints).map_err(Error::Getaddrinfo)?;
+    let res = res_list.first();
 
     // SAFETY:
-    // All the required vars are initialized for getaddrinfo().
-    // gai_stderror() is used for error cases only.
+    // `res` is filled by a successful `getaddrinfo()` call.
     //
     // Having a one big unsafe block is just for showcase purposes.
     unsafe {
-        let s = libc::getaddrinfo(node_ptr, service_ptr, &hints, &mut res_ptr);
-        if s != 0 {
-            let err = CStr::from_ptr(libc::gai_strerror(s)).to_string_lossy();
-            return Err(Error::Getaddrinfo(err.into_owned()));
-        }
-
-        // SAFETY: `res_ptr` is initialized upon a successful `getaddrinfo()` call.
-        // Therefore we can guarantee that there is atleast one addrinfo that `res_ptr` points to, making deref safe in the usages below.
-        let res = *res_ptr;
-
-        let sock_fd = libc::socket(res.ai_family, res.ai_socktype, 0);
+        let sock_fd = trace::socket(res.ai_family, res.ai_socktype, 0);
         if sock_fd == -1 {
             let err = io::Error::last_os_error();
             return Err(Error::Socket(err));
@@ -140,14 +113,11 @@ pub fn reuse_port() -> Result<(), Error> {
         }
 
         // SAFETY: `bind()` is called on a valid `sock_fd` upon a successful `socket()` call.
-        let s = libc::bind(sock_fd, res.ai_addr, res.ai_addrlen);
+        let s = trace::bind(sock_fd, res.ai_addr, res.ai_addrlen);
         if s != 0 {
             let err = io::Error::last_os_error();
             return Err(Error::Bind(sock_fd, err));
         }
-
-        // SAFETY: `res_ptr` will not be used after this call, therefore it is safe to free it.
-        libc::freeaddrinfo(res_ptr);
     }
 
     Ok(())
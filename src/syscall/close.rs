@@ -1,7 +1,8 @@
-use std::{
-    error,
-    ffi::{CStr, CString},
-    fmt, io, mem, ptr,
+use std::{error, ffi::CString, fmt, io};
+
+use crate::{
+    addrinfo::{AddrInfoList, Hints},
+    fd, trace,
 };
 
 #[derive(Debug)]
@@ -35,37 +36,22 @@ impl error::Error for Error {}
 // man 3 close (POSIX)
 // man errno
 pub fn close() -> Result<(), Error> {
-    let node = ptr::null();
     let port = CString::from(c"3490");
 
-    // SAFETY: hints is initialized as empty, but the required fields are set later on.
-    let mut hints: libc::addrinfo = unsafe { mem::zeroed() };
-    hints.ai_family = libc::AF_UNSPEC;
-    hints.ai_socktype = libc::SOCK_DGRAM;
+    let hints = Hints::new()
+        .family(libc::AF_UNSPEC)
+        .socktype(libc::SOCK_DGRAM)
+        .build();
 
-    let mut res_ptr: *mut libc::addrinfo = ptr::null_mut();
+    // The list is freed by `freeaddrinfo()` when `res_list` goes out of scope.
+    let res_list = AddrInfoList::new(None, Some(&port), &hints).map_err(Error::Getaddrinfo)?;
+    let res = res_list.first();
 
     // SAFETY:
-    // 1 - All the required vars are initialized for getaddrinfo().
-    // 2 - gai_stderror() is used for error cases only.
-    unsafe {
-        let ecode = libc::getaddrinfo(node, port.as_ptr(), &hints, &mut res_ptr);
-        match ecode {
-            0 => Ok(()),
-            _ => {
-                let err = CStr::from_ptr(libc::gai_strerror(ecode)).to_string_lossy();
-                Err(Error::Getaddrinfo(err.into_owned()))
-            }
-        }
-    }?;
-
-    // SAFETY:
-    // 1 - Since we are trying to get our loopback IP address via `getaddrinfo()`, we know that `res_ptr` points to an initialized memory, making `socket()` safe to use.
+    // 1 - Since we are trying to get our loopback IP address via `getaddrinfo()`, we know that `res` points to an initialized memory, making `socket()` safe to use.
     // 2 - Any potential `socket()` error is checked by reading `errno` instantly after the `socket()` call. This ensures that `sock_fd` contains the fd of a successfully created socket.
     let sock_fd = unsafe {
-        let res = *res_ptr;
-
-        let fd = libc::socket(res.ai_family, res.ai_socktype, 0);
+        let fd = trace::socket(res.ai_family, res.ai_socktype, 0);
         match fd {
             -1 => {
                 let err = io::Error::last_os_error();
@@ -75,33 +61,20 @@ pub fn close() -> Result<(), Error> {
         }
     }?;
 
-    // SAFETY:
-    // 1 - `sock_fd` points to a valid socket file descriptor created by `socket()`.
-    // 2 - Any potential `close()` error is checked by reading `errno` instantly after the `close()` call.
-    unsafe {
-        let ecode = libc::close(sock_fd);
-        match ecode {
-            -1 => {
-                let err = io::Error::last_os_error();
-                Err(Error::Close(sock_fd, err))
-            }
-            _ => Ok(()),
-        }
-    }?;
+    // NOTE: `close_fd()` is used instead of `libc::close()` to handle `EINTR` correctly.
+    // Check out `crate::fd::close_fd` to see why retrying `close()` is dangerous.
+    fd::close_fd(sock_fd).map_err(|err| Error::Close(sock_fd, err))?;
 
     let buf = b"will this message be able to go through?";
     let len = buf.len();
 
     // SAFETY:
     // 1 - `sock_fd` points to a valid socket file descriptor created by `socket()`.
-    // 2 - `res_ptr` points to a valid memory filled via `getaddrinfo()`.
+    // 2 - `res` points to a valid memory filled via `getaddrinfo()`.
     // 3 - The fixed message buf is initialized as a simple byte array.
     // 4 - Any potential `sendto()` error is checked by reading `errno` instantly after the `sendto()` call.
-    // 5 - Since `res_ptr` is not used after `sendto()`, it can be freed without any side effects.
     let sent_bytes = unsafe {
-        let res = *res_ptr;
-
-        let bytes = libc::sendto(
+        let bytes = trace::sendto(
             sock_fd,
             buf.as_ptr() as _,
             len,
@@ -110,17 +83,13 @@ pub fn close() -> Result<(), Error> {
             res.ai_addrlen,
         );
 
-        let send_res = match bytes {
+        match bytes {
             -1 => {
                 let err = io::Error::last_os_error();
                 Err(Error::Send(sock_fd, err))
             }
             _ => Ok(bytes),
-        };
-
-        libc::freeaddrinfo(res_ptr);
-
-        send_res
+        }
     }?;
 
     // We cannot reach the line below.
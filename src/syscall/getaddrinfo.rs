@@ -1,20 +1,22 @@
-use std::{
-    error,
-    ffi::{CStr, CString},
-    fmt, mem,
-    net::{IpAddr, Ipv4Addr, Ipv6Addr},
-    ptr,
+use std::{error, fmt, slice};
+
+use crate::{
+    addrinfo::Hints,
+    color, hexdump,
+    resolve::{self, resolve_with},
+    sockaddr,
+    socket_kind::{AddressFamily, SocketType},
 };
 
 #[derive(Debug)]
 pub enum Error {
-    Getaddrinfo(String),
+    Resolve(resolve::Error),
 }
 
 impl fmt::Display for Error {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
-            Error::Getaddrinfo(err) => write!(f, "getaddrinfo error: {}", err),
+            Error::Resolve(err) => write!(f, "resolve error: {}", err),
         }
     }
 }
@@ -24,65 +26,72 @@ impl error::Error for Error {}
 // EXAMPLE: Prints the IP address of the given host.
 // Section 5.1 - `getaddrinfo()` - Prepare to Launch!
 // MANPAGE: man 3 getaddrinfo
-pub fn getaddrinfo(host: &str) -> Result<(), Error> {
-    let node = CString::new(host).unwrap();
-    let node: *const libc::c_char = node.as_ptr();
-
-    let port: *const libc::c_char = ptr::null();
-
-    // SAFETY: hints is initialized as empty, but the required fields are set later on.
-    let mut hints: libc::addrinfo = unsafe { mem::zeroed() };
-    hints.ai_family = libc::AF_UNSPEC;
-    hints.ai_socktype = libc::SOCK_STREAM;
-
-    let mut res_ptr = ptr::null_mut();
-
-    // SAFETY: all the required vars are initialized for getaddrinfo().
-    // gai_stderror() is used for error cases only.
-    unsafe {
-        let s = libc::getaddrinfo(node, port, &hints, &mut res_ptr);
-        if s != 0 {
-            let err = CStr::from_ptr(libc::gai_strerror(s)).to_string_lossy();
-            return Err(Error::Getaddrinfo(err.into_owned()));
-        }
+//
+// The results are restricted to `family` and `socktype`. The list is resolved by `resolve::resolve_with()`,
+// which converts every entry into a `SocketAddr`.
+//
+// With `service`, the port of each address is printed as well. It can be a port number or a service name
+// from `/etc/services`, e.g. `http` resolves to port 80.
+//
+// With `addrconfig`, `AI_ADDRCONFIG` is set: the IPv6 addresses are only returned if the host has a non-loopback
+// IPv6 address configured, and the same goes for IPv4. This keeps an IPv4-only host from trying IPv6 addresses
+// that it cannot reach anyway.
+//
+// With `v4mapped`, only the IPv6 addresses are asked for, and `AI_V4MAPPED` is set: if the host has no IPv6
+// addresses, its IPv4 addresses are returned as IPv4-mapped IPv6 addresses instead (`::ffff:a.b.c.d`).
+// This lets an IPv6-only program (or a dual-stack socket) reach an IPv4-only host.
+//
+// With `raw`, the bytes of each address are dumped as well, the way it is passed to the syscalls
+// (see `sockaddr::from_socket_addr()`). For a `sockaddr_in`, the family comes first in host byte order
+// (e.g. `02 00` on a little-endian host), followed by the port and the address in network byte order
+// (the port is 0 without a `service`), and 8 bytes of padding. A `sockaddr_in6` also carries the flow info
+// and the scope id around the address.
+pub fn getaddrinfo(
+    host: &str,
+    service: Option<&str>,
+    family: AddressFamily,
+    socktype: SocketType,
+    addrconfig: bool,
+    v4mapped: bool,
+    raw: bool,
+) -> Result<(), Error> {
+    // `AI_V4MAPPED` is ignored unless the family is `AF_INET6`.
+    let family = if v4mapped { AddressFamily::V6 } else { family };
+    let hints = Hints::new()
+        .family(family.as_raw())
+        .socktype(socktype.as_raw())
+        .addrconfig(addrconfig)
+        .v4mapped(v4mapped)
+        .build();
+
+    if addrconfig {
+        println!("AI_ADDRCONFIG: only the families with a configured address are returned");
+    }
+    if v4mapped {
+        println!(
+            "AI_V4MAPPED: only IPv6 is asked for, IPv4 addresses are mapped if there are no IPv6 ones"
+        );
     }
 
-    println!("IP addresses for {}: \n\n", host);
-
-    while !res_ptr.is_null() {
-        // SAFETY: As long as the pointer is not null, we know that it points to a valid libc::addrinfo initialized by getaddrinfo().
-        // We do not deref the pointer when it becomes null (aka at the end of the addrinfo list).
-        let res = unsafe { *res_ptr };
-
-        let addr = match res.ai_family {
-            libc::AF_INET => {
-                let sock_ipv4 = res.ai_addr as *const libc::sockaddr_in;
-                // SAFETY: sock_ipv4 points to an initialized memory after getaddrinfo().
-                let bits = unsafe { (*sock_ipv4).sin_addr.s_addr };
-
-                IpAddr::V4(Ipv4Addr::from_bits(bits))
-            }
-
-            libc::AF_INET6 => {
-                let sock_ipv6 = res.ai_addr as *const libc::sockaddr_in6;
-                // SAFETY: sock_ipv6 points to an initialized memory after getaddrinfo().
-                // *sock_ipv6 points an IPv6 (16 bytes) as fixed 16 length array containing each byte. Therefore, it is safe to call transmute().
-                let bits = unsafe {
-                    let addr = (*sock_ipv6).sin6_addr.s6_addr;
-                    mem::transmute::<[u8; 16], u128>(addr)
-                };
-
-                IpAddr::V6(Ipv6Addr::from_bits(bits))
-            }
+    let addrs = resolve_with(host, service, &hints).map_err(Error::Resolve)?;
 
-            _ => unreachable!(),
-        };
+    println!("IP addresses for {}: \n\n", host);
 
+    for addr in addrs {
         let ipver = if addr.is_ipv4() { "IP" } else { "IPv6" };
 
-        println!("{}: {:?}", ipver, addr);
+        match service {
+            Some(_) => println!("{}: {:?}, port {}", ipver, addr.ip(), addr.port()),
+            None => println!("{}: {:?}", ipver, addr.ip()),
+        }
 
-        res_ptr = res.ai_next;
+        if raw {
+            let (sa, sa_len) = sockaddr::from_socket_addr(&addr);
+            // SAFETY: `sa` is an initialized `sockaddr_storage`, and `sa_len` does not exceed its size.
+            let bytes =
+                unsafe { slice::from_raw_parts(&raw const sa as *const u8, sa_len as usize) };
+            print!("{}", hexdump::hexdump_with(bytes, color::stdout()));
+        }
     }
 
     Ok(())
@@ -1,26 +1,76 @@
 use std::{
+    error,
     ffi::CStr,
+    fmt,
     io::{self, Write},
 };
 
-pub fn gethostname() -> Result<(), io::Error> {
-    let mut host_buf: Vec<i8> = vec![0; 30];
-    let len = host_buf.len();
+#[derive(Debug)]
+pub enum Error {
+    Gethostname(io::Error),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Gethostname(err) => write!(f, "gethostname error: {}", err),
+        }
+    }
+}
 
-    // SAFETY: `host_buf` is initialized. Accessing it is safe.
-    let ecode = unsafe { libc::gethostname(host_buf.as_mut_ptr(), len) };
-    match ecode {
-        -1 => Err(io::Error::last_os_error()),
-        _ => Ok(()),
-    }?;
+impl error::Error for Error {}
 
-    // SAFETY: `host_buf` is initialized. Accessing it is safe.
-    let host = unsafe { CStr::from_ptr(host_buf.as_ptr() as _) };
+// The buffer starts at `HOST_NAME_MAX` on Linux, and it is doubled until the name fits or it reaches the limit.
+const INITIAL_BUF_SIZE: usize = 64;
+const MAX_BUF_SIZE: usize = 4096;
 
-    let msg = [b"hostname: ", host.to_bytes()].concat();
+// EXAMPLE: Print the name of the host the program runs on.
+// Section 5.11 - `gethostname()` - Who am I?
+// MANPAGE:
+// man 2 gethostname (Linux)
+// man 3 gethostname (POSIX)
+pub fn gethostname() -> Result<(), Error> {
+    let host = hostname().map_err(Error::Gethostname)?;
+
+    let msg = [b"hostname: ", host.as_bytes()].concat();
     io::stdout()
         .write_all(&msg)
         .expect("message to be written to stdout");
 
     Ok(())
 }
+
+/// Returns the name of the host by using `gethostname()`.
+///
+/// A name that does not fit the buffer is truncated, and depending on the system it is either reported
+/// with `ENAMETOOLONG` or silently left without a null terminator. Either way, the buffer is grown and the call retried.
+pub fn hostname() -> io::Result<String> {
+    let mut len = INITIAL_BUF_SIZE;
+    loop {
+        let mut host_buf: Vec<libc::c_char> = vec![0; len];
+
+        // SAFETY: `host_buf` is initialized and its length is passed along with it.
+        let ecode = unsafe { libc::gethostname(host_buf.as_mut_ptr(), host_buf.len()) };
+        let truncated = match ecode {
+            -1 => {
+                let err = io::Error::last_os_error();
+                if err.raw_os_error() != Some(libc::ENAMETOOLONG) {
+                    return Err(err);
+                }
+                true
+            }
+            _ => !host_buf.contains(&0),
+        };
+
+        if !truncated {
+            let bytes: Vec<u8> = host_buf.iter().map(|c| *c as u8).collect();
+            let host = CStr::from_bytes_until_nul(&bytes).expect("a null terminator in the buffer");
+            return Ok(host.to_string_lossy().into_owned());
+        }
+
+        if len >= MAX_BUF_SIZE {
+            return Err(io::Error::from_raw_os_error(libc::ENAMETOOLONG));
+        }
+        len *= 2;
+    }
+}
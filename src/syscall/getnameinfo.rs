@@ -0,0 +1,117 @@
+use std::{
+    error,
+    ffi::{CStr, c_char},
+    fmt,
+    net::SocketAddr,
+};
+
+use crate::{
+    resolve::{self, resolve},
+    sockaddr,
+};
+
+#[derive(Debug)]
+pub enum Error {
+    Resolve(resolve::Error),
+    NoName(SocketAddr),
+    Getnameinfo(SocketAddr, String),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Resolve(err) => write!(f, "resolve error: {}", err),
+            Error::NoName(addr) => write!(
+                f,
+                "getnameinfo error: {} has no name (EAI_NONAME), NI_NAMEREQD does not fall back to the numeric form",
+                addr.ip()
+            ),
+            Error::Getnameinfo(addr, err) => {
+                write!(f, "getnameinfo error for {}: {}", addr, err)
+            }
+        }
+    }
+}
+
+impl error::Error for Error {}
+
+// RFC 2553 sizes, `getnameinfo()` truncates the names to the buffers it is given.
+const MAXHOST: usize = 1025;
+const MAXSERV: usize = 32;
+
+// EXAMPLE: Turns the addresses of the given host back into a hostname and a service name.
+// Section 5.1 - `getaddrinfo()` - Prepare to Launch!
+// MANPAGE: man 3 getnameinfo
+//
+// `getnameinfo()` is the reverse of `getaddrinfo()`: it takes a `sockaddr` and returns the name of its address
+// (a reverse DNS lookup, or a look into `/etc/hosts`) and the name of its port (from `/etc/services`).
+// Each address `host` resolves to is passed back to it. With `service`, the ports are set as well.
+//
+// By default, an address without a name is returned in its numeric form, so it is hard to tell whether
+// the reverse lookup succeeded or not.
+// With `numeric_host`, `NI_NUMERICHOST` is set: the lookup is skipped, and the numeric form is always returned.
+// With `name_required`, `NI_NAMEREQD` is set: an address without a name fails with `EAI_NONAME` instead.
+pub fn getnameinfo(
+    host: &str,
+    service: Option<&str>,
+    numeric_host: bool,
+    name_required: bool,
+) -> Result<(), Error> {
+    let mut flags = 0;
+    if numeric_host {
+        flags |= libc::NI_NUMERICHOST;
+    }
+    if name_required {
+        flags |= libc::NI_NAMEREQD;
+    }
+
+    let addrs = resolve(host, service).map_err(Error::Resolve)?;
+
+    for addr in addrs {
+        let (name, serv) = name_info(&addr, flags)?;
+        println!("{} -> host: {}, service: {}", addr, name, serv);
+    }
+
+    Ok(())
+}
+
+/// Returns the host and the service names of `addr` by using `getnameinfo()` with `flags`.
+fn name_info(addr: &SocketAddr, flags: i32) -> Result<(String, String), Error> {
+    let (sa, sa_len) = sockaddr::from_socket_addr(addr);
+    let mut host: [c_char; MAXHOST] = [0; MAXHOST];
+    let mut serv: [c_char; MAXSERV] = [0; MAXSERV];
+
+    // SAFETY:
+    // 1 - `sa` is an initialized `sockaddr_storage`, and `sa_len` does not exceed its size.
+    // 2 - `host` and `serv` are initialized, and their lengths are passed along with them.
+    let ecode = unsafe {
+        libc::getnameinfo(
+            &raw const sa as *const libc::sockaddr,
+            sa_len,
+            host.as_mut_ptr(),
+            host.len() as libc::socklen_t,
+            serv.as_mut_ptr(),
+            serv.len() as libc::socklen_t,
+            flags,
+        )
+    };
+    match ecode {
+        0 => {}
+        libc::EAI_NONAME => return Err(Error::NoName(*addr)),
+        _ => {
+            // SAFETY: `gai_strerror` is valid to call on a failed `getnameinfo()` call.
+            let err = unsafe { CStr::from_ptr(libc::gai_strerror(ecode)) };
+            return Err(Error::Getnameinfo(
+                *addr,
+                err.to_string_lossy().into_owned(),
+            ));
+        }
+    }
+
+    // SAFETY: `getnameinfo()` NUL-terminates both names on success, truncating them if needed.
+    let (host, serv) = unsafe { (CStr::from_ptr(host.as_ptr()), CStr::from_ptr(serv.as_ptr())) };
+    Ok((
+        host.to_string_lossy().into_owned(),
+        serv.to_string_lossy().into_owned(),
+    ))
+}
@@ -1,9 +1,12 @@
-use std::{
-    error,
-    ffi::{CStr, CString},
-    fmt, io, mem,
-    net::Ipv4Addr,
-    ptr,
+use std::{error, ffi::CString, fmt, io, mem, net::SocketAddr, ptr, thread};
+
+use crate::{
+    addrinfo::{AddrInfoList, Hints},
+    client_builder::Client,
+    inet::ConnectError,
+    listener,
+    server_builder::{self, Server},
+    sockaddr, stream_io, trace,
 };
 
 #[derive(Debug)]
@@ -14,6 +17,17 @@ pub enum Error {
     Listen(io::Error),
     Accept(io::Error),
     Getpeername(io::Error),
+    InvalidAddrFamily(i32),
+    Send(io::Error),
+    Server(server_builder::Error),
+    Connect(ConnectError),
+    Getsockname(listener::Error),
+    Mismatch {
+        side: &'static str,
+        seen: SocketAddr,
+        reported: SocketAddr,
+    },
+    ClientPanicked,
 }
 
 impl fmt::Display for Error {
@@ -25,6 +39,24 @@ impl fmt::Display for Error {
             Error::Listen(err) => write!(f, "listen error: {}", err),
             Error::Accept(err) => write!(f, "accept error: {}", err),
             Error::Getpeername(err) => write!(f, "getpeername error: {}", err),
+            Error::InvalidAddrFamily(af) => {
+                write!(f, "getpeername error: invalid address family {}", af)
+            }
+            Error::Send(err) => write!(f, "send error: {}", err),
+            Error::Server(err) => write!(f, "server error: {}", err),
+            Error::Connect(err) => write!(f, "connect error: {}", err),
+            // `listener::Error` already names the syscall.
+            Error::Getsockname(err) => write!(f, "{}", err),
+            Error::Mismatch {
+                side,
+                seen,
+                reported,
+            } => write!(
+                f,
+                "the {} end is seen as {}, but it reports itself as {}",
+                side, seen, reported
+            ),
+            Error::ClientPanicked => write!(f, "the client thread panicked"),
         }
     }
 }
@@ -35,38 +67,26 @@ impl error::Error for Error {}
 // MANPAGE:
 // man 2 getpeername (Linux)
 // man 2 getpeername (POSIX)
-pub fn getpeername() -> Result<(), Error> {
-    let node = ptr::null();
+//
+// If `reply_with_peer` is set, the peer address is also sent back to the client as a line.
+// This is the address the server sees, which differs from the local address of the client behind a NAT.
+pub fn getpeername(reply_with_peer: bool) -> Result<(), Error> {
     let port = CString::from(c"3490");
 
-    // SAFETY: hints is initialized as zeroes, but the required fields are set later on.
-    let mut hints: libc::addrinfo = unsafe { mem::zeroed() };
-    hints.ai_family = libc::AF_INET;
-    hints.ai_socktype = libc::SOCK_STREAM;
+    let hints = Hints::new()
+        .family(libc::AF_INET)
+        .socktype(libc::SOCK_STREAM)
+        .build();
 
-    let mut res_ptr: *mut libc::addrinfo = ptr::null_mut();
+    // The list is freed by `freeaddrinfo()` when `res_list` goes out of scope.
+    let res_list = AddrInfoList::new(None, Some(&port), &hints).map_err(Error::Getaddrinfo)?;
+    let res = res_list.first();
 
     // SAFETY:
-    // 1 - All the required vars are initialized for getaddrinfo().
-    // 2 - gai_stderror() is used for error cases only.
-    unsafe {
-        let ecode = libc::getaddrinfo(node, port.as_ptr(), &hints, &mut res_ptr);
-        match ecode {
-            0 => Ok(()),
-            _ => {
-                let err = CStr::from_ptr(libc::gai_strerror(ecode)).to_string_lossy();
-                Err(Error::Getaddrinfo(err.into_owned()))
-            }
-        }
-    }?;
-
-    // SAFETY:
-    // 1 - Since we are trying to get our loopback IP address via `getaddrinfo()`, we know that `res_ptr` points to an initialized memory, making `socket()` safe to use.
+    // 1 - Since we are trying to get our loopback IP address via `getaddrinfo()`, we know that `res` points to an initialized memory, making `socket()` safe to use.
     // 2 - Any potential `socket()` error is checked by reading `errno` instantly after the `socket()` call. This ensures that `sock_fd` contains the fd of a successfully created socket.
     let sock_fd = unsafe {
-        let res = *res_ptr;
-
-        let fd = libc::socket(res.ai_family, res.ai_socktype, 0);
+        let fd = trace::socket(res.ai_family, res.ai_socktype, 0);
         match fd {
             -1 => {
                 let err = io::Error::last_os_error();
@@ -77,13 +97,11 @@ pub fn getpeername() -> Result<(), Error> {
     }?;
 
     // SAFETY:
-    // 1 - Due to the points above, `res_ptr` and `sock_fd` are safe to use.
+    // 1 - Due to the points above, `res` and `sock_fd` are safe to use.
     // 2 - Any potential `bind()` error is checked by reading `errno` instantly after the `bind()` call.
     // This ensures that any errors that may happen in `bind()` are caught.
     unsafe {
-        let res = *res_ptr;
-
-        let ecode = libc::bind(sock_fd, res.ai_addr, res.ai_addrlen);
+        let ecode = trace::bind(sock_fd, res.ai_addr, res.ai_addrlen);
         match ecode {
             -1 => {
                 let err = io::Error::last_os_error();
@@ -93,18 +111,13 @@ pub fn getpeername() -> Result<(), Error> {
         }
     }?;
 
-    // SAFETY: Since `res_ptr` points to a valid initialized memory and will not be used after `bind()`, it is safe to free it upon a successful `bind()` call.
-    unsafe {
-        libc::freeaddrinfo(res_ptr);
-    }
-
     // SAFETY:
     // 1- The `sock_fd` used for `listen()` is guaranteed to be valid due to the points above.
     // 2 - Any potential `listen()` error is checked by reading `errno` instantly after the `listen()` call.
     unsafe {
         const BACKLOG: i32 = 10;
 
-        let ecode = libc::listen(sock_fd, BACKLOG);
+        let ecode = trace::listen(sock_fd, BACKLOG);
         match ecode {
             -1 => {
                 let err = io::Error::last_os_error();
@@ -119,7 +132,7 @@ pub fn getpeername() -> Result<(), Error> {
     // 2 - Any potential `accept()` error is checked by reading `errno` instantly after the `accept()` call.
     // 3 - The returned sock_fd is a valid fd created by a successful `accept()` call to interact with the accepted connection.
     let conn_sock_fd = unsafe {
-        let fd = libc::accept(sock_fd, ptr::null_mut(), ptr::null_mut());
+        let fd = trace::accept(sock_fd, ptr::null_mut(), ptr::null_mut());
         match fd {
             -1 => {
                 let err = io::Error::last_os_error();
@@ -129,18 +142,102 @@ pub fn getpeername() -> Result<(), Error> {
         }
     }?;
 
+    reply_to_peer(conn_sock_fd, reply_with_peer)
+}
+
+/// Prints the address of the peer `conn_sock_fd` is connected to, and sends it back to the peer as a line
+/// if `reply_with_peer` is set.
+fn reply_to_peer(conn_sock_fd: i32, reply_with_peer: bool) -> Result<(), Error> {
+    let peer_addr = peer_addr(conn_sock_fd)?;
+    println!(
+        "peer ip addr: {}, port: {}",
+        peer_addr.ip(),
+        peer_addr.port()
+    );
+
+    if reply_with_peer {
+        let reply = format!("{}\n", peer_addr);
+        stream_io::sendall(conn_sock_fd, reply.as_bytes())
+            .map_err(|err| Error::Send(err.into()))?;
+    }
+
+    Ok(())
+}
+
+// EXAMPLE: The same as `getpeername()`, without an external client.
+// MANPAGE:
+// man 2 getpeername (Linux)
+// man 2 getsockname (Linux)
+//
+// The server listens on an ephemeral port on 127.0.0.1, and a thread connects to it as the client.
+// Both ends of a connection are described by the same pair of addresses, only in reverse:
+// what the server gets from `getpeername()` is what the client gets from `getsockname()`, and vice versa.
+// The example checks both directions, and fails with `Error::Mismatch` if they do not agree.
+pub fn getpeername_loopback() -> Result<(), Error> {
+    let listener = Server::new()
+        .bind(Some("127.0.0.1"), 0)
+        .listen()
+        .map_err(Error::Server)?;
+    let port = listener.local_addr().port();
+
+    let client = thread::spawn(move || {
+        let (sock, _) = Client::new()
+            .host("127.0.0.1")
+            .port(port)
+            .connect()
+            .map_err(Error::Connect)?;
+        let local_addr = listener::local_addr(sock.as_raw()).map_err(Error::Getsockname)?;
+        let peer_addr = peer_addr(sock.as_raw())?;
+
+        // The socket is returned as well, so that the connection stays open until the server is done with it.
+        Ok::<_, Error>((sock, local_addr, peer_addr))
+    });
+
+    let (conn_sock, _) = listener.accept().map_err(Error::Server)?;
+    let server_peer = peer_addr(conn_sock.as_raw())?;
+    let server_local = listener::local_addr(conn_sock.as_raw()).map_err(Error::Getsockname)?;
+
+    let (_client_sock, client_local, client_peer) =
+        client.join().map_err(|_| Error::ClientPanicked)??;
+
+    println!("server: getpeername() = {}", server_peer);
+    println!("client: getsockname() = {}", client_local);
+    println!("server: getsockname() = {}", server_local);
+    println!("client: getpeername() = {}", client_peer);
+
+    if server_peer != client_local {
+        return Err(Error::Mismatch {
+            side: "client",
+            seen: server_peer,
+            reported: client_local,
+        });
+    }
+    if client_peer != server_local {
+        return Err(Error::Mismatch {
+            side: "server",
+            seen: client_peer,
+            reported: server_local,
+        });
+    }
+
+    println!("both ends agree on the connection endpoints");
+    Ok(())
+}
+
+/// Returns the address of the peer `sock_fd` is connected to by using `getpeername()`.
+fn peer_addr(sock_fd: i32) -> Result<SocketAddr, Error> {
     // SAFETY:
     // 1 - Zeroed out `sockaddr_storage` is a valid initialization.
-    // 2 - `conn_sock_fd` is a valid sock fd to use.
-    // 3 - Any potential `accept()` error is checked by reading `errno` instantly after the `accept()` call.
+    // 2 - `sock_fd` is a valid sock fd to use.
+    // 3 - Any potential `getpeername()` error is checked by reading `errno` instantly after the `getpeername()` call.
     let sockaddr_storage = unsafe {
         let mut sockaddr_storage: libc::sockaddr_storage = mem::zeroed();
-        let mut storage_len = mem::size_of_val(&sockaddr_storage);
+        let mut storage_len = mem::size_of_val(&sockaddr_storage) as libc::socklen_t;
 
-        let ecode = libc::getpeername(
-            conn_sock_fd,
+        let ecode = trace::getpeername(
+            sock_fd,
             &raw mut sockaddr_storage as *mut libc::sockaddr,
-            &raw mut storage_len as _,
+            &raw mut storage_len,
         );
         match ecode {
             -1 => {
@@ -151,16 +248,61 @@ pub fn getpeername() -> Result<(), Error> {
         }
     }?;
 
-    // SAFETY: `sockaddr_storage` is filled by a valid `getpeername()` call.
-    // Therefore, reading from it is safe.
-    let sockaddr_in = unsafe { *(&raw const sockaddr_storage as *const libc::sockaddr_in) };
+    // `sockaddr_storage` is filled by a valid `getpeername()` call.
+    sockaddr::to_socket_addr(&sockaddr_storage)
+        .ok_or(Error::InvalidAddrFamily(sockaddr_storage.ss_family as i32))
+}
 
-    let bits = u32::from_be(sockaddr_in.sin_addr.s_addr);
-    let from_addr = Ipv4Addr::from_bits(bits);
-    println!(
-        "peer ip addr: {}, port: {}",
-        from_addr, sockaddr_in.sin_port
-    );
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil;
 
-    Ok(())
+    #[test]
+    fn both_ends_agree_over_loopback() {
+        let listener = Server::new().bind(Some("127.0.0.1"), 0).listen().unwrap();
+        let (client, _) = Client::new()
+            .host("127.0.0.1")
+            .port(listener.local_addr().port())
+            .connect()
+            .unwrap();
+        let (conn, accepted_from) = listener.accept().unwrap();
+
+        let client_local = listener::local_addr(client.as_raw()).unwrap();
+        let server_local = listener::local_addr(conn.as_raw()).unwrap();
+
+        assert_eq!(peer_addr(conn.as_raw()).unwrap(), client_local);
+        assert_eq!(peer_addr(client.as_raw()).unwrap(), server_local);
+        assert_eq!(accepted_from, client_local);
+        assert_eq!(server_local, listener.local_addr());
+    }
+
+    #[test]
+    fn reply_matches_the_local_address_of_the_client() {
+        let (client, server) = testutil::loopback_pair();
+
+        reply_to_peer(server.as_raw(), true).unwrap();
+
+        let mut pending = Vec::new();
+        let reply = stream_io::recv_until(client.as_raw(), &mut pending, b'\n')
+            .unwrap()
+            .unwrap();
+        let reply: SocketAddr = String::from_utf8(reply).unwrap().parse().unwrap();
+        assert_eq!(reply, listener::local_addr(client.as_raw()).unwrap());
+    }
+
+    #[test]
+    fn loopback_example_succeeds() {
+        getpeername_loopback().unwrap();
+    }
+
+    #[test]
+    fn unconnected_socket_has_no_peer() {
+        let listener = Server::new().bind(Some("127.0.0.1"), 0).listen().unwrap();
+
+        match peer_addr(listener.as_raw()) {
+            Err(Error::Getpeername(err)) => assert_eq!(err.raw_os_error(), Some(libc::ENOTCONN)),
+            res => panic!("unexpected result: {:?}", res),
+        }
+    }
 }
@@ -0,0 +1,111 @@
+use std::{error, ffi::CString, fmt, io, mem, net::SocketAddr};
+
+use crate::{
+    addrinfo::{AddrInfoList, Hints},
+    sockaddr,
+    socket_fd::Socket,
+    trace,
+};
+
+#[derive(Debug)]
+pub enum Error {
+    Getaddrinfo(String),
+    Socket(io::Error),
+    Bind(io::Error),
+    Getsockname(io::Error),
+    InvalidAddrFamily(i32),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Getaddrinfo(err) => write!(f, "getaddrinfo error: {}", err),
+            Error::Socket(err) => write!(f, "socket error: {}", err),
+            Error::Bind(err) => write!(f, "bind error: {}", err),
+            Error::Getsockname(err) => write!(f, "getsockname error: {}", err),
+            Error::InvalidAddrFamily(af) => {
+                write!(f, "getsockname error: invalid address family {}", af)
+            }
+        }
+    }
+}
+
+impl error::Error for Error {}
+
+// EXAMPLE: See which address a socket is bound to.
+// Section 5.3 - `bind()` - What Port Am I On?
+// MANPAGE:
+// man 2 getsockname (Linux)
+// man 3 getsockname (POSIX)
+//
+// The socket is bound to port 0 on all local addresses, which lets the kernel pick a free (ephemeral) port.
+// The caller does not learn which port it got from `bind()`, `getsockname()` is the only way to find out.
+// This is something `getpeername()` cannot do: it describes the other end, and it needs a connection to do that.
+//
+// `getsockname()` is called before `bind()` as well, to show that an unbound socket reports the wildcard
+// address with port 0.
+pub fn getsockname() -> Result<(), Error> {
+    let service = CString::from(c"0");
+
+    let hints = Hints::new()
+        .family(libc::AF_INET)
+        .socktype(libc::SOCK_STREAM)
+        .passive(true)
+        .build();
+
+    // The list is freed by `freeaddrinfo()` when `res_list` goes out of scope.
+    let res_list = AddrInfoList::new(None, Some(&service), &hints).map_err(Error::Getaddrinfo)?;
+    let res = res_list.first();
+
+    // SAFETY: `res` is filled by a successful `getaddrinfo()` call.
+    let sock_fd = unsafe { trace::socket(res.ai_family, res.ai_socktype, 0) };
+    if sock_fd == -1 {
+        return Err(Error::Socket(io::Error::last_os_error()));
+    }
+    // The socket is closed when `sock` goes out of scope, on the error paths as well.
+    let sock = Socket::from_raw(sock_fd);
+
+    println!("before bind(): {}", local_addr(sock.as_raw())?);
+
+    // SAFETY: `bind()` is called on a valid `sock_fd`, with the address `getaddrinfo()` returned.
+    let ecode = unsafe { trace::bind(sock.as_raw(), res.ai_addr, res.ai_addrlen) };
+    if ecode == -1 {
+        return Err(Error::Bind(io::Error::last_os_error()));
+    }
+
+    let addr = local_addr(sock.as_raw())?;
+    println!("after bind() to port 0: {}", addr);
+    println!(
+        "the kernel picked ip addr: {}, port: {}",
+        addr.ip(),
+        addr.port()
+    );
+
+    Ok(())
+}
+
+/// Returns the address `sock_fd` is bound to by using `getsockname()`.
+fn local_addr(sock_fd: i32) -> Result<SocketAddr, Error> {
+    // SAFETY:
+    // 1 - Zeroed out `sockaddr_storage` is a valid initialization.
+    // 2 - `sock_fd` is a valid sock fd to use, and `storage_len` holds the size of the buffer that is passed.
+    // 3 - Any potential `getsockname()` error is checked by reading `errno` instantly after the `getsockname()` call.
+    let sockaddr_storage = unsafe {
+        let mut sockaddr_storage: libc::sockaddr_storage = mem::zeroed();
+        let mut storage_len = mem::size_of_val(&sockaddr_storage) as libc::socklen_t;
+
+        let ecode = trace::getsockname(
+            sock_fd,
+            &raw mut sockaddr_storage as *mut libc::sockaddr,
+            &raw mut storage_len,
+        );
+        match ecode {
+            -1 => Err(Error::Getsockname(io::Error::last_os_error())),
+            _ => Ok(sockaddr_storage),
+        }
+    }?;
+
+    // `sockaddr_storage` is filled by a valid `getsockname()` call.
+    sockaddr::to_socket_addr(&sockaddr_storage)
+        .ok_or(Error::InvalidAddrFamily(sockaddr_storage.ss_family as i32))
+}
@@ -1,7 +1,8 @@
-use std::{
-    error,
-    ffi::{CStr, CString},
-    fmt, io, mem, ptr,
+use std::{error, ffi::CString, fmt, io};
+
+use crate::{
+    addrinfo::{AddrInfoList, Hints},
+    trace,
 };
 
 #[derive(Debug)]
@@ -31,36 +32,21 @@ impl error::Error for Error {}
 // man 2 listen (Linux)
 // man 3 listen (POSIX)
 pub fn listen() -> Result<(), Error> {
-    let node = ptr::null();
     let port = CString::from(c"3490");
 
-    // SAFETY: hints is initialized as empty, but the required fields are set later on.
-    let mut hints: libc::addrinfo = unsafe { mem::zeroed() };
-    hints.ai_family = libc::AF_UNSPEC;
-    hints.ai_socktype = libc::SOCK_STREAM;
-
-    let mut res_ptr = ptr::null_mut();
+    let hints = Hints::new()
+        .family(libc::AF_UNSPEC)
+        .socktype(libc::SOCK_STREAM)
+        .build();
 
-    // SAFETY:
-    // All the required vars are initialized for getaddrinfo().
-    // gai_stderror() is used for error cases only.
-    unsafe {
-        let s = libc::getaddrinfo(node, port.as_ptr(), &hints, &mut res_ptr);
-        match s {
-            0 => Ok(()),
-            _ => {
-                let err = CStr::from_ptr(libc::gai_strerror(s)).to_string_lossy();
-                Err(Error::Getaddrinfo(err.into_owned()))
-            }
-        }
-    }?;
+    // The list is freed by `freeaddrinfo()` when `res_list` goes out of scope.
+    let res_list = AddrInfoList::new(None, Some(&port), &hints).map_err(Error::Getaddrinfo)?;
+    let res = res_list.first();
 
-    // SAFETY: Since we are trying to get our local public IP address via `getaddrinfo()`, we know that `res_ptr` points to an initialized memory, making `socket()` safe to use.
+    // SAFETY: Since we are trying to get our local public IP address via `getaddrinfo()`, we know that `res` points to an initialized memory, making `socket()` safe to use.
     // Any potential `socket()` error is checked by reading `errno` instantly after the `socket()` call. This ensures that `sock_fd` contains the fd of a successfully created socket.
     let sock_fd = unsafe {
-        let res = *res_ptr;
-
-        let sock_fd = libc::socket(res.ai_family, res.ai_socktype, 0);
+        let sock_fd = trace::socket(res.ai_family, res.ai_socktype, 0);
         match sock_fd {
             -1 => {
                 let err = io::Error::last_os_error();
@@ -70,32 +56,24 @@ pub fn listen() -> Result<(), Error> {
         }
     }?;
 
-    // SAFETY: Due to the points above, `res_ptr` and `sock_fd` are safe to use.
+    // SAFETY: Due to the points above, `res` and `sock_fd` are safe to use.
     // Any potential `bind()` error is checked by reading `errno` instantly after the `bind()` call.
     // This ensures that any errors that may happen in `bind()` are caught.
-    //
-    // Since `res_ptr` points to a valid initialized memory and will not be used after `bind()`, it is safe to free it upon a successful `bind()` call.
     unsafe {
-        let res = *res_ptr;
-
-        let s = libc::bind(sock_fd, res.ai_addr, res.ai_addrlen);
-        let res = match s {
+        let s = trace::bind(sock_fd, res.ai_addr, res.ai_addrlen);
+        match s {
             -1 => {
                 let err = io::Error::last_os_error();
                 Err(Error::Bind(sock_fd, err))
             }
             _ => Ok(sock_fd),
-        };
-
-        libc::freeaddrinfo(res_ptr);
-
-        res
+        }
     }?;
 
     // SAFETY: The `sock_fd` used for `listen()` is guaranteed to be valid due to the points above.
     // Any potential `listen()` error is checked by reading `errno` instantly after the `listen()` call.
     unsafe {
-        let s = libc::listen(sock_fd, 10);
+        let s = trace::listen(sock_fd, 10);
         match s {
             -1 => {
                 let err = io::Error::last_os_error();
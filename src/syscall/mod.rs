@@ -4,7 +4,9 @@ mod close;
 mod connect;
 mod getaddrinfo;
 mod gethostname;
+mod getnameinfo;
 mod getpeername;
+mod getsockname;
 mod listen;
 mod recv;
 mod recvfrom;
@@ -18,12 +20,14 @@ pub use bind::{bind, reuse_port};
 pub use close::close;
 pub use connect::connect;
 pub use getaddrinfo::getaddrinfo;
-pub use gethostname::gethostname;
-pub use getpeername::getpeername;
+pub use gethostname::{gethostname, hostname};
+pub use getnameinfo::getnameinfo;
+pub use getpeername::{getpeername, getpeername_loopback};
+pub use getsockname::getsockname;
 pub use listen::listen;
-pub use recv::recv;
-pub use recvfrom::recvfrom;
-pub use send::send;
-pub use sendto::sendto;
+pub use recv::{recv, recv_with};
+pub use recvfrom::{recvfrom, recvfrom_with};
+pub use send::{send, send_with};
+pub use sendto::{sendto, sendto_with};
 pub use shutdown::shutdown;
 pub use socket::socket;
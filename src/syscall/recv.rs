@@ -3,7 +3,7 @@ use std::{
     io::{self, Write},
 };
 
-use crate::syscall;
+use crate::{deadline, socket_fd::Socket, stats::ReceiveSummary, syscall, trace};
 
 #[derive(Debug)]
 pub enum Error {
@@ -38,20 +38,61 @@ impl From<syscall::accept::Error> for Error {
 // MANPAGE:
 // man 2 recv (Linux)
 // man 3 recv (POSIX)
-pub fn recv() -> Result<(), Error> {
-    let conn_sock_fd = syscall::accept()?;
+//
+// Returns what was received. With `summary`, it is also printed as a trailing line, see `ReceiveSummary`.
+pub fn recv(summary: bool) -> Result<ReceiveSummary, Error> {
+    let (conn_sock, peer_addr) = syscall::accept()?;
+    let conn_sock_fd = conn_sock.as_raw();
 
+    let buf = recv_with(&conn_sock)?;
+
+    let msg = [
+        format!(
+            "received {} bytes from {} on sock fd {}: ",
+            buf.len(),
+            peer_addr,
+            conn_sock_fd
+        )
+        .as_bytes(),
+        &buf,
+    ]
+    .concat();
+
+    io::stdout()
+        .write_all(&msg)
+        .expect("received msg to be written to stdout");
+
+    let mut res = ReceiveSummary::new("segment");
+    res.on_recv(buf.len(), peer_addr);
+    if summary {
+        // The message is written as is, it may not end with a newline.
+        if !buf.ends_with(b"\n") {
+            println!();
+        }
+        println!("summary: {}", res);
+    }
+
+    Ok(res)
+}
+
+/// Same as `recv()`, but receives the message from `sock` instead of accepting a connection first.
+/// `sock` can be any connected stream socket, e.g. one end of a `socketpair()`.
+///
+/// Returns the received bytes.
+pub fn recv_with(sock: &Socket) -> Result<Vec<u8>, Error> {
     let mut buf: Vec<u8> = vec![0; 30];
     let len = buf.len();
 
+    deadline::wait_readable(sock.as_raw()).map_err(Error::Recv)?;
+
     // SAFETY:
-    // 1 - `conn_sock_fd` contains an initialized sock fd when `accept()` succeeds.
+    // 1 - `sock` contains an initialized sock fd.
     // 2 - Any potential `recv()` error is checked by reading `errno` instantly after the `recv()` call.
     // 3 - The `buf` passed to `recv()` is initialized.
     //
     // In addition, since receiving 0 bytes from `recv()` is not expected because the socket in example is of type SOCK_STREAM, `recv()` is accepted as failed if it does not read any bytes at all.
     let recv_bytes = unsafe {
-        let bytes = libc::recv(conn_sock_fd, buf.as_mut_ptr() as *mut libc::c_void, len, 0);
+        let bytes = trace::recv(sock.as_raw(), buf.as_mut_ptr() as *mut libc::c_void, len, 0);
         match bytes {
             -1 => {
                 let err = io::Error::last_os_error();
@@ -62,19 +103,32 @@ pub fn recv() -> Result<(), Error> {
         }
     }?;
 
-    let msg = [
-        format!(
-            "received {} bytes from sock fd {}: ",
-            recv_bytes, conn_sock_fd
-        )
-        .as_bytes(),
-        &buf,
-    ]
-    .concat();
+    buf.truncate(recv_bytes as usize);
 
-    io::stdout()
-        .write_all(&msg)
-        .expect("received msg to be written to stdout");
+    Ok(buf)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{syscall::send_with, testutil};
+
+    #[test]
+    fn receives_what_send_with_sent() {
+        let (sender, receiver) = testutil::socketpair();
 
-    Ok(())
+        assert_eq!(send_with(&sender).unwrap(), 13);
+        assert_eq!(recv_with(&receiver).unwrap(), b"hello world!\n");
+    }
+
+    #[test]
+    fn closed_peer_is_an_error() {
+        let (sender, receiver) = testutil::socketpair();
+        drop(sender);
+
+        assert!(matches!(
+            recv_with(&receiver),
+            Err(Error::ZeroBytesRecv(30))
+        ));
+    }
 }
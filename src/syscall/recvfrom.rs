@@ -1,9 +1,12 @@
-use std::{
-    error,
-    ffi::{CStr, CString},
-    fmt,
-    io::{self, Write},
-    mem, ptr,
+use std::{error, ffi::CString, fmt, io, mem};
+
+use crate::{
+    addrinfo::{AddrInfoList, Hints},
+    cidr::SourceFilter,
+    deadline, fd, signal, sockaddr,
+    socket_fd::Socket,
+    stats::ReceiveSummary,
+    trace,
 };
 
 #[derive(Debug)]
@@ -12,6 +15,9 @@ pub enum Error {
     Socket(io::Error),
     Bind(i32, io::Error),
     Recvfrom(io::Error),
+    InvalidAddrFamily(i32),
+    Signal(io::Error),
+    Close(i32, io::Error),
 }
 
 impl fmt::Display for Error {
@@ -21,6 +27,11 @@ impl fmt::Display for Error {
             Error::Socket(err) => write!(f, "sock error: {}", err),
             Error::Bind(sock_fd, err) => write!(f, "bind error on sock fd {}: {}", sock_fd, err),
             Error::Recvfrom(err) => write!(f, "recvfrom error: {}", err),
+            Error::InvalidAddrFamily(family) => {
+                write!(f, "recvfrom returned an unknown address family {}", family)
+            }
+            Error::Signal(err) => write!(f, "sigaction error: {}", err),
+            Error::Close(sock_fd, err) => write!(f, "close error on sock fd {}: {}", sock_fd, err),
         }
     }
 }
@@ -30,37 +41,33 @@ impl error::Error for Error {}
 // MANPAGE:
 // man 2 recvfrom (Linux)
 // man 3 recvfrom (POSIX)
-pub fn recvfrom() -> Result<(), Error> {
-    let node_ptr = ptr::null();
+//
+// `count` is the number of datagrams to receive before exiting, `None` keeps receiving until `SIGINT`.
+// The source address that `recvfrom()` fills in is decoded and printed along with each datagram.
+// The datagrams from the sources that `filter` does not permit are dropped, they do not count towards `count`.
+//
+// Returns what was received. With `summary`, it is also printed as a trailing line, see `ReceiveSummary`.
+pub fn recvfrom(
+    count: Option<usize>,
+    filter: &SourceFilter,
+    summary: bool,
+) -> Result<ReceiveSummary, Error> {
     let port = CString::from(c"3490");
 
-    // SAFETY: hints is initialized as empty, but the required fields are set later on.
-    let mut hints: libc::addrinfo = unsafe { mem::zeroed() };
-    hints.ai_family = libc::AF_INET;
-    hints.ai_socktype = libc::SOCK_DGRAM;
-
-    let mut res_ptr: *mut libc::addrinfo = ptr::null_mut();
+    let hints = Hints::new()
+        .family(libc::AF_INET)
+        .socktype(libc::SOCK_DGRAM)
+        .build();
 
-    // SAFETY:
-    // 1 - All the required vars are initialized for getaddrinfo().
-    // 2 - gai_stderror() is used for error cases only.
-    unsafe {
-        let s = libc::getaddrinfo(node_ptr, port.as_ptr(), &hints, &mut res_ptr);
-        match s {
-            0 => Ok(()),
-            s => {
-                let err = CStr::from_ptr(libc::gai_strerror(s)).to_string_lossy();
-                Err(Error::Getaddrinfo(err.into_owned()))
-            }
-        }
-    }?;
+    // The list is freed by `freeaddrinfo()` when `res_list` goes out of scope.
+    let res_list = AddrInfoList::new(None, Some(&port), &hints).map_err(Error::Getaddrinfo)?;
+    let res = res_list.first();
 
     // SAFETY:
-    // 1 - Since we are trying to get our loopback IP address via `getaddrinfo()`, we know that `res_ptr` points to an initialized memory, making `socket()` safe to use.
+    // 1 - Since we are trying to get our loopback IP address via `getaddrinfo()`, we know that `res` points to an initialized memory, making `socket()` safe to use.
     // 2 - Any potential `socket()` error is checked by reading `errno` instantly after the `socket()` call. This ensures that `sock_fd` contains the fd of a successfully created socket.
     let sock_fd = unsafe {
-        let res = *res_ptr;
-        let fd = libc::socket(res.ai_family, res.ai_socktype, 0);
+        let fd = trace::socket(res.ai_family, res.ai_socktype, 0);
         match fd {
             -1 => {
                 let err = io::Error::last_os_error();
@@ -69,67 +76,161 @@ pub fn recvfrom() -> Result<(), Error> {
             _ => Ok(fd),
         }
     }?;
+    // From now on, `sock_fd` is closed on the error paths as well.
+    let sock = Socket::from_raw(sock_fd);
 
     // SAFETY:
-    // 1 - Due to the points above, `res_ptr` and `sock_fd` are safe to use.
+    // 1 - Due to the points above, `res` and `sock_fd` are safe to use.
     //
     // 2 - Any potential `bind()` error is checked by reading `errno` instantly after the `bind()` call.
     // This ensures that any errors that may happen in `bind()` are caught.
-    //
-    // 3 - Since `res_ptr` points to a valid initialized memory and will not be used after `bind()`, it is safe to free it upon a successful `bind()` call.
     unsafe {
-        let res = *res_ptr;
-        let s = libc::bind(sock_fd, res.ai_addr, res.ai_addrlen);
-        let res = match s {
+        let s = trace::bind(sock_fd, res.ai_addr, res.ai_addrlen);
+        match s {
             -1 => {
                 let err = io::Error::last_os_error();
                 Err(Error::Bind(sock_fd, err))
             }
             _ => Ok(s),
-        };
+        }
+    }?;
 
-        libc::freeaddrinfo(res_ptr);
+    // Without `SA_RESTART`, a blocking `recvfrom()` returns `EINTR` on `SIGINT` and the loop below can exit cleanly.
+    signal::install_shutdown_handler().map_err(Error::Signal)?;
 
-        res
-    }?;
+    let res = recvfrom_with(&sock, count, filter);
+
+    // `sock_fd` is not used after this call.
+    fd::close_fd(sock.into_raw()).map_err(|err| Error::Close(sock_fd, err))?;
+
+    let res = res?;
+    if summary {
+        println!("summary: {}", res);
+    }
 
+    Ok(res)
+}
+
+/// Same as `recvfrom()`, but receives the datagrams from `sock` instead of binding a socket to port 3490.
+/// `sock` can be any bound `SOCK_DGRAM` socket of `AF_INET` or `AF_INET6`, e.g. one bound to an ephemeral port.
+///
+/// An interrupted `recvfrom()` stops the loop only if `signal::install_shutdown_handler()` was called.
+/// Returns what was received, not counting the datagrams dropped by `filter`.
+pub fn recvfrom_with(
+    sock: &Socket,
+    count: Option<usize>,
+    filter: &SourceFilter,
+) -> Result<ReceiveSummary, Error> {
+    let sock_fd = sock.as_raw();
     let mut buf: Vec<u8> = vec![0; 30];
     let len = buf.len();
 
-    // SAFETY:
-    // 1 - `sock_fd` points to a valid socket.
-    //
-    // 2 - Since we do not control the incoming message, we may receive a lot more bytes than we expect. It is intentionally kept unchecked in here to focus on showing how a `recvfrom()` call is constructed.
-    //
-    // 3 - A big enough memory is allocated for `from_addr` by using `sockaddr_storage`.
-    // Even though the source address is not used in the example, it is just added here to show the difference between `recv()` and `recvfrom()`.
-    //
-    // 4 - Any potential `recvfrom()` error is checked by reading `errno` instantly after the `recvfrom()` call.
-    let recv_bytes = unsafe {
-        let mut from_addr: libc::sockaddr_storage = mem::zeroed();
-        let mut from_addr_len = mem::size_of_val(&from_addr) as u32;
-
-        let bytes = libc::recvfrom(
-            sock_fd,
-            buf.as_mut_ptr() as _,
-            len,
-            0,
-            &raw mut from_addr as _,
-            &raw mut from_addr_len,
-        );
-        match bytes {
-            -1 => {
-                let err = io::Error::last_os_error();
-                Err(Error::Recvfrom(err))
+    let mut summary = ReceiveSummary::new("datagram");
+    while count.is_none_or(|count| summary.messages < count) {
+        // SAFETY:
+        // 1 - `sock_fd` points to a valid socket.
+        //
+        // 2 - Since we do not control the incoming message, we may receive a lot more bytes than we expect.
+        // `recvfrom()` writes at most `len` bytes into `buf`, the rest of a longer datagram is discarded.
+        //
+        // 3 - A big enough memory is allocated for `from_addr` by using `sockaddr_storage`.
+        // This is the difference between `recv()` and `recvfrom()`: the source address of the datagram is filled in.
+        //
+        // 4 - Any potential `recvfrom()` error is checked by reading `errno` instantly after the `recvfrom()` call.
+        //
+        // A missed `--deadline` is reported like a `recvfrom()` error, see `deadline::wait_readable()`.
+        let res = deadline::wait_readable(sock_fd).and_then(|()| unsafe {
+            let mut from_addr: libc::sockaddr_storage = mem::zeroed();
+            let mut from_addr_len = mem::size_of_val(&from_addr) as u32;
+
+            let bytes = trace::recvfrom(
+                sock_fd,
+                buf.as_mut_ptr() as _,
+                len,
+                0,
+                &raw mut from_addr as _,
+                &raw mut from_addr_len,
+            );
+            match bytes {
+                -1 => Err(io::Error::last_os_error()),
+                _ => Ok((bytes as usize, from_addr)),
+            }
+        });
+
+        let (recv_bytes, from_addr) = match res {
+            Ok(res) => res,
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => {
+                if signal::shutdown_requested() {
+                    println!(
+                        "recvfrom: interrupted, received {} datagrams",
+                        summary.messages
+                    );
+                    break;
+                }
+                continue;
             }
-            _ => Ok(bytes),
+            Err(err) => return Err(Error::Recvfrom(err)),
+        };
+        let from_addr = sockaddr::to_socket_addr(&from_addr)
+            .ok_or(Error::InvalidAddrFamily(from_addr.ss_family as i32))?;
+        if !filter.permits(from_addr.ip()) {
+            println!(
+                "dropped {} bytes from {}, the source is not allowed",
+                recv_bytes, from_addr
+            );
+            continue;
         }
-    }?;
+        summary.on_recv(recv_bytes, from_addr);
 
-    let msg = [format!("received {} bytes: ", recv_bytes).as_bytes(), &buf].concat();
-    io::stdout()
-        .write_all(&msg)
-        .expect("received msg to be written to stdout");
+        println!(
+            "received {} bytes from {}: {}",
+            recv_bytes,
+            from_addr,
+            String::from_utf8_lossy(&buf[..recv_bytes]).trim_end()
+        );
+    }
 
-    Ok(())
+    Ok(summary)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{syscall::sendto_with, udp};
+
+    #[test]
+    fn receives_what_sendto_with_sent() {
+        let (receiver_fd, receiver_addr) = udp::bind_udp(Some(c"127.0.0.1"), c"0").unwrap();
+        let (sender_fd, sender_addr) = udp::bind_udp(Some(c"127.0.0.1"), c"0").unwrap();
+        let (receiver, sender) = (Socket::from_raw(receiver_fd), Socket::from_raw(sender_fd));
+
+        assert_eq!(sendto_with(&sender, &receiver_addr).unwrap(), 13);
+        let summary = recvfrom_with(&receiver, Some(1), &SourceFilter::default()).unwrap();
+
+        assert_eq!(summary.messages, 1);
+        assert_eq!(summary.bytes, 13);
+        assert_eq!(summary.sources, [sender_addr]);
+    }
+
+    #[test]
+    fn datagrams_from_a_denied_source_are_dropped() {
+        let (receiver_fd, receiver_addr) = udp::bind_udp(Some(c"127.0.0.1"), c"0").unwrap();
+        let receiver = Socket::from_raw(receiver_fd);
+        let (denied_fd, _) = udp::bind_udp(Some(c"127.0.0.3"), c"0").unwrap();
+        let (allowed_fd, allowed_addr) = udp::bind_udp(Some(c"127.0.0.2"), c"0").unwrap();
+        let (denied, allowed) = (Socket::from_raw(denied_fd), Socket::from_raw(allowed_fd));
+
+        // The denied datagram is queued first, so it is received and dropped before the allowed one.
+        sendto_with(&denied, &receiver_addr).unwrap();
+        sendto_with(&allowed, &receiver_addr).unwrap();
+
+        let filter = SourceFilter {
+            allow: vec!["127.0.0.0/8".parse().unwrap()],
+            deny: vec!["127.0.0.3".parse().unwrap()],
+        };
+        let summary = recvfrom_with(&receiver, Some(1), &filter).unwrap();
+
+        assert_eq!(summary.messages, 1);
+        assert_eq!(summary.sources, [allowed_addr]);
+    }
 }
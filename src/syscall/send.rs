@@ -1,11 +1,18 @@
-use std::{error, fmt, io};
+use std::{error, fmt, io, time::Duration};
 
-use crate::syscall;
+use crate::{
+    socket_fd::Socket,
+    sockopt,
+    stream_io::{self, SendallError},
+    syscall,
+};
 
 #[derive(Debug)]
 pub enum Error {
     Accept(syscall::accept::Error),
-    Send(io::Error),
+    Setsockopt(io::Error),
+    Send(SendallError),
+    SendTimedOut(SendallError),
 }
 
 impl fmt::Display for Error {
@@ -14,7 +21,11 @@ impl fmt::Display for Error {
             Error::Accept(err) => {
                 write!(f, "failed to get accepted connection sock fd: {}", err)
             }
+            Error::Setsockopt(err) => write!(f, "setsockopt SO_SNDTIMEO err: {}", err),
             Error::Send(err) => write!(f, "send err: {}", err),
+            Error::SendTimedOut(err) => {
+                write!(f, "send err: timed out, the peer is not reading: {}", err)
+            }
         }
     }
 }
@@ -31,26 +42,72 @@ impl From<syscall::accept::Error> for Error {
 // MANPAGE:
 // man 2 send (Linux)
 // man 3 send (POSIX)
-pub fn send() -> Result<(), Error> {
+// man 7 socket (SO_SNDTIMEO)
+//
+// If `write_timeout` is set, `SO_SNDTIMEO` is set on the accepted connection, so that `send()` gives up
+// with `EAGAIN` instead of blocking forever once the peer stops reading and its receive buffer is full.
+pub fn send(write_timeout: Option<Duration>) -> Result<(), Error> {
     // NOTE: Since the example about `send()` is a pseudo-code, it is decided to use `accept()` to set up the process beforehand.
-    let conn_sock_fd = syscall::accept()?;
+    let (conn_sock, peer_addr) = syscall::accept()?;
+
+    if let Some(timeout) = write_timeout {
+        sockopt::set_timeout(conn_sock.as_raw(), libc::SO_SNDTIMEO, timeout)
+            .map_err(Error::Setsockopt)?;
+    }
+
+    send_with(&conn_sock)?;
+
+    println!("sent message to {}", peer_addr);
 
+    Ok(())
+}
+
+/// Same as `send()`, but sends the message through `sock` instead of accepting a connection first.
+/// `sock` can be any connected stream socket, e.g. one end of a `socketpair()`.
+///
+/// Returns the number of bytes sent. A send that times out (see `SO_SNDTIMEO`) fails with `Error::SendTimedOut`.
+pub fn send_with(sock: &Socket) -> Result<usize, Error> {
     let buf = b"hello world!\n";
-    let len = buf.len();
-
-    // SAFETY: For example purposes, the `send()` call is explicitly not checked to see whether all of buf is sent through the sock or not.
-    // `send()` is just checked to see whether it succeeded or not.
-    // Since the `conn_sock_fd` contains a initialized socket, and a fixed buf is used, it is safe to use `send()`.
-    unsafe {
-        let bytes_sent = libc::send(conn_sock_fd, buf.as_ptr() as *const libc::c_void, len, 0);
-        match bytes_sent {
-            -1 => {
-                let err = io::Error::last_os_error();
-                Err(Error::Send(err))
+
+    // A single `send()` may send less than it is asked for, e.g. once the send buffer is almost full.
+    // `sendall()` calls it again for the rest, and the error tells how much was sent before it failed.
+    stream_io::sendall(sock.as_raw(), buf).map_err(|err| match err.kind() {
+        io::ErrorKind::WouldBlock => Error::SendTimedOut(err),
+        _ => Error::Send(err),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Instant;
+
+    use super::*;
+    use crate::testutil::socketpair;
+
+    #[test]
+    fn send_to_a_peer_that_does_not_read_times_out() {
+        // The peer never reads, so the tiny send buffer fills up after a few messages.
+        let (sock, _peer) = socketpair();
+        sockopt::set_int(sock.as_raw(), libc::SOL_SOCKET, libc::SO_SNDBUF, 4096).unwrap();
+        sockopt::set_timeout(sock.as_raw(), libc::SO_SNDTIMEO, Duration::from_millis(200)).unwrap();
+
+        let (err, waited) = loop {
+            let started_at = Instant::now();
+            match send_with(&sock) {
+                Ok(_) => continue,
+                Err(err) => break (err, started_at.elapsed()),
             }
-            _ => Ok(()),
-        }
-    }?;
+        };
 
-    Ok(())
+        assert!(
+            matches!(err, Error::SendTimedOut(_)),
+            "unexpected error: {}",
+            err
+        );
+        assert!(
+            waited >= Duration::from_millis(150),
+            "gave up after {:?}",
+            waited
+        );
+    }
 }
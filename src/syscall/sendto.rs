@@ -1,14 +1,19 @@
-use std::{
-    error,
-    ffi::{CStr, CString},
-    fmt, io, mem, ptr,
+use std::{error, ffi::CString, fmt, io, net::SocketAddr};
+
+use crate::{
+    addrinfo::{AddrInfoList, Hints},
+    inet::{self, SendtoError},
+    sockaddr,
+    socket_fd::Socket,
+    trace,
 };
 
 #[derive(Debug)]
 pub enum Error {
     Getaddrinfo(String),
     Socket(io::Error),
-    Sendto(io::Error),
+    Sendto(SendtoError),
+    InvalidAddrFamily(i32),
 }
 
 impl fmt::Display for Error {
@@ -17,6 +22,7 @@ impl fmt::Display for Error {
             Error::Getaddrinfo(err) => write!(f, "getaddrinfo err: {}", err),
             Error::Socket(err) => write!(f, "sock err: {}", err),
             Error::Sendto(err) => write!(f, "sendto err: {}", err),
+            Error::InvalidAddrFamily(af) => write!(f, "invalid address family {}", af),
         }
     }
 }
@@ -31,36 +37,21 @@ pub fn sendto() -> Result<(), Error> {
     // This time, we are working with a DGRAM socket.
     // Therefore, we are not using `accept()` like we did for `send()`.
     // We simply try to send a message through a SOCK_DGRAM configured for 127.0.0.1:3490.
-    let node = ptr::null();
     let port = CString::from(c"3490");
 
-    // SAFETY: hints is initialized as empty, but the required fields are set later on.
-    let mut hints: libc::addrinfo = unsafe { mem::zeroed() };
-    hints.ai_family = libc::AF_INET;
-    hints.ai_socktype = libc::SOCK_DGRAM;
-
-    let mut res_ptr: *mut libc::addrinfo = ptr::null_mut();
+    let hints = Hints::new()
+        .family(libc::AF_INET)
+        .socktype(libc::SOCK_DGRAM)
+        .build();
 
-    // SAFETY:
-    // All the required vars are initialized for getaddrinfo().
-    // gai_stderror() is used for error cases only.
-    unsafe {
-        let s = libc::getaddrinfo(node, port.as_ptr(), &hints, &mut res_ptr);
-        match s {
-            0 => Ok(()),
-            _ => {
-                let err = CStr::from_ptr(libc::gai_strerror(s)).to_string_lossy();
-                Err(Error::Getaddrinfo(err.into_owned()))
-            }
-        }
-    }?;
+    // The list is freed by `freeaddrinfo()` when `res_list` goes out of scope.
+    let res_list = AddrInfoList::new(None, Some(&port), &hints).map_err(Error::Getaddrinfo)?;
+    let res = res_list.first();
 
-    // SAFETY: Since we are trying to get our loopback IP address via `getaddrinfo()`, we know that `res_ptr` points to an initialized memory, making `socket()` safe to use.
+    // SAFETY: Since we are trying to get our loopback IP address via `getaddrinfo()`, we know that `res` points to an initialized memory, making `socket()` safe to use.
     // Any potential `socket()` error is checked by reading `errno` instantly after the `socket()` call. This ensures that `sock_fd` contains the fd of a successfully created socket.
     let sock_fd = unsafe {
-        let res = *res_ptr;
-
-        let fd = libc::socket(res.ai_family, res.ai_socktype, 0);
+        let fd = trace::socket(res.ai_family, res.ai_socktype, 0);
         match fd {
             -1 => {
                 let err = io::Error::last_os_error();
@@ -70,40 +61,42 @@ pub fn sendto() -> Result<(), Error> {
         }
     }?;
 
+    let sock = Socket::from_raw(sock_fd);
+
+    // SAFETY: `res.ai_addr` is filled by `getaddrinfo()` and it is valid for `res.ai_addrlen` bytes.
+    let sa = unsafe { sockaddr::copy_to_storage(res.ai_addr, res.ai_addrlen) };
+    let dest_addr = sockaddr::to_socket_addr(&sa).ok_or(Error::InvalidAddrFamily(res.ai_family))?;
+
+    sendto_with(&sock, &dest_addr)?;
+
+    Ok(())
+}
+
+/// Same as `sendto()`, but sends the message through `sock` to `dest_addr`, instead of creating a socket
+/// for 127.0.0.1:3490. `sock` can be any `SOCK_DGRAM` socket of the same family as `dest_addr`.
+///
+/// Returns the number of bytes sent.
+pub fn sendto_with(sock: &Socket, dest_addr: &SocketAddr) -> Result<usize, Error> {
     let buf = b"hello world!\n";
     let len = buf.len();
+    let (sa, sa_len) = sockaddr::from_socket_addr(dest_addr);
 
-    // SAFETY: Due to the points above, `*res_ptr` is safe to use.
+    // SAFETY: `sa` holds a valid address of `sa_len` bytes.
     //
-    // For example purposes, the `sendto()` call is explicitly not checked to see whether all of buf is sent through the sock or not.
-    //
-    // `sendto()` is just checked to see whether it succeeded or not.
-    //
-    // Since the `sock_fd` contains an initialized socket, and the buf is initialized, it is safe to use `sendto()`.
-    unsafe {
-        let res = *res_ptr;
-
-        let bytes_sent = libc::sendto(
-            sock_fd,
+    // Since `sock` contains an initialized socket, and the buf is initialized, it is safe to use `sendto()`.
+    let bytes_sent = unsafe {
+        trace::sendto(
+            sock.as_raw(),
             buf.as_ptr() as *const libc::c_void,
             len,
             0,
-            res.ai_addr,
-            res.ai_addrlen,
-        );
-        match bytes_sent {
-            -1 => {
-                let err = io::Error::last_os_error();
-                Err(Error::Sendto(err))
-            }
-            _ => Ok(()),
-        }
-    }?;
+            &raw const sa as *const libc::sockaddr,
+            sa_len,
+        )
+    };
 
-    // Since `res_ptr` points to a valid initialized memory and will not be used after `sendto()`, it is safe to free it upon a successful `sendto()` call.
-    unsafe {
-        libc::freeaddrinfo(res_ptr);
-    }
-
-    Ok(())
+    // Unlike `send()` on a stream socket, there is no "rest of buf" to send in a second call.
+    // A datagram is sent as a whole, so fewer bytes than `len` means that it is truncated.
+    // A datagram that is too large for the socket fails with `EMSGSIZE` instead.
+    inet::check_sendto(bytes_sent, len).map_err(Error::Sendto)
 }
@@ -1,9 +1,14 @@
 use std::{
     error,
-    ffi::{CStr, CString},
+    ffi::CString,
     fmt,
     io::{self, Write},
-    mem, ptr,
+    ptr,
+};
+
+use crate::{
+    addrinfo::{AddrInfoList, Hints},
+    trace,
 };
 
 #[derive(Debug)]
@@ -45,37 +50,22 @@ impl error::Error for Error {}
 // man 2 send (to see the reason of EPIPE error)
 // man errno
 pub fn shutdown() -> Result<(), Error> {
-    let node = ptr::null();
     let port = CString::from(c"3490");
 
-    // SAFETY: hints is initialized as empty, but the required fields are set later on.
-    let mut hints: libc::addrinfo = unsafe { mem::zeroed() };
-    hints.ai_family = libc::AF_INET;
-    hints.ai_socktype = libc::SOCK_STREAM;
-
-    let mut res_ptr: *mut libc::addrinfo = ptr::null_mut();
+    let hints = Hints::new()
+        .family(libc::AF_INET)
+        .socktype(libc::SOCK_STREAM)
+        .build();
 
-    // SAFETY:
-    // 1 - All the required vars are initialized for getaddrinfo().
-    // 2 - gai_stderror() is used for error cases only.
-    unsafe {
-        let ecode = libc::getaddrinfo(node, port.as_ptr(), &hints, &mut res_ptr);
-        match ecode {
-            0 => Ok(()),
-            _ => {
-                let err = CStr::from_ptr(libc::gai_strerror(ecode)).to_string_lossy();
-                Err(Error::Getaddrinfo(err.into_owned()))
-            }
-        }
-    }?;
+    // The list is freed by `freeaddrinfo()` when `res_list` goes out of scope.
+    let res_list = AddrInfoList::new(None, Some(&port), &hints).map_err(Error::Getaddrinfo)?;
+    let res = res_list.first();
 
     // SAFETY:
-    // 1 - Since we are trying to get our loopback IP address via `getaddrinfo()`, we know that `res_ptr` points to an initialized memory, making `socket()` safe to use.
+    // 1 - Since we are trying to get our loopback IP address via `getaddrinfo()`, we know that `res` points to an initialized memory, making `socket()` safe to use.
     // 2 - Any potential `socket()` error is checked by reading `errno` instantly after the `socket()` call. This ensures that `sock_fd` contains the fd of a successfully created socket.
     let sock_fd = unsafe {
-        let res = *res_ptr;
-
-        let fd = libc::socket(res.ai_family, res.ai_socktype, 0);
+        let fd = trace::socket(res.ai_family, res.ai_socktype, 0);
         match fd {
             -1 => {
                 let err = io::Error::last_os_error();
@@ -86,25 +76,18 @@ pub fn shutdown() -> Result<(), Error> {
     }?;
 
     // SAFETY:
-    // 1 - Due to the points above, `res_ptr` and `sock_fd` are safe to use.
+    // 1 - Due to the points above, `res` and `sock_fd` are safe to use.
     // 2 - Any potential `bind()` error is checked by reading `errno` instantly after the `bind()` call.
     // This ensures that any errors that may happen in `bind()` are caught.
-    // 3 - Since `res_ptr` points to a valid initialized memory and will not be used after `bind()`, it is safe to free it upon a successful `bind()` call.
     unsafe {
-        let res = *res_ptr;
-
-        let ecode = libc::bind(sock_fd, res.ai_addr, res.ai_addrlen);
-        let res = match ecode {
+        let ecode = trace::bind(sock_fd, res.ai_addr, res.ai_addrlen);
+        match ecode {
             -1 => {
                 let err = io::Error::last_os_error();
                 Err(Error::Bind(sock_fd, err))
             }
             _ => Ok(()),
-        };
-
-        libc::freeaddrinfo(res_ptr);
-
-        res
+        }
     }?;
 
     // SAFETY:
@@ -113,7 +96,7 @@ pub fn shutdown() -> Result<(), Error> {
     unsafe {
         const BACKLOG: i32 = 10;
 
-        let ecode = libc::listen(sock_fd, BACKLOG);
+        let ecode = trace::listen(sock_fd, BACKLOG);
         match ecode {
             -1 => {
                 let err = io::Error::last_os_error();
@@ -123,33 +106,13 @@ pub fn shutdown() -> Result<(), Error> {
         }
     }?;
 
-    // SAFETY:
-    // 1- The uninitialized memory of `*addr_ptr` is initialized via `accept()`. This memory will hold the object regarding the accepted connection.
-    // 2 - Any potential `accept()` error is checked by reading `errno` instantly after the `accept()` call.
-    // 3 - The returned sock_fd is a valid fd created by a successful `accept()` call to interact with the accepted connection.
-    let conn_sock_fd = unsafe {
-        let from_addr: *mut libc::sockaddr_storage = ptr::null_mut();
-        let from_addr_len = mem::size_of::<libc::sockaddr_storage>();
-
-        let conn_sock_fd = libc::accept(
-            sock_fd,
-            from_addr as *mut libc::sockaddr,
-            from_addr_len as *mut u32,
-        );
-        match conn_sock_fd {
-            -1 => {
-                let err = io::Error::last_os_error();
-                Err(Error::Accept(sock_fd, err))
-            }
-            _ => Ok(conn_sock_fd),
-        }
-    }?;
+    let conn_sock_fd = accept_conn(sock_fd)?;
 
     // SAFETY:
     // 1 - The `conn_sock_fd` is a valid socket fd initialized by a successful `accept()` call.
     // 2 - Any potential `shutdown()` error is checked by reading `errno` instantly after the `shutdown()` call.
     unsafe {
-        let ecode = libc::shutdown(conn_sock_fd, 1);
+        let ecode = trace::shutdown(conn_sock_fd, 1);
         match ecode {
             -1 => {
                 let err = io::Error::last_os_error();
@@ -168,7 +131,7 @@ pub fn shutdown() -> Result<(), Error> {
     // 3 - Since the `conn_sock_fd` contains a initialized socket, and a fixed buf is used, it is safe to use `send()`.
     // 4 - Any potential `send()` error is checked by reading `errno` instantly after the `send()` call.
     unsafe {
-        let ecode = libc::send(
+        let ecode = trace::send(
             conn_sock_fd,
             send_buf.as_ptr() as *const libc::c_void,
             len,
@@ -190,3 +153,61 @@ pub fn shutdown() -> Result<(), Error> {
 
     Ok(())
 }
+
+// Accepts a connection on `sock_fd` without asking for the address of the peer.
+fn accept_conn(sock_fd: i32) -> Result<i32, Error> {
+    // SAFETY:
+    // 1 - The address of the peer is not needed, so both the address and its length are null, which `accept()` allows.
+    // 2 - Any potential `accept()` error is checked by reading `errno` instantly after the `accept()` call.
+    // 3 - The returned sock_fd is a valid fd created by a successful `accept()` call to interact with the accepted connection.
+    unsafe {
+        let conn_sock_fd = trace::accept(sock_fd, ptr::null_mut(), ptr::null_mut());
+        match conn_sock_fd {
+            -1 => {
+                let err = io::Error::last_os_error();
+                Err(Error::Accept(sock_fd, err))
+            }
+            _ => Ok(conn_sock_fd),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        inet,
+        listener::{self, ListenerOptions},
+        socket_fd::Socket,
+        stream_io,
+    };
+
+    #[test]
+    fn accept_without_a_peer_address_returns_a_valid_fd() {
+        let (listener_fd, addr) = listener::bind_listener_with(
+            Some(c"127.0.0.1"),
+            libc::AF_INET,
+            c"0",
+            &ListenerOptions::default(),
+        )
+        .unwrap();
+        let listener = Socket::from_raw(listener_fd);
+
+        // The connection completes in the backlog, before it is accepted.
+        let client = Socket::from_raw(inet::connect_to(&addr).unwrap());
+        let conn = Socket::from_raw(accept_conn(listener.as_raw()).unwrap());
+
+        // SAFETY: `fcntl()` only reads the flags of the fd.
+        assert_ne!(unsafe { libc::fcntl(conn.as_raw(), libc::F_GETFD) }, -1);
+        assert_eq!(
+            listener::local_addr(conn.as_raw()).unwrap(),
+            addr,
+            "the connection is not on the listener's address"
+        );
+
+        stream_io::sendall(conn.as_raw(), b"hello").unwrap();
+        let mut buf = [0u8; 5];
+        assert_eq!(stream_io::recv_exact(client.as_raw(), &mut buf).unwrap(), 5);
+        assert_eq!(&buf, b"hello");
+    }
+}
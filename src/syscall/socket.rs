@@ -1,8 +1,9 @@
 use core::fmt;
-use std::{
-    error,
-    ffi::{CStr, CString},
-    io, mem, ptr,
+use std::{error, ffi::CString, io};
+
+use crate::{
+    addrinfo::{AddrInfoList, Hints},
+    trace,
 };
 
 #[derive(Debug)]
@@ -28,35 +29,21 @@ impl error::Error for Error {}
 pub fn socket() -> Result<(), Error> {
     // Preparing the getaddrinfo call.
     let node = CString::new("www.example.com").unwrap();
-    let node_ptr = node.as_ptr();
-
     let service = CString::new("http").unwrap();
-    let service_ptr = service.as_ptr();
-
-    // SAFETY: hints is initialized as empty, but the required fields are set later on.
-    let mut hints: libc::addrinfo = unsafe { mem::zeroed() };
-    hints.ai_family = libc::AF_INET;
-    hints.ai_socktype = libc::SOCK_STREAM;
 
-    let mut res_ptr = ptr::null_mut();
+    let hints = Hints::new()
+        .family(libc::AF_INET)
+        .socktype(libc::SOCK_STREAM)
+        .build();
 
-    // SAFETY: all the required vars are initialized for getaddrinfo().
-    // gai_stderror() is used for error cases only.
-    unsafe {
-        let s = libc::getaddrinfo(node_ptr, service_ptr, &hints, &mut res_ptr);
-        if s != 0 {
-            let err = CStr::from_ptr(libc::gai_strerror(s)).to_string_lossy();
-            Err(Error::Getaddrinfo(err.into_owned()))
-        } else {
-            Ok(())
-        }
-    }?;
+    // The list is freed by `freeaddrinfo()` when `res_list` goes out of scope.
+    let res_list =
+        AddrInfoList::new(Some(&node), Some(&service), &hints).map_err(Error::Getaddrinfo)?;
+    let res = res_list.first();
 
-    // SAFETY: `res_ptr` is initialized upon a successful getaddrinfo() call.
-    // Therefore we can guarantee that there is atleast one addrinfo that `res_ptr` points to, making deref safe.
+    // SAFETY: `res` is filled by a successful getaddrinfo() call.
     let sock_fd = unsafe {
-        let res = *res_ptr;
-        let sock_fd = libc::socket(res.ai_family, res.ai_socktype, 0);
+        let sock_fd = trace::socket(res.ai_family, res.ai_socktype, 0);
         if sock_fd == -1 {
             let err = io::Error::last_os_error();
             return Err(Error::Socket(err));
@@ -67,10 +54,5 @@ pub fn socket() -> Result<(), Error> {
 
     println!("created sock fd: {}", sock_fd);
 
-    // SAFETY: `res_ptr` will not be used after this call, therefore it is safe to free it.
-    unsafe {
-        libc::freeaddrinfo(res_ptr);
-    }
-
     Ok(())
 }
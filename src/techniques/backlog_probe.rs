@@ -0,0 +1,119 @@
+use std::{error, fmt, io};
+
+use crate::{
+    fd,
+    listener::{self, BindRetry, ListenerOptions},
+};
+
+#[derive(Debug)]
+pub enum Error {
+    Listener(listener::Error),
+    Getsockopt(io::Error),
+    Close(io::Error),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Listener(err) => write!(f, "listener error: {}", err),
+            Error::Getsockopt(err) => write!(f, "getsockopt TCP_INFO error: {}", err),
+            Error::Close(err) => write!(f, "close error: {}", err),
+        }
+    }
+}
+
+impl error::Error for Error {}
+
+impl From<listener::Error> for Error {
+    fn from(value: listener::Error) -> Self {
+        Self::Listener(value)
+    }
+}
+
+// EXAMPLE: Listens with the `requested` backlog and reports the accept queue length the kernel actually uses.
+// MANPAGE:
+// man 2 listen (Linux)
+// man 7 tcp (Linux)
+//
+// `listen()` does not fail when the backlog is too large, it silently caps it.
+// On Linux, the cap is `net.core.somaxconn`, and a negative backlog is taken as the cap itself.
+// The effective value is only visible through `TCP_INFO`: for a listening socket, `tcpi_sacked` is the
+// maximum length of the accept queue and `tcpi_unacked` is the number of connections waiting in it.
+// On the other platforms, neither of them can be read, so only the requested backlog is printed.
+pub fn backlog_probe(requested: i32) -> Result<(), Error> {
+    let opts = ListenerOptions {
+        reuseaddr: true,
+        backlog: requested,
+        retry: BindRetry::default(),
+        max_segment: None,
+        v6only: false,
+        defer_accept: None,
+    };
+    // Port 0 lets the kernel pick a free port, the probe does not care which one it is.
+    let (sock_fd, addr) = listener::bind_listener_with(None, libc::AF_INET, c"0", &opts)?;
+    println!(
+        "backlog_probe: listening on {} with backlog {}",
+        addr, requested
+    );
+
+    let res = report(sock_fd, requested);
+
+    // `sock_fd` is not used after this call.
+    fd::close_fd(sock_fd).map_err(Error::Close)?;
+
+    res
+}
+
+#[cfg(target_os = "linux")]
+fn report(sock_fd: i32, requested: i32) -> Result<(), Error> {
+    use std::{fs, mem};
+
+    let somaxconn = fs::read_to_string("/proc/sys/net/core/somaxconn")
+        .ok()
+        .and_then(|value| value.trim().parse::<i32>().ok());
+    match somaxconn {
+        Some(somaxconn) => println!("backlog_probe: net.core.somaxconn = {}", somaxconn),
+        None => println!("backlog_probe: net.core.somaxconn could not be read"),
+    }
+
+    // SAFETY: All zero `tcp_info` is a valid initialization, it only consists of integers.
+    let mut info: libc::tcp_info = unsafe { mem::zeroed() };
+    let mut len = mem::size_of_val(&info) as libc::socklen_t;
+
+    // SAFETY: `info` is valid for `len` bytes, and `len` is updated with the number of bytes written to it.
+    let ecode = unsafe {
+        libc::getsockopt(
+            sock_fd,
+            libc::IPPROTO_TCP,
+            libc::TCP_INFO,
+            &raw mut info as *mut libc::c_void,
+            &raw mut len,
+        )
+    };
+    if ecode == -1 {
+        return Err(Error::Getsockopt(io::Error::last_os_error()));
+    }
+
+    println!(
+        "backlog_probe: effective backlog = {}, queued connections = {}",
+        info.tcpi_sacked, info.tcpi_unacked
+    );
+    if i64::from(info.tcpi_sacked) != i64::from(requested) {
+        println!(
+            "backlog_probe: the requested backlog of {} is capped to {} by the kernel",
+            requested, info.tcpi_sacked
+        );
+    }
+
+    Ok(())
+}
+
+#[cfg(not(target_os = "linux"))]
+fn report(_sock_fd: i32, requested: i32) -> Result<(), Error> {
+    println!(
+        "backlog_probe: the effective backlog cannot be read on this OS, requested {}",
+        requested
+    );
+
+    Ok(())
+}
@@ -1,10 +1,13 @@
 use std::{error, fmt, io, ptr};
 
+use crate::{fd, trace};
+
 #[derive(Debug)]
 pub enum Error {
     Socket(io::Error),
     Fcntl(io::Error),
     Recv(io::Error),
+    Close(io::Error),
 }
 
 impl fmt::Display for Error {
@@ -13,6 +16,7 @@ impl fmt::Display for Error {
             Error::Socket(err) => write!(f, "socket error: {}", err),
             Error::Fcntl(err) => write!(f, "fcntl error: {}", err),
             Error::Recv(err) => write!(f, "recv error: {}", err),
+            Error::Close(err) => write!(f, "close error: {}", err),
         }
     }
 }
@@ -20,28 +24,28 @@ impl fmt::Display for Error {
 impl error::Error for Error {}
 
 // EXAMPLE: Attempt to recv from a non-blocking socket.
+// The socket is put back into blocking mode before returning, `O_NONBLOCK` can be cleared just like it is set.
 // MANPAGE:
 // man 2 fcntl (Linux)
 // man 3 fcntl (POSIX)
 // man errno
 pub fn blocking() -> Result<(), Error> {
     // SAFETY: There are no reads to uninitialized memory, making `socket()` safe to use.
-    let sock = unsafe { libc::socket(libc::PF_INET, libc::SOCK_DGRAM, 0) };
+    let sock = unsafe { trace::socket(libc::PF_INET, libc::SOCK_DGRAM, 0) };
     match sock {
         -1 => Err(Error::Socket(io::Error::last_os_error())),
         _ => Ok(()),
     }?;
 
-    // SAFETY: `fnctl()` is called on a valid socket.
-    let res = unsafe { libc::fcntl(sock, libc::F_SETFL, libc::O_NONBLOCK) };
-    match res {
-        -1 => Err(Error::Fcntl(io::Error::last_os_error())),
-        _ => Ok(()),
-    }?;
+    fd::set_nonblocking(sock).map_err(Error::Fcntl)?;
+    println!(
+        "O_NONBLOCK is set: {}",
+        fd::is_nonblocking(sock).map_err(Error::Fcntl)?
+    );
 
     // SAFETY: There are no reads to uninitialized memory, making `recvfrom()` safe to use.
     let bytes = unsafe {
-        libc::recvfrom(
+        trace::recvfrom(
             sock,
             [0; 1].as_mut_ptr() as *mut libc::c_void,
             1,
@@ -50,12 +54,24 @@ pub fn blocking() -> Result<(), Error> {
             ptr::null_mut(),
         )
     };
-    match bytes {
+    let res = match bytes {
         // NOTE: EAGAIN or EWOULDBLOCK may be received from the OS.
         // Search the err message in `man errno` to find our the exact err code.
         -1 => Err(Error::Recv(io::Error::last_os_error())),
         _ => Ok(()),
-    }?;
+    };
+
+    // The failed `recvfrom()` above is reported only after the socket is restored and closed.
+    fd::set_blocking(sock).map_err(Error::Fcntl)?;
+    println!(
+        "O_NONBLOCK is set: {}",
+        fd::is_nonblocking(sock).map_err(Error::Fcntl)?
+    );
+
+    // `sock` is not used after this call.
+    fd::close_fd(sock).map_err(Error::Close)?;
+
+    res?;
 
     // Bytes are intentionally printed here to observe that the process
     // cannot reach the line below.
@@ -4,14 +4,23 @@ use std::{
     mem,
     net::{AddrParseError, Ipv4Addr},
     str::FromStr,
+    thread,
+    time::Duration,
+};
+
+use crate::{
+    fd,
+    inet::{self, SendtoError},
+    trace,
 };
 
 #[derive(Debug)]
 pub enum Error {
     Socket(io::Error),
     InvalidInetAddr(AddrParseError),
-    Sendto(io::Error),
+    Sendto(SendtoError),
     Setsockopt(io::Error),
+    Close(io::Error),
 }
 
 impl fmt::Display for Error {
@@ -21,6 +30,7 @@ impl fmt::Display for Error {
             Error::InvalidInetAddr(err) => write!(f, "failed to parse host IP addr: {}", err),
             Error::Sendto(err) => write!(f, "sendto error: {}", err),
             Error::Setsockopt(err) => write!(f, "setsockopt error: {}", err),
+            Error::Close(err) => write!(f, "close error: {}", err),
         }
     }
 }
@@ -38,12 +48,21 @@ impl From<AddrParseError> for Error {
 // man 2 setsockopt
 // man 7 socket
 // man errno
-pub fn broadcaster(host: &str, msg: &str) -> Result<(), Error> {
+//
+// The message is sent to `port`, `count` times, `interval` apart, like a discovery beacon. The same broadcast-enabled
+// socket is used for all of them, and the number of datagrams and bytes sent is reported at the end.
+pub fn broadcaster(
+    host: &str,
+    port: u16,
+    msg: &str,
+    count: u32,
+    interval: Duration,
+) -> Result<(), Error> {
     let host_ip_addr = Ipv4Addr::from_str(host)?;
 
     // SAFETY: Hardcoded opts are used: An INET DGRAM sock.
     // `socket()` is safe to call.
-    let sock_fd = unsafe { libc::socket(libc::AF_INET, libc::SOCK_DGRAM, 0) };
+    let sock_fd = unsafe { trace::socket(libc::AF_INET, libc::SOCK_DGRAM, 0) };
     if sock_fd == -1 {
         Err(Error::Socket(io::Error::last_os_error()))?;
     }
@@ -65,8 +84,6 @@ pub fn broadcaster(host: &str, msg: &str) -> Result<(), Error> {
         Err(Error::Setsockopt(io::Error::last_os_error()))?;
     }
 
-    let port: u16 = 4950;
-
     // SAFETY: The required fields are set to initialize a valid
     // `sockaddr_in`.
     // `sockaddr_in.sin_zero` is left as full zeroes, which is valid
@@ -74,30 +91,90 @@ pub fn broadcaster(host: &str, msg: &str) -> Result<(), Error> {
     // It is safe to read from `sa_host`.
     let mut sa_host: libc::sockaddr_in = unsafe { mem::zeroed() };
     sa_host.sin_family = libc::AF_INET as u16;
-    sa_host.sin_port = u16::from_be(port);
-    sa_host.sin_addr.s_addr = u32::from_be(host_ip_addr.to_bits());
+    sa_host.sin_port = port.to_be();
+    sa_host.sin_addr.s_addr = host_ip_addr.to_bits().to_be();
 
-    // SAFETY: All variables are initialized properly.
-    // `sendto()` is safe to call.
-    let sbytes = unsafe {
-        libc::sendto(
-            sock_fd,
-            msg.as_ptr() as *const libc::c_void,
-            msg.len(),
-            0,
-            &raw const sa_host as *const libc::sockaddr,
-            mem::size_of_val(&sa_host) as u32,
-        )
-    };
-    if sbytes == -1 {
-        Err(Error::Sendto(io::Error::last_os_error()))?;
-    }
+    let res = send_beacons(sock_fd, &sa_host, msg, count, interval);
 
-    println!("sent {} bytes to {}", sbytes, host_ip_addr);
-
-    // SAFETY: We have no use for `sock_fd` at this point.
+    // We have no use for `sock_fd` at this point.
     // It is safe to close.
-    unsafe { libc::close(sock_fd) };
+    fd::close_fd(sock_fd).map_err(Error::Close)?;
+
+    let (datagrams, bytes) = res?;
+    println!(
+        "broadcaster: sent {} datagrams, {} bytes in total",
+        datagrams, bytes
+    );
 
     Ok(())
 }
+
+// Sends `msg` to `sa_host` `count` times, sleeping `interval` in between.
+// Returns the number of datagrams and bytes sent.
+fn send_beacons(
+    sock_fd: i32,
+    sa_host: &libc::sockaddr_in,
+    msg: &str,
+    count: u32,
+    interval: Duration,
+) -> Result<(u32, usize), Error> {
+    let host_ip_addr = Ipv4Addr::from_bits(u32::from_be(sa_host.sin_addr.s_addr));
+    let mut bytes = 0;
+
+    for i in 0..count {
+        if i > 0 {
+            thread::sleep(interval);
+        }
+
+        // SAFETY: All variables are initialized properly.
+        // `sendto()` is safe to call.
+        let sbytes = unsafe {
+            trace::sendto(
+                sock_fd,
+                msg.as_ptr() as *const libc::c_void,
+                msg.len(),
+                0,
+                sa_host as *const libc::sockaddr_in as *const libc::sockaddr,
+                mem::size_of_val(sa_host) as u32,
+            )
+        };
+        let sbytes = inet::check_sendto(sbytes, msg.len()).map_err(Error::Sendto)?;
+        bytes += sbytes;
+
+        println!("sent {} bytes to {}", sbytes, host_ip_addr);
+    }
+
+    Ok((count, bytes))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{socket_fd::Socket, udp};
+
+    #[test]
+    fn loopback_receiver_counts_every_beacon() {
+        let (sock_fd, addr) = udp::bind_udp(Some(c"127.0.0.1"), c"0").unwrap();
+        let receiver = Socket::from_raw(sock_fd);
+        udp::set_recv_timeout(receiver.as_raw(), Duration::from_millis(500)).unwrap();
+
+        broadcaster(
+            "127.0.0.1",
+            addr.port(),
+            "beacon",
+            3,
+            Duration::from_millis(10),
+        )
+        .unwrap();
+
+        let mut buf = [0u8; 64];
+        let mut beacons = 0;
+        // Nothing else is sent to the receiver, so the wait only times out once every beacon is counted.
+        while let Ok((rbytes, _)) = udp::recv_from(receiver.as_raw(), &mut buf) {
+            assert_eq!(&buf[..rbytes], b"beacon");
+            beacons += 1;
+        }
+
+        assert_eq!(beacons, 3);
+    }
+}
@@ -0,0 +1,588 @@
+use std::{
+    error,
+    ffi::CStr,
+    fmt,
+    io::{self, BufRead, Read, Write},
+    net::SocketAddr,
+    ptr, thread,
+};
+
+use crate::{
+    client_builder::Client,
+    color, fd, hexdump, inet,
+    listener::{self, BindRetry},
+    socket_fd::{ReadHalf, Socket, WriteHalf},
+    stream_io, trace,
+};
+
+#[derive(Debug)]
+pub enum Error {
+    Listener(listener::Error),
+    Inet(inet::Error),
+    Accept(io::Error),
+    Connect(inet::ConnectError),
+    Poll(io::Error),
+    Read(io::Error),
+    Recv(io::Error),
+    Send(io::Error),
+    Shutdown(io::Error),
+    Close(io::Error),
+    Split(io::Error),
+    ReceiverPanicked,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Listener(err) => write!(f, "listener error: {}", err),
+            Error::Inet(err) => write!(f, "inet error: {}", err),
+            Error::Accept(err) => write!(f, "accept error: {}", err),
+            Error::Connect(err) => write!(f, "connect error: {}", err),
+            Error::Poll(err) => write!(f, "poll error: {}", err),
+            Error::Read(err) => write!(f, "stdin read error: {}", err),
+            Error::Recv(err) => write!(f, "recv error: {}", err),
+            Error::Send(err) => write!(f, "send error: {}", err),
+            Error::Shutdown(err) => write!(f, "shutdown error: {}", err),
+            Error::Close(err) => write!(f, "close error: {}", err),
+            Error::Split(err) => write!(f, "failed to split the socket: {}", err),
+            Error::ReceiverPanicked => write!(f, "the receiver thread panicked"),
+        }
+    }
+}
+
+impl error::Error for Error {}
+
+impl From<listener::Error> for Error {
+    fn from(value: listener::Error) -> Self {
+        Self::Listener(value)
+    }
+}
+
+impl From<inet::Error> for Error {
+    fn from(value: inet::Error) -> Self {
+        Self::Inet(value)
+    }
+}
+
+/// The way `echoclient()` talks to the echo server.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Duplex {
+    /// Send a line, then wait for its echo before sending the next one.
+    Half,
+    /// Send and receive at the same time by polling stdin and the socket together.
+    Full,
+    /// Send and receive at the same time from two threads, each with its own half of the socket.
+    Threads,
+}
+
+const ECHO_PORT: &CStr = c"3490";
+const ECHO_PORT_NUM: u16 = 3490;
+const ECHO_BUF_SIZE: usize = 256;
+
+// EXAMPLE: An echo server that sends back everything it receives from a connected peer.
+// Clients are served one at a time.
+// MANPAGE:
+// man 2 recv (Linux)
+// man 2 send (Linux)
+// man errno
+//
+// If `hexdump_both` is set, every received chunk and every sent chunk is hexdumped,
+// which shows that an echo server sends back exactly the bytes it receives.
+pub fn echoserver(
+    retry: &BindRetry,
+    max_bytes: Option<usize>,
+    hexdump_both: bool,
+) -> Result<(), Error> {
+    let (sock_fd, _) = listener::bind_listener(libc::AF_UNSPEC, ECHO_PORT, retry)?;
+
+    println!("echoserver: waiting for connections...");
+
+    loop {
+        // SAFETY: The peer address is not needed, passing null pointers to `accept()` is valid.
+        let conn_sock_fd = unsafe { trace::accept(sock_fd, ptr::null_mut(), ptr::null_mut()) };
+        match conn_sock_fd {
+            -1 => Err(Error::Accept(io::Error::last_os_error())),
+            _ => Ok(()),
+        }?;
+
+        println!("echoserver: serving client on socket {}", conn_sock_fd);
+
+        let res = echo_back(conn_sock_fd, max_bytes, hexdump_both);
+
+        // The client is served, `conn_sock_fd` is not used after this call.
+        fd::close_fd(conn_sock_fd).map_err(Error::Close)?;
+
+        match res {
+            Ok(rbytes) => println!(
+                "echoserver: socket {} closed after {} bytes",
+                conn_sock_fd, rbytes
+            ),
+            Err(err) => eprintln!("echoserver: {}", err),
+        }
+    }
+}
+
+// Echoes until the client hangs up, or until `max_bytes` are read.
+// Returns the number of bytes read from the client.
+fn echo_back(
+    conn_sock_fd: i32,
+    max_bytes: Option<usize>,
+    hexdump_both: bool,
+) -> Result<usize, Error> {
+    let mut buf = [0u8; ECHO_BUF_SIZE];
+    let mut total = 0;
+
+    loop {
+        // Never ask for more than what is left, so that exactly `max_bytes` are read from a chatty client.
+        let len = max_bytes.map_or(buf.len(), |max| buf.len().min(max - total));
+        if len == 0 {
+            return Ok(total);
+        }
+
+        // SAFETY: `buf` is initialized and `len` does not exceed its size, making `recv()` safe to call.
+        let rbytes =
+            unsafe { trace::recv(conn_sock_fd, buf.as_mut_ptr() as *mut libc::c_void, len, 0) };
+        match rbytes {
+            -1 => {
+                let err = io::Error::last_os_error();
+                if !stream_io::is_reset(&err) {
+                    return Err(Error::Recv(err));
+                }
+
+                println!(
+                    "echoserver: socket {} was reset by the client",
+                    conn_sock_fd
+                );
+                return Ok(total);
+            }
+            0 => return Ok(total),
+            _ => total += rbytes as usize,
+        }
+
+        if hexdump_both {
+            print!(
+                "echoserver: received {} bytes\n{}",
+                rbytes,
+                hexdump::hexdump_with(&buf[..rbytes as usize], color::stdout())
+            );
+        }
+
+        // A single `send()` may send less than it is asked for, `sendall()` sends the rest as well.
+        // Only the first `rbytes` of `buf` are sent back, which are written by `recv()` above.
+        let echoed = &buf[..rbytes as usize];
+        let sbytes =
+            stream_io::sendall(conn_sock_fd, echoed).map_err(|err| Error::Send(err.into()))?;
+
+        if hexdump_both {
+            print!(
+                "echoserver: sent {} bytes\n{}",
+                sbytes,
+                hexdump::hexdump_with(echoed, color::stdout())
+            );
+        }
+    }
+}
+
+// EXAMPLE: An echo client that sends stdin to the echo server and prints the echoed bytes.
+// In half-duplex mode, the client can only do one thing at a time: it is either sending or receiving.
+// In full-duplex mode, `poll()` is used to wait on stdin and the socket at the same time,
+// which is required to talk and listen on a bidirectional stream without blocking on either side.
+// The threads mode does the same without `poll()`: the socket is split into two halves, and a thread
+// receives on one half while the main thread blocks on stdin and sends on the other.
+// MANPAGE:
+// man 2 poll (Linux)
+// man 2 shutdown (Linux)
+// man errno
+//
+// If `host` is already an IP address, it is connected to directly without going through `getaddrinfo()`.
+// Otherwise, the address that is connected to is printed, since `host` may resolve to both IPv4 and IPv6 addresses.
+//
+// A link-local IPv6 `host` needs a zone, either as a suffix (e.g. `fe80::1%eth0`) or as `scope`.
+pub fn echoclient(host: &str, scope: Option<&str>, duplex: Duplex) -> Result<(), Error> {
+    let host = match scope {
+        Some(scope) if !host.contains('%') => format!("{}%{}", host, scope),
+        _ => host.to_string(),
+    };
+
+    let (sock_fd, server_addr) = match inet::parse_ip_addr(&host, ECHO_PORT_NUM) {
+        Some(server_addr) => {
+            let server_addr = server_addr?;
+            (inet::connect_to(&server_addr)?, server_addr)
+        }
+        None => connect_host(&host)?,
+    };
+    println!("echoclient: connected to {}", server_addr);
+
+    if duplex == Duplex::Threads {
+        // The halves close their own fds.
+        let (read_half, write_half) = Socket::from_raw(sock_fd)
+            .into_split()
+            .map_err(Error::Split)?;
+        return threads_duplex(read_half, write_half);
+    }
+
+    let res = match duplex {
+        Duplex::Half => half_duplex(sock_fd),
+        _ => full_duplex(libc::STDIN_FILENO, sock_fd, &mut io::stdout()),
+    };
+
+    // The conversation has ended, `sock_fd` is not used after this call.
+    fd::close_fd(sock_fd).map_err(Error::Close)?;
+
+    res
+}
+
+/// Connects to the first address of `host` that accepts the connection.
+///
+/// Returns the sock fd and the address it is connected to.
+fn connect_host(host: &str) -> Result<(i32, SocketAddr), Error> {
+    let (sock, server_addr) = Client::new()
+        .host(host)
+        .port(ECHO_PORT_NUM)
+        .connect()
+        .map_err(Error::Connect)?;
+
+    Ok((sock.into_raw(), server_addr))
+}
+
+fn half_duplex(sock_fd: i32) -> Result<(), Error> {
+    let mut buf = [0u8; ECHO_BUF_SIZE];
+
+    for line in io::stdin().lock().lines() {
+        let line = line.map_err(Error::Read)? + "\n";
+
+        // A single `send()` may take only a part of a long line, the rest would be lost without `sendall()`.
+        let sbytes =
+            stream_io::sendall(sock_fd, line.as_bytes()).map_err(|err| Error::Send(err.into()))?;
+
+        // While waiting for the echo, the client cannot send anything else.
+        // The echo may arrive in multiple segments, so keep receiving until all of it is back.
+        let mut pending = sbytes;
+        while pending > 0 {
+            // SAFETY: `buf` is initialized, making `recv()` safe to call.
+            let rbytes = unsafe {
+                trace::recv(sock_fd, buf.as_mut_ptr() as *mut libc::c_void, buf.len(), 0)
+            };
+            match rbytes {
+                -1 => return Err(Error::Recv(io::Error::last_os_error())),
+                0 => {
+                    println!("echoclient: server hung up");
+                    return Ok(());
+                }
+                n => {
+                    io::stdout()
+                        .write_all(&buf[..n as usize])
+                        .expect("echo to be written to stdout");
+                    pending = pending.saturating_sub(n as usize);
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn threads_duplex(read_half: ReadHalf, write_half: WriteHalf) -> Result<(), Error> {
+    let receiver = thread::spawn(move || -> Result<(), Error> {
+        let mut buf = [0u8; ECHO_BUF_SIZE];
+        loop {
+            match read_half.recv(&mut buf).map_err(Error::Recv)? {
+                0 => {
+                    println!("echoclient: server hung up");
+                    return Ok(());
+                }
+                n => io::stdout()
+                    .write_all(&buf[..n])
+                    .expect("echo to be written to stdout"),
+            }
+        }
+    });
+
+    let mut buf = [0u8; ECHO_BUF_SIZE];
+    let mut stdin = io::stdin().lock();
+    let res = loop {
+        match stdin.read(&mut buf) {
+            Ok(0) => break write_half.shutdown().map_err(Error::Shutdown),
+            Ok(n) => {
+                if let Err(err) = write_half.send_all(&buf[..n]) {
+                    break Err(Error::Send(err));
+                }
+            }
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => {}
+            Err(err) => break Err(Error::Read(err)),
+        }
+    };
+
+    // On an error, the receiver is only waited for once the server hangs up.
+    // Shutting the write half down makes that happen, the server closes after echoing everything back.
+    if res.is_err() {
+        let _ = write_half.shutdown();
+    }
+    let received = receiver.join().map_err(|_| Error::ReceiverPanicked)?;
+
+    res.and(received)
+}
+
+// Sends everything read from `input_fd` to `sock_fd`, and writes everything received from `sock_fd` into `output`,
+// both at the same time.
+//
+// `poll()` waits on both directions of the socket: `POLLIN` for the echo, and `POLLOUT` while there is
+// input that is not sent yet. The socket is never written to unless `poll()` says there is room,
+// and then only as much as it takes without blocking (`MSG_DONTWAIT`), the rest is kept for later.
+// A blocking `send()` could otherwise wait for the server to read, while the server waits for the client
+// to read its echo: with enough input (e.g. `< /etc/services`), both send buffers fill up and neither side moves.
+// For the same reason, the input is only read once the previous chunk is sent in full.
+fn full_duplex(input_fd: i32, sock_fd: i32, output: &mut impl Write) -> Result<(), Error> {
+    const INPUT: usize = 0;
+    const SOCK: usize = 1;
+
+    let mut pfds = [
+        libc::pollfd {
+            fd: input_fd,
+            events: libc::POLLIN,
+            revents: 0,
+        },
+        libc::pollfd {
+            fd: sock_fd,
+            events: libc::POLLIN,
+            revents: 0,
+        },
+    ];
+
+    let mut buf = [0u8; ECHO_BUF_SIZE];
+    let mut outgoing: Vec<u8> = Vec::with_capacity(ECHO_BUF_SIZE);
+    let mut input_done = false;
+    let mut write_shut = false;
+
+    loop {
+        // A negative fd is ignored by `poll()`, the input is not read while there is a chunk left to send.
+        pfds[INPUT].fd = if input_done || !outgoing.is_empty() {
+            -1
+        } else {
+            input_fd
+        };
+        pfds[SOCK].events = if outgoing.is_empty() {
+            libc::POLLIN
+        } else {
+            libc::POLLIN | libc::POLLOUT
+        };
+
+        // SAFETY: `pfds` is initialized properly, making `poll()` safe to call.
+        let ecode = unsafe { libc::poll(pfds.as_mut_ptr(), pfds.len() as libc::nfds_t, -1) };
+        if ecode == -1 {
+            let err = io::Error::last_os_error();
+            if err.kind() == io::ErrorKind::Interrupted {
+                continue;
+            }
+            return Err(Error::Poll(err));
+        }
+
+        if pfds[INPUT].revents & (libc::POLLIN | libc::POLLHUP) != 0 {
+            // NOTE: The input is read via `read()` instead of `io::stdin()` on purpose.
+            // Buffered stdin may hold data that `poll()` does not know about anymore.
+            //
+            // SAFETY: `buf` is initialized, making `read()` safe to call.
+            let rbytes =
+                unsafe { libc::read(input_fd, buf.as_mut_ptr() as *mut libc::c_void, buf.len()) };
+            match rbytes {
+                -1 => {
+                    let err = io::Error::last_os_error();
+                    if err.kind() != io::ErrorKind::Interrupted {
+                        return Err(Error::Read(err));
+                    }
+                }
+                0 => input_done = true,
+                n => outgoing.extend_from_slice(&buf[..n as usize]),
+            }
+        }
+
+        if pfds[SOCK].revents & libc::POLLOUT != 0 && !outgoing.is_empty() {
+            // SAFETY: `outgoing` is initialized and valid for the entire `send()` call.
+            let sbytes = unsafe {
+                trace::send(
+                    sock_fd,
+                    outgoing.as_ptr() as *const libc::c_void,
+                    outgoing.len(),
+                    libc::MSG_DONTWAIT,
+                )
+            };
+            match sbytes {
+                -1 => {
+                    let err = io::Error::last_os_error();
+                    if !matches!(
+                        err.kind(),
+                        io::ErrorKind::WouldBlock | io::ErrorKind::Interrupted
+                    ) {
+                        return Err(Error::Send(err));
+                    }
+                }
+                n => {
+                    outgoing.drain(..n as usize);
+                }
+            }
+        }
+
+        // There is nothing left to send. Tell the server we are done writing,
+        // but keep reading until it echoes everything back and hangs up.
+        if input_done && outgoing.is_empty() && !write_shut {
+            // SAFETY: `sock_fd` is a valid connected socket.
+            let ecode = unsafe { trace::shutdown(sock_fd, libc::SHUT_WR) };
+            if ecode == -1 {
+                return Err(Error::Shutdown(io::Error::last_os_error()));
+            }
+            write_shut = true;
+        }
+
+        if pfds[SOCK].revents & (libc::POLLIN | libc::POLLHUP | libc::POLLERR) != 0 {
+            // SAFETY: `buf` is initialized, making `recv()` safe to call.
+            let rbytes = unsafe {
+                trace::recv(sock_fd, buf.as_mut_ptr() as *mut libc::c_void, buf.len(), 0)
+            };
+            match rbytes {
+                -1 => {
+                    let err = io::Error::last_os_error();
+                    if err.kind() != io::ErrorKind::Interrupted {
+                        return Err(Error::Recv(err));
+                    }
+                }
+                0 => {
+                    println!("echoclient: server hung up");
+                    return Ok(());
+                }
+                n => output
+                    .write_all(&buf[..n as usize])
+                    .expect("echo to be written to the output"),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{payload, server_builder::Server, testutil::socketpair};
+
+    // Starts an echo server on an ephemeral loopback port that serves a single client.
+    fn spawn_echoserver() -> (u16, thread::JoinHandle<usize>) {
+        let listener = Server::new().bind(Some("127.0.0.1"), 0).listen().unwrap();
+        let port = listener.local_addr().port();
+
+        let server = thread::spawn(move || {
+            let (conn, _) = listener.accept().unwrap();
+            echo_back(conn.as_raw(), None, false).unwrap()
+        });
+
+        (port, server)
+    }
+
+    fn connect(port: u16) -> Socket {
+        let (sock, _) = Client::new()
+            .host("127.0.0.1")
+            .port(port)
+            .connect()
+            .unwrap();
+        sock
+    }
+
+    fn pipe() -> (Socket, Socket) {
+        let mut fds = [0i32; 2];
+        // SAFETY: `fds` has room for the two fds that `pipe()` writes.
+        assert_eq!(unsafe { libc::pipe(fds.as_mut_ptr()) }, 0);
+        (Socket::from_raw(fds[0]), Socket::from_raw(fds[1]))
+    }
+
+    #[test]
+    fn full_duplex_echoes_more_than_the_socket_buffers_hold() {
+        // Far more than the send and receive buffers of both sides hold together, so the client has to
+        // receive the echo while it is still sending. A client that only sends would never finish.
+        let input = payload::generate(8 * 1024 * 1024, payload::PayloadKind::Pattern);
+
+        let (port, server) = spawn_echoserver();
+        let sock = connect(port);
+
+        let (read_end, write_end) = pipe();
+        let writer = {
+            let input = input.clone();
+            thread::spawn(move || write_all(write_end, &input))
+        };
+
+        let mut output = Vec::new();
+        full_duplex(read_end.as_raw(), sock.as_raw(), &mut output).unwrap();
+
+        writer.join().unwrap();
+        assert_eq!(server.join().unwrap(), input.len());
+        assert!(output == input, "the echo does not match the input");
+    }
+
+    #[test]
+    fn echo_back_sends_back_exactly_what_it_receives() {
+        let input = b"\x00\x01hello\xff\r\n".repeat(1000);
+
+        let (port, server) = spawn_echoserver();
+        let sock = connect(port);
+
+        stream_io::sendall(sock.as_raw(), &input).unwrap();
+        // SAFETY: `sock` is a valid connected socket.
+        assert_eq!(unsafe { libc::shutdown(sock.as_raw(), libc::SHUT_WR) }, 0);
+
+        let mut echo = vec![0u8; input.len()];
+        let rbytes = stream_io::recv_exact(sock.as_raw(), &mut echo).unwrap();
+        assert_eq!(rbytes, input.len());
+        assert_eq!(server.join().unwrap(), input.len());
+
+        // These are the two dumps that `--hexdump-both` prints, one for each direction.
+        assert_eq!(hexdump::hexdump(&echo), hexdump::hexdump(&input));
+    }
+
+    #[test]
+    fn echo_back_reads_exactly_max_bytes() {
+        let input = payload::generate(5000, payload::PayloadKind::Pattern);
+        let (client, server) = socketpair();
+
+        // The client sends far more than the cap, all of it is queued before the server starts reading.
+        stream_io::sendall(client.as_raw(), &input).unwrap();
+        let server = thread::spawn(move || {
+            let total = echo_back(server.as_raw(), Some(1000), false).unwrap();
+            // `server` is kept open, the test only checks what the echo server read.
+            (total, server)
+        });
+        let (total, server) = server.join().unwrap();
+        assert_eq!(total, 1000);
+
+        let mut echo = vec![0u8; 1000];
+        stream_io::recv_exact(client.as_raw(), &mut echo).unwrap();
+        assert_eq!(echo, input[..1000]);
+
+        // The rest is still waiting in the receive buffer of the server.
+        let mut rest = vec![0u8; input.len()];
+        fd::set_nonblocking(server.as_raw()).unwrap();
+        // SAFETY: `rest` is valid for `rest.len()` bytes for the entire `recv()` call.
+        let rbytes = unsafe {
+            libc::recv(
+                server.as_raw(),
+                rest.as_mut_ptr() as *mut libc::c_void,
+                rest.len(),
+                0,
+            )
+        };
+        assert_eq!(rbytes, 4000);
+    }
+
+    // Writes `buf` into the pipe `fd` in full, the pipe is closed afterwards so that the reader sees the EOF.
+    fn write_all(fd: Socket, buf: &[u8]) {
+        let mut written = 0;
+        while written < buf.len() {
+            let left = &buf[written..];
+            // SAFETY: `left` is a valid, initialized slice for the entire `write()` call.
+            let n = unsafe {
+                libc::write(
+                    fd.as_raw(),
+                    left.as_ptr() as *const libc::c_void,
+                    left.len(),
+                )
+            };
+            assert!(n > 0, "write error: {}", io::Error::last_os_error());
+            written += n as usize;
+        }
+    }
+}
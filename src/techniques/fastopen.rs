@@ -0,0 +1,248 @@
+use std::{error, fmt, fs, io, mem, net::SocketAddr, thread};
+
+use crate::{
+    server_builder::{self, Listener, Server},
+    sockaddr,
+    socket_fd::Socket,
+    sockopt, stream_io, trace,
+};
+
+#[derive(Debug)]
+pub enum Error {
+    Server(server_builder::Error),
+    Socket(io::Error),
+    Setsockopt(io::Error),
+    Sendto(io::Error),
+    Connect(io::Error),
+    Send(io::Error),
+    Getsockopt(io::Error),
+    Recv(io::Error),
+    ServerPanicked,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Server(err) => write!(f, "server error: {}", err),
+            Error::Socket(err) => write!(f, "socket error: {}", err),
+            Error::Setsockopt(err) => write!(f, "setsockopt TCP_FASTOPEN error: {}", err),
+            Error::Sendto(err) => write!(f, "sendto MSG_FASTOPEN error: {}", err),
+            Error::Connect(err) => write!(f, "connect error: {}", err),
+            Error::Send(err) => write!(f, "send error: {}", err),
+            Error::Getsockopt(err) => write!(f, "getsockopt TCP_INFO error: {}", err),
+            Error::Recv(err) => write!(f, "recv error: {}", err),
+            Error::ServerPanicked => write!(f, "the server thread panicked"),
+        }
+    }
+}
+
+impl error::Error for Error {}
+
+impl From<server_builder::Error> for Error {
+    fn from(value: server_builder::Error) -> Self {
+        Self::Server(value)
+    }
+}
+
+const SYSCTL: &str = "/proc/sys/net/ipv4/tcp_fastopen";
+// The bits of `net.ipv4.tcp_fastopen`, see `Documentation/networking/ip-sysctl.rst`.
+const SYSCTL_CLIENT: i32 = 0x1;
+const SYSCTL_SERVER: i32 = 0x2;
+
+// The length of the queue of the connections that have sent data in the SYN, but are not accepted yet.
+const FASTOPEN_QLEN: i32 = 16;
+
+// `tcpi_options` has this bit set if the data sent in the SYN was acknowledged, see `include/uapi/linux/tcp.h`.
+const TCPI_OPT_SYN_DATA: u8 = 32;
+
+// EXAMPLE: TCP Fast Open, sending data along with the SYN of a connection on Linux.
+// MANPAGE:
+// man 7 tcp (Linux), see TCP_FASTOPEN
+// man 2 sendto (Linux), see MSG_FASTOPEN
+//
+// A regular client has to wait a full round trip for the handshake before it can send anything.
+// With TCP Fast Open (RFC 7413), the server hands out a cookie on the first connection, and the client sends
+// that cookie and the request in the SYN of the next ones, so the server can answer a round trip earlier.
+//
+// - The server turns it on per listener with `setsockopt(TCP_FASTOPEN)`, whose value is a queue length.
+// - The client calls `sendto()` with `MSG_FASTOPEN` instead of `connect()` + `send()`,
+//   it connects and sends in one call. Without a cookie yet, it falls back to a regular handshake.
+//
+// Both sides also have to be enabled by the `net.ipv4.tcp_fastopen` sysctl: 1 enables the client,
+// 2 enables the server, and 3 enables both (e.g. `sysctl -w net.ipv4.tcp_fastopen=3`).
+// If either side is disabled, the connections still work, they just never carry data in the SYN.
+//
+// The example makes `connections` connections to its own listener on 127.0.0.1, and reports
+// for each of them whether the data went in the SYN.
+pub fn fastopen(connections: u32) -> Result<(), Error> {
+    report_sysctl();
+
+    let listener = Server::new().bind(Some("127.0.0.1"), 0).listen()?;
+    sockopt::set_int(
+        listener.as_raw(),
+        libc::IPPROTO_TCP,
+        libc::TCP_FASTOPEN,
+        FASTOPEN_QLEN,
+    )
+    .map_err(Error::Setsockopt)?;
+    let addr = listener.local_addr();
+    println!("fastopen: listening on {} with TCP_FASTOPEN", addr);
+
+    let server = thread::spawn(move || serve(listener, connections));
+
+    let mut res = Ok(());
+    for i in 1..=connections {
+        res = connect_and_send(addr, i);
+        if res.is_err() {
+            break;
+        }
+    }
+
+    // The server is waiting for the rest of the connections if the client failed early, it is not joined then.
+    if res.is_ok() {
+        server.join().map_err(|_| Error::ServerPanicked)??;
+    }
+
+    res
+}
+
+fn report_sysctl() {
+    let value = fs::read_to_string(SYSCTL)
+        .ok()
+        .and_then(|value| value.trim().parse::<i32>().ok());
+    let Some(value) = value else {
+        println!("fastopen: {} could not be read", SYSCTL);
+        return;
+    };
+
+    println!("fastopen: net.ipv4.tcp_fastopen = {}", value);
+    if value & SYSCTL_CLIENT == 0 {
+        println!("fastopen: the client side is disabled, no data will go in the SYN");
+    }
+    if value & SYSCTL_SERVER == 0 {
+        println!("fastopen: the server side is disabled, the data in the SYN will be ignored");
+    }
+}
+
+fn connect_and_send(addr: SocketAddr, i: u32) -> Result<(), Error> {
+    let sock = Socket::new(libc::AF_INET, libc::SOCK_STREAM, 0).map_err(Error::Socket)?;
+    let msg = format!("request {}", i);
+    let (sa, sa_len) = sockaddr::from_socket_addr(&addr);
+
+    // SAFETY: `msg` is a valid, initialized slice, and `sa` holds a valid address of `sa_len` bytes.
+    let sbytes = unsafe {
+        trace::sendto(
+            sock.as_raw(),
+            msg.as_ptr() as *const libc::c_void,
+            msg.len(),
+            libc::MSG_FASTOPEN,
+            &raw const sa as *const libc::sockaddr,
+            sa_len,
+        )
+    };
+
+    if sbytes == -1 {
+        let err = io::Error::last_os_error();
+        // `MSG_FASTOPEN` is not supported on a kernel without TCP Fast Open, connect as usual then.
+        if err.raw_os_error() != Some(libc::EOPNOTSUPP) {
+            return Err(Error::Sendto(err));
+        }
+        println!(
+            "client: MSG_FASTOPEN is not supported ({}), connecting as usual",
+            err
+        );
+
+        // SAFETY: `sa` holds a valid address of `sa_len` bytes.
+        let ecode = unsafe {
+            trace::connect(
+                sock.as_raw(),
+                &raw const sa as *const libc::sockaddr,
+                sa_len,
+            )
+        };
+        if ecode == -1 {
+            return Err(Error::Connect(io::Error::last_os_error()));
+        }
+        stream_io::sendall(sock.as_raw(), msg.as_bytes()).map_err(|err| Error::Send(err.into()))?;
+    }
+
+    // The reply is waited for, so that the handshake is over and `TCP_INFO` tells how it went.
+    let mut buf = [0u8; 64];
+    // SAFETY: `buf` is initialized and its length is passed along with it.
+    let rbytes = unsafe {
+        trace::recv(
+            sock.as_raw(),
+            buf.as_mut_ptr() as *mut libc::c_void,
+            buf.len(),
+            0,
+        )
+    };
+    if rbytes == -1 {
+        return Err(Error::Recv(io::Error::last_os_error()));
+    }
+
+    let in_syn = syn_data_acked(&sock)?;
+    println!(
+        "client: connection {}, the data {}",
+        i,
+        if in_syn {
+            "went in the SYN"
+        } else {
+            "was sent after the handshake"
+        }
+    );
+
+    Ok(())
+}
+
+fn syn_data_acked(sock: &Socket) -> Result<bool, Error> {
+    // SAFETY: All zero `tcp_info` is a valid initialization, it only consists of integers.
+    let mut info: libc::tcp_info = unsafe { mem::zeroed() };
+    let mut len = mem::size_of_val(&info) as libc::socklen_t;
+
+    // SAFETY: `info` is valid for `len` bytes, and `len` is updated with the number of bytes written to it.
+    let ecode = unsafe {
+        libc::getsockopt(
+            sock.as_raw(),
+            libc::IPPROTO_TCP,
+            libc::TCP_INFO,
+            &raw mut info as *mut libc::c_void,
+            &raw mut len,
+        )
+    };
+    if ecode == -1 {
+        return Err(Error::Getsockopt(io::Error::last_os_error()));
+    }
+
+    Ok(info.tcpi_options & TCPI_OPT_SYN_DATA != 0)
+}
+
+// Answers each request with a short reply, so that the client can tell the round trip is over.
+fn serve(listener: Listener, connections: u32) -> Result<(), Error> {
+    for _ in 0..connections {
+        let (sock, peer) = listener.accept()?;
+
+        let mut buf = [0u8; 64];
+        // SAFETY: `buf` is initialized and its length is passed along with it.
+        let rbytes = unsafe {
+            trace::recv(
+                sock.as_raw(),
+                buf.as_mut_ptr() as *mut libc::c_void,
+                buf.len(),
+                0,
+            )
+        };
+        if rbytes == -1 {
+            return Err(Error::Recv(io::Error::last_os_error()));
+        }
+        println!(
+            "server: {} sent {:?}",
+            peer,
+            String::from_utf8_lossy(&buf[..rbytes as usize])
+        );
+
+        stream_io::sendall(sock.as_raw(), b"ok").map_err(|err| Error::Send(err.into()))?;
+    }
+
+    Ok(())
+}
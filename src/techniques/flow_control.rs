@@ -0,0 +1,234 @@
+use std::{
+    error, fmt, io, thread,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    client_builder::Client,
+    fd,
+    inet::ConnectError,
+    readiness,
+    server_builder::{self, Listener, Server},
+    socket_fd::Socket,
+    sockopt, trace,
+};
+
+#[derive(Debug)]
+pub enum Error {
+    Server(server_builder::Error),
+    Connect(ConnectError),
+    Setsockopt(io::Error),
+    Getsockopt(io::Error),
+    Fcntl(io::Error),
+    Send(io::Error),
+    Poll(io::Error),
+    Recv(io::Error),
+    ReceiverPanicked,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Server(err) => write!(f, "server error: {}", err),
+            Error::Connect(err) => write!(f, "connect error: {}", err),
+            Error::Setsockopt(err) => write!(f, "setsockopt error: {}", err),
+            Error::Getsockopt(err) => write!(f, "getsockopt error: {}", err),
+            Error::Fcntl(err) => write!(f, "fcntl error: {}", err),
+            Error::Send(err) => write!(f, "send error: {}", err),
+            Error::Poll(err) => write!(f, "poll error: {}", err),
+            Error::Recv(err) => write!(f, "recv error: {}", err),
+            Error::ReceiverPanicked => write!(f, "the receiver thread panicked"),
+        }
+    }
+}
+
+impl error::Error for Error {}
+
+impl From<server_builder::Error> for Error {
+    fn from(value: server_builder::Error) -> Self {
+        Self::Server(value)
+    }
+}
+
+const CHUNK_SIZE: usize = 16 * 1024;
+
+// EXAMPLE: TCP flow control, a sender that is slowed down by a slow receiver with a tiny receive buffer.
+// The receiver sets `SO_RCVBUF` to `rcvbuf`, waits for `reader_delay` before reading anything,
+// and then reads in small pieces. The sender pushes `total` bytes through a non-blocking socket.
+// MANPAGE:
+// man 7 tcp (Linux)
+// man 7 socket (Linux)
+// man 2 send (Linux)
+//
+// The receiver advertises how much room is left in its buffer (the window) with every ACK.
+// Once the receive buffer and the send buffer of the sender are both full, `send()` cannot queue anything
+// and fails with `EWOULDBLOCK` (a blocking socket would just block instead).
+// The sender then waits with `poll(POLLOUT)` until the receiver reads and the window opens again.
+//
+// `SO_RCVBUF` is set on the listener before the connection is made, the accepted socket inherits it.
+// The window scale is agreed on during the handshake, so setting it afterwards would be too late.
+pub fn flow_control(rcvbuf: i32, total: usize, reader_delay: Duration) -> Result<(), Error> {
+    let listener = Server::new().bind(Some("127.0.0.1"), 0).listen()?;
+    sockopt::set_int(listener.as_raw(), libc::SOL_SOCKET, libc::SO_RCVBUF, rcvbuf)
+        .map_err(Error::Setsockopt)?;
+    let addr = listener.local_addr();
+
+    let receiver = thread::spawn(move || receive(listener, total, reader_delay));
+
+    let (sock, _) = Client::new()
+        .host("127.0.0.1")
+        .port(addr.port())
+        .connect()
+        .map_err(Error::Connect)?;
+    let res = send(&sock, total);
+
+    // Closing the socket is what the receiver sees as EOF, it is closed on the error path as well.
+    drop(sock);
+    let received = receiver.join().map_err(|_| Error::ReceiverPanicked)?;
+
+    let (sent, stalls, blocked) = res?;
+    println!(
+        "sender: sent {} bytes, send() would block {} times, {:?} in total",
+        sent, stalls, blocked
+    );
+    println!("receiver: received {} bytes", received?);
+
+    Ok(())
+}
+
+// Returns the number of bytes sent, how many times `send()` would block, and how long the sender waited in total.
+fn send(sock: &Socket, total: usize) -> Result<(usize, u32, Duration), Error> {
+    fd::set_nonblocking(sock.as_raw()).map_err(Error::Fcntl)?;
+
+    let chunk = [b'x'; CHUNK_SIZE];
+    let mut sent = 0;
+    let mut stalls = 0;
+    let mut blocked = Duration::ZERO;
+
+    while sent < total {
+        let len = CHUNK_SIZE.min(total - sent);
+
+        // SAFETY: `chunk` is initialized and it is at least `len` bytes long.
+        let sbytes =
+            unsafe { trace::send(sock.as_raw(), chunk.as_ptr() as *const libc::c_void, len, 0) };
+        if sbytes >= 0 {
+            sent += sbytes as usize;
+            continue;
+        }
+
+        let err = io::Error::last_os_error();
+        match err.kind() {
+            io::ErrorKind::Interrupted => continue,
+            io::ErrorKind::WouldBlock => {}
+            _ => return Err(Error::Send(err)),
+        }
+
+        if stalls == 0 {
+            println!(
+                "sender: {} of {} bytes are queued instantly, send() would block now: {}",
+                sent, total, err
+            );
+        }
+        stalls += 1;
+
+        let started_at = Instant::now();
+        wait_writable(sock.as_raw())?;
+        blocked += started_at.elapsed();
+    }
+
+    Ok((sent, stalls, blocked))
+}
+
+fn wait_writable(sock_fd: i32) -> Result<(), Error> {
+    loop {
+        match readiness::wait_writable(sock_fd, None) {
+            Ok(_) => return Ok(()),
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => {}
+            Err(err) => return Err(Error::Poll(err)),
+        }
+    }
+}
+
+// Returns the number of bytes received until EOF.
+fn receive(listener: Listener, total: usize, reader_delay: Duration) -> Result<usize, Error> {
+    let (sock, addr) = listener.accept()?;
+
+    // The kernel doubles the requested size to make room for its own bookkeeping, and enforces a minimum.
+    let rcvbuf = sockopt::get_int(sock.as_raw(), libc::SOL_SOCKET, libc::SO_RCVBUF)
+        .map_err(Error::Getsockopt)?;
+    println!(
+        "receiver: accepted {}, SO_RCVBUF = {}, not reading for {:?}",
+        addr, rcvbuf, reader_delay
+    );
+    thread::sleep(reader_delay);
+
+    let mut buf = [0u8; 1024];
+    let mut received = 0;
+    let mut next_report = total / 4;
+
+    loop {
+        // SAFETY: `buf` is initialized and its length is passed along with it.
+        let rbytes = unsafe {
+            trace::recv(
+                sock.as_raw(),
+                buf.as_mut_ptr() as *mut libc::c_void,
+                buf.len(),
+                0,
+            )
+        };
+        match rbytes {
+            -1 => {
+                let err = io::Error::last_os_error();
+                if err.kind() != io::ErrorKind::Interrupted {
+                    return Err(Error::Recv(err));
+                }
+            }
+            0 => return Ok(received),
+            n => received += n as usize,
+        }
+
+        if next_report > 0 && received >= next_report {
+            println!("receiver: {} of {} bytes are read", received, total);
+            next_report += total / 4;
+        }
+
+        // Reading slowly keeps the window small, so that the sender has to wait for it to open.
+        thread::sleep(Duration::from_micros(100));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sender_cannot_queue_everything_into_a_tiny_buffer() {
+        const TOTAL: usize = 1024 * 1024;
+        let reader_delay = Duration::from_millis(200);
+
+        let listener = Server::new().bind(Some("127.0.0.1"), 0).listen().unwrap();
+        sockopt::set_int(listener.as_raw(), libc::SOL_SOCKET, libc::SO_RCVBUF, 4096).unwrap();
+        let port = listener.local_addr().port();
+        let receiver = thread::spawn(move || receive(listener, TOTAL, reader_delay));
+
+        let (sock, _) = Client::new()
+            .host("127.0.0.1")
+            .port(port)
+            .connect()
+            .unwrap();
+        // A fixed send buffer, the kernel would grow it far enough to queue everything otherwise.
+        sockopt::set_int(sock.as_raw(), libc::SOL_SOCKET, libc::SO_SNDBUF, 4096).unwrap();
+        let (sent, stalls, blocked) = send(&sock, TOTAL).unwrap();
+        drop(sock);
+
+        assert_eq!(sent, TOTAL);
+        assert_eq!(receiver.join().unwrap().unwrap(), TOTAL);
+        // Nothing is read until `reader_delay` passes, so the sender has to wait at least that long.
+        assert!(stalls > 0, "send() never blocked");
+        assert!(
+            blocked >= reader_delay - Duration::from_millis(50),
+            "the sender only waited {:?}",
+            blocked
+        );
+    }
+}
@@ -0,0 +1,373 @@
+use std::{
+    error,
+    ffi::CString,
+    fmt, io,
+    net::SocketAddr,
+    thread,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    fd,
+    payload::{self, PayloadKind},
+    serialize, udp,
+};
+
+#[derive(Debug)]
+pub enum Error {
+    Udp(udp::Error),
+    InvalidHost(String),
+    Close(io::Error),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Udp(err) => write!(f, "udp error: {}", err),
+            Error::InvalidHost(host) => write!(f, "invalid host {}", host),
+            Error::Close(err) => write!(f, "close error: {}", err),
+        }
+    }
+}
+
+impl error::Error for Error {}
+
+impl From<udp::Error> for Error {
+    fn from(value: udp::Error) -> Self {
+        Self::Udp(value)
+    }
+}
+
+// A heartbeat starts with a sequence number and the time it is sent at (in microseconds since the client started),
+// both packed as big-endian u64s. An optional payload follows them.
+const HEARTBEAT_SIZE: usize = 16;
+// The largest UDP payload, so that no heartbeat is ever truncated by `recvfrom()`.
+const MAX_DATAGRAM_SIZE: usize = 65_507;
+
+// EXAMPLE: The server side of the UDP heartbeat example.
+// Every heartbeat is sent back to its sender as is (including its payload), the client does all the measuring.
+// MANPAGE:
+// man 2 recvfrom (Linux)
+// man 2 sendto (Linux)
+pub fn heartbeatserver(port: u16) -> Result<(), Error> {
+    let port = CString::new(port.to_string()).unwrap();
+    let (sock_fd, addr) = udp::bind_udp(None, &port)?;
+
+    println!("heartbeatserver: waiting for heartbeats on {}...", addr);
+
+    let mut buf = vec![0u8; MAX_DATAGRAM_SIZE];
+    loop {
+        let (rbytes, from_addr) = match udp::recv_from(sock_fd, &mut buf) {
+            Ok(res) => res,
+            Err(err) => {
+                let _ = fd::close_fd(sock_fd);
+                return Err(err.into());
+            }
+        };
+        if rbytes < HEARTBEAT_SIZE {
+            eprintln!(
+                "heartbeatserver: ignoring a {} byte datagram from {}",
+                rbytes, from_addr
+            );
+            continue;
+        }
+
+        if let Err(err) = udp::send_to(sock_fd, &buf[..rbytes], &from_addr) {
+            eprintln!("heartbeatserver: {}", err);
+        }
+    }
+}
+
+/// RTT and jitter over the heartbeats of a single run.
+#[derive(Debug, Default)]
+struct Summary {
+    sent: u64,
+    received: u64,
+    corrupted: u64,
+    rtt_min: Option<Duration>,
+    rtt_max: Duration,
+    rtt_sum: Duration,
+    last_rtt: Option<Duration>,
+    // Smoothed jitter, as it is calculated for RTP (RFC 3550, section 6.4.1).
+    jitter: f64,
+}
+
+impl Summary {
+    fn on_reply(&mut self, rtt: Duration) {
+        self.received += 1;
+        self.rtt_min = Some(self.rtt_min.map_or(rtt, |min| min.min(rtt)));
+        self.rtt_max = self.rtt_max.max(rtt);
+        self.rtt_sum += rtt;
+
+        if let Some(last_rtt) = self.last_rtt {
+            let diff = rtt.as_secs_f64() - last_rtt.as_secs_f64();
+            self.jitter += (diff.abs() - self.jitter) / 16.0;
+        }
+        self.last_rtt = Some(rtt);
+    }
+
+    fn loss(&self) -> f64 {
+        match self.sent {
+            0 => 0.0,
+            sent => (sent - self.received - self.corrupted) as f64 * 100.0 / sent as f64,
+        }
+    }
+
+    // The summary as a single JSON object, the durations are in microseconds.
+    // The RTT fields are `null` when no heartbeat was echoed back.
+    fn to_json(&self) -> String {
+        let us = |rtt: Option<Duration>| match rtt {
+            Some(rtt) => rtt.as_micros().to_string(),
+            None => "null".to_string(),
+        };
+        let rtt_avg = self.rtt_min.map(|_| self.rtt_sum / self.received as u32);
+        let rtt_max = self.rtt_min.map(|_| self.rtt_max);
+        let jitter = self.rtt_min.map(|_| Duration::from_secs_f64(self.jitter));
+
+        format!(
+            "{{\"sent\":{},\"received\":{},\"corrupted\":{},\"loss_percent\":{:.1},\"rtt_min_us\":{},\"rtt_avg_us\":{},\"rtt_max_us\":{},\"jitter_us\":{}}}",
+            self.sent,
+            self.received,
+            self.corrupted,
+            self.loss(),
+            us(self.rtt_min),
+            us(rtt_avg),
+            us(rtt_max),
+            us(jitter)
+        )
+    }
+}
+
+impl fmt::Display for Summary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} sent, {} received, {} corrupted, {:.1}% loss",
+            self.sent,
+            self.received,
+            self.corrupted,
+            self.loss()
+        )?;
+
+        if let Some(rtt_min) = self.rtt_min {
+            let rtt_avg = self.rtt_sum / self.received as u32;
+            write!(
+                f,
+                ", rtt min/avg/max = {:?}/{:?}/{:?}, jitter = {:?}",
+                rtt_min,
+                rtt_avg,
+                self.rtt_max,
+                Duration::from_secs_f64(self.jitter)
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+// EXAMPLE: A UDP heartbeat client that measures the round-trip time (RTT) and the jitter to the heartbeat server.
+// A heartbeat is sent every `interval`, and the client waits for its echo until the next one is due.
+// Since UDP does not retransmit anything, a heartbeat that is not echoed back in time is counted as lost.
+// The wait is implemented with `SO_RCVTIMEO`, which makes a blocking `recvfrom()` give up with `EAGAIN`.
+// Each heartbeat carries the same `payload_size` byte payload, an echo whose payload differs is counted as corrupted.
+// MANPAGE:
+// man 7 socket (SO_RCVTIMEO)
+// man 2 recvfrom (Linux)
+// man 2 sendto (Linux)
+//
+// With `summary_json`, the per-heartbeat lines are not printed, and the summary is printed as a single JSON object.
+pub fn heartbeat(
+    host: &str,
+    port: u16,
+    interval: Duration,
+    count: u64,
+    payload_size: usize,
+    payload_kind: PayloadKind,
+    summary_json: bool,
+) -> Result<(), Error> {
+    let node = CString::new(host).map_err(|_| Error::InvalidHost(host.to_string()))?;
+    let service = CString::new(port.to_string()).unwrap();
+    let server_addr = udp::resolve(&node, &service)?;
+
+    let family = match server_addr {
+        SocketAddr::V4(_) => libc::AF_INET,
+        SocketAddr::V6(_) => libc::AF_INET6,
+    };
+    let sock_fd = udp::udp_socket(family)?;
+
+    let payload = payload::generate(
+        payload_size.min(MAX_DATAGRAM_SIZE - HEARTBEAT_SIZE),
+        payload_kind,
+    );
+    let res = send_heartbeats(
+        sock_fd,
+        server_addr,
+        interval,
+        count,
+        &payload,
+        !summary_json,
+    );
+
+    // The run is over, `sock_fd` is not used after this call.
+    fd::close_fd(sock_fd).map_err(Error::Close)?;
+
+    let summary = res?;
+    match summary_json {
+        true => println!("{}", summary.to_json()),
+        false => println!("heartbeat: {}", summary),
+    }
+
+    Ok(())
+}
+
+fn send_heartbeats(
+    sock_fd: i32,
+    server_addr: SocketAddr,
+    interval: Duration,
+    count: u64,
+    payload: &[u8],
+    verbose: bool,
+) -> Result<Summary, Error> {
+    let start = Instant::now();
+    let mut summary = Summary::default();
+
+    let mut buf = vec![0u8; HEARTBEAT_SIZE + payload.len()];
+    buf[HEARTBEAT_SIZE..].copy_from_slice(payload);
+    let mut recv_buf = vec![0u8; MAX_DATAGRAM_SIZE];
+
+    for seq in 0..count {
+        let sent_at = Instant::now();
+        let deadline = sent_at + interval;
+
+        serialize::packi64(&mut buf[..8], seq);
+        serialize::packi64(&mut buf[8..], start.elapsed().as_micros() as u64);
+        udp::send_to(sock_fd, &buf, &server_addr)?;
+        summary.sent += 1;
+
+        // Keep receiving until the echo of this heartbeat arrives, or until the next one is due.
+        // A late echo of an earlier heartbeat may show up first, it is already counted as lost.
+        loop {
+            let left = deadline.saturating_duration_since(Instant::now());
+            if left.is_zero() {
+                if verbose {
+                    println!("heartbeat: seq={} lost", seq);
+                }
+                break;
+            }
+            udp::set_recv_timeout(sock_fd, left)?;
+
+            let (rbytes, from_addr) = match udp::recv_from(sock_fd, &mut recv_buf) {
+                Ok(res) => res,
+                Err(udp::Error::Recvfrom(err)) if err.kind() == io::ErrorKind::WouldBlock => {
+                    if verbose {
+                        println!("heartbeat: seq={} lost", seq);
+                    }
+                    break;
+                }
+                Err(err) => return Err(err.into()),
+            };
+            if rbytes < HEARTBEAT_SIZE || from_addr != server_addr {
+                continue;
+            }
+
+            let echoed_seq = serialize::unpacku64(&recv_buf[..8]);
+            if echoed_seq != seq {
+                continue;
+            }
+
+            if recv_buf[HEARTBEAT_SIZE..rbytes] != *payload {
+                summary.corrupted += 1;
+                if verbose {
+                    println!("heartbeat: seq={} corrupted", seq);
+                }
+                break;
+            }
+
+            let echoed_at = Duration::from_micros(serialize::unpacku64(&recv_buf[8..16]));
+            let rtt = start.elapsed().saturating_sub(echoed_at);
+            summary.on_reply(rtt);
+
+            if verbose {
+                println!(
+                    "heartbeat: seq={} rtt={:?} jitter={:?}",
+                    seq,
+                    rtt,
+                    Duration::from_secs_f64(summary.jitter)
+                );
+            }
+            break;
+        }
+
+        thread::sleep(deadline.saturating_duration_since(Instant::now()));
+    }
+
+    Ok(summary)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+
+    // Splits a flat JSON object of numbers and `null`s into its fields.
+    fn fields(json: &str) -> HashMap<String, String> {
+        let inner = json
+            .strip_prefix('{')
+            .and_then(|json| json.strip_suffix('}'))
+            .expect("not a JSON object");
+
+        inner
+            .split(',')
+            .map(|field| {
+                let (key, value) = field.split_once(':').expect("not a key-value pair");
+                let key = key
+                    .strip_prefix('"')
+                    .and_then(|key| key.strip_suffix('"'))
+                    .expect("the key is not a string");
+                (key.to_string(), value.to_string())
+            })
+            .collect()
+    }
+
+    #[test]
+    fn json_summary_has_every_field() {
+        let mut summary = Summary {
+            sent: 4,
+            corrupted: 1,
+            ..Default::default()
+        };
+        summary.on_reply(Duration::from_micros(100));
+        summary.on_reply(Duration::from_micros(300));
+
+        let fields = fields(&summary.to_json());
+
+        assert_eq!(fields.len(), 8);
+        assert_eq!(fields["sent"], "4");
+        assert_eq!(fields["received"], "2");
+        assert_eq!(fields["corrupted"], "1");
+        assert_eq!(fields["loss_percent"], "25.0");
+        assert_eq!(fields["rtt_min_us"], "100");
+        assert_eq!(fields["rtt_avg_us"], "200");
+        assert_eq!(fields["rtt_max_us"], "300");
+        // The jitter moves 1/16 of the way towards the 200us difference.
+        assert_eq!(fields["jitter_us"], "12");
+    }
+
+    #[test]
+    fn json_summary_without_replies_has_null_rtts() {
+        let summary = Summary {
+            sent: 2,
+            ..Default::default()
+        };
+
+        let fields = fields(&summary.to_json());
+
+        assert_eq!(fields["sent"], "2");
+        assert_eq!(fields["received"], "0");
+        assert_eq!(fields["loss_percent"], "100.0");
+        for key in ["rtt_min_us", "rtt_avg_us", "rtt_max_us", "jitter_us"] {
+            assert_eq!(fields[key], "null", "{}", key);
+        }
+    }
+}
@@ -0,0 +1,295 @@
+use std::{error, ffi::CString, fmt, io, ptr};
+
+use crate::{
+    fd,
+    listener::{self, BindRetry},
+    serialize, stream_io, trace,
+};
+
+#[derive(Debug)]
+pub enum Error {
+    Listener(listener::Error),
+    Accept(io::Error),
+    Recv(io::Error),
+    Send(io::Error),
+    Close(io::Error),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Listener(err) => write!(f, "listener error: {}", err),
+            Error::Accept(err) => write!(f, "accept error: {}", err),
+            Error::Recv(err) => write!(f, "recv error: {}", err),
+            Error::Send(err) => write!(f, "send error: {}", err),
+            Error::Close(err) => write!(f, "close error: {}", err),
+        }
+    }
+}
+
+impl error::Error for Error {}
+
+impl From<listener::Error> for Error {
+    fn from(value: listener::Error) -> Self {
+        Self::Listener(value)
+    }
+}
+
+/// What the server does after receiving a command line.
+#[derive(Debug, PartialEq, Eq)]
+pub enum Reply {
+    /// Send the line back to the client, the newline is appended by the server.
+    Line(String),
+    /// Close the connection.
+    Quit,
+}
+
+/// Maps a single command line to its reply.
+///
+/// Supported commands:
+/// - `PING` -> `PONG`
+/// - `ECHO <text>` -> `<text>`
+/// - `QUIT` -> closes the connection
+///
+/// Anything else is answered with an `ERR` line, the connection is kept open.
+pub fn dispatch(line: &str) -> Reply {
+    // Clients like `telnet` terminate lines with "\r\n".
+    let line = line.strip_suffix('\r').unwrap_or(line);
+
+    let (cmd, arg) = match line.split_once(' ') {
+        Some((cmd, arg)) => (cmd, Some(arg)),
+        None => (line, None),
+    };
+
+    match (cmd, arg) {
+        ("PING", None) => Reply::Line(String::from("PONG")),
+        ("ECHO", Some(text)) => Reply::Line(String::from(text)),
+        ("ECHO", None) => Reply::Line(String::new()),
+        ("QUIT", None) => Reply::Quit,
+        _ => Reply::Line(format!("ERR unknown command: {}", line)),
+    }
+}
+
+/// Appends the CRC32 of `line` to it as 8 hex digits, separated by a space.
+///
+/// `"PING"` becomes `"PING 1340d049"` and so on.
+pub fn append_checksum(line: &str) -> String {
+    format!("{} {:08x}", line, serialize::crc32(line.as_bytes()))
+}
+
+/// Splits the trailing CRC32 added by `append_checksum()` off of `line` and verifies it.
+///
+/// Returns the payload, or `None` if the checksum is missing or does not match the payload.
+pub fn verify_checksum(line: &str) -> Option<&str> {
+    let (payload, checksum) = line.rsplit_once(' ')?;
+    if checksum.len() != 8 {
+        return None;
+    }
+
+    let checksum = u32::from_str_radix(checksum, 16).ok()?;
+    if serialize::crc32(payload.as_bytes()) != checksum {
+        return None;
+    }
+
+    Some(payload)
+}
+
+// EXAMPLE: A server that speaks a tiny newline-delimited text protocol.
+// Each client line is a command, and each reply is a single line.
+// Since `recv()` does not preserve message boundaries, lines are assembled with `recv_until()`,
+// and replies are sent with `sendall()` so that a partial `send()` cannot cut a reply in half.
+// Clients are served one at a time.
+//
+// If `checksum` is set, every line in both directions ends with the CRC32 of the line (see `append_checksum()`).
+// A line with a missing or wrong checksum is rejected with an `ERR` reply, and it is never dispatched.
+// MANPAGE:
+// man 2 recv (Linux)
+// man 2 send (Linux)
+// man errno
+pub fn lineserver(retry: &BindRetry, checksum: bool) -> Result<(), Error> {
+    let port = CString::from(c"3490");
+
+    let (sock_fd, _) = listener::bind_listener(libc::AF_UNSPEC, &port, retry)?;
+
+    println!(
+        "lineserver: waiting for connections on port {}...",
+        port.to_string_lossy()
+    );
+
+    loop {
+        // SAFETY: The peer address is not needed, passing null pointers to `accept()` is valid.
+        let conn_sock_fd = unsafe { trace::accept(sock_fd, ptr::null_mut(), ptr::null_mut()) };
+        match conn_sock_fd {
+            -1 => Err(Error::Accept(io::Error::last_os_error())),
+            _ => Ok(()),
+        }?;
+
+        println!("lineserver: serving client on socket {}", conn_sock_fd);
+
+        let res = serve_client(conn_sock_fd, checksum);
+
+        // The client is served, `conn_sock_fd` is not used after this call.
+        fd::close_fd(conn_sock_fd).map_err(Error::Close)?;
+
+        match res {
+            Ok(()) => println!("lineserver: socket {} hung up", conn_sock_fd),
+            Err(Error::Recv(err)) if stream_io::is_reset(&err) => {
+                println!(
+                    "lineserver: socket {} was reset by the client",
+                    conn_sock_fd
+                )
+            }
+            Err(err) => eprintln!("lineserver: {}", err),
+        }
+    }
+}
+
+fn serve_client(conn_sock_fd: i32, checksum: bool) -> Result<(), Error> {
+    let mut pending = vec![];
+
+    while let Some(line) =
+        stream_io::recv_until(conn_sock_fd, &mut pending, b'\n').map_err(Error::Recv)?
+    {
+        let line = String::from_utf8_lossy(&line);
+        // The "\r" of telnet is not a part of the checksummed payload.
+        let line = line.strip_suffix('\r').unwrap_or(&line);
+
+        let reply = if !checksum {
+            dispatch(line)
+        } else {
+            match verify_checksum(line) {
+                Some(payload) => dispatch(payload),
+                None => Reply::Line(String::from("ERR bad checksum")),
+            }
+        };
+
+        match reply {
+            Reply::Line(reply) => {
+                let reply = if checksum {
+                    append_checksum(&reply)
+                } else {
+                    reply
+                };
+                let reply = reply + "\n";
+                stream_io::sendall(conn_sock_fd, reply.as_bytes())
+                    .map_err(|err| Error::Send(err.into()))?;
+            }
+            Reply::Quit => break,
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use super::*;
+    use crate::testutil::{recv_to_end, socketpair};
+
+    #[test]
+    fn ping_is_answered_with_pong() {
+        assert_eq!(dispatch("PING"), Reply::Line(String::from("PONG")));
+        assert_eq!(dispatch("PING\r"), Reply::Line(String::from("PONG")));
+    }
+
+    #[test]
+    fn echo_sends_back_its_argument() {
+        assert_eq!(
+            dispatch("ECHO hello world"),
+            Reply::Line(String::from("hello world"))
+        );
+        assert_eq!(dispatch("ECHO"), Reply::Line(String::new()));
+    }
+
+    #[test]
+    fn quit_closes_the_connection() {
+        assert_eq!(dispatch("QUIT"), Reply::Quit);
+        assert_eq!(dispatch("QUIT\r"), Reply::Quit);
+    }
+
+    #[test]
+    fn unknown_commands_are_answered_with_an_error() {
+        assert_eq!(
+            dispatch("PING now"),
+            Reply::Line(String::from("ERR unknown command: PING now"))
+        );
+        assert_eq!(
+            dispatch("ping"),
+            Reply::Line(String::from("ERR unknown command: ping"))
+        );
+    }
+
+    #[test]
+    fn serves_a_session_until_quit() {
+        let (client, server) = socketpair();
+        let session = thread::spawn(move || serve_client(server.as_raw(), false));
+
+        // The last PING is never answered, the server stops reading at QUIT.
+        stream_io::sendall(client.as_raw(), b"PING\nECHO hi\nNOPE\nQUIT\nPING\n").unwrap();
+        session.join().unwrap().unwrap();
+
+        // `server` is dropped with the thread, so the client sees the EOF after the replies.
+        assert_eq!(
+            recv_to_end(&client),
+            b"PONG\nhi\nERR unknown command: NOPE\n"
+        );
+    }
+
+    #[test]
+    fn checksum_round_trips() {
+        let line = append_checksum("ECHO hi");
+        assert_eq!(
+            line,
+            format!("ECHO hi {:08x}", serialize::crc32(b"ECHO hi"))
+        );
+        assert_eq!(verify_checksum(&line), Some("ECHO hi"));
+    }
+
+    #[test]
+    fn a_flipped_byte_is_rejected() {
+        let line = append_checksum("ECHO hi");
+
+        for i in 0..line.len() {
+            let mut corrupted = line.clone().into_bytes();
+            corrupted[i] ^= 0x01;
+            let corrupted = String::from_utf8(corrupted).unwrap();
+            assert_eq!(
+                verify_checksum(&corrupted),
+                None,
+                "{:?} is accepted",
+                corrupted
+            );
+        }
+    }
+
+    #[test]
+    fn a_missing_checksum_is_rejected() {
+        assert_eq!(verify_checksum("PING"), None);
+        assert_eq!(verify_checksum("ECHO hi"), None);
+    }
+
+    #[test]
+    fn a_corrupted_line_is_not_dispatched() {
+        let (client, server) = socketpair();
+        let session = thread::spawn(move || serve_client(server.as_raw(), true));
+
+        let mut quit = append_checksum("QUIT").into_bytes();
+        // "QUIT" becomes "QUIU", which must not close the connection.
+        quit[3] ^= 0x01;
+        let quit = String::from_utf8(quit).unwrap();
+        let lines = format!("{}\n{}\n", quit, append_checksum("PING"));
+        stream_io::sendall(client.as_raw(), lines.as_bytes()).unwrap();
+        // SAFETY: `client` is a valid connected socket.
+        assert_eq!(unsafe { libc::shutdown(client.as_raw(), libc::SHUT_WR) }, 0);
+        session.join().unwrap().unwrap();
+
+        let expected = format!(
+            "{}\n{}\n",
+            append_checksum("ERR bad checksum"),
+            append_checksum("PONG")
+        );
+        assert_eq!(recv_to_end(&client), expected.as_bytes());
+    }
+}
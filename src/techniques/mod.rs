@@ -1,13 +1,56 @@
+mod backlog_probe;
 mod blocking;
 mod broadcaster;
+mod echo;
+#[cfg(target_os = "linux")]
+mod fastopen;
+mod flow_control;
+mod heartbeat;
+mod lineserver;
+mod omniserver;
+mod pipe_and_socket;
 mod poll;
 mod pollserver;
 mod select;
 mod selectserver;
+mod sockinfo;
+mod socks;
+mod starvation;
+#[cfg(target_os = "linux")]
+mod tcpinfo;
+mod time_wait;
+#[cfg(target_os = "linux")]
+mod udp_errqueue;
+mod udp_probe;
+mod udp_reliable;
 
+pub use backlog_probe::backlog_probe;
 pub use blocking::blocking;
 pub use broadcaster::broadcaster;
+pub use echo::{Duplex, echoclient, echoserver};
+#[cfg(target_os = "linux")]
+pub use fastopen::fastopen;
+pub use flow_control::flow_control;
+pub use heartbeat::{heartbeat, heartbeatserver};
+pub use lineserver::{Reply, append_checksum, dispatch, lineserver, verify_checksum};
+pub use omniserver::omniserver;
+pub use pipe_and_socket::pipe_and_socket;
 pub use poll::poll;
-pub use pollserver::pollserver;
+pub use pollserver::{
+    PollserverOptions, pollserver, run_pollserver, run_pollserver_with, setup_pollserver_listener,
+};
 pub use select::select;
-pub use selectserver::selectserver;
+pub use selectserver::{
+    Bounds, SelectserverOptions, run_selectserver, run_selectserver_with, selectserver,
+    selectserver_with,
+};
+pub use sockinfo::sockinfo;
+pub use socks::socks_connect;
+pub use starvation::starvation;
+#[cfg(target_os = "linux")]
+pub use tcpinfo::tcpinfo;
+pub use time_wait::time_wait;
+#[cfg(target_os = "linux")]
+pub use udp_errqueue::udp_errqueue;
+pub use udp_probe::{udp_probe, udp_probe_receiver};
+pub use udp_reliable::{reliable_receiver, reliable_sender};
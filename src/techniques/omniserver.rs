@@ -0,0 +1,447 @@
+use std::{
+    collections::HashMap,
+    error,
+    ffi::CString,
+    fmt, fs, io, mem,
+    os::unix::fs::FileTypeExt,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    fairness::RoundRobin,
+    listener::{self, ListenerOptions},
+    reactor::{Interest, Reactor, ReadyKind},
+    signal, sockaddr,
+    socket_fd::Socket,
+    stream_io, trace,
+};
+
+#[derive(Debug)]
+pub enum Error {
+    Listener(listener::Error),
+    UnixPath(sockaddr::Error),
+    Unix(PathBuf, io::Error),
+    Poll(io::Error),
+    Signal(io::Error),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Listener(err) => write!(f, "listener error: {}", err),
+            Error::UnixPath(err) => write!(f, "unix listener error: {}", err),
+            Error::Unix(path, err) => {
+                write!(f, "unix listener error on {}: {}", path.display(), err)
+            }
+            Error::Poll(err) => write!(f, "poll error: {}", err),
+            Error::Signal(err) => write!(f, "sigaction error: {}", err),
+        }
+    }
+}
+
+impl error::Error for Error {}
+
+impl From<listener::Error> for Error {
+    fn from(value: listener::Error) -> Self {
+        Self::Listener(value)
+    }
+}
+
+impl From<sockaddr::Error> for Error {
+    fn from(value: sockaddr::Error) -> Self {
+        Self::UnixPath(value)
+    }
+}
+
+/// The transport a listener accepts on, the clients are labeled by it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Transport {
+    Tcp4,
+    Tcp6,
+    Unix,
+}
+
+impl fmt::Display for Transport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Transport::Tcp4 => write!(f, "tcp4"),
+            Transport::Tcp6 => write!(f, "tcp6"),
+            Transport::Unix => write!(f, "unix"),
+        }
+    }
+}
+
+struct Client {
+    sock: Socket,
+    label: String,
+}
+
+// The pollfd list of the listeners and the clients, the listeners always come first.
+struct Pfds {
+    listener_fds: Vec<i32>,
+    pfds: Vec<libc::pollfd>,
+}
+
+impl Pfds {
+    fn new(listener_fds: Vec<i32>) -> Self {
+        let pfds = listener_fds
+            .iter()
+            .map(|fd| libc::pollfd {
+                fd: *fd,
+                events: Interest::Read.poll_events(),
+                revents: 0,
+            })
+            .collect();
+
+        Self { listener_fds, pfds }
+    }
+
+    fn insert(&mut self, fd: i32, interest: Interest) {
+        self.pfds.push(libc::pollfd {
+            fd,
+            events: interest.poll_events(),
+            revents: 0,
+        });
+    }
+
+    fn remove(&mut self, fd: i32) {
+        self.pfds.retain(|pfd| pfd.fd != fd);
+    }
+}
+
+impl Reactor for Pfds {
+    fn listener_fds(&self) -> &[i32] {
+        &self.listener_fds
+    }
+
+    fn ready(&self) -> Vec<(i32, ReadyKind)> {
+        self.pfds
+            .iter()
+            .filter_map(|pfd| ReadyKind::from_revents(pfd.revents).map(|kind| (pfd.fd, kind)))
+            .collect()
+    }
+}
+
+const BACKLOG: i32 = 10;
+const ECHO_BUF_SIZE: usize = 256;
+
+// EXAMPLE: An echo server that listens on several transports at once, and serves all of them from one loop.
+// MANPAGE:
+// man 2 poll (Linux)
+// man 7 ipv6 (Linux), see IPV6_V6ONLY
+// man 7 unix (Linux)
+//
+// The server listens on TCP `port` over IPv4 and IPv6, and on the Unix domain socket at `unix_path` if it is set.
+// Every listener is watched by the same `poll()` call along with the clients, so a client is served the same way
+// regardless of the transport it comes from. Each new client is labeled by its transport and its address.
+//
+// An IPv6 listener is dual-stack on most systems, and would take the IPv4 port as well. `IPV6_V6ONLY` is set
+// on it, so that the IPv4 clients go to the IPv4 listener instead. If the host has no IPv6, the server goes on
+// without it.
+//
+// A Unix domain socket is a file: a stale one left by a previous run is removed before `bind()`,
+// and the socket is removed again on exit. On Linux, a path that starts with `@` names a socket
+// in the abstract namespace instead, which is not a file and goes away with the listener. The clients of a Unix domain socket usually do not bind,
+// so their address has no path.
+//
+// The server runs until SIGINT (Ctrl-C) or SIGTERM is received.
+pub fn omniserver(port: u16, unix_path: Option<&Path>) -> Result<(), Error> {
+    signal::install_shutdown_handler().map_err(Error::Signal)?;
+
+    let listeners = bind_listeners(port, unix_path)?;
+    let mut pfds = Pfds::new(listeners.keys().copied().collect());
+    let mut clients: HashMap<i32, Client> = HashMap::new();
+    let mut round_robin = RoundRobin::new(None);
+
+    let res = loop {
+        if signal::shutdown_requested() {
+            break Ok(());
+        }
+
+        // SAFETY: The pollfd buf is initialized properly, and its length is passed along with it.
+        let poll_count = unsafe { libc::poll(pfds.pfds.as_mut_ptr(), pfds.pfds.len() as u64, -1) };
+        if poll_count == -1 {
+            let err = io::Error::last_os_error();
+            // A signal interrupted `poll()`. The loop condition decides whether it was a shutdown request.
+            if err.kind() == io::ErrorKind::Interrupted {
+                continue;
+            }
+
+            break Err(Error::Poll(err));
+        }
+
+        for ev in pfds.events(&mut round_robin) {
+            if ev.is_listener {
+                let (sock, transport) = &listeners[&ev.fd];
+                if let Some(client) = accept_client(sock, *transport) {
+                    println!("omniserver: new {}", client.label);
+                    pfds.insert(client.sock.as_raw(), Interest::Read);
+                    clients.insert(client.sock.as_raw(), client);
+                }
+            } else if let Some(client) = clients.get(&ev.fd)
+                && !echo_once(client)
+            {
+                println!("omniserver: {} hung up", client.label);
+                pfds.remove(ev.fd);
+                // The client is closed when its socket is dropped.
+                clients.remove(&ev.fd);
+            }
+        }
+    };
+
+    println!("omniserver: shutting down...");
+
+    if let Some(path) = unix_path
+        && !sockaddr::is_abstract_unix_path(path)
+    {
+        let _ = fs::remove_file(path);
+    }
+
+    res
+}
+
+// Binds a listener for each transport, keyed by their fds.
+fn bind_listeners(
+    port: u16,
+    unix_path: Option<&Path>,
+) -> Result<HashMap<i32, (Socket, Transport)>, Error> {
+    let port = CString::new(port.to_string()).unwrap();
+    let opts = ListenerOptions {
+        v6only: true,
+        ..Default::default()
+    };
+    let mut listeners = HashMap::new();
+
+    let (sock_fd, addr) = listener::bind_listener_with(None, libc::AF_INET, &port, &opts)?;
+    println!("omniserver: listening on {} {}", Transport::Tcp4, addr);
+    listeners.insert(sock_fd, (Socket::from_raw(sock_fd), Transport::Tcp4));
+
+    match listener::bind_listener_with(None, libc::AF_INET6, &port, &opts) {
+        Ok((sock_fd, addr)) => {
+            println!("omniserver: listening on {} {}", Transport::Tcp6, addr);
+            listeners.insert(sock_fd, (Socket::from_raw(sock_fd), Transport::Tcp6));
+        }
+        Err(err) => eprintln!("omniserver: no {} listener: {}", Transport::Tcp6, err),
+    }
+
+    if let Some(path) = unix_path {
+        let (sa, sa_len) = sockaddr::fill_sockaddr_un(path)?;
+        let sock =
+            bind_unix_listener(path, &sa, sa_len).map_err(|err| Error::Unix(path.into(), err))?;
+        println!(
+            "omniserver: listening on {} {}",
+            Transport::Unix,
+            path.display()
+        );
+        listeners.insert(sock.as_raw(), (sock, Transport::Unix));
+    }
+
+    Ok(listeners)
+}
+
+/// Creates a `SOCK_STREAM` Unix domain socket that listens on `sa`, which is filled with `path`.
+///
+/// A socket file that is already at `path` is removed first. Anything else there is left alone,
+/// and `bind()` fails with `EADDRINUSE`.
+fn bind_unix_listener(
+    path: &Path,
+    sa: &libc::sockaddr_un,
+    sa_len: libc::socklen_t,
+) -> io::Result<Socket> {
+    if !sockaddr::is_abstract_unix_path(path)
+        && fs::symlink_metadata(path).is_ok_and(|meta| meta.file_type().is_socket())
+    {
+        fs::remove_file(path)?;
+    }
+
+    let sock = Socket::new(libc::AF_UNIX, libc::SOCK_STREAM, 0)?;
+
+    // SAFETY: `sa` is a valid `sockaddr_un`, and `sa_len` does not exceed its size.
+    let ecode = unsafe {
+        trace::bind(
+            sock.as_raw(),
+            sa as *const libc::sockaddr_un as *const libc::sockaddr,
+            sa_len,
+        )
+    };
+    if ecode == -1 {
+        return Err(io::Error::last_os_error());
+    }
+
+    // SAFETY: `listen()` is safe to use on a valid, bound socket.
+    let ecode = unsafe { trace::listen(sock.as_raw(), BACKLOG) };
+    if ecode == -1 {
+        return Err(io::Error::last_os_error());
+    }
+
+    Ok(sock)
+}
+
+// Accepts a client on `listener`, and labels it by `transport` and its address.
+// Returns `None` if `accept()` fails, the error is only printed so that the other clients are still served.
+fn accept_client(listener: &Socket, transport: Transport) -> Option<Client> {
+    // SAFETY: All zero `sockaddr_storage` is a valid initialization.
+    // Read will happen after it is written by `accept()`.
+    let mut sa: libc::sockaddr_storage = unsafe { mem::zeroed() };
+    let mut sa_len = mem::size_of_val(&sa) as libc::socklen_t;
+
+    // SAFETY: `sa` is big enough for any address family, and `sa_len` holds its size.
+    let sock_fd = unsafe {
+        trace::accept(
+            listener.as_raw(),
+            &raw mut sa as *mut libc::sockaddr,
+            &raw mut sa_len,
+        )
+    };
+    if sock_fd == -1 {
+        eprintln!(
+            "omniserver: {} accept error: {}",
+            transport,
+            io::Error::last_os_error()
+        );
+        return None;
+    }
+    let sock = Socket::from_raw(sock_fd);
+
+    let from = match transport {
+        Transport::Unix => unix_peer(&sa, sa_len),
+        Transport::Tcp4 | Transport::Tcp6 => match sockaddr::to_socket_addr(&sa) {
+            Some(addr) => addr.to_string(),
+            None => format!("an unknown address family {}", sa.ss_family),
+        },
+    };
+
+    Some(Client {
+        label: format!("{} client {} on socket {}", transport, from, sock_fd),
+        sock,
+    })
+}
+
+// Decodes the path of a Unix domain peer, which is usually empty since the clients rarely bind.
+fn unix_peer(sa: &libc::sockaddr_storage, sa_len: libc::socklen_t) -> String {
+    // SAFETY: The address is accepted on an `AF_UNIX` listener, so `sockaddr_storage` holds a `sockaddr_un`.
+    let sa_un = unsafe { &*(sa as *const libc::sockaddr_storage as *const libc::sockaddr_un) };
+
+    // Only `sa_len - offset_of(sun_path)` bytes of the path are filled, and the null terminator is optional.
+    let path_len = (sa_len as usize)
+        .saturating_sub(mem::offset_of!(libc::sockaddr_un, sun_path))
+        .min(sa_un.sun_path.len());
+    let path: Vec<u8> = sa_un.sun_path[..path_len]
+        .iter()
+        .take_while(|c| **c != 0)
+        .map(|c| *c as u8)
+        .collect();
+
+    if path.is_empty() {
+        String::from("(unnamed)")
+    } else {
+        String::from_utf8_lossy(&path).into_owned()
+    }
+}
+
+// Sends back a single chunk from `client`.
+// Returns `false` once the client is gone, either hung up or failed.
+fn echo_once(client: &Client) -> bool {
+    let mut buf = [0u8; ECHO_BUF_SIZE];
+
+    // SAFETY: `buf` is initialized and its length is passed along with it.
+    let rbytes = unsafe {
+        trace::recv(
+            client.sock.as_raw(),
+            buf.as_mut_ptr() as *mut libc::c_void,
+            buf.len(),
+            0,
+        )
+    };
+    match rbytes {
+        -1 => {
+            let err = io::Error::last_os_error();
+            if err.kind() == io::ErrorKind::Interrupted {
+                return true;
+            }
+            if !stream_io::is_reset(&err) {
+                eprintln!("omniserver: recv error on {}: {}", client.label, err);
+            }
+            false
+        }
+        0 => false,
+        _ => match stream_io::sendall(client.sock.as_raw(), &buf[..rbytes as usize]) {
+            Ok(_) => true,
+            Err(err) => {
+                eprintln!("omniserver: send error on {}: {}", client.label, err);
+                false
+            }
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::process;
+
+    use super::*;
+    use crate::client_builder::Client;
+
+    // Connects a client to the listener of `transport`, the Unix domain clients do not bind.
+    fn connect(listener: &Socket, transport: Transport, unix_path: &Path) -> Socket {
+        match transport {
+            Transport::Tcp4 | Transport::Tcp6 => {
+                let addr = listener::local_addr(listener.as_raw()).unwrap();
+                let (sock, _) = Client::new()
+                    .host(&addr.ip().to_string())
+                    .port(addr.port())
+                    .connect()
+                    .unwrap();
+                sock
+            }
+            Transport::Unix => {
+                let (sa, sa_len) = sockaddr::fill_sockaddr_un(unix_path).unwrap();
+                let sock = Socket::new(libc::AF_UNIX, libc::SOCK_STREAM, 0).unwrap();
+                // SAFETY: `sa` is a valid `sockaddr_un`, and `sa_len` does not exceed its size.
+                let ecode = unsafe {
+                    libc::connect(
+                        sock.as_raw(),
+                        &raw const sa as *const libc::sockaddr,
+                        sa_len,
+                    )
+                };
+                assert_eq!(ecode, 0, "connect error: {}", io::Error::last_os_error());
+                sock
+            }
+        }
+    }
+
+    #[test]
+    fn echoes_over_every_transport() {
+        let unix_path =
+            std::env::temp_dir().join(format!("bjrs-omniserver-{}.sock", process::id()));
+        let listeners = bind_listeners(0, Some(&unix_path)).unwrap();
+
+        let mut transports: Vec<Transport> = listeners.values().map(|(_, t)| *t).collect();
+        transports.sort_by_key(|t| t.to_string());
+        assert_eq!(
+            transports,
+            [Transport::Tcp4, Transport::Tcp6, Transport::Unix]
+        );
+
+        for (listener, transport) in listeners.values() {
+            let sock = connect(listener, *transport, &unix_path);
+            let client = accept_client(listener, *transport).unwrap();
+            assert!(client.label.starts_with(&format!("{} client", transport)));
+
+            let msg = format!("hello over {}\n", transport);
+            stream_io::sendall(sock.as_raw(), msg.as_bytes()).unwrap();
+            assert!(echo_once(&client));
+            let mut echoed = vec![0u8; msg.len()];
+            let rbytes = stream_io::recv_exact(sock.as_raw(), &mut echoed).unwrap();
+            assert_eq!(rbytes, msg.len());
+            assert_eq!(echoed, msg.as_bytes(), "echo over {}", transport);
+
+            // The client hanging up is told apart from an echo.
+            drop(sock);
+            assert!(!echo_once(&client));
+        }
+
+        fs::remove_file(&unix_path).unwrap();
+    }
+}
@@ -0,0 +1,159 @@
+use std::{error, ffi::CString, fmt, io, thread, time::Duration};
+
+use crate::{fd, udp};
+
+#[derive(Debug)]
+pub enum Error {
+    Pipe(io::Error),
+    Udp(udp::Error),
+    Poll(io::Error),
+    Read(io::Error),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Pipe(err) => write!(f, "pipe error: {}", err),
+            Error::Udp(err) => write!(f, "udp error: {}", err),
+            Error::Poll(err) => write!(f, "poll error: {}", err),
+            Error::Read(err) => write!(f, "read error: {}", err),
+        }
+    }
+}
+
+impl error::Error for Error {}
+
+impl From<udp::Error> for Error {
+    fn from(value: udp::Error) -> Self {
+        Self::Udp(value)
+    }
+}
+
+const RECV_BUF_SIZE: usize = 256;
+
+// EXAMPLE: `poll()` works on any fd, not just on sockets.
+// A thread writes a line to a pipe every `interval`, and closes its write end after `ticks` lines.
+// Meanwhile, the main thread polls the read end of the pipe and a UDP socket together,
+// and prints which one of them became ready.
+// MANPAGE:
+// man 2 pipe (Linux)
+// man 2 poll (Linux)
+//
+// Closing the write end makes the read end readable one last time, and `read()` returns 0 (EOF).
+// The example ends at that point.
+pub fn pipe_and_socket(port: u16, interval: Duration, ticks: u32) -> Result<(), Error> {
+    let mut pipe_fds = [0i32; 2];
+
+    // SAFETY: `pipe_fds` has room for the two fds that `pipe()` writes.
+    let ecode = unsafe { libc::pipe(pipe_fds.as_mut_ptr()) };
+    if ecode == -1 {
+        return Err(Error::Pipe(io::Error::last_os_error()));
+    }
+    let [read_fd, write_fd] = pipe_fds;
+
+    let port = CString::new(port.to_string()).unwrap();
+    let (sock_fd, addr) = match udp::bind_udp(Some(c"127.0.0.1"), &port) {
+        Ok(res) => res,
+        Err(err) => {
+            let _ = fd::close_fd(read_fd);
+            let _ = fd::close_fd(write_fd);
+            return Err(err.into());
+        }
+    };
+
+    println!(
+        "pipe_and_socket: watching pipe fd {} and socket fd {} ({})...",
+        read_fd, sock_fd, addr
+    );
+
+    let writer = thread::spawn(move || write_ticks(write_fd, interval, ticks));
+    let res = watch(read_fd, sock_fd);
+
+    // The writer closes its own end, the rest are not used after this point.
+    let _ = writer.join();
+    let _ = fd::close_fd(read_fd);
+    let _ = fd::close_fd(sock_fd);
+
+    res
+}
+
+fn write_ticks(write_fd: i32, interval: Duration, ticks: u32) {
+    for tick in 1..=ticks {
+        thread::sleep(interval);
+
+        let line = format!("tick {}\n", tick);
+
+        // SAFETY: `line` is initialized and valid for the entire `write()` call.
+        let wbytes =
+            unsafe { libc::write(write_fd, line.as_ptr() as *const libc::c_void, line.len()) };
+        if wbytes == -1 {
+            eprintln!(
+                "pipe_and_socket: write error: {}",
+                io::Error::last_os_error()
+            );
+            break;
+        }
+    }
+
+    // This is what the reader sees as EOF.
+    let _ = fd::close_fd(write_fd);
+}
+
+fn watch(read_fd: i32, sock_fd: i32) -> Result<(), Error> {
+    let mut pfds = [
+        libc::pollfd {
+            fd: read_fd,
+            events: libc::POLLIN,
+            revents: 0,
+        },
+        libc::pollfd {
+            fd: sock_fd,
+            events: libc::POLLIN,
+            revents: 0,
+        },
+    ];
+    let mut buf = [0u8; RECV_BUF_SIZE];
+
+    loop {
+        // SAFETY: `pfds` is initialized and its length is passed along with it.
+        let nfds = unsafe { libc::poll(pfds.as_mut_ptr(), pfds.len() as libc::nfds_t, -1) };
+        if nfds == -1 {
+            let err = io::Error::last_os_error();
+            if err.kind() == io::ErrorKind::Interrupted {
+                continue;
+            }
+
+            return Err(Error::Poll(err));
+        }
+
+        // A closed write end is reported as `POLLHUP`, which may come without `POLLIN`.
+        if pfds[0].revents & (libc::POLLIN | libc::POLLHUP) != 0 {
+            // SAFETY: `buf` is initialized and its length is passed along with it.
+            let rbytes =
+                unsafe { libc::read(read_fd, buf.as_mut_ptr() as *mut libc::c_void, buf.len()) };
+            match rbytes {
+                -1 => return Err(Error::Read(io::Error::last_os_error())),
+                0 => {
+                    println!("pipe_and_socket: pipe fd {} reached EOF", read_fd);
+                    return Ok(());
+                }
+                n => print!(
+                    "pipe_and_socket: pipe fd {} is ready: {}",
+                    read_fd,
+                    String::from_utf8_lossy(&buf[..n as usize])
+                ),
+            }
+        }
+
+        if pfds[1].revents & libc::POLLIN != 0 {
+            let (rbytes, from_addr) = udp::recv_from(sock_fd, &mut buf)?;
+            println!(
+                "pipe_and_socket: socket fd {} is ready: {} bytes from {}: {:?}",
+                sock_fd,
+                rbytes,
+                from_addr,
+                String::from_utf8_lossy(&buf[..rbytes])
+            );
+        }
+    }
+}
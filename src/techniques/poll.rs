@@ -1,11 +1,26 @@
-use std::{io, ptr};
+use std::{error, fmt, io, ptr};
+
+#[derive(Debug)]
+pub enum Error {
+    Poll(io::Error),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Poll(err) => write!(f, "poll error: {}", err),
+        }
+    }
+}
+
+impl error::Error for Error {}
 
 // EXAMPLE: Poll stdin to see whether it is ready to be read or not.
 // MANPAGE:
 // man 2 poll (Linux)
 // man 3 poll (POSIX)
 // man errno
-pub fn poll() -> Result<(), io::Error> {
+pub fn poll() -> Result<(), Error> {
     let mut pfds = [libc::pollfd {
         fd: 0,                // stdin
         events: libc::POLLIN, // notify when fd is ready to be read
@@ -25,13 +40,13 @@ pub fn poll() -> Result<(), io::Error> {
         )
     };
     match num_events {
-        -1 => Err(io::Error::last_os_error()),
+        -1 => Err(Error::Poll(io::Error::last_os_error())),
         0 => {
             println!("Poll timed out!");
             Ok(())
         }
         _ => {
-            let pollin_happened = (pfds[0].revents & libc::POLLIN) == 1;
+            let pollin_happened = pfds[0].revents & libc::POLLIN != 0;
             if pollin_happened {
                 let fd = pfds[0].fd;
                 println!("File descriptor {} is ready to read", fd);
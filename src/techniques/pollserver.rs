@@ -1,40 +1,58 @@
 use std::{
-    error,
-    ffi::{CStr, CString},
-    fmt,
+    collections::HashMap,
+    error, fmt,
     io::{self, Write},
-    mem,
-    net::{IpAddr, Ipv4Addr, Ipv6Addr},
-    ptr,
+    iter, mem,
+    net::SocketAddr,
+    num::NonZeroUsize,
+    slice,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    chat::{self, MessageHandler},
+    emfile::EmfileGuard,
+    fairness::RoundRobin,
+    fd,
+    listener::{self, BindRetry, ListenerOptions},
+    reactor::{Interest, Reactor, ReadyKind},
+    signal, sockaddr,
+    socket_kind::AddressFamily,
+    stats::ServerStats,
+    stream_io, trace,
 };
 
 #[derive(Debug)]
 pub enum Error {
-    Getaddrinfo(String),
-    Socket(io::Error),
-    Setsockopt(io::Error),
-    Bind(io::Error),
-    Listen(io::Error),
+    Listener(listener::Error),
     Poll(io::Error),
+    Signal(io::Error),
+    Guard(io::Error),
 }
 
 impl fmt::Display for Error {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
-            Error::Getaddrinfo(err) => write!(f, "getaddrinfo error: {}", err),
-            Error::Socket(err) => write!(f, "socket error: {}", err),
-            Error::Setsockopt(err) => write!(f, "setsockopt error: {}", err),
-            Error::Bind(err) => write!(f, "bind error: {}", err),
-            Error::Listen(err) => write!(f, "listen error: {}", err),
+            Error::Listener(err) => write!(f, "listener error: {}", err),
             Error::Poll(err) => write!(f, "poll error: {}", err),
+            Error::Signal(err) => write!(f, "sigaction error: {}", err),
+            Error::Guard(err) => write!(f, "failed to reserve the EMFILE guard fd: {}", err),
         }
     }
 }
 
 impl error::Error for Error {}
 
+impl From<listener::Error> for Error {
+    fn from(value: listener::Error) -> Self {
+        Self::Listener(value)
+    }
+}
+
 struct Pfds {
+    listener_fd: i32,
     pfds: Vec<libc::pollfd>,
+    last_active: HashMap<i32, Instant>,
 }
 
 impl Pfds {
@@ -44,12 +62,16 @@ impl Pfds {
 
         let listener_pfd = libc::pollfd {
             fd: listener_fd,
-            events: libc::POLLIN,
+            events: Interest::Read.poll_events(),
             revents: 0,
         };
         pfds.push(listener_pfd);
 
-        Self { pfds }
+        Self {
+            listener_fd,
+            pfds,
+            last_active: HashMap::new(),
+        }
     }
 
     pub fn len(&self) -> usize {
@@ -72,23 +94,93 @@ impl Pfds {
                     if let Some(idx) = idx {
                         self.pfds.swap_remove(idx);
                     }
+                    self.last_active.remove(fd);
                 }
-                PfdChange::Insert(fd) => {
+                PfdChange::Insert(fd, interest) => {
                     let pfd = libc::pollfd {
                         fd: *fd,
-                        events: libc::POLLIN,
+                        events: interest.poll_events(),
                         revents: 0,
                     };
                     self.pfds.push(pfd);
+                    self.last_active.insert(*fd, Instant::now());
+                }
+                PfdChange::Touch(fd) => {
+                    self.last_active.insert(*fd, Instant::now());
                 }
             }
         }
     }
+
+    /// Returns the clients that have not sent anything for longer than `idle_timeout`.
+    pub fn idle_fds(&self, idle_timeout: Duration) -> Vec<i32> {
+        self.last_active
+            .iter()
+            .filter(|(_, last_active)| last_active.elapsed() >= idle_timeout)
+            .map(|(fd, _)| *fd)
+            .collect()
+    }
+
+    /// Returns the `poll()` timeout in milliseconds until the next client becomes idle.
+    /// Without any clients, `poll()` can block indefinitely.
+    pub fn poll_timeout(&self, idle_timeout: Duration) -> i32 {
+        self.last_active
+            .values()
+            .map(|last_active| idle_timeout.saturating_sub(last_active.elapsed()))
+            .min()
+            // Round up, otherwise `poll()` wakes up a little too early and the sweep finds nothing.
+            .map(|left| left.as_micros().div_ceil(1000).min(i32::MAX as u128) as i32)
+            .unwrap_or(-1)
+    }
+}
+
+impl Reactor for Pfds {
+    fn listener_fds(&self) -> &[i32] {
+        slice::from_ref(&self.listener_fd)
+    }
+
+    fn ready(&self) -> Vec<(i32, ReadyKind)> {
+        self.pfds
+            .iter()
+            .filter_map(|pfd| ReadyKind::from_revents(pfd.revents).map(|kind| (pfd.fd, kind)))
+            .collect()
+    }
 }
 
 enum PfdChange {
     Remove(i32),
-    Insert(i32),
+    Insert(i32, Interest),
+    Touch(i32),
+}
+
+/// The knobs of `run_pollserver()`, the defaults run the server until it is stopped by a signal.
+#[derive(Debug, Default, Clone)]
+pub struct PollserverOptions {
+    /// Close the clients that stay silent for longer than this. The `poll()` timeout is used as a watchdog
+    /// to close them in time, and the rest of the clients are notified.
+    pub idle_timeout: Option<Duration>,
+    /// Serve at most this many ready sockets per `poll()` call.
+    /// The rest stay ready, and they are served first on the next call (see `RoundRobin`).
+    pub max_events: Option<NonZeroUsize>,
+    /// Stop after relaying this many messages.
+    pub max_messages: Option<u64>,
+    /// Keep a spare fd to accept and close right away the connections that arrive once the server runs out of fds,
+    /// see `EmfileGuard`. Otherwise, they stay in the accept queue and `poll()` keeps reporting the listener
+    /// as readable, so the server spins on a failing `accept()` until a client leaves.
+    pub emfile_guard: bool,
+    /// Send this message of the day in full to every new client right after `accept()`, before the client is added
+    /// to the pollfd list. It only goes to that client, and it always arrives before the first relayed message.
+    pub motd: Option<Vec<u8>>,
+    /// On exit, spend at most this long turning away the connections that are queued but not accepted yet,
+    /// so they are told that the server is shutting down instead of being reset, see `listener::drain_pending()`.
+    pub drain_on_exit: Option<Duration>,
+    /// Set `TCP_DEFER_ACCEPT` on the listener (Linux only), in seconds. Only `pollserver()` sets up a listener,
+    /// `run_pollserver()` takes the one it is given as is.
+    ///
+    /// The listener then only becomes readable once a client sends its first message (or once the kernel gives up
+    /// waiting for it). A client that stays silent is not accepted until then, so it does not count as a client,
+    /// does not receive the `motd`, and is not subject to `idle_timeout` either.
+    pub defer_accept: Option<i32>,
 }
 
 // EXAMPLE: A multiperson chat server.
@@ -97,142 +189,190 @@ enum PfdChange {
 // man 2 poll (Linux)
 // man 3 poll (POSIX)
 // man errno
-pub fn pollserver() -> Result<(), Error> {
-    let listener_fd = get_listener_socket()?;
-    let mut pfds = Pfds::new(listener_fd);
-
-    println!("pollserver: waiting for connections...");
+//
+// The server runs until SIGINT (Ctrl-C) or SIGTERM is received, see `PollserverOptions` for the rest.
+// Then, every socket is closed and the collected `ServerStats` are returned.
+//
+// The listener is bound to `family`. An IPv6 listener is dual-stack on most systems: IPv4 clients are accepted
+// through it as well, with their addresses mapped to IPv6 (`::ffff:a.b.c.d`). The clients are labeled by the
+// family they actually connect with.
+pub fn pollserver(
+    retry: &BindRetry,
+    family: AddressFamily,
+    opts: &PollserverOptions,
+) -> Result<ServerStats, Error> {
+    let listener_fd = setup_pollserver_listener(retry, family, opts.defer_accept)?;
+    run_pollserver(listener_fd, opts)
+}
 
-    loop {
-        // SAFETY: The pollfd buf is initialized properly.
-        // There are no reads to uninitialized memory, hence `poll()` is safe to use.
-        let poll_count = unsafe { libc::poll(pfds.as_mut_ptr(), pfds.len() as u64, -1) };
-        match poll_count {
-            -1 => Err(Error::Poll(io::Error::last_os_error())),
-            _ => Ok(()),
-        }?;
+/// Binds the listener of the chat server to port 9034, on all local addresses of `family`.
+///
+/// `defer_accept` is only set on Linux, and the value the kernel settled on is printed.
+pub fn setup_pollserver_listener(
+    retry: &BindRetry,
+    family: AddressFamily,
+    defer_accept: Option<i32>,
+) -> Result<i32, Error> {
+    let opts = ListenerOptions {
+        retry: *retry,
+        defer_accept,
+        ..Default::default()
+    };
+    let (listener_fd, _) = listener::bind_listener_with(None, family.as_raw(), c"9034", &opts)?;
 
-        let changes = process_connections(listener_fd, &pfds);
-        pfds.apply_changes(&changes);
+    #[cfg(target_os = "linux")]
+    if defer_accept.is_some() {
+        match listener::defer_accept(listener_fd) {
+            Ok(secs) => println!("pollserver: TCP_DEFER_ACCEPT = {}", secs),
+            Err(err) => eprintln!("pollserver: getsockopt TCP_DEFER_ACCEPT error: {}", err),
+        }
     }
+
+    Ok(listener_fd)
 }
 
-fn get_listener_socket() -> Result<i32, Error> {
-    let port = CString::from(c"9034");
-
-    // SAFETY: All zero hints is a valid initialization.
-    // Required fields are set later on.
-    let mut hints: libc::addrinfo = unsafe { mem::zeroed() };
-    hints.ai_family = libc::AF_INET;
-    hints.ai_socktype = libc::SOCK_STREAM;
-
-    let mut gai_res_ptr: *mut libc::addrinfo = ptr::null_mut();
-
-    // SAFETY: There are no uninitialized reads. `getaddrinfo()` is safe to use.
-    let ecode = unsafe { libc::getaddrinfo(ptr::null(), port.as_ptr(), &hints, &mut gai_res_ptr) };
-    match ecode {
-        0 => Ok(()),
-        _ => {
-            // SAFETY: `gai_strerror` is valid to call on a failed `getaddrinfo()` call.
-            let err = unsafe { CStr::from_ptr(libc::gai_strerror(ecode)).to_string_lossy() };
-            Err(Error::Getaddrinfo(err.into_owned()))
+/// Runs the chat server on `listener_fd`, which may be any listening socket (e.g. one bound to an ephemeral port).
+///
+/// `listener_fd` is owned by the server from now on, it is closed along with the clients on exit.
+pub fn run_pollserver(listener_fd: i32, opts: &PollserverOptions) -> Result<ServerStats, Error> {
+    run_pollserver_with(listener_fd, opts, chat::broadcast_to_others)
+}
+
+/// Same as `run_pollserver()`, but every received message is routed by `handler` instead of being
+/// broadcast to the other clients.
+pub fn run_pollserver_with(
+    listener_fd: i32,
+    opts: &PollserverOptions,
+    mut handler: impl MessageHandler,
+) -> Result<ServerStats, Error> {
+    if let Err(err) = signal::install_shutdown_handler() {
+        let _ = fd::close_fd(listener_fd);
+        return Err(Error::Signal(err));
+    }
+
+    // The family of the listener decides how the addresses of the accepted clients are decoded.
+    let family = match listener::local_addr(listener_fd) {
+        Ok(addr) => AddressFamily::of(&addr),
+        Err(err) => {
+            let _ = fd::close_fd(listener_fd);
+            return Err(Error::Listener(err));
         }
-    }?;
-
-    let mut sock_fd = -1;
-
-    while !gai_res_ptr.is_null() {
-        // SAFETY: `gai_res_ptr` is guaranteed to point atleast one valid addrinfo struct on a successful `getaddrinfo()` call.
-        let ai = unsafe { *gai_res_ptr };
-        let next_ai_ptr = ai.ai_next;
-
-        // SAFETY: `socket()` is safe to call since `gai_res` is valid.
-        let sock = unsafe { libc::socket(ai.ai_family, ai.ai_socktype, 0) };
-        if sock == -1 {
-            if next_ai_ptr.is_null() {
-                return Err(Error::Socket(io::Error::last_os_error()));
-            } else {
-                gai_res_ptr = next_ai_ptr;
-                continue;
-            }
+    };
+
+    let mut guard = match opts.emfile_guard.then(EmfileGuard::new).transpose() {
+        Ok(guard) => guard,
+        Err(err) => {
+            let _ = fd::close_fd(listener_fd);
+            return Err(Error::Guard(err));
         }
+    };
 
-        let yes: i32 = 1;
-        // SAFETY: `setsockopt()` is called for a valid sock_fd created by a successful `socket()` call, making it safe to use.
-        let ecode = unsafe {
-            libc::setsockopt(
-                sock,
-                libc::SOL_SOCKET,
-                libc::SO_REUSEADDR,
-                &raw const yes as *const libc::c_void,
-                mem::size_of::<i32>() as u32,
-            )
-        };
-        if ecode == -1 {
-            if next_ai_ptr.is_null() {
-                return Err(Error::Setsockopt(io::Error::last_os_error()));
-            } else {
-                gai_res_ptr = next_ai_ptr;
-                continue;
-            }
+    let mut pfds = Pfds::new(listener_fd);
+    let mut stats = ServerStats::new();
+    let mut round_robin = RoundRobin::new(opts.max_events);
+    let mut messages = 0;
+
+    println!("pollserver: waiting for connections...");
+
+    let res = loop {
+        if signal::shutdown_requested() || opts.max_messages.is_some_and(|max| messages >= max) {
+            break Ok(stats);
         }
 
-        // SAFETY: The socket and address used for `bind()` are valid due to `socket()` and `getaddrinfo()` calls above.
-        // Bind is safe to call.
-        let ecode = unsafe { libc::bind(sock, ai.ai_addr, ai.ai_addrlen) };
-        if ecode == -1 {
-            if next_ai_ptr.is_null() {
-                return Err(Error::Bind(io::Error::last_os_error()));
-            } else {
-                gai_res_ptr = next_ai_ptr;
+        let timeout = opts
+            .idle_timeout
+            .map_or(-1, |idle_timeout| pfds.poll_timeout(idle_timeout));
+
+        // SAFETY: The pollfd buf is initialized properly.
+        // There are no reads to uninitialized memory, hence `poll()` is safe to use.
+        let poll_count = unsafe { libc::poll(pfds.as_mut_ptr(), pfds.len() as u64, timeout) };
+        if poll_count == -1 {
+            let err = io::Error::last_os_error();
+            // A signal interrupted `poll()`. The loop condition decides whether it was a shutdown request.
+            if err.kind() == io::ErrorKind::Interrupted {
                 continue;
             }
+
+            break Err(Error::Poll(err));
         }
 
-        sock_fd = sock;
-        break;
-    }
+        let changes = process_connections(
+            family,
+            opts.motd.as_deref(),
+            &pfds,
+            &mut round_robin,
+            guard.as_mut(),
+            &mut handler,
+            &mut stats,
+        );
+        // Every relayed message touches its sender.
+        messages += changes
+            .iter()
+            .filter(|change| matches!(change, PfdChange::Touch(_)))
+            .count() as u64;
+        pfds.apply_changes(&changes);
 
-    // SAFETY: `gai_res_ptr` will not be used after this call, therefore it is safe to free it.
-    unsafe { libc::freeaddrinfo(gai_res_ptr) };
+        if let Some(idle_timeout) = opts.idle_timeout {
+            let changes = sweep_idle_clients(listener_fd, &pfds, idle_timeout, &mut stats);
+            pfds.apply_changes(&changes);
+        }
+    };
+
+    println!("pollserver: shutting down...");
 
-    const BACKLOG: i32 = 10;
-    // SAFETY: The `sock_fd` used for `listen()` is guaranteed to be valid due to above, making `listen()` safe to use.
-    let ecode = unsafe { libc::listen(sock_fd, BACKLOG) };
-    match ecode {
-        -1 => Err(Error::Listen(io::Error::last_os_error())),
-        _ => Ok(()),
-    }?;
+    if let Some(budget) = opts.drain_on_exit {
+        match listener::drain_pending(listener_fd, listener::SHUTDOWN_NOTICE, budget) {
+            Ok(drained) => println!("pollserver: drained {} pending connections", drained),
+            Err(err) => eprintln!(
+                "pollserver: failed to drain the pending connections: {}",
+                err
+            ),
+        }
+    }
+
+    for pfd in pfds.iter() {
+        if let Err(err) = fd::close_fd(pfd.fd) {
+            eprintln!("pollserver: close error on socket {}: {}", pfd.fd, err);
+        }
+    }
 
-    Ok(sock_fd)
+    res
 }
 
-fn process_connections(listener_fd: i32, pfds: &Pfds) -> Vec<PfdChange> {
+fn process_connections(
+    family: AddressFamily,
+    motd: Option<&[u8]>,
+    pfds: &Pfds,
+    round_robin: &mut RoundRobin,
+    mut guard: Option<&mut EmfileGuard>,
+    handler: &mut impl MessageHandler,
+    stats: &mut ServerStats,
+) -> Vec<PfdChange> {
+    let listener_fd = pfds.listener_fd;
     let mut changes = vec![];
 
-    let source_fds = pfds.iter().filter_map(|pfd| {
-        if (pfd.revents & (libc::POLLIN | libc::POLLHUP)) == 1 {
-            Some(pfd.fd)
-        } else {
-            None
-        }
-    });
-
-    for source_fd in source_fds {
-        if source_fd == listener_fd {
-            let client_fd = accept_new_client(listener_fd);
-            changes.push(PfdChange::Insert(client_fd));
-        } else {
-            let dest_fds = pfds.iter().filter_map(|pfd| {
-                if pfd.fd != source_fd && pfd.fd != listener_fd {
-                    Some(pfd.fd)
-                } else {
-                    None
+    // A hung up or reset client is served like a readable one, so that `recv()` can report it and the fd can be removed.
+    for ev in pfds.events(round_robin) {
+        let source_fd = ev.fd;
+        if ev.is_listener {
+            let client_fd = accept_new_client(listener_fd, family, guard.as_deref_mut());
+            if client_fd != -1 {
+                stats.on_accept();
+                if let Some(motd) = motd {
+                    send_motd(client_fd, motd, stats);
                 }
-            });
-            let closed_fd = send_message_to_clients(source_fd, dest_fds);
-            if let Some(fd) = closed_fd {
-                changes.push(PfdChange::Remove(fd))
+                changes.push(PfdChange::Insert(client_fd, Interest::Read));
+            }
+        } else {
+            let client_fds: Vec<i32> = pfds
+                .iter()
+                .map(|pfd| pfd.fd)
+                .filter(|fd| *fd != listener_fd)
+                .collect();
+            let closed_fd = relay_message(source_fd, &client_fds, handler, stats);
+            match closed_fd {
+                Some(fd) => changes.push(PfdChange::Remove(fd)),
+                None => changes.push(PfdChange::Touch(source_fd)),
             }
         }
     }
@@ -240,102 +380,236 @@ fn process_connections(listener_fd: i32, pfds: &Pfds) -> Vec<PfdChange> {
     changes
 }
 
-fn accept_new_client(sock_fd: i32) -> i32 {
+fn accept_new_client(sock_fd: i32, family: AddressFamily, guard: Option<&mut EmfileGuard>) -> i32 {
     // SAFETY: Initializing `sockaddr` as all zeroes is a valid initialization.
     // It will be filled by `accept()`.
     let mut sockaddr: libc::sockaddr_storage = unsafe { mem::zeroed() };
-    let mut len = mem::size_of_val(&sockaddr);
+    let mut len = mem::size_of_val(&sockaddr) as libc::socklen_t;
 
     // SAFETY: There are no reads to uninitialized memory, making `accept()` safe to use.
     let (conn_sock_fd, sockaddr) = unsafe {
-        let sock = libc::accept(
+        let sock = trace::accept(
             sock_fd,
             &raw mut sockaddr as *mut libc::sockaddr,
-            &raw mut len as *mut libc::socklen_t,
+            &raw mut len,
         );
         (sock, sockaddr)
     };
     if conn_sock_fd == -1 {
-        eprintln!("accept error: {}", io::Error::last_os_error());
+        let err = io::Error::last_os_error();
+        match guard {
+            Some(guard) if EmfileGuard::is_fd_limit(&err) => match guard.shed(sock_fd) {
+                Ok(()) => eprintln!("pollserver: out of fds, turned a new connection away"),
+                Err(err) => eprintln!("pollserver: out of fds, failed to turn away: {}", err),
+            },
+            _ => eprintln!("accept error: {}", err),
+        }
+        return conn_sock_fd;
     }
 
-    let ip_addr = try_into_ip_addr(sockaddr);
-    if let Some(ip_addr) = ip_addr {
-        println!(
-            "pollserver: new connection from {} on socket {}",
-            ip_addr, conn_sock_fd
-        );
+    match sockaddr::to_socket_addr(&sockaddr) {
+        Some(addr) => println!(
+            "pollserver: new {} on socket {}",
+            describe_client(family, addr),
+            conn_sock_fd
+        ),
+        None => eprintln!("pollserver: invalid address family {}", sockaddr.ss_family),
     }
 
     conn_sock_fd
 }
 
-fn send_message_to_clients(source_fd: i32, dest_fds: impl Iterator<Item = i32>) -> Option<i32> {
+// Labels a client accepted by a listener of `family`.
+// An IPv6 listener reports its IPv4 clients with mapped addresses, they are labeled as IPv4 nonetheless.
+fn describe_client(family: AddressFamily, addr: SocketAddr) -> String {
+    match (family, addr) {
+        (AddressFamily::V6, SocketAddr::V6(addr6)) => match addr6.ip().to_ipv4_mapped() {
+            Some(ipv4) => format!(
+                "IPv4 connection from {}:{} (as {} on the IPv6 listener)",
+                ipv4,
+                addr6.port(),
+                addr6.ip()
+            ),
+            None => format!("IPv6 connection from {}", addr),
+        },
+        (_, SocketAddr::V4(_)) => format!("IPv4 connection from {}", addr),
+        (_, SocketAddr::V6(_)) => format!("IPv6 connection from {}", addr),
+    }
+}
+
+fn relay_message(
+    source_fd: i32,
+    client_fds: &[i32],
+    handler: &mut impl MessageHandler,
+    stats: &mut ServerStats,
+) -> Option<i32> {
     let mut recv_buf = vec![0; 256];
     let len = recv_buf.len();
 
     // SAFETY: The buffer is initialized as desired, making `recv()` safe to use.
-    let bytes = unsafe {
-        libc::recv(
-            source_fd,
-            recv_buf.as_mut_ptr() as *mut libc::c_void,
-            len,
-            0,
-        )
-    };
+    // A `recv()` interrupted by a signal is retried, the client is only dropped on a genuine error.
+    let bytes = fd::retry_on_eintr(
+        || unsafe {
+            trace::recv(
+                source_fd,
+                recv_buf.as_mut_ptr() as *mut libc::c_void,
+                len,
+                0,
+            )
+        },
+        |bytes| *bytes == -1,
+    );
 
     if bytes <= 0 {
-        if bytes < 0 {
-            eprintln!("pollserver: recv error: {}", io::Error::last_os_error());
+        match bytes {
+            0 => eprintln!("pollserver: socket {} hung up", source_fd),
+            _ => {
+                let err = io::Error::last_os_error();
+                if stream_io::is_reset(&err) {
+                    eprintln!("pollserver: socket {} was reset by the client", source_fd);
+                } else {
+                    eprintln!("pollserver: recv error on socket {}: {}", source_fd, err);
+                }
+            }
         }
-        eprintln!("pollserver: socket {} hung up", source_fd);
+        stats.on_disconnect();
 
-        // SAFETY: If a `recv()` fails for a socket, the process stops listening it. Therefore, `close()` is safe to call. There will be no more messages coming through that socket.
-        unsafe { libc::close(source_fd) };
+        // If a `recv()` fails for a socket, the process stops listening it. There will be no more messages coming through that socket.
+        if let Err(err) = fd::close_fd(source_fd) {
+            eprintln!("pollserver: close error: {}", err);
+        }
 
         Some(source_fd)
     } else {
+        stats.on_recv(bytes as usize);
+
         let msg = [
             format!("pollserver: recv from fd {}: ", source_fd).as_bytes(),
-            &recv_buf[..],
+            &recv_buf[..bytes as usize],
         ]
         .concat();
         io::stdout()
             .write_all(&msg)
             .expect("message to be written to stdout");
 
-        for fd in dest_fds {
-            let bytes: usize = bytes.try_into().unwrap();
-
-            // SAFETY: `recv_buf` is safe to use, making `send()` safe.
-            let ecode =
-                unsafe { libc::send(fd, recv_buf.as_mut_ptr() as *const libc::c_void, bytes, 0) };
-            if ecode == -1 {
-                eprintln!("pollserver: send error: {}", io::Error::last_os_error());
-            };
+        for (dest_fd, msg) in handler(source_fd, &recv_buf[..bytes as usize], client_fds) {
+            send_to_clients(iter::once(dest_fd), &msg, stats);
         }
 
         None
     }
 }
 
-fn try_into_ip_addr(sockaddr: libc::sockaddr_storage) -> Option<IpAddr> {
-    match sockaddr.ss_family as i32 {
-        libc::AF_INET => {
-            // SAFETY: `ss_family == AF_INET` means that it is safe to cast `sockaddr_storage` to `sockaddr_in`.
-            let sockaddr_in = unsafe { *(&raw const sockaddr as *const libc::sockaddr_in) };
-            let bits = u32::from_be(sockaddr_in.sin_addr.s_addr);
-            Some(IpAddr::V4(Ipv4Addr::from_bits(bits)))
-        }
-        libc::AF_INET6 => {
-            // SAFETY: `ss_family == AF_INET6` means that it is safe to cast `sockaddr_storage` to `sockaddr_in6`.
-            let sockaddr_in6 = unsafe { *(&raw const sockaddr as *const libc::sockaddr_in6) };
-            let bits = u128::from_be_bytes(sockaddr_in6.sin6_addr.s6_addr);
-            Some(IpAddr::V6(Ipv6Addr::from_bits(bits)))
+// A failed MOTD is only reported, the client is added nonetheless. If it is gone, the next `recv()` notices it.
+fn send_motd(client_fd: i32, motd: &[u8], stats: &mut ServerStats) {
+    match stream_io::sendall(client_fd, motd) {
+        Ok(sbytes) => stats.on_send(sbytes),
+        Err(err) => eprintln!(
+            "pollserver: failed to send the MOTD to socket {}: {}",
+            client_fd, err
+        ),
+    }
+}
+
+fn send_to_clients(dest_fds: impl Iterator<Item = i32>, buf: &[u8], stats: &mut ServerStats) {
+    for fd in dest_fds {
+        // SAFETY: `buf` is a valid, initialized slice, making `send()` safe.
+        let ecode = unsafe { trace::send(fd, buf.as_ptr() as *const libc::c_void, buf.len(), 0) };
+        if ecode == -1 {
+            eprintln!("pollserver: send error: {}", io::Error::last_os_error());
+        } else {
+            stats.on_send(ecode as usize);
         }
-        af => {
-            eprintln!("pollserver: invalid address family {}", af);
-            None
+    }
+}
+
+fn sweep_idle_clients(
+    listener_fd: i32,
+    pfds: &Pfds,
+    idle_timeout: Duration,
+    stats: &mut ServerStats,
+) -> Vec<PfdChange> {
+    let idle_fds = pfds.idle_fds(idle_timeout);
+
+    for idle_fd in &idle_fds {
+        eprintln!("pollserver: socket {} timed out", idle_fd);
+        stats.on_disconnect();
+
+        // The idle client is dropped from the pollfd list right after the sweep, it is not used after this call.
+        if let Err(err) = fd::close_fd(*idle_fd) {
+            eprintln!("pollserver: close error: {}", err);
         }
     }
+
+    for idle_fd in &idle_fds {
+        let notice = format!("pollserver: socket {} timed out\n", idle_fd);
+        let dest_fds = pfds
+            .iter()
+            .map(|pfd| pfd.fd)
+            .filter(|fd| *fd != listener_fd && !idle_fds.contains(fd));
+        send_to_clients(dest_fds, notice.as_bytes(), stats);
+    }
+
+    idle_fds.into_iter().map(PfdChange::Remove).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        client_builder::Client, listener::ListenerOptions, server_builder::Server, testutil,
+    };
+
+    #[test]
+    fn a_write_only_fd_is_reported_only_as_writable() {
+        // Neither end of the listener pair has anything to read, so the listener is never ready.
+        let (listener, _listener_peer) = testutil::socketpair();
+        let (client, peer) = testutil::socketpair();
+        // The client has something to read as well, which it is not watched for.
+        stream_io::sendall(peer.as_raw(), b"unread").unwrap();
+
+        let mut pfds = Pfds::new(listener.as_raw());
+        pfds.apply_changes(&[PfdChange::Insert(client.as_raw(), Interest::Write)]);
+
+        // SAFETY: The pollfd buf is initialized properly, and its length is passed along with it.
+        let poll_count = unsafe { libc::poll(pfds.as_mut_ptr(), pfds.len() as u64, 0) };
+        assert_eq!(poll_count, 1);
+
+        let client_pfd = pfds.iter().find(|pfd| pfd.fd == client.as_raw()).unwrap();
+        assert_eq!(client_pfd.revents, libc::POLLOUT);
+        assert_eq!(pfds.ready(), [(client.as_raw(), ReadyKind::Writable)]);
+    }
+
+    #[test]
+    fn clients_of_a_dual_stack_listener_are_labeled_by_their_family() {
+        let opts = ListenerOptions::default();
+        let (listener_fd, addr) =
+            listener::bind_listener_with(Some(c"::"), libc::AF_INET6, c"0", &opts).unwrap();
+        let listener = Server::new().listen_fd(Some(listener_fd)).listen().unwrap();
+
+        let label_of = |host: &str| {
+            let (client, _) = Client::new()
+                .host(host)
+                .port(addr.port())
+                .connect()
+                .unwrap();
+            let (_, client_addr) = listener.accept().unwrap();
+            let client_port = listener::local_addr(client.as_raw()).unwrap().port();
+
+            (describe_client(AddressFamily::V6, client_addr), client_port)
+        };
+
+        // The IPv4 client is accepted with an IPv4-mapped address.
+        let (label, port) = label_of("127.0.0.1");
+        assert_eq!(
+            label,
+            format!(
+                "IPv4 connection from 127.0.0.1:{} (as ::ffff:127.0.0.1 on the IPv6 listener)",
+                port
+            )
+        );
+
+        let (label, port) = label_of("::1");
+        assert_eq!(label, format!("IPv6 connection from [::1]:{}", port));
+    }
 }
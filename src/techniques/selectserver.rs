@@ -1,35 +1,40 @@
 use std::{
-    error,
-    ffi::{CStr, CString},
-    fmt, io, mem,
-    net::{IpAddr, Ipv4Addr, Ipv6Addr},
-    ptr,
+    collections::HashMap,
+    error, fmt, io, mem,
+    num::NonZeroUsize,
+    ptr, slice,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    chat::{self, MessageHandler},
+    emfile::EmfileGuard,
+    fairness::RoundRobin,
+    fd,
+    listener::{self, BindRetry, ListenerOptions},
+    reactor::{Reactor, ReadyKind},
+    signal, sockaddr,
+    stats::ServerStats,
+    stream_io, trace,
 };
 
 #[derive(Debug)]
 pub enum Error {
-    Getaddrinfo(String),
-    Socket(io::Error),
-    Bind(i32, io::Error),
-    Listen(i32, io::Error),
+    Listener(listener::Error),
     InvalidAddressFamily,
-    Setsockopt(io::Error),
     Select(io::Error),
     Accept(io::Error),
     Recv(i32, io::Error),
     Send(i32, io::Error),
+    Close(i32, io::Error),
+    Signal(io::Error),
+    Guard(io::Error),
 }
 
 impl fmt::Display for Error {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
-            Error::Getaddrinfo(err) => write!(f, "getaddrinfo error: {}", err),
-            Error::Socket(err) => write!(f, "socket error: {}", err),
-            Error::Setsockopt(err) => write!(f, "setsockopt error: {}", err),
-            Error::Bind(sock_fd, err) => write!(f, "bind error for sock fd {}: {}", sock_fd, err),
-            Error::Listen(sock_fd, err) => {
-                write!(f, "listen error for sock fd {}: {}", sock_fd, err)
-            }
+            Error::Listener(err) => write!(f, "listener error: {}", err),
             Error::InvalidAddressFamily => write!(
                 f,
                 "ip conv failed: the given address family is not AF_INET or AF_INET6"
@@ -38,16 +43,27 @@ impl fmt::Display for Error {
             Error::Accept(err) => write!(f, "accept error: {}", err),
             Error::Recv(sock_fd, err) => write!(f, "recv error on sock fd {}: {}", sock_fd, err),
             Error::Send(sock_fd, err) => write!(f, "send error on sock fd {}: {}", sock_fd, err),
+            Error::Close(sock_fd, err) => write!(f, "close error on sock fd {}: {}", sock_fd, err),
+            Error::Signal(err) => write!(f, "sigaction error: {}", err),
+            Error::Guard(err) => write!(f, "failed to reserve the EMFILE guard fd: {}", err),
         }
     }
 }
 
 impl error::Error for Error {}
 
+impl From<listener::Error> for Error {
+    fn from(value: listener::Error) -> Self {
+        Self::Listener(value)
+    }
+}
+
 struct FdSet {
+    listener_fd: i32,
     master_set: libc::fd_set,
     op_set: libc::fd_set,
     max_fd: i32,
+    last_active: HashMap<i32, Instant>,
 }
 
 impl FdSet {
@@ -67,9 +83,11 @@ impl FdSet {
         };
 
         Self {
+            listener_fd,
             master_set,
             op_set,
             max_fd: listener_fd,
+            last_active: HashMap::new(),
         }
     }
 
@@ -107,35 +125,205 @@ impl FdSet {
                     if *fd > self.max_fd {
                         self.max_fd = *fd;
                     }
+                    self.last_active.insert(*fd, Instant::now());
+                }
+                SfdChange::Remove(fd) => {
+                    // SAFETY: `self.master_set` is initialized correctly
+                    // for each instance of `Self`, making `FD_CLR` safe to call.
+                    unsafe { libc::FD_CLR(*fd, &mut self.master_set) };
+                    self.last_active.remove(fd);
+                }
+                SfdChange::Touch(fd) => {
+                    self.last_active.insert(*fd, Instant::now());
                 }
-                // SAFETY: `self.master_set` is initialized correctly
-                // for each instance of `Self`, making `FD_CLR` safe to call.
-                SfdChange::Remove(fd) => unsafe { libc::FD_CLR(*fd, &mut self.master_set) },
             }
         }
     }
+
+    /// Returns the clients that have not sent anything for longer than `idle_timeout`.
+    pub fn idle_fds(&self, idle_timeout: Duration) -> Vec<i32> {
+        self.last_active
+            .iter()
+            .filter(|(_, last_active)| last_active.elapsed() >= idle_timeout)
+            .map(|(fd, _)| *fd)
+            .collect()
+    }
+
+    /// Returns how long until the next client becomes idle, `None` without any clients.
+    pub fn idle_timeout(&self, idle_timeout: Duration) -> Option<Duration> {
+        self.last_active
+            .values()
+            .map(|last_active| idle_timeout.saturating_sub(last_active.elapsed()))
+            .min()
+    }
+}
+
+impl Reactor for FdSet {
+    fn listener_fds(&self) -> &[i32] {
+        slice::from_ref(&self.listener_fd)
+    }
+
+    // `select()` only tells that a read would not block, a hangup or an error is seen when `recv()` returns.
+    fn ready(&self) -> Vec<(i32, ReadyKind)> {
+        self.iter_sfd()
+            .map(|fd| (fd, ReadyKind::Readable))
+            .collect()
+    }
 }
 
 enum SfdChange {
     Add(i32),
     Remove(i32),
+    Touch(i32),
 }
 
 const RECV_MESSAGE_SIZE: usize = 256;
 
+/// Makes `selectserver` stop on its own instead of waiting for a signal.
+///
+/// The server stops after relaying `broadcasts` messages or after running for `duration`, whichever comes first.
+/// Both are unbounded by default.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Bounds {
+    pub broadcasts: Option<u64>,
+    pub duration: Option<Duration>,
+}
+
+impl Bounds {
+    fn reached(&self, broadcasts: u64, started_at: Instant) -> bool {
+        self.broadcasts.is_some_and(|max| broadcasts >= max)
+            || self
+                .duration
+                .is_some_and(|duration| started_at.elapsed() >= duration)
+    }
+
+    // The time `select()` may block for, so that a bounded run does not outlive its `duration`.
+    fn select_timeout(&self, started_at: Instant) -> Option<Duration> {
+        self.duration
+            .map(|duration| duration.saturating_sub(started_at.elapsed()))
+    }
+}
+
+/// The knobs of `run_selectserver()`, the defaults run the server until it is stopped by a signal.
+#[derive(Debug, Default, Clone)]
+pub struct SelectserverOptions {
+    /// Close the clients that stay silent for longer than this. The `select()` timeout wakes the server up
+    /// in time to close them, and the rest of the clients are notified.
+    pub idle_timeout: Option<Duration>,
+    /// Serve at most this many ready sockets per `select()` call.
+    /// The rest stay ready, and they are served first on the next call (see `RoundRobin`).
+    pub max_events: Option<NonZeroUsize>,
+    /// Stop on its own once `bounds` is reached, which makes it possible to drive the server from a test.
+    pub bounds: Bounds,
+    /// Keep a spare fd to turn away the connections that arrive once the server runs out of fds, instead of
+    /// making `select()` report the listener as readable over and over, see `EmfileGuard`.
+    pub emfile_guard: bool,
+    /// Send this message of the day to every new client before it joins the fd set,
+    /// so it only goes to that client and it arrives before any relayed message.
+    pub motd: Option<Vec<u8>>,
+    /// On exit, spend at most this long turning away the connections that are queued but not accepted yet,
+    /// so they are told that the server is shutting down instead of being reset, see `listener::drain_pending()`.
+    pub drain_on_exit: Option<Duration>,
+    /// Set `TCP_DEFER_ACCEPT` on the listener (Linux only), in seconds, so that a client is not accepted until
+    /// it sends its first message. Only `selectserver()` sets up a listener, `run_selectserver()` takes the one
+    /// it is given as is.
+    pub defer_accept: Option<i32>,
+}
+
 // EXAMPLE: A multiperson chat server.
 // This example is a more complete version of the `select()` syscall example.
 // MANPAGE:
 // man 2 select
 // man errno
-pub fn selectserver() -> Result<(), Error> {
-    let listener_fd = setup_listener_socket()?;
+//
+// The server runs until SIGINT (Ctrl-C) or SIGTERM is received, see `SelectserverOptions` for the rest.
+// Then, every socket is closed and the collected `ServerStats` are returned.
+pub fn selectserver(retry: &BindRetry, opts: &SelectserverOptions) -> Result<ServerStats, Error> {
+    selectserver_with(retry, opts, chat::broadcast_to_others)
+}
+
+/// Same as `selectserver()`, but every received message is routed by `handler` instead of being
+/// broadcast to the other clients.
+pub fn selectserver_with(
+    retry: &BindRetry,
+    opts: &SelectserverOptions,
+    handler: impl MessageHandler,
+) -> Result<ServerStats, Error> {
+    let listener_opts = ListenerOptions {
+        retry: *retry,
+        defer_accept: opts.defer_accept,
+        ..Default::default()
+    };
+    let (listener_fd, addr) =
+        listener::bind_listener_with(None, libc::AF_UNSPEC, c"9034", &listener_opts)?;
+    println!("server is listening on {}", addr);
+
+    #[cfg(target_os = "linux")]
+    if opts.defer_accept.is_some() {
+        match listener::defer_accept(listener_fd) {
+            Ok(secs) => println!("selectserver: TCP_DEFER_ACCEPT = {}", secs),
+            Err(err) => eprintln!("selectserver: getsockopt TCP_DEFER_ACCEPT error: {}", err),
+        }
+    }
+
+    run_selectserver_with(listener_fd, opts, handler)
+}
+
+/// Runs the chat server of `selectserver()` on `listener_fd`, which may be any listening socket
+/// (e.g. one bound to an ephemeral port).
+///
+/// `listener_fd` is owned by the server from now on, it is closed along with the clients on exit.
+pub fn run_selectserver(
+    listener_fd: i32,
+    opts: &SelectserverOptions,
+) -> Result<ServerStats, Error> {
+    run_selectserver_with(listener_fd, opts, chat::broadcast_to_others)
+}
+
+/// Same as `run_selectserver()`, but every received message is routed by `handler` instead of being
+/// broadcast to the other clients.
+pub fn run_selectserver_with(
+    listener_fd: i32,
+    opts: &SelectserverOptions,
+    mut handler: impl MessageHandler,
+) -> Result<ServerStats, Error> {
+    if let Err(err) = signal::install_shutdown_handler() {
+        let _ = fd::close_fd(listener_fd);
+        return Err(Error::Signal(err));
+    }
+    let mut guard = match opts.emfile_guard.then(EmfileGuard::new).transpose() {
+        Ok(guard) => guard,
+        Err(err) => {
+            let _ = fd::close_fd(listener_fd);
+            return Err(Error::Guard(err));
+        }
+    };
+
     let mut fds = FdSet::new(listener_fd);
+    let mut stats = ServerStats::new();
+    let mut round_robin = RoundRobin::new(opts.max_events);
+    let bounds = &opts.bounds;
+    let started_at = Instant::now();
+    let mut broadcasts = 0;
+    let mut res = Ok(());
+
+    while !signal::shutdown_requested() && !bounds.reached(broadcasts, started_at) {
+        let idle_timeout = opts
+            .idle_timeout
+            .and_then(|idle_timeout| fds.idle_timeout(idle_timeout));
+        let mut timeout = [bounds.select_timeout(started_at), idle_timeout]
+            .into_iter()
+            .flatten()
+            .min()
+            .map(to_timeval);
+        let timeout_ptr = timeout
+            .as_mut()
+            .map_or(ptr::null_mut(), |timeout| timeout as *mut libc::timeval);
 
-    loop {
         // SAFETY: The fd set for read operations is correctly
         // initialized via `FdSet::new()`.
         // The remaining sets for other operations are intentionally set as null.
+        // `timeout_ptr` is either null or points to `timeout`, which outlives the call.
         // There are no uninitialized reads during `select()`.
         // It is safe to call.
         let ecode = unsafe {
@@ -144,54 +332,141 @@ pub fn selectserver() -> Result<(), Error> {
                 fds.as_mut(),
                 ptr::null_mut(),
                 ptr::null_mut(),
-                ptr::null_mut(),
+                timeout_ptr,
             )
         };
         if ecode == -1 {
             let err = io::Error::last_os_error();
-            Err(Error::Select(err))?;
+            // A signal interrupted `select()`. The loop condition decides whether it was a shutdown request.
+            if err.kind() == io::ErrorKind::Interrupted {
+                continue;
+            }
+
+            // The sockets are closed below on this path as well.
+            res = Err(Error::Select(err));
+            break;
         }
 
         let mut changes: Vec<SfdChange> = vec![];
-        for sfd in fds.iter_sfd() {
-            if sfd == listener_fd {
-                let client_fd = accept_new_client(listener_fd);
-                changes.push(SfdChange::Add(client_fd));
+        for ev in fds.events(&mut round_robin) {
+            let sfd = ev.fd;
+            if ev.is_listener {
+                let client_fd = accept_new_client(listener_fd, guard.as_mut());
+                if client_fd != -1 {
+                    stats.on_accept();
+                    if let Some(motd) = &opts.motd {
+                        send_motd(client_fd, motd, &mut stats);
+                    }
+                    changes.push(SfdChange::Add(client_fd));
+                }
                 continue;
             }
 
             let (closed_fd, msg_buf, rbytes) = recv_client_message(sfd);
             if let Some(fd) = closed_fd {
+                stats.on_disconnect();
                 changes.push(SfdChange::Remove(fd));
                 continue;
             }
+            stats.on_recv(rbytes as usize);
 
-            let dest_fds = fds.iter_fd().filter(|fd| *fd != listener_fd && *fd != sfd);
+            let client_fds: Vec<i32> = fds.iter_fd().filter(|fd| *fd != listener_fd).collect();
 
-            broadcast_message(msg_buf, rbytes, dest_fds);
+            for (dest_fd, msg) in handler(sfd, &msg_buf[..rbytes as usize], &client_fds) {
+                send_message(dest_fd, &msg, &mut stats);
+            }
+            broadcasts += 1;
+            changes.push(SfdChange::Touch(sfd));
         }
 
         fds.apply_changes(&changes);
+
+        if let Some(idle_timeout) = opts.idle_timeout {
+            let changes = sweep_idle_clients(listener_fd, &fds, idle_timeout, &mut stats);
+            fds.apply_changes(&changes);
+        }
+    }
+
+    println!("selectserver: shutting down...");
+
+    if let Some(budget) = opts.drain_on_exit {
+        match listener::drain_pending(listener_fd, listener::SHUTDOWN_NOTICE, budget) {
+            Ok(drained) => println!("selectserver: drained {} pending connections", drained),
+            Err(err) => eprintln!(
+                "selectserver: failed to drain the pending connections: {}",
+                err
+            ),
+        }
+    }
+
+    for sock_fd in fds.iter_fd() {
+        if let Err(err) = fd::close_fd(sock_fd) {
+            eprintln!("{}", Error::Close(sock_fd, err));
+        }
+    }
+
+    res.map(|()| stats)
+}
+
+// `select()` takes its timeout in microseconds. It is rounded up, otherwise `select()` wakes up a little
+// too early and the sweep finds nothing.
+fn to_timeval(timeout: Duration) -> libc::timeval {
+    let micros = timeout.as_nanos().div_ceil(1000);
+    libc::timeval {
+        tv_sec: (micros / 1_000_000) as libc::time_t,
+        tv_usec: (micros % 1_000_000) as libc::suseconds_t,
     }
 }
 
-fn broadcast_message(
-    buf: [u8; RECV_MESSAGE_SIZE],
-    nbytes: isize,
-    dest_fds: impl Iterator<Item = i32>,
-) {
-    for fd in dest_fds {
-        // SAFETY: A readonly reference to `buf` is used for
-        // each iteration.
-        // `buf` is valid for the entire duration of the iteration.
-        // There are no uninitialized reads on `buf`.
-        // Therefore, it is safe to call `send()`.
-        let sbytes =
-            unsafe { libc::send(fd, buf.as_ptr() as *const libc::c_void, nbytes as usize, 0) };
-        if sbytes == -1 {
-            eprintln!("{}", Error::Send(fd, io::Error::last_os_error()));
+fn sweep_idle_clients(
+    listener_fd: i32,
+    fds: &FdSet,
+    idle_timeout: Duration,
+    stats: &mut ServerStats,
+) -> Vec<SfdChange> {
+    let idle_fds = fds.idle_fds(idle_timeout);
+
+    for idle_fd in &idle_fds {
+        eprintln!("selectserver: socket {} timed out", idle_fd);
+        stats.on_disconnect();
+
+        // The idle client is dropped from the fd set right after the sweep, it is not used after this call.
+        if let Err(err) = fd::close_fd(*idle_fd) {
+            eprintln!("{}", Error::Close(*idle_fd, err));
         }
     }
+
+    for idle_fd in &idle_fds {
+        let notice = format!("selectserver: socket {} timed out\n", idle_fd);
+        let dest_fds = fds
+            .iter_fd()
+            .filter(|fd| *fd != listener_fd && !idle_fds.contains(fd));
+        for dest_fd in dest_fds {
+            send_message(dest_fd, notice.as_bytes(), stats);
+        }
+    }
+
+    idle_fds.into_iter().map(SfdChange::Remove).collect()
+}
+
+// A failed MOTD is only reported, the client joins nonetheless. If it is gone, the next `recv()` notices it.
+fn send_motd(client_fd: i32, motd: &[u8], stats: &mut ServerStats) {
+    match stream_io::sendall(client_fd, motd) {
+        Ok(sbytes) => stats.on_send(sbytes),
+        Err(err) => eprintln!("{}", Error::Send(client_fd, err.into())),
+    }
+}
+
+fn send_message(fd: i32, buf: &[u8], stats: &mut ServerStats) {
+    // SAFETY: `buf` is valid for the entire duration of the call.
+    // There are no uninitialized reads on `buf`.
+    // Therefore, it is safe to call `send()`.
+    let sbytes = unsafe { trace::send(fd, buf.as_ptr() as *const libc::c_void, buf.len(), 0) };
+    if sbytes == -1 {
+        eprintln!("{}", Error::Send(fd, io::Error::last_os_error()));
+    } else {
+        stats.on_send(sbytes as usize);
+    }
 }
 
 fn recv_client_message(source_fd: i32) -> (Option<i32>, [u8; 256], isize) {
@@ -200,25 +475,36 @@ fn recv_client_message(source_fd: i32) -> (Option<i32>, [u8; 256], isize) {
 
     // SAFETY: There are no uninitialized reads on `source_fd`, `recv_buf` and `len`.
     // It is safe to call `recv()`.
-    let nbytes = unsafe {
-        libc::recv(
-            source_fd,
-            recv_buf.as_mut_ptr() as *mut libc::c_void,
-            len,
-            0,
-        )
-    };
+    // A `recv()` interrupted by a signal is retried, the client is only dropped on a genuine error.
+    let nbytes = fd::retry_on_eintr(
+        || unsafe {
+            trace::recv(
+                source_fd,
+                recv_buf.as_mut_ptr() as *mut libc::c_void,
+                len,
+                0,
+            )
+        },
+        |nbytes| *nbytes == -1,
+    );
     match nbytes {
         n if n <= 0 => {
             if n == 0 {
                 println!("selectserver: socket {} hung up", source_fd);
             } else {
-                eprintln!("{}", Error::Recv(source_fd, io::Error::last_os_error()));
+                let err = io::Error::last_os_error();
+                if stream_io::is_reset(&err) {
+                    println!("selectserver: socket {} was reset by the client", source_fd);
+                } else {
+                    eprintln!("{}", Error::Recv(source_fd, err));
+                }
             }
 
-            // SAFETY: `source_fd` is not used after a failed `recv()` attempt.
-            // Therefore, `close()` is safe to call.
-            unsafe { libc::close(source_fd) };
+            // `source_fd` is not used after a failed `recv()` attempt.
+            // Therefore, it is safe to close.
+            if let Err(err) = fd::close_fd(source_fd) {
+                eprintln!("{}", Error::Close(source_fd, err));
+            }
 
             (Some(source_fd), recv_buf, n)
         }
@@ -226,166 +512,42 @@ fn recv_client_message(source_fd: i32) -> (Option<i32>, [u8; 256], isize) {
     }
 }
 
-fn accept_new_client(listener_fd: i32) -> i32 {
+fn accept_new_client(listener_fd: i32, guard: Option<&mut EmfileGuard>) -> i32 {
     // SAFETY: A full zeroed `sockaddr_storage` will be initialized
     // correctly upon a successful `accept()` call.
     // Upon a failure, it is not read.
     // Therefore it is safe to initialize it like this.
     let mut client_addr: libc::sockaddr_storage = unsafe { mem::zeroed() };
-    let mut len = mem::size_of_val(&client_addr);
+    let mut len = mem::size_of_val(&client_addr) as libc::socklen_t;
 
     // SAFETY: All required variables are initialized correctly.
     // `accept()` is safe to call.
     let client_fd = unsafe {
-        libc::accept(
+        trace::accept(
             listener_fd,
             &raw mut client_addr as *mut libc::sockaddr,
-            &raw mut len as *mut u32,
+            &raw mut len,
         )
     };
     if client_fd == -1 {
-        eprintln!("{}", Error::Accept(io::Error::last_os_error()));
+        let err = io::Error::last_os_error();
+        match guard {
+            Some(guard) if EmfileGuard::is_fd_limit(&err) => match guard.shed(listener_fd) {
+                Ok(()) => eprintln!("selectserver: out of fds, turned a new connection away"),
+                Err(err) => eprintln!("selectserver: out of fds, failed to turn away: {}", err),
+            },
+            _ => eprintln!("{}", Error::Accept(err)),
+        }
+        return client_fd;
     }
 
-    // SAFETY: It is safe to cast `sockaddr_storage` to `sockaddr` upon a successful `accept()` call.
-    let sa_client = unsafe { *(&raw const client_addr as *const libc::sockaddr) };
-    match try_into_ip_addr(sa_client) {
-        Some(ip_addr) => println!(
+    match sockaddr::to_socket_addr(&client_addr) {
+        Some(addr) => println!(
             "selectserver: new connection from {} on socket {}",
-            ip_addr, client_fd
+            addr, client_fd
         ),
         None => eprintln!("{}", Error::InvalidAddressFamily),
     }
 
     client_fd
 }
-
-fn setup_listener_socket() -> Result<i32, Error> {
-    let node = ptr::null();
-    let port = CString::from(c"9034");
-
-    // SAFETY: All zero hints is a valid initialization.
-    // Required fields are set later on.
-    let mut hints: libc::addrinfo = unsafe { mem::zeroed() };
-    hints.ai_family = libc::AF_UNSPEC;
-    hints.ai_socktype = libc::SOCK_STREAM;
-    hints.ai_flags = libc::AI_PASSIVE;
-
-    let mut gai_res_ptr: *mut libc::addrinfo = ptr::null_mut();
-
-    // SAFETY: There are no uninitialized reads. `getaddrinfo()` is safe to use.
-    let ecode = unsafe { libc::getaddrinfo(node, port.as_ptr(), &hints, &mut gai_res_ptr) };
-    if ecode != 0 {
-        // SAFETY: `gai_strerror` is valid to call on a failed `getaddrinfo()` call.
-        let err = unsafe { CStr::from_ptr(libc::gai_strerror(ecode)) }.to_string_lossy();
-        return Err(Error::Getaddrinfo(err.into_owned()));
-    };
-
-    let mut listener_sockaddr: *mut libc::sockaddr = ptr::null_mut();
-    let mut listener_fd = -1;
-
-    while !gai_res_ptr.is_null() {
-        // SAFETY: `gai_res_ptr` is guaranteed to point atleast one valid addrinfo struct on a successful `getaddrinfo()` call.
-        let ai = unsafe { *gai_res_ptr };
-        let next_ai_ptr = ai.ai_next;
-
-        // SAFETY: `socket()` is safe to call since `ai` is valid.
-        let sock_fd = unsafe { libc::socket(ai.ai_family, ai.ai_socktype, 0) };
-        if sock_fd == -1 {
-            if next_ai_ptr.is_null() {
-                let err = io::Error::last_os_error();
-                return Err(Error::Socket(err));
-            } else {
-                gai_res_ptr = next_ai_ptr;
-                continue;
-            }
-        }
-
-        let yes = 1;
-        let len = mem::size_of::<i32>();
-        // SAFETY: `setsockopt()` is called for a valid sock_fd created by a successful `socket()` call, making it safe to use.
-        let ecode = unsafe {
-            libc::setsockopt(
-                sock_fd,
-                libc::SOL_SOCKET,
-                libc::SO_REUSEADDR,
-                &raw const yes as *const libc::c_void,
-                len as u32,
-            )
-        };
-        if ecode == -1 {
-            if next_ai_ptr.is_null() {
-                let err = io::Error::last_os_error();
-                return Err(Error::Setsockopt(err));
-            } else {
-                gai_res_ptr = next_ai_ptr;
-                continue;
-            }
-        }
-
-        // SAFETY: The socket and address used for `bind()` are valid due to `socket()` and `getaddrinfo()` calls above.
-        // Bind is safe to call.
-        let ecode = unsafe { libc::bind(sock_fd, ai.ai_addr, ai.ai_addrlen) };
-        if ecode == -1 {
-            if next_ai_ptr.is_null() {
-                let err = io::Error::last_os_error();
-                return Err(Error::Bind(sock_fd, err));
-            } else {
-                gai_res_ptr = next_ai_ptr;
-                continue;
-            }
-        }
-
-        listener_sockaddr = ai.ai_addr;
-        listener_fd = sock_fd;
-        break;
-    }
-
-    const BACKLOG: i32 = 10;
-    // SAFETY: `listener_fd` is a valid fd obtained above through `socket()`.
-    // It is safe to call `listen()`.
-    let ecode = unsafe { libc::listen(listener_fd, BACKLOG) };
-    if ecode == -1 {
-        let err = io::Error::last_os_error();
-        return Err(Error::Listen(listener_fd, err));
-    }
-
-    // SAFETY: `listener_sockaddr` is filled by a successful `getaddrinfo()`
-    // call and is valid to read.
-    let sa = unsafe { *listener_sockaddr };
-    let ip_addr = try_into_ip_addr(sa).ok_or(Error::InvalidAddressFamily)?;
-
-    println!(
-        "server is listening on {} port {}",
-        ip_addr,
-        port.to_str().unwrap()
-    );
-
-    // SAFETY: The `getaddrinfo()` response is not used from now on.
-    // It is safe to free the allocated memory for `getaddrinfo()`.
-    unsafe {
-        libc::freeaddrinfo(gai_res_ptr);
-    }
-
-    Ok(listener_fd)
-}
-
-fn try_into_ip_addr(sa: libc::sockaddr) -> Option<IpAddr> {
-    match sa.sa_family as i32 {
-        libc::AF_INET => {
-            // SAFETY: For `AF_INET`, it is safe to cast the `sockaddr` container to `sockaddr_in`.
-            let sockaddr_in = unsafe { *(&raw const sa as *const libc::sockaddr_in) };
-            let bits = u32::from_be(sockaddr_in.sin_addr.s_addr);
-            let inet = Ipv4Addr::from_bits(bits);
-            Some(IpAddr::V4(inet))
-        }
-        libc::AF_INET6 => {
-            // SAFETY: For `AF_INET6`, it is safe to cast the `sockaddr` container to `sockaddr_in6`.
-            let sockaddr_in6 = unsafe { *(&raw const sa as *const libc::sockaddr_in6) };
-            let bits = u128::from_be_bytes(sockaddr_in6.sin6_addr.s6_addr);
-            let inet6 = Ipv6Addr::from_bits(bits);
-            Some(IpAddr::V6(inet6))
-        }
-        _ => None,
-    }
-}
@@ -0,0 +1,91 @@
+use std::{error, fmt, io};
+
+use crate::{privilege, socket_fd::Socket, sockopt};
+
+#[derive(Debug)]
+pub enum Error {
+    Socket(io::Error),
+    Getsockopt(&'static str, io::Error),
+    Setsockopt(&'static str, privilege::Error),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Socket(err) => write!(f, "socket error: {}", err),
+            Error::Getsockopt(name, err) => write!(f, "getsockopt {} error: {}", name, err),
+            Error::Setsockopt(name, err) => write!(f, "setsockopt {} error: {}", name, err),
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            // Exposed so that a missing privilege can be told apart from the other failures.
+            Error::Setsockopt(_, err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+// The `SOL_SOCKET` options that are printed for a fresh socket.
+const OPTIONS: [(&str, i32); 6] = [
+    ("SO_TYPE", libc::SO_TYPE),
+    ("SO_RCVBUF", libc::SO_RCVBUF),
+    ("SO_SNDBUF", libc::SO_SNDBUF),
+    ("SO_REUSEADDR", libc::SO_REUSEADDR),
+    ("SO_KEEPALIVE", libc::SO_KEEPALIVE),
+    ("SO_DEBUG", libc::SO_DEBUG),
+];
+
+// EXAMPLE: Prints the socket level options of a fresh TCP socket by using `getsockopt()`.
+// MANPAGE:
+// man 2 getsockopt (Linux)
+// man 7 socket (Linux)
+//
+// If `so_debug` is set, `SO_DEBUG` is turned on with `setsockopt()` before the options are read back.
+// Linux only lets a process with `CAP_NET_ADMIN` do that, otherwise `setsockopt()` fails with `EACCES`.
+// The option only asks the kernel to record debug information for the socket,
+// capturing it requires kernel tooling (e.g. `trpt` on the BSDs, or tracepoints on Linux).
+//
+// If `max_segment` is set, `TCP_MAXSEG` is set and read back as well. On a socket that is not connected yet,
+// the value read back is only the requested one (or the default of 536 without it). The MSS of a connection
+// is settled in the handshake and clamped to the path MTU, see `bjrs stream client --max-segment`.
+pub fn sockinfo(so_debug: bool, max_segment: Option<i32>) -> Result<(), Error> {
+    let sock = Socket::new(libc::AF_INET, libc::SOCK_STREAM, 0).map_err(Error::Socket)?;
+
+    if so_debug {
+        sockopt::set_int(sock.as_raw(), libc::SOL_SOCKET, libc::SO_DEBUG, 1).map_err(|err| {
+            Error::Setsockopt(
+                "SO_DEBUG",
+                privilege::classify("SO_DEBUG", "cap_net_admin", err),
+            )
+        })?;
+    }
+
+    for (name, option) in OPTIONS {
+        let value = sockopt::get_int(sock.as_raw(), libc::SOL_SOCKET, option)
+            .map_err(|err| Error::Getsockopt(name, err))?;
+        println!("sockinfo: {} = {}", name, value);
+    }
+
+    if let Some(max_segment) = max_segment {
+        sockopt::set_int(
+            sock.as_raw(),
+            libc::IPPROTO_TCP,
+            libc::TCP_MAXSEG,
+            max_segment,
+        )
+        // Out of range values fail with `EINVAL`, no privilege is needed.
+        .map_err(|err| Error::Setsockopt("TCP_MAXSEG", privilege::Error::Other(err)))?;
+        let value = sockopt::get_int(sock.as_raw(), libc::IPPROTO_TCP, libc::TCP_MAXSEG)
+            .map_err(|err| Error::Getsockopt("TCP_MAXSEG", err))?;
+        println!(
+            "sockinfo: TCP_MAXSEG = {} (requested {})",
+            value, max_segment
+        );
+    }
+
+    Ok(())
+}
@@ -0,0 +1,243 @@
+use std::{
+    error, fmt,
+    io::{self, Write},
+    net::{IpAddr, Ipv4Addr, Ipv6Addr},
+};
+
+use crate::{client_builder::Client, inet::ConnectError, serialize, stream_io, trace};
+
+#[derive(Debug)]
+pub enum Error {
+    InvalidAddr(String),
+    Connect(ConnectError),
+    Send(io::Error),
+    Recv(io::Error),
+    UnexpectedEof,
+    InvalidVersion(u8),
+    NoAcceptableMethod(u8),
+    InvalidAddrType(u8),
+    Rejected(u8),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::InvalidAddr(addr) => write!(f, "invalid address {}, expected HOST:PORT", addr),
+            Error::Connect(err) => write!(f, "connect error: {}", err),
+            Error::Send(err) => write!(f, "send error: {}", err),
+            Error::Recv(err) => write!(f, "recv error: {}", err),
+            Error::UnexpectedEof => write!(f, "the proxy closed the connection"),
+            Error::InvalidVersion(ver) => write!(f, "the proxy replied with SOCKS version {}", ver),
+            Error::NoAcceptableMethod(method) => write!(
+                f,
+                "the proxy requires an authentication method (0x{:02x}) that is not supported",
+                method
+            ),
+            Error::InvalidAddrType(atyp) => write!(f, "invalid address type 0x{:02x}", atyp),
+            Error::Rejected(rep) => {
+                write!(f, "the proxy rejected the CONNECT: {}", reply_message(*rep))
+            }
+        }
+    }
+}
+
+impl error::Error for Error {}
+
+const SOCKS_VERSION: u8 = 0x05;
+const METHOD_NO_AUTH: u8 = 0x00;
+const CMD_CONNECT: u8 = 0x01;
+const ATYP_IPV4: u8 = 0x01;
+const ATYP_DOMAIN: u8 = 0x03;
+const ATYP_IPV6: u8 = 0x04;
+const REP_SUCCEEDED: u8 = 0x00;
+
+// The reply codes of RFC 1928, section 6.
+fn reply_message(rep: u8) -> &'static str {
+    match rep {
+        0x01 => "general SOCKS server failure",
+        0x02 => "connection not allowed by ruleset",
+        0x03 => "network unreachable",
+        0x04 => "host unreachable",
+        0x05 => "connection refused",
+        0x06 => "TTL expired",
+        0x07 => "command not supported",
+        0x08 => "address type not supported",
+        _ => "unassigned reply code",
+    }
+}
+
+// EXAMPLE: A client that connects to `target` through a SOCKS5 proxy (RFC 1928).
+// The proxy is asked to open a TCP connection to `target`, and then it relays the bytes in both directions.
+// Once the connection is set up, the client reads whatever `target` sends until it closes the connection.
+// MANPAGE:
+// RFC 1928 (SOCKS Protocol Version 5)
+//
+// The handshake is a small binary protocol:
+// 1 - The client offers its authentication methods, and only "no authentication" is offered here.
+// 2 - The proxy picks one of them.
+// 3 - The client sends a CONNECT request with the address type (IPv4, IPv6 or a domain name), the address and the port.
+// 4 - The proxy replies with a status code and the address it uses to reach `target`.
+// All of the multi-byte fields are in network byte order.
+pub fn socks_connect(proxy: &str, target: &str) -> Result<(), Error> {
+    let (proxy_host, proxy_port) = split_host_port(proxy)?;
+    let (target_host, target_port) = split_host_port(target)?;
+
+    let (sock, proxy_addr) = Client::new()
+        .host(proxy_host)
+        .port(proxy_port)
+        .connect()
+        .map_err(Error::Connect)?;
+    println!("socks: connected to the proxy at {}", proxy_addr);
+
+    let sock_fd = sock.as_raw();
+
+    // 1 - 2: Method negotiation.
+    send(sock_fd, &[SOCKS_VERSION, 1, METHOD_NO_AUTH])?;
+    let mut reply = [0u8; 2];
+    recv(sock_fd, &mut reply)?;
+    if reply[0] != SOCKS_VERSION {
+        return Err(Error::InvalidVersion(reply[0]));
+    }
+    if reply[1] != METHOD_NO_AUTH {
+        return Err(Error::NoAcceptableMethod(reply[1]));
+    }
+
+    // 3: The CONNECT request.
+    send(sock_fd, &connect_request(target_host, target_port)?)?;
+
+    // 4: The reply, the length of its address depends on its type.
+    let mut header = [0u8; 4];
+    recv(sock_fd, &mut header)?;
+    if header[0] != SOCKS_VERSION {
+        return Err(Error::InvalidVersion(header[0]));
+    }
+    if header[1] != REP_SUCCEEDED {
+        return Err(Error::Rejected(header[1]));
+    }
+    let bound_addr = recv_addr(sock_fd, header[3])?;
+
+    println!(
+        "socks: connected to {} through the proxy, which uses {}",
+        target, bound_addr
+    );
+
+    let total = drain(sock_fd)?;
+    println!(
+        "socks: {} closed the connection after {} bytes",
+        target, total
+    );
+
+    Ok(())
+}
+
+fn split_host_port(addr: &str) -> Result<(&str, u16), Error> {
+    let invalid = || Error::InvalidAddr(addr.to_string());
+
+    let (host, port) = addr.rsplit_once(':').ok_or_else(invalid)?;
+    let port = port.parse().map_err(|_| invalid())?;
+
+    // IPv6 addresses are written as `[::1]:1080`.
+    let host = host
+        .strip_prefix('[')
+        .and_then(|host| host.strip_suffix(']'))
+        .unwrap_or(host);
+
+    Ok((host, port))
+}
+
+fn connect_request(host: &str, port: u16) -> Result<Vec<u8>, Error> {
+    let mut req = vec![SOCKS_VERSION, CMD_CONNECT, 0x00];
+
+    match host.parse::<IpAddr>() {
+        Ok(IpAddr::V4(ip_addr)) => {
+            req.push(ATYP_IPV4);
+            req.extend_from_slice(&ip_addr.octets());
+        }
+        Ok(IpAddr::V6(ip_addr)) => {
+            req.push(ATYP_IPV6);
+            req.extend_from_slice(&ip_addr.octets());
+        }
+        // The proxy resolves the name, which is prefixed with its length instead of being null terminated.
+        Err(_) => {
+            let len = u8::try_from(host.len()).map_err(|_| Error::InvalidAddr(host.to_string()))?;
+            req.push(ATYP_DOMAIN);
+            req.push(len);
+            req.extend_from_slice(host.as_bytes());
+        }
+    }
+
+    let mut port_buf = [0u8; 2];
+    serialize::packi16(&mut port_buf, port);
+    req.extend_from_slice(&port_buf);
+
+    Ok(req)
+}
+
+fn recv_addr(sock_fd: i32, atyp: u8) -> Result<String, Error> {
+    let host = match atyp {
+        ATYP_IPV4 => {
+            let mut octets = [0u8; 4];
+            recv(sock_fd, &mut octets)?;
+            Ipv4Addr::from(octets).to_string()
+        }
+        ATYP_IPV6 => {
+            let mut octets = [0u8; 16];
+            recv(sock_fd, &mut octets)?;
+            format!("[{}]", Ipv6Addr::from(octets))
+        }
+        ATYP_DOMAIN => {
+            let mut len = [0u8; 1];
+            recv(sock_fd, &mut len)?;
+            let mut name = vec![0u8; len[0] as usize];
+            recv(sock_fd, &mut name)?;
+            String::from_utf8_lossy(&name).into_owned()
+        }
+        _ => return Err(Error::InvalidAddrType(atyp)),
+    };
+
+    let mut port_buf = [0u8; 2];
+    recv(sock_fd, &mut port_buf)?;
+    let port = serialize::unpacku16(&port_buf);
+
+    Ok(format!("{}:{}", host, port))
+}
+
+fn send(sock_fd: i32, buf: &[u8]) -> Result<(), Error> {
+    stream_io::sendall(sock_fd, buf).map_err(|err| Error::Send(err.into()))?;
+    Ok(())
+}
+
+// The handshake messages have known lengths, a shorter read means that the proxy hung up.
+fn recv(sock_fd: i32, buf: &mut [u8]) -> Result<(), Error> {
+    let rbytes = stream_io::recv_exact(sock_fd, buf).map_err(Error::Recv)?;
+    match rbytes == buf.len() {
+        true => Ok(()),
+        false => Err(Error::UnexpectedEof),
+    }
+}
+
+fn drain(sock_fd: i32) -> Result<usize, Error> {
+    let mut buf = [0u8; 256];
+    let mut total = 0;
+
+    loop {
+        // SAFETY: `buf` is initialized and its length is passed along with it.
+        let rbytes =
+            unsafe { trace::recv(sock_fd, buf.as_mut_ptr() as *mut libc::c_void, buf.len(), 0) };
+        match rbytes {
+            -1 => {
+                let err = io::Error::last_os_error();
+                if err.kind() != io::ErrorKind::Interrupted {
+                    return Err(Error::Recv(err));
+                }
+            }
+            0 => return Ok(total),
+            n => {
+                io::stdout()
+                    .write_all(&buf[..n as usize])
+                    .expect("message to be written to stdout");
+                total += n as usize;
+            }
+        }
+    }
+}
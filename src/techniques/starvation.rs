@@ -0,0 +1,192 @@
+use std::{collections::BTreeMap, error, fmt, io, num::NonZeroUsize};
+
+use crate::{fairness::RoundRobin, reactor::ReadyKind, socket_fd::Socket, stream_io};
+
+#[derive(Debug)]
+pub enum Error {
+    Socketpair(io::Error),
+    Send(io::Error),
+    Poll(io::Error),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Socketpair(err) => write!(f, "socketpair error: {}", err),
+            Error::Send(err) => write!(f, "send error: {}", err),
+            Error::Poll(err) => write!(f, "poll error: {}", err),
+        }
+    }
+}
+
+impl error::Error for Error {}
+
+/// How the ready fds of an iteration are picked.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Scan {
+    /// From the lowest fd every time, as a plain loop over the `pollfd` list does.
+    Naive,
+    /// Right after the last fd that was served, see `RoundRobin`.
+    RoundRobin,
+}
+
+impl fmt::Display for Scan {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Scan::Naive => write!(f, "naive scan"),
+            Scan::RoundRobin => write!(f, "round-robin scan"),
+        }
+    }
+}
+
+// EXAMPLE: Starvation of the high fds in a server loop that caps the events it serves per iteration.
+// MANPAGE:
+// man 2 poll (Linux)
+// man 2 socketpair (Linux)
+//
+// `clients` socket pairs are created, and a byte is written into each of them that is never read,
+// so that every one of them is readable on every `poll()`. This is what a server under sustained load sees.
+// The loop serves at most `max_events` of them per iteration, for `iterations` iterations.
+//
+// With a naive scan that always starts from the lowest fd, the same few fds are served every time,
+// and the rest are never served at all. With `RoundRobin`, the scan starts right after the last fd that
+// was served, so every fd gets its turn. The number of times each fd was served is printed for both.
+pub fn starvation(
+    clients: usize,
+    max_events: NonZeroUsize,
+    iterations: usize,
+) -> Result<(), Error> {
+    // The peer ends are kept open, otherwise the fds would be reported as hung up instead.
+    let pairs = always_ready(clients)?;
+    let mut pfds = pollfds(&pairs);
+
+    println!(
+        "starvation: {} always-ready fds, at most {} served per iteration, {} iterations",
+        clients, max_events, iterations
+    );
+
+    for scan in [Scan::Naive, Scan::RoundRobin] {
+        let served = serve(&mut pfds, scan, max_events, iterations)?;
+
+        println!("starvation: {}:", scan);
+        for (fd, count) in &served {
+            println!("  fd {}: served {} times", fd, count);
+        }
+        let starved = served.values().filter(|count| **count == 0).count();
+        println!("starvation: {} of {} fds starved", starved, served.len());
+    }
+
+    Ok(())
+}
+
+// Creates `clients` socket pairs with an unread byte in each.
+fn always_ready(clients: usize) -> Result<Vec<(Socket, Socket)>, Error> {
+    let mut pairs = Vec::with_capacity(clients);
+    for _ in 0..clients {
+        let (sock, peer) = socketpair()?;
+        stream_io::sendall(peer.as_raw(), b"x").map_err(|err| Error::Send(err.into()))?;
+        pairs.push((sock, peer));
+    }
+
+    Ok(pairs)
+}
+
+// The pollfd list of the first ends of `pairs`, the pairs have to outlive it.
+fn pollfds(pairs: &[(Socket, Socket)]) -> Vec<libc::pollfd> {
+    pairs
+        .iter()
+        .map(|(sock, _)| libc::pollfd {
+            fd: sock.as_raw(),
+            events: libc::POLLIN,
+            revents: 0,
+        })
+        .collect()
+}
+
+// Runs the loop with `scan`, and counts how many times each fd is served.
+fn serve(
+    pfds: &mut [libc::pollfd],
+    scan: Scan,
+    max_events: NonZeroUsize,
+    iterations: usize,
+) -> Result<BTreeMap<i32, usize>, Error> {
+    let mut served: BTreeMap<i32, usize> = pfds.iter().map(|pfd| (pfd.fd, 0)).collect();
+    let mut round_robin = RoundRobin::new(Some(max_events));
+
+    for _ in 0..iterations {
+        // SAFETY: The pollfd buf is initialized properly, and its length is passed along with it.
+        let poll_count = unsafe { libc::poll(pfds.as_mut_ptr(), pfds.len() as u64, -1) };
+        if poll_count == -1 {
+            let err = io::Error::last_os_error();
+            if err.kind() == io::ErrorKind::Interrupted {
+                continue;
+            }
+            return Err(Error::Poll(err));
+        }
+
+        let ready = pfds
+            .iter()
+            .filter(|pfd| ReadyKind::from_revents(pfd.revents) == Some(ReadyKind::Readable))
+            .map(|pfd| pfd.fd);
+
+        let picked = match scan {
+            Scan::Naive => ready.take(max_events.get()).collect(),
+            Scan::RoundRobin => round_robin.pick(ready),
+        };
+
+        // The byte is left in the socket, so that it is ready again on the next iteration.
+        for fd in picked {
+            *served.entry(fd).or_default() += 1;
+        }
+    }
+
+    Ok(served)
+}
+
+fn socketpair() -> Result<(Socket, Socket), Error> {
+    let mut fds = [0i32; 2];
+
+    // SAFETY: `fds` has room for the two fds that `socketpair()` writes.
+    let ecode = unsafe { libc::socketpair(libc::AF_UNIX, libc::SOCK_STREAM, 0, fds.as_mut_ptr()) };
+    if ecode == -1 {
+        return Err(Error::Socketpair(io::Error::last_os_error()));
+    }
+
+    Ok((Socket::from_raw(fds[0]), Socket::from_raw(fds[1])))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CLIENTS: usize = 8;
+    const MAX_EVENTS: NonZeroUsize = NonZeroUsize::new(2).unwrap();
+    const ITERATIONS: usize = 100;
+
+    #[test]
+    fn round_robin_serves_every_ready_fd_equally() {
+        let pairs = always_ready(CLIENTS).unwrap();
+        let mut pfds = pollfds(&pairs);
+
+        let served = serve(&mut pfds, Scan::RoundRobin, MAX_EVENTS, ITERATIONS).unwrap();
+
+        // Every fd is ready on every iteration, so the turns are shared out evenly.
+        let fair_share = ITERATIONS * MAX_EVENTS.get() / CLIENTS;
+        assert_eq!(served.len(), CLIENTS);
+        for (fd, count) in &served {
+            assert_eq!(*count, fair_share, "fd {} was served {} times", fd, count);
+        }
+    }
+
+    #[test]
+    fn naive_scan_starves_the_high_fds() {
+        let pairs = always_ready(CLIENTS).unwrap();
+        let mut pfds = pollfds(&pairs);
+
+        let served = serve(&mut pfds, Scan::Naive, MAX_EVENTS, ITERATIONS).unwrap();
+
+        // This is what the loop does without `RoundRobin`: only the lowest fds are ever served.
+        let starved = served.values().filter(|count| **count == 0).count();
+        assert_eq!(starved, CLIENTS - MAX_EVENTS.get());
+    }
+}
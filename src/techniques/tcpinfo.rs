@@ -0,0 +1,95 @@
+use std::{error, fmt, io, mem};
+
+use crate::{client_builder::Client, inet::ConnectError};
+
+#[derive(Debug)]
+pub enum Error {
+    Connect(ConnectError),
+    Getsockopt(io::Error),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Connect(err) => write!(f, "connect error: {}", err),
+            Error::Getsockopt(err) => write!(f, "getsockopt TCP_INFO error: {}", err),
+        }
+    }
+}
+
+impl error::Error for Error {}
+
+// The states of `include/net/tcp_states.h`, `tcpi_state` is one of them.
+fn state_name(state: u8) -> &'static str {
+    match state {
+        1 => "ESTABLISHED",
+        2 => "SYN_SENT",
+        3 => "SYN_RECV",
+        4 => "FIN_WAIT1",
+        5 => "FIN_WAIT2",
+        6 => "TIME_WAIT",
+        7 => "CLOSE",
+        8 => "CLOSE_WAIT",
+        9 => "LAST_ACK",
+        10 => "LISTEN",
+        11 => "CLOSING",
+        _ => "UNKNOWN",
+    }
+}
+
+// EXAMPLE: Connects to `host` and prints what the kernel knows about the connection by reading `TCP_INFO`.
+// MANPAGE:
+// man 7 tcp (Linux)
+// man 2 getsockopt (Linux)
+//
+// Unlike the options in `sockinfo`, `TCP_INFO` is not an int, it is a whole `struct tcp_info`.
+// `getsockopt()` copies at most `optlen` bytes of it and writes back how many it actually copied,
+// an older kernel may fill less than the struct that libc knows about, and the rest stays zeroed.
+// The times (RTT, RTO) are in microseconds, the congestion window is in segments.
+pub fn tcpinfo(host: &str, port: u16) -> Result<(), Error> {
+    let (sock, addr) = Client::new()
+        .host(host)
+        .port(port)
+        .connect()
+        .map_err(Error::Connect)?;
+    println!("tcpinfo: connected to {}", addr);
+
+    // SAFETY: All zero `tcp_info` is a valid initialization, it only consists of integers.
+    let mut info: libc::tcp_info = unsafe { mem::zeroed() };
+    let mut len = mem::size_of_val(&info) as libc::socklen_t;
+
+    // SAFETY: `info` is valid for `len` bytes, and `len` is updated with the number of bytes written to it.
+    let ecode = unsafe {
+        libc::getsockopt(
+            sock.as_raw(),
+            libc::IPPROTO_TCP,
+            libc::TCP_INFO,
+            &raw mut info as *mut libc::c_void,
+            &raw mut len,
+        )
+    };
+    if ecode == -1 {
+        return Err(Error::Getsockopt(io::Error::last_os_error()));
+    }
+
+    println!(
+        "tcpinfo: {} of {} bytes of tcp_info are filled",
+        len,
+        mem::size_of_val(&info)
+    );
+    println!("tcpinfo: state = {}", state_name(info.tcpi_state));
+    println!(
+        "tcpinfo: rtt = {}us, rttvar = {}us, rto = {}us",
+        info.tcpi_rtt, info.tcpi_rttvar, info.tcpi_rto
+    );
+    println!(
+        "tcpinfo: snd_cwnd = {}, snd_ssthresh = {}, snd_mss = {}, pmtu = {}",
+        info.tcpi_snd_cwnd, info.tcpi_snd_ssthresh, info.tcpi_snd_mss, info.tcpi_pmtu
+    );
+    println!(
+        "tcpinfo: retransmits = {}, total_retrans = {}",
+        info.tcpi_retransmits, info.tcpi_total_retrans
+    );
+
+    Ok(())
+}
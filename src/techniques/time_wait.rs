@@ -0,0 +1,159 @@
+use std::{error, fmt, io, net::SocketAddr};
+
+use crate::{
+    client_builder::Client,
+    inet::ConnectError,
+    listener,
+    server_builder::{self, Server},
+    socket_fd::Socket,
+    trace,
+};
+
+#[derive(Debug)]
+pub enum Error {
+    Server(server_builder::Error),
+    Connect(ConnectError),
+    Recv(io::Error),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Server(err) => write!(f, "server error: {}", err),
+            Error::Connect(err) => write!(f, "connect error: {}", err),
+            Error::Recv(err) => write!(f, "recv error: {}", err),
+        }
+    }
+}
+
+impl error::Error for Error {}
+
+impl From<server_builder::Error> for Error {
+    fn from(value: server_builder::Error) -> Self {
+        Self::Server(value)
+    }
+}
+
+// EXAMPLE: TIME_WAIT and `SO_REUSEADDR`, cause and effect.
+// Section 5.3 - `bind()` - What Port Am I On?
+// MANPAGE:
+// man 7 socket, see SO_REUSEADDR
+// man 7 tcp
+//
+// The side that closes a TCP connection first (the active close) keeps its end of the connection in TIME_WAIT
+// for a while (60 seconds on Linux), so that a late segment of the old connection is not mistaken for a new one.
+// As long as it is there, the address it was bound to counts as in use.
+//
+// The example listens on 127.0.0.1, connects to itself, and lets the server close the connection first.
+// The server side of the connection is in TIME_WAIT on the listener's address then, even though the listener
+// itself is closed. Binding that address again fails with `EADDRINUSE` without `SO_REUSEADDR`,
+// and succeeds with it. This is what `reuse_port()` is for, and why a restarted server needs it.
+//
+// On Linux, the connection in TIME_WAIT has to have `SO_REUSEADDR` set as well (it inherits it from the listener),
+// otherwise the new `bind()` fails even with the option. The listener of the example sets it, like every server here.
+pub fn time_wait() -> Result<(), Error> {
+    let addr = leave_in_time_wait()?;
+    println!(
+        "time_wait: the server closed first, its end of the connection is in TIME_WAIT on {}",
+        addr
+    );
+
+    rebind(addr.port(), false)?;
+    rebind(addr.port(), true)?;
+
+    Ok(())
+}
+
+// Runs a connection over 127.0.0.1 that the server closes first, and returns the address of the listener.
+fn leave_in_time_wait() -> Result<SocketAddr, Error> {
+    let listener = Server::new().bind(Some("127.0.0.1"), 0).listen()?;
+    let addr = listener.local_addr();
+    println!("time_wait: listening on {}", addr);
+
+    let (client, _) = Client::new()
+        .host("127.0.0.1")
+        .port(addr.port())
+        .connect()
+        .map_err(Error::Connect)?;
+    let (conn, peer) = listener.accept()?;
+    println!("time_wait: accepted {}", peer);
+
+    // The server closes first, the client only closes once it sees the EOF.
+    drop(conn);
+    wait_for_eof(&client)?;
+    drop(client);
+    drop(listener);
+
+    Ok(addr)
+}
+
+fn wait_for_eof(sock: &Socket) -> Result<(), Error> {
+    let mut buf = [0u8; 64];
+    loop {
+        // SAFETY: `buf` is initialized and its length is passed along with it.
+        let rbytes = unsafe {
+            trace::recv(
+                sock.as_raw(),
+                buf.as_mut_ptr() as *mut libc::c_void,
+                buf.len(),
+                0,
+            )
+        };
+        match rbytes {
+            -1 => {
+                let err = io::Error::last_os_error();
+                if err.kind() != io::ErrorKind::Interrupted {
+                    return Err(Error::Recv(err));
+                }
+            }
+            0 => return Ok(()),
+            _ => {}
+        }
+    }
+}
+
+// A failed `bind()` is the expected outcome without `SO_REUSEADDR`, so it is reported instead of returned.
+// Returns whether the port could be bound again.
+fn rebind(port: u16, reuseaddr: bool) -> Result<bool, Error> {
+    let how = match reuseaddr {
+        true => "with",
+        false => "without",
+    };
+
+    let res = Server::new()
+        .bind(Some("127.0.0.1"), port)
+        .reuseaddr(reuseaddr)
+        .listen();
+    match res {
+        Ok(listener) => {
+            println!(
+                "time_wait: binding {} again {} SO_REUSEADDR succeeded",
+                listener.local_addr(),
+                how
+            );
+            Ok(true)
+        }
+        Err(server_builder::Error::Listener(listener::Error::Bind(err))) => {
+            println!(
+                "time_wait: binding port {} again {} SO_REUSEADDR failed: {}",
+                port, how, err
+            );
+            Ok(false)
+        }
+        Err(err) => Err(err.into()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn rebind_needs_reuseaddr_while_in_time_wait() {
+        let addr = leave_in_time_wait().unwrap();
+
+        assert!(!rebind(addr.port(), false).unwrap());
+        assert!(rebind(addr.port(), true).unwrap());
+    }
+}
@@ -0,0 +1,220 @@
+use std::{
+    error,
+    ffi::CString,
+    fmt, io, mem,
+    net::SocketAddr,
+    time::{Duration, Instant},
+};
+
+use crate::{sockaddr, socket_fd::Socket, sockopt, trace, udp};
+
+#[derive(Debug)]
+pub enum Error {
+    InvalidHost(String),
+    Udp(udp::Error),
+    Socket(io::Error),
+    Setsockopt(io::Error),
+    Connect(io::Error),
+    Send(io::Error),
+    Poll(io::Error),
+    Recvmsg(io::Error),
+    NothingQueued(Duration),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::InvalidHost(host) => write!(f, "invalid host {}", host),
+            Error::Udp(err) => write!(f, "udp error: {}", err),
+            Error::Socket(err) => write!(f, "socket error: {}", err),
+            Error::Setsockopt(err) => write!(f, "setsockopt error: {}", err),
+            Error::Connect(err) => write!(f, "connect error: {}", err),
+            Error::Send(err) => write!(f, "send error: {}", err),
+            Error::Poll(err) => write!(f, "poll error: {}", err),
+            Error::Recvmsg(err) => write!(f, "recvmsg error: {}", err),
+            Error::NothingQueued(timeout) => write!(
+                f,
+                "no error is queued within {:?}, the port may be open or the ICMP error may be filtered",
+                timeout
+            ),
+        }
+    }
+}
+
+impl error::Error for Error {}
+
+impl From<udp::Error> for Error {
+    fn from(value: udp::Error) -> Self {
+        Self::Udp(value)
+    }
+}
+
+const ERROR_TIMEOUT: Duration = Duration::from_secs(2);
+
+// EXAMPLE: Reads the ICMP error caused by a UDP datagram from the error queue of the socket on Linux.
+// MANPAGE:
+// man 7 ip (Linux), see IP_RECVERR
+// man 7 ipv6 (Linux), see IPV6_RECVERR
+// man 2 recvmsg (Linux)
+// man 3 cmsg (Linux)
+//
+// A datagram sent to a port that nothing listens on is answered with an ICMP "port unreachable" error.
+// By default, a connected UDP socket only keeps the `errno` of it (`ECONNREFUSED`), which the next `send()` or
+// `recv()` returns, and an unconnected one does not even get that.
+//
+// With `IP_RECVERR` (`IPV6_RECVERR` for IPv6), the kernel also queues the whole error on the socket.
+// The queue is separate from the received data, it is signalled by `POLLERR` and read with `recvmsg(MSG_ERRQUEUE)`.
+// The error comes as a control message that carries a `sock_extended_err`: where the error came from (a local
+// check or an ICMP message), the ICMP type and code, and the address of the host that sent the ICMP message.
+pub fn udp_errqueue(host: &str, port: u16) -> Result<(), Error> {
+    let node = CString::new(host).map_err(|_| Error::InvalidHost(host.to_string()))?;
+    let service = CString::new(port.to_string()).unwrap();
+    let addr = udp::resolve(&node, &service)?;
+
+    let (family, level, optname) = match addr {
+        SocketAddr::V4(_) => (libc::AF_INET, libc::SOL_IP, libc::IP_RECVERR),
+        SocketAddr::V6(_) => (libc::AF_INET6, libc::SOL_IPV6, libc::IPV6_RECVERR),
+    };
+    let sock = Socket::new(family, libc::SOCK_DGRAM, 0).map_err(Error::Socket)?;
+    sockopt::set_int(sock.as_raw(), level, optname, 1).map_err(Error::Setsockopt)?;
+
+    let (sa, sa_len) = sockaddr::from_socket_addr(&addr);
+    // SAFETY: `sa` holds a valid address of `sa_len` bytes.
+    let ecode = unsafe {
+        trace::connect(
+            sock.as_raw(),
+            &raw const sa as *const libc::sockaddr,
+            sa_len,
+        )
+    };
+    if ecode == -1 {
+        return Err(Error::Connect(io::Error::last_os_error()));
+    }
+
+    let msg = b"is anybody there?";
+    // SAFETY: `msg` is a valid, initialized slice.
+    let sbytes = unsafe {
+        trace::send(
+            sock.as_raw(),
+            msg.as_ptr() as *const libc::c_void,
+            msg.len(),
+            0,
+        )
+    };
+    if sbytes == -1 {
+        return Err(Error::Send(io::Error::last_os_error()));
+    }
+    println!("udp_errqueue: sent {} bytes to {}", sbytes, addr);
+
+    wait_error(sock.as_raw())?;
+    read_error(sock.as_raw())
+}
+
+// Waits until an error is queued, which `poll()` reports as `POLLERR` even though it is not asked for.
+fn wait_error(sock_fd: i32) -> Result<(), Error> {
+    let deadline = Instant::now() + ERROR_TIMEOUT;
+
+    loop {
+        let left = deadline.saturating_duration_since(Instant::now());
+        let mut pfd = libc::pollfd {
+            fd: sock_fd,
+            events: 0,
+            revents: 0,
+        };
+
+        // SAFETY: `pfd` is an initialized `pollfd`, and it is the only element that is passed.
+        let nfds = unsafe { libc::poll(&mut pfd, 1, left.as_millis() as i32) };
+        match nfds {
+            -1 => {
+                let err = io::Error::last_os_error();
+                if err.kind() != io::ErrorKind::Interrupted {
+                    return Err(Error::Poll(err));
+                }
+            }
+            0 => return Err(Error::NothingQueued(ERROR_TIMEOUT)),
+            _ if pfd.revents & libc::POLLERR != 0 => return Ok(()),
+            _ => {}
+        }
+    }
+}
+
+fn read_error(sock_fd: i32) -> Result<(), Error> {
+    // The queued error also carries the datagram that caused it, as the regular data of the message.
+    let mut payload = [0u8; 512];
+    let mut iov = libc::iovec {
+        iov_base: payload.as_mut_ptr() as *mut libc::c_void,
+        iov_len: payload.len(),
+    };
+    // `u64`s keep the buffer aligned for `cmsghdr`.
+    let mut control = [0u64; 64];
+
+    // SAFETY: All zero `msghdr` is a valid initialization, the buffers are set before it is used.
+    let mut msg: libc::msghdr = unsafe { mem::zeroed() };
+    msg.msg_iov = &raw mut iov;
+    msg.msg_iovlen = 1;
+    msg.msg_control = control.as_mut_ptr() as *mut libc::c_void;
+    msg.msg_controllen = mem::size_of_val(&control);
+
+    // SAFETY: `msg` points to `iov` and `control`, which outlive the call, along with their lengths.
+    let rbytes = unsafe { libc::recvmsg(sock_fd, &mut msg, libc::MSG_ERRQUEUE) };
+    if rbytes == -1 {
+        return Err(Error::Recvmsg(io::Error::last_os_error()));
+    }
+    println!(
+        "udp_errqueue: the error is about our {} byte datagram: {}",
+        rbytes,
+        String::from_utf8_lossy(&payload[..rbytes as usize])
+    );
+
+    // SAFETY: `msg` is filled by `recvmsg()`, and `msg_controllen` is set to the length of the control messages.
+    let mut cmsg = unsafe { libc::CMSG_FIRSTHDR(&msg) };
+    while !cmsg.is_null() {
+        // SAFETY: `cmsg` is not null, and it points into `control`.
+        let hdr = unsafe { &*cmsg };
+        let is_recverr = (hdr.cmsg_level == libc::SOL_IP && hdr.cmsg_type == libc::IP_RECVERR)
+            || (hdr.cmsg_level == libc::SOL_IPV6 && hdr.cmsg_type == libc::IPV6_RECVERR);
+
+        if is_recverr {
+            // SAFETY: The data of an `IP_RECVERR`/`IPV6_RECVERR` control message is a `sock_extended_err`,
+            // followed by the address of the offender. The data may not be aligned, so it is read unaligned.
+            let ee = unsafe { libc::CMSG_DATA(cmsg) as *const libc::sock_extended_err };
+            print_extended_err(ee);
+        }
+
+        // SAFETY: `msg` and `cmsg` are valid, `CMSG_NXTHDR` returns null after the last control message.
+        cmsg = unsafe { libc::CMSG_NXTHDR(&msg, cmsg) };
+    }
+
+    Ok(())
+}
+
+fn print_extended_err(ee: *const libc::sock_extended_err) {
+    // SAFETY: `ee` points to a `sock_extended_err` in the control buffer, see `read_error()`.
+    let err = unsafe { ee.read_unaligned() };
+
+    let origin = match err.ee_origin {
+        libc::SO_EE_ORIGIN_LOCAL => "the local stack",
+        libc::SO_EE_ORIGIN_ICMP => "an ICMP message",
+        libc::SO_EE_ORIGIN_ICMP6 => "an ICMPv6 message",
+        _ => "an unknown origin",
+    };
+    println!(
+        "udp_errqueue: {} from {}, type = {}, code = {}",
+        io::Error::from_raw_os_error(err.ee_errno as i32),
+        origin,
+        err.ee_type,
+        err.ee_code
+    );
+
+    // SAFETY: The offender address follows `sock_extended_err` in the same control message.
+    // The buffer is copied to a `sockaddr_storage` before it is read, which also takes care of the alignment.
+    // The copy may read past a `sockaddr_in`, but it stays within the control buffer, which is much larger.
+    let offender = unsafe {
+        let sa = libc::SO_EE_OFFENDER(ee);
+        sockaddr::copy_to_storage(sa, mem::size_of::<libc::sockaddr_in6>() as libc::socklen_t)
+    };
+    match sockaddr::to_socket_addr(&offender) {
+        Some(offender) => println!("udp_errqueue: reported by {}", offender.ip()),
+        None => println!("udp_errqueue: the offender is unknown"),
+    }
+}
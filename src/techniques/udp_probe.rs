@@ -0,0 +1,207 @@
+use std::{collections::HashSet, error, ffi::CString, fmt, io, net::SocketAddr, time::Duration};
+
+use crate::{
+    payload::{self, PayloadKind},
+    serialize,
+    socket_fd::Socket,
+    udp,
+};
+
+#[derive(Debug)]
+pub enum Error {
+    Udp(udp::Error),
+    InvalidHost(String),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Udp(err) => write!(f, "udp error: {}", err),
+            Error::InvalidHost(host) => write!(f, "invalid host {}", host),
+        }
+    }
+}
+
+impl error::Error for Error {}
+
+impl From<udp::Error> for Error {
+    fn from(value: udp::Error) -> Self {
+        Self::Udp(value)
+    }
+}
+
+// A probe starts with its sequence number and the number of probes in the run, both packed as big-endian u32s.
+// A fixed payload follows them, and the CRC32 of everything before it closes the datagram.
+const HEADER_SIZE: usize = 8;
+const PAYLOAD_SIZE: usize = 64;
+const CRC_SIZE: usize = 4;
+const PROBE_SIZE: usize = HEADER_SIZE + PAYLOAD_SIZE + CRC_SIZE;
+
+// The receiver gives up on the rest of the run once nothing arrives for this long.
+const IDLE_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// What the receiver saw over a single run.
+#[derive(Debug, Default)]
+struct Summary {
+    expected: u32,
+    received: u32,
+    reordered: u32,
+    duplicated: u32,
+    corrupted: u32,
+}
+
+impl Summary {
+    fn loss(&self) -> f64 {
+        match self.expected {
+            0 => 0.0,
+            expected => expected.saturating_sub(self.received) as f64 * 100.0 / expected as f64,
+        }
+    }
+}
+
+impl fmt::Display for Summary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} expected, {} received, {:.1}% loss, {} reordered, {} duplicated, {} corrupted",
+            self.expected,
+            self.received,
+            self.loss(),
+            self.reordered,
+            self.duplicated,
+            self.corrupted
+        )
+    }
+}
+
+// EXAMPLE: The sending side of a UDP integrity probe.
+// `count` probes are sent back to back, each with a sequence number and a CRC32 of its contents,
+// so that the receiver can tell which of them were lost, reordered or corrupted on the way.
+// MANPAGE:
+// man 2 sendto (Linux)
+//
+// Nothing is acknowledged, the sender does not learn the outcome. See `udp_probe_receiver()` for the summary.
+pub fn udp_probe(host: &str, port: u16, count: u32) -> Result<(), Error> {
+    let node = CString::new(host).map_err(|_| Error::InvalidHost(host.to_string()))?;
+    let service = CString::new(port.to_string()).unwrap();
+    let receiver_addr = udp::resolve(&node, &service)?;
+
+    let family = match receiver_addr {
+        SocketAddr::V4(_) => libc::AF_INET,
+        SocketAddr::V6(_) => libc::AF_INET6,
+    };
+    let sock = Socket::from_raw(udp::udp_socket(family)?);
+
+    let mut buf = [0u8; PROBE_SIZE];
+    buf[HEADER_SIZE..HEADER_SIZE + PAYLOAD_SIZE]
+        .copy_from_slice(&payload::generate(PAYLOAD_SIZE, PayloadKind::Pattern));
+
+    for seq in 0..count {
+        serialize::packi32(&mut buf[..4], seq);
+        serialize::packi32(&mut buf[4..8], count);
+        let crc = serialize::crc32(&buf[..PROBE_SIZE - CRC_SIZE]);
+        serialize::packi32(&mut buf[PROBE_SIZE - CRC_SIZE..], crc);
+
+        udp::send_to(sock.as_raw(), &buf, &receiver_addr)?;
+    }
+
+    println!("udp_probe: sent {} probes to {}", count, receiver_addr);
+
+    Ok(())
+}
+
+// EXAMPLE: The receiving side of a UDP integrity probe, see `udp_probe()`.
+// MANPAGE:
+// man 2 recvfrom (Linux)
+// man 7 socket (SO_RCVTIMEO)
+//
+// Each probe is checked against its CRC32 first, a probe that does not match (or is too short) is counted as
+// corrupted, and its sequence number is not trusted. The rest are tracked by their sequence numbers:
+// a probe that arrives after a higher one is counted as reordered, and a probe that is seen twice as duplicated.
+// The number of probes in the run is carried by every probe, the ones that never show up are counted as lost.
+//
+// The run ends once every probe arrives, or once nothing arrives for a while, since any of them may be lost.
+// The receiver then prints the summary and waits for the next run.
+pub fn udp_probe_receiver(port: u16) -> Result<(), Error> {
+    let port = CString::new(port.to_string()).unwrap();
+    let (sock_fd, addr) = udp::bind_udp(None, &port)?;
+    let sock = Socket::from_raw(sock_fd);
+
+    println!("udp_probe_receiver: waiting for probes on {}...", addr);
+
+    loop {
+        let summary = receive_run(&sock)?;
+        println!("udp_probe_receiver: {}", summary);
+    }
+}
+
+fn receive_run(sock: &Socket) -> Result<Summary, Error> {
+    let mut summary = Summary::default();
+    let mut seen = HashSet::new();
+    let mut highest = None;
+    let mut buf = [0u8; PROBE_SIZE + 1];
+
+    // The first probe of a run is waited for indefinitely, the rest only for `IDLE_TIMEOUT`.
+    udp::set_recv_timeout(sock.as_raw(), Duration::ZERO)?;
+
+    loop {
+        let rbytes = match udp::recv_from(sock.as_raw(), &mut buf) {
+            Ok((rbytes, _)) => rbytes,
+            Err(udp::Error::Recvfrom(err)) if err.kind() == io::ErrorKind::WouldBlock => {
+                return Ok(summary);
+            }
+            Err(err) => return Err(err.into()),
+        };
+        udp::set_recv_timeout(sock.as_raw(), IDLE_TIMEOUT)?;
+
+        // A longer datagram is truncated into `PROBE_SIZE + 1` bytes, which is enough to reject it.
+        let probe = &buf[..rbytes];
+        let crc_at = PROBE_SIZE - CRC_SIZE;
+        if probe.len() != PROBE_SIZE
+            || serialize::crc32(&probe[..crc_at]) != serialize::unpacku32(&probe[crc_at..])
+        {
+            summary.corrupted += 1;
+            continue;
+        }
+
+        let seq = serialize::unpacku32(&probe[..4]);
+        summary.expected = serialize::unpacku32(&probe[4..8]);
+
+        if !seen.insert(seq) {
+            summary.duplicated += 1;
+            continue;
+        }
+        summary.received += 1;
+
+        if highest.is_some_and(|highest| seq < highest) {
+            summary.reordered += 1;
+        }
+        highest = highest.max(Some(seq));
+
+        if summary.received == summary.expected {
+            return Ok(summary);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn loopback_run_has_no_loss_or_corruption() {
+        let (sock_fd, addr) = udp::bind_udp(Some(c"127.0.0.1"), c"0").unwrap();
+        let sock = Socket::from_raw(sock_fd);
+
+        // A small run fits in the receive buffer, so it is sent in full before it is received.
+        udp_probe("127.0.0.1", addr.port(), 20).unwrap();
+        let summary = receive_run(&sock).unwrap();
+
+        assert_eq!(summary.expected, 20);
+        assert_eq!(summary.received, 20);
+        assert_eq!(summary.loss(), 0.0);
+        assert_eq!(summary.corrupted, 0);
+        assert_eq!(summary.reordered, 0);
+        assert_eq!(summary.duplicated, 0);
+    }
+}
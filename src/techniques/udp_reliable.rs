@@ -0,0 +1,353 @@
+use std::{
+    error,
+    ffi::CString,
+    fmt, io,
+    net::SocketAddr,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    fd,
+    payload::{self, PayloadKind},
+    readiness, serialize, udp,
+};
+
+#[derive(Debug)]
+pub enum Error {
+    Udp(udp::Error),
+    InvalidHost(String),
+    Poll(io::Error),
+    GaveUp(u32, u32),
+    Close(io::Error),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Udp(err) => write!(f, "udp error: {}", err),
+            Error::InvalidHost(host) => write!(f, "invalid host {}", host),
+            Error::Poll(err) => write!(f, "poll error: {}", err),
+            Error::GaveUp(seq, retries) => write!(
+                f,
+                "gave up on seq={} after {} retransmissions without an ACK",
+                seq, retries
+            ),
+            Error::Close(err) => write!(f, "close error: {}", err),
+        }
+    }
+}
+
+impl error::Error for Error {}
+
+impl From<udp::Error> for Error {
+    fn from(value: udp::Error) -> Self {
+        Self::Udp(value)
+    }
+}
+
+// Every datagram starts with its kind and a sequence number packed as a big-endian u32.
+// A DATA datagram carries the message after them, an ACK carries nothing.
+const KIND_DATA: u8 = 1;
+const KIND_ACK: u8 = 2;
+const HEADER_SIZE: usize = 5;
+const MAX_DATAGRAM_SIZE: usize = 1024;
+
+fn pack_header(buf: &mut [u8], kind: u8, seq: u32) {
+    buf[0] = kind;
+    serialize::packi32(&mut buf[1..HEADER_SIZE], seq);
+}
+
+fn unpack_header(buf: &[u8]) -> Option<(u8, u32)> {
+    if buf.len() < HEADER_SIZE {
+        return None;
+    }
+
+    Some((buf[0], serialize::unpacku32(&buf[1..HEADER_SIZE])))
+}
+
+// Packs a DATA datagram of `msg` followed by `payload`, the payload is truncated to fit in a datagram.
+fn pack_data(seq: u32, msg: &[u8], payload: &[u8]) -> Vec<u8> {
+    let msg = &msg[..msg.len().min(MAX_DATAGRAM_SIZE - HEADER_SIZE)];
+    let payload = &payload[..payload
+        .len()
+        .min(MAX_DATAGRAM_SIZE - HEADER_SIZE - msg.len())];
+
+    let mut datagram = vec![0u8; HEADER_SIZE + msg.len() + payload.len()];
+    pack_header(&mut datagram, KIND_DATA, seq);
+    datagram[HEADER_SIZE..HEADER_SIZE + msg.len()].copy_from_slice(msg);
+    datagram[HEADER_SIZE + msg.len()..].copy_from_slice(payload);
+    datagram
+}
+
+// EXAMPLE: The receiving side of a stop-and-wait protocol on top of UDP.
+// Every DATA datagram is acknowledged with an ACK that carries the same sequence number.
+// A retransmitted DATA datagram (e.g. because its ACK is lost) is acknowledged again, but it is not printed twice.
+// MANPAGE:
+// man 2 recvfrom (Linux)
+// man 2 sendto (Linux)
+//
+// The first `drop_acks` ACKs are not sent, to observe the sender retransmitting.
+pub fn reliable_receiver(port: u16, drop_acks: u32) -> Result<(), Error> {
+    let port = CString::new(port.to_string()).unwrap();
+    let (sock_fd, addr) = udp::bind_udp(None, &port)?;
+
+    println!("reliable_receiver: waiting for messages on {}...", addr);
+
+    receive(sock_fd, drop_acks)
+}
+
+// Receives and acknowledges the messages on `sock_fd` until `recvfrom()` fails, `sock_fd` is closed then.
+fn receive(sock_fd: i32, mut drop_acks: u32) -> Result<(), Error> {
+    let mut buf = [0u8; MAX_DATAGRAM_SIZE];
+    let mut last_seq: Option<(SocketAddr, u32)> = None;
+    loop {
+        let (rbytes, from_addr) = match udp::recv_from(sock_fd, &mut buf) {
+            Ok(res) => res,
+            Err(err) => {
+                let _ = fd::close_fd(sock_fd);
+                return Err(err.into());
+            }
+        };
+        let Some((KIND_DATA, seq)) = unpack_header(&buf[..rbytes]) else {
+            eprintln!(
+                "reliable_receiver: ignoring a {} byte datagram from {}",
+                rbytes, from_addr
+            );
+            continue;
+        };
+
+        if last_seq == Some((from_addr, seq)) {
+            println!(
+                "reliable_receiver: seq={} from {} is a duplicate",
+                seq, from_addr
+            );
+        } else {
+            // A `random` payload is not text, only its size is printed.
+            let data = &buf[HEADER_SIZE..rbytes];
+            match std::str::from_utf8(data) {
+                Ok(text) => println!(
+                    "reliable_receiver: seq={} from {}: {}",
+                    seq, from_addr, text
+                ),
+                Err(_) => println!(
+                    "reliable_receiver: seq={} from {}: {} bytes of binary data",
+                    seq,
+                    from_addr,
+                    data.len()
+                ),
+            }
+            last_seq = Some((from_addr, seq));
+        }
+
+        if drop_acks > 0 {
+            drop_acks -= 1;
+            println!("reliable_receiver: dropping the ACK of seq={}", seq);
+            continue;
+        }
+
+        let mut ack = [0u8; HEADER_SIZE];
+        pack_header(&mut ack, KIND_ACK, seq);
+        if let Err(err) = udp::send_to(sock_fd, &ack, &from_addr) {
+            eprintln!("reliable_receiver: {}", err);
+        }
+    }
+}
+
+// EXAMPLE: The sending side of a stop-and-wait protocol on top of UDP.
+// UDP does not retransmit anything, so each message is sent and then its ACK is waited for.
+// If the ACK does not arrive within `timeout`, the message is sent again, at most `max_retries` times.
+// MANPAGE:
+// man 2 poll (Linux)
+// man 2 sendto (Linux)
+// man 2 recvfrom (Linux)
+//
+// The wait is implemented with `poll(POLLIN)` instead of `SO_RCVTIMEO`: the timeout belongs to a single wait
+// instead of the socket, and the same `poll()` call could watch other fds as well.
+//
+// Each message is followed by the same `payload_size` byte payload, to observe larger datagrams being retransmitted.
+pub fn reliable_sender(
+    host: &str,
+    port: u16,
+    timeout: Duration,
+    max_retries: u32,
+    msgs: &[String],
+    payload_size: usize,
+    payload_kind: PayloadKind,
+) -> Result<(), Error> {
+    let node = CString::new(host).map_err(|_| Error::InvalidHost(host.to_string()))?;
+    let service = CString::new(port.to_string()).unwrap();
+    let receiver_addr = udp::resolve(&node, &service)?;
+
+    let family = match receiver_addr {
+        SocketAddr::V4(_) => libc::AF_INET,
+        SocketAddr::V6(_) => libc::AF_INET6,
+    };
+    let sock_fd = udp::udp_socket(family)?;
+
+    let payload = payload::generate(
+        payload_size.min(MAX_DATAGRAM_SIZE - HEADER_SIZE),
+        payload_kind,
+    );
+    let res = send_all(sock_fd, receiver_addr, timeout, max_retries, msgs, &payload);
+
+    // Every message is either acknowledged or given up on, `sock_fd` is not used after this call.
+    fd::close_fd(sock_fd).map_err(Error::Close)?;
+
+    let retransmissions = res?;
+    println!(
+        "reliable_sender: {} messages acknowledged, {} retransmissions",
+        msgs.len(),
+        retransmissions
+    );
+
+    Ok(())
+}
+
+// Returns the total number of retransmissions.
+fn send_all(
+    sock_fd: i32,
+    receiver_addr: SocketAddr,
+    timeout: Duration,
+    max_retries: u32,
+    msgs: &[String],
+    payload: &[u8],
+) -> Result<u32, Error> {
+    let mut retransmissions = 0;
+    let mut buf = [0u8; MAX_DATAGRAM_SIZE];
+
+    for (seq, msg) in msgs.iter().enumerate() {
+        let seq = seq as u32;
+        let datagram = pack_data(seq, msg.as_bytes(), payload);
+
+        let mut attempt = 0;
+        loop {
+            udp::send_to(sock_fd, &datagram, &receiver_addr)?;
+
+            if wait_ack(sock_fd, receiver_addr, seq, timeout, &mut buf)? {
+                println!("reliable_sender: seq={} acknowledged", seq);
+                break;
+            }
+
+            if attempt == max_retries {
+                return Err(Error::GaveUp(seq, attempt));
+            }
+            attempt += 1;
+            retransmissions += 1;
+            println!(
+                "reliable_sender: seq={} timed out, retransmitting ({}/{})",
+                seq, attempt, max_retries
+            );
+        }
+    }
+
+    Ok(retransmissions)
+}
+
+// Waits for the ACK of `seq` for at most `timeout`.
+// Late ACKs of earlier messages and datagrams from anyone else are skipped without extending the wait.
+// Returns whether the ACK arrived in time.
+fn wait_ack(
+    sock_fd: i32,
+    receiver_addr: SocketAddr,
+    seq: u32,
+    timeout: Duration,
+    buf: &mut [u8],
+) -> Result<bool, Error> {
+    let deadline = Instant::now() + timeout;
+
+    loop {
+        let left = deadline.saturating_duration_since(Instant::now());
+        match readiness::wait_readable(sock_fd, Some(left)) {
+            Ok(true) => {}
+            Ok(false) => return Ok(false),
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+            Err(err) => return Err(Error::Poll(err)),
+        }
+
+        let (rbytes, from_addr) = udp::recv_from(sock_fd, buf)?;
+        if from_addr == receiver_addr && unpack_header(&buf[..rbytes]) == Some((KIND_ACK, seq)) {
+            return Ok(true);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use super::*;
+
+    #[test]
+    fn a_dropped_ack_is_retransmitted() {
+        let (receiver_fd, receiver_addr) = udp::bind_udp(Some(c"127.0.0.1"), c"0").unwrap();
+        // The receiver runs until the test process exits.
+        thread::spawn(move || receive(receiver_fd, 1));
+
+        let sender_fd = udp::udp_socket(libc::AF_INET).unwrap();
+        let msgs = [String::from("one"), String::from("two")];
+        let res = send_all(
+            sender_fd,
+            receiver_addr,
+            Duration::from_millis(100),
+            3,
+            &msgs,
+            &[],
+        );
+        fd::close_fd(sender_fd).unwrap();
+
+        // Only the ACK of the first message is dropped, so it is the only one sent twice.
+        assert_eq!(res.unwrap(), 1);
+    }
+
+    #[test]
+    fn gives_up_without_a_receiver() {
+        // Nobody reads from this socket, so nothing is ever acknowledged.
+        let (silent_fd, silent_addr) = udp::bind_udp(Some(c"127.0.0.1"), c"0").unwrap();
+
+        let sender_fd = udp::udp_socket(libc::AF_INET).unwrap();
+        let msgs = [String::from("one")];
+        let res = send_all(
+            sender_fd,
+            silent_addr,
+            Duration::from_millis(20),
+            2,
+            &msgs,
+            &[],
+        );
+        fd::close_fd(sender_fd).unwrap();
+        fd::close_fd(silent_fd).unwrap();
+
+        assert!(matches!(res, Err(Error::GaveUp(0, 2))), "{:?}", res);
+    }
+
+    #[test]
+    fn payload_round_trips_unchanged() {
+        let payload = payload::generate(512, PayloadKind::Random { seed: 42 });
+        let datagram = pack_data(7, b"hello", &payload);
+
+        let (receiver_fd, receiver_addr) = udp::bind_udp(Some(c"127.0.0.1"), c"0").unwrap();
+        let sender_fd = udp::udp_socket(libc::AF_INET).unwrap();
+        udp::send_to(sender_fd, &datagram, &receiver_addr).unwrap();
+
+        let mut buf = [0u8; MAX_DATAGRAM_SIZE];
+        let (rbytes, _) = udp::recv_from(receiver_fd, &mut buf).unwrap();
+        fd::close_fd(sender_fd).unwrap();
+        fd::close_fd(receiver_fd).unwrap();
+
+        assert_eq!(unpack_header(&buf[..rbytes]), Some((KIND_DATA, 7)));
+        assert_eq!(&buf[HEADER_SIZE..HEADER_SIZE + 5], b"hello");
+        assert_eq!(&buf[HEADER_SIZE + 5..rbytes], payload);
+    }
+
+    #[test]
+    fn payload_is_truncated_to_fit_in_a_datagram() {
+        let payload = payload::generate(MAX_DATAGRAM_SIZE, PayloadKind::Pattern);
+        let datagram = pack_data(0, b"hi", &payload);
+
+        assert_eq!(datagram.len(), MAX_DATAGRAM_SIZE);
+        assert_eq!(
+            &datagram[HEADER_SIZE + 2..],
+            &payload[..MAX_DATAGRAM_SIZE - HEADER_SIZE - 2]
+        );
+    }
+}
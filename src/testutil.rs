@@ -0,0 +1,49 @@
+// Helpers shared by the unit tests of the crate.
+
+use std::io;
+
+use crate::{client_builder::Client, server_builder::Server, socket_fd::Socket};
+
+/// Returns the two ends of a connected `AF_UNIX` stream socket pair.
+pub fn socketpair() -> (Socket, Socket) {
+    let mut fds = [0i32; 2];
+    // SAFETY: `fds` has room for the two fds that `socketpair()` writes.
+    let ecode = unsafe { libc::socketpair(libc::AF_UNIX, libc::SOCK_STREAM, 0, fds.as_mut_ptr()) };
+    assert_eq!(ecode, 0, "socketpair error: {}", io::Error::last_os_error());
+    (Socket::from_raw(fds[0]), Socket::from_raw(fds[1]))
+}
+
+/// Returns the client and the server ends of a TCP connection over 127.0.0.1, on an ephemeral port.
+pub fn loopback_pair() -> (Socket, Socket) {
+    let listener = Server::new().bind(Some("127.0.0.1"), 0).listen().unwrap();
+    // The connection completes in the backlog, before it is accepted.
+    let (client, _) = Client::new()
+        .host("127.0.0.1")
+        .port(listener.local_addr().port())
+        .connect()
+        .unwrap();
+    let (server, _) = listener.accept().unwrap();
+    (client, server)
+}
+
+/// Receives until `sock` reaches EOF.
+pub fn recv_to_end(sock: &Socket) -> Vec<u8> {
+    let mut received = Vec::new();
+    let mut buf = [0u8; 4096];
+    loop {
+        // SAFETY: `buf` is valid for `buf.len()` bytes for the entire `recv()` call.
+        let rbytes = unsafe {
+            libc::recv(
+                sock.as_raw(),
+                buf.as_mut_ptr() as *mut libc::c_void,
+                buf.len(),
+                0,
+            )
+        };
+        assert!(rbytes >= 0, "recv error: {}", io::Error::last_os_error());
+        if rbytes == 0 {
+            return received;
+        }
+        received.extend_from_slice(&buf[..rbytes as usize]);
+    }
+}
@@ -0,0 +1,330 @@
+use std::{
+    fmt, io,
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+use crate::{color, sockaddr};
+
+// Thin wrappers around the socket syscalls that print each call, its arguments and its result
+// to stderr, similar to a tiny `strace`.
+//
+// Each wrapper has the same signature and the same contract as its `libc` counterpart,
+// therefore they are `unsafe` to call for the same reasons.
+// `errno` is preserved, so `io::Error::last_os_error()` still works right after a wrapper returns.
+//
+// Tracing is off by default, the wrappers then only forward the call.
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+/// Turns tracing on or off for the whole process.
+pub fn set_enabled(enabled: bool) {
+    ENABLED.store(enabled, Ordering::Relaxed);
+}
+
+/// Reports whether tracing is on.
+pub fn enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+fn log(call: fmt::Arguments, ret: isize) {
+    // `isatty()` may overwrite `errno` as well, it is read first.
+    let err = io::Error::last_os_error();
+    let color = color::stderr();
+    let prefix = color::paint("trace:", color::DIM, color);
+
+    if ret != -1 {
+        eprintln!("{} {} = {}", prefix, call, ret);
+        return;
+    }
+
+    // Printing may overwrite `errno`, it is restored for the caller afterwards.
+    let failure = color::paint(&format!("-1 ({})", err), color::RED, color);
+    eprintln!("{} {} = {}", prefix, call, failure);
+    if let Some(errno) = err.raw_os_error() {
+        set_errno(errno);
+    }
+}
+
+fn set_errno(errno: i32) {
+    // SAFETY: The errno location is a valid, thread local `int` for the lifetime of the calling thread.
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    unsafe {
+        *libc::__errno_location() = errno
+    };
+
+    // SAFETY: The errno location is a valid, thread local `int` for the lifetime of the calling thread.
+    #[cfg(any(target_os = "macos", target_os = "ios", target_os = "freebsd"))]
+    unsafe {
+        *libc::__error() = errno
+    };
+}
+
+/// Formats the address behind `addr` for the trace output.
+///
+/// # Safety
+///
+/// `addr` must either be null or point to a valid address of `len` bytes.
+unsafe fn fmt_addr(addr: *const libc::sockaddr, len: libc::socklen_t) -> String {
+    if addr.is_null() {
+        return String::from("NULL");
+    }
+
+    // SAFETY: `addr` is valid for `len` bytes as required by the caller.
+    let sa = unsafe { sockaddr::copy_to_storage(addr, len) };
+    match sockaddr::to_socket_addr(&sa) {
+        Some(addr) => addr.to_string(),
+        None => format!("{{family {}}}", sa.ss_family),
+    }
+}
+
+/// Traced `libc::socket()`.
+///
+/// # Safety
+///
+/// Same as `libc::socket()`.
+pub unsafe fn socket(domain: i32, ty: i32, protocol: i32) -> i32 {
+    // SAFETY: The caller upholds the contract of `socket()`.
+    let ret = unsafe { libc::socket(domain, ty, protocol) };
+    if enabled() {
+        log(
+            format_args!("socket({}, {}, {})", domain, ty, protocol),
+            ret as isize,
+        );
+    }
+
+    ret
+}
+
+/// Traced `libc::bind()`.
+///
+/// # Safety
+///
+/// Same as `libc::bind()`.
+pub unsafe fn bind(fd: i32, addr: *const libc::sockaddr, len: libc::socklen_t) -> i32 {
+    // SAFETY: The caller upholds the contract of `bind()`.
+    let ret = unsafe { libc::bind(fd, addr, len) };
+    if enabled() {
+        // SAFETY: `addr` is valid for `len` bytes, `bind()` requires it as well.
+        let addr = unsafe { fmt_addr(addr, len) };
+        log(
+            format_args!("bind({}, {}, {})", fd, addr, len),
+            ret as isize,
+        );
+    }
+
+    ret
+}
+
+/// Traced `libc::listen()`.
+///
+/// # Safety
+///
+/// Same as `libc::listen()`.
+pub unsafe fn listen(fd: i32, backlog: i32) -> i32 {
+    // SAFETY: The caller upholds the contract of `listen()`.
+    let ret = unsafe { libc::listen(fd, backlog) };
+    if enabled() {
+        log(format_args!("listen({}, {})", fd, backlog), ret as isize);
+    }
+
+    ret
+}
+
+/// Traced `libc::accept()`.
+///
+/// # Safety
+///
+/// Same as `libc::accept()`.
+pub unsafe fn accept(fd: i32, addr: *mut libc::sockaddr, len: *mut libc::socklen_t) -> i32 {
+    // SAFETY: The caller upholds the contract of `accept()`.
+    let ret = unsafe { libc::accept(fd, addr, len) };
+    if enabled() {
+        // SAFETY: On success, `accept()` fills `addr` and stores its length in `len`.
+        let peer = match ret {
+            -1 => String::from("?"),
+            _ if len.is_null() => String::from("NULL"),
+            _ => unsafe { fmt_addr(addr, *len) },
+        };
+        log(format_args!("accept({}, {})", fd, peer), ret as isize);
+    }
+
+    ret
+}
+
+/// Traced `libc::connect()`.
+///
+/// # Safety
+///
+/// Same as `libc::connect()`.
+pub unsafe fn connect(fd: i32, addr: *const libc::sockaddr, len: libc::socklen_t) -> i32 {
+    // SAFETY: The caller upholds the contract of `connect()`.
+    let ret = unsafe { libc::connect(fd, addr, len) };
+    if enabled() {
+        // SAFETY: `addr` is valid for `len` bytes, `connect()` requires it as well.
+        let addr = unsafe { fmt_addr(addr, len) };
+        log(
+            format_args!("connect({}, {}, {})", fd, addr, len),
+            ret as isize,
+        );
+    }
+
+    ret
+}
+
+/// Traced `libc::send()`.
+///
+/// # Safety
+///
+/// Same as `libc::send()`.
+pub unsafe fn send(fd: i32, buf: *const libc::c_void, len: usize, flags: i32) -> isize {
+    // SAFETY: The caller upholds the contract of `send()`.
+    let ret = unsafe { libc::send(fd, buf, len, flags) };
+    if enabled() {
+        log(format_args!("send({}, <buf>, {}, {})", fd, len, flags), ret);
+    }
+
+    ret
+}
+
+/// Traced `libc::recv()`.
+///
+/// # Safety
+///
+/// Same as `libc::recv()`.
+pub unsafe fn recv(fd: i32, buf: *mut libc::c_void, len: usize, flags: i32) -> isize {
+    // SAFETY: The caller upholds the contract of `recv()`.
+    let ret = unsafe { libc::recv(fd, buf, len, flags) };
+    if enabled() {
+        log(format_args!("recv({}, <buf>, {}, {})", fd, len, flags), ret);
+    }
+
+    ret
+}
+
+/// Traced `libc::sendto()`.
+///
+/// # Safety
+///
+/// Same as `libc::sendto()`.
+pub unsafe fn sendto(
+    fd: i32,
+    buf: *const libc::c_void,
+    len: usize,
+    flags: i32,
+    addr: *const libc::sockaddr,
+    addrlen: libc::socklen_t,
+) -> isize {
+    // SAFETY: The caller upholds the contract of `sendto()`.
+    let ret = unsafe { libc::sendto(fd, buf, len, flags, addr, addrlen) };
+    if enabled() {
+        // SAFETY: `addr` is valid for `addrlen` bytes, `sendto()` requires it as well.
+        let addr = unsafe { fmt_addr(addr, addrlen) };
+        log(
+            format_args!("sendto({}, <buf>, {}, {}, {})", fd, len, flags, addr),
+            ret,
+        );
+    }
+
+    ret
+}
+
+/// Traced `libc::recvfrom()`.
+///
+/// # Safety
+///
+/// Same as `libc::recvfrom()`.
+pub unsafe fn recvfrom(
+    fd: i32,
+    buf: *mut libc::c_void,
+    len: usize,
+    flags: i32,
+    addr: *mut libc::sockaddr,
+    addrlen: *mut libc::socklen_t,
+) -> isize {
+    // SAFETY: The caller upholds the contract of `recvfrom()`.
+    let ret = unsafe { libc::recvfrom(fd, buf, len, flags, addr, addrlen) };
+    if enabled() {
+        // SAFETY: On success, `recvfrom()` fills `addr` and stores its length in `addrlen`.
+        let from = match ret {
+            -1 => String::from("?"),
+            _ if addrlen.is_null() => String::from("NULL"),
+            _ => unsafe { fmt_addr(addr, *addrlen) },
+        };
+        log(
+            format_args!("recvfrom({}, <buf>, {}, {}, {})", fd, len, flags, from),
+            ret,
+        );
+    }
+
+    ret
+}
+
+/// Traced `libc::shutdown()`.
+///
+/// # Safety
+///
+/// Same as `libc::shutdown()`.
+pub unsafe fn shutdown(fd: i32, how: i32) -> i32 {
+    // SAFETY: The caller upholds the contract of `shutdown()`.
+    let ret = unsafe { libc::shutdown(fd, how) };
+    if enabled() {
+        log(format_args!("shutdown({}, {})", fd, how), ret as isize);
+    }
+
+    ret
+}
+
+/// Traced `libc::getpeername()`.
+///
+/// # Safety
+///
+/// Same as `libc::getpeername()`.
+pub unsafe fn getpeername(fd: i32, addr: *mut libc::sockaddr, len: *mut libc::socklen_t) -> i32 {
+    // SAFETY: The caller upholds the contract of `getpeername()`.
+    let ret = unsafe { libc::getpeername(fd, addr, len) };
+    if enabled() {
+        // SAFETY: On success, `getpeername()` fills `addr` and stores its length in `len`.
+        let peer = match ret {
+            -1 => String::from("?"),
+            _ => unsafe { fmt_addr(addr, *len) },
+        };
+        log(format_args!("getpeername({}, {})", fd, peer), ret as isize);
+    }
+
+    ret
+}
+
+/// Traced `libc::getsockname()`.
+///
+/// # Safety
+///
+/// Same as `libc::getsockname()`.
+pub unsafe fn getsockname(fd: i32, addr: *mut libc::sockaddr, len: *mut libc::socklen_t) -> i32 {
+    // SAFETY: The caller upholds the contract of `getsockname()`.
+    let ret = unsafe { libc::getsockname(fd, addr, len) };
+    if enabled() {
+        // SAFETY: On success, `getsockname()` fills `addr` and stores its length in `len`.
+        let local = match ret {
+            -1 => String::from("?"),
+            _ => unsafe { fmt_addr(addr, *len) },
+        };
+        log(format_args!("getsockname({}, {})", fd, local), ret as isize);
+    }
+
+    ret
+}
+
+/// Traced `libc::close()`.
+///
+/// # Safety
+///
+/// Same as `libc::close()`.
+pub unsafe fn close(fd: i32) -> i32 {
+    // SAFETY: The caller upholds the contract of `close()`.
+    let ret = unsafe { libc::close(fd) };
+    if enabled() {
+        log(format_args!("close({})", fd), ret as isize);
+    }
+
+    ret
+}
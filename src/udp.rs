@@ -0,0 +1,286 @@
+use std::{error, ffi::CStr, fmt, io, mem, net::SocketAddr, time::Duration};
+
+use crate::{
+    addrinfo::{AddrInfoList, Hints},
+    fd,
+    inet::{self, SendtoError},
+    sockaddr, sockopt, trace,
+};
+
+#[derive(Debug)]
+pub enum Error {
+    Getaddrinfo(String),
+    Socket(io::Error),
+    Bind(io::Error),
+    Getsockname(io::Error),
+    Setsockopt(io::Error),
+    Sendto(SendtoError),
+    Recvfrom(io::Error),
+    InvalidAddrFamily(i32),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Getaddrinfo(err) => write!(f, "getaddrinfo error: {}", err),
+            Error::Socket(err) => write!(f, "socket error: {}", err),
+            Error::Bind(err) => write!(f, "bind error: {}", err),
+            Error::Getsockname(err) => write!(f, "getsockname error: {}", err),
+            Error::Setsockopt(err) => write!(f, "setsockopt error: {}", err),
+            Error::Sendto(err) => write!(f, "sendto error: {}", err),
+            Error::Recvfrom(err) => write!(f, "recvfrom error: {}", err),
+            Error::InvalidAddrFamily(af) => write!(f, "invalid address family {}", af),
+        }
+    }
+}
+
+impl error::Error for Error {}
+
+/// Creates an unbound `SOCK_DGRAM` socket for `family`.
+///
+/// The kernel binds it to an ephemeral port on the first `sendto()`.
+pub fn udp_socket(family: i32) -> Result<i32, Error> {
+    // SAFETY: `socket()` does not touch any memory owned by the process.
+    let sock_fd = unsafe { trace::socket(family, libc::SOCK_DGRAM, 0) };
+    match sock_fd {
+        -1 => Err(Error::Socket(io::Error::last_os_error())),
+        _ => Ok(sock_fd),
+    }
+}
+
+/// Creates a `SOCK_DGRAM` socket bound to `node` and `port`.
+///
+/// If `node` is `None`, the socket is bound to the wildcard address (`AI_PASSIVE`).
+/// Each address returned by `getaddrinfo()` is tried in order until one of them can be bound.
+/// Pass `c"0"` as `port` to let the kernel pick a free one.
+///
+/// Returns the sock fd and the address it is actually bound to.
+pub fn bind_udp(node: Option<&CStr>, port: &CStr) -> Result<(i32, SocketAddr), Error> {
+    let ai_list = getaddrinfo(node, port)?;
+
+    let mut res = Err(Error::Socket(io::Error::from(
+        io::ErrorKind::AddrNotAvailable,
+    )));
+
+    for ai in &ai_list {
+        res = bind_addrinfo(ai);
+        if res.is_ok() {
+            break;
+        }
+    }
+
+    res
+}
+
+/// Resolves `host` and `port` to the first `SOCK_DGRAM` address returned by `getaddrinfo()`.
+pub fn resolve(host: &CStr, port: &CStr) -> Result<SocketAddr, Error> {
+    let ai_list = getaddrinfo(Some(host), port)?;
+    let ai = ai_list.first();
+
+    // SAFETY: `ai.ai_addr` is filled by `getaddrinfo()` and it is valid for `ai.ai_addrlen` bytes.
+    let sa = unsafe { sockaddr::copy_to_storage(ai.ai_addr, ai.ai_addrlen) };
+
+    sockaddr::to_socket_addr(&sa).ok_or(Error::InvalidAddrFamily(ai.ai_family))
+}
+
+fn getaddrinfo(node: Option<&CStr>, port: &CStr) -> Result<AddrInfoList, Error> {
+    let hints = Hints::new()
+        .family(libc::AF_UNSPEC)
+        .socktype(libc::SOCK_DGRAM)
+        .passive(node.is_none())
+        .build();
+
+    AddrInfoList::new(node, Some(port), &hints).map_err(Error::Getaddrinfo)
+}
+
+fn bind_addrinfo(ai: &libc::addrinfo) -> Result<(i32, SocketAddr), Error> {
+    let sock_fd = udp_socket(ai.ai_family)?;
+
+    // SAFETY: `bind()` is safe to call since `sock_fd` and `ai` are valid.
+    let ecode = unsafe { trace::bind(sock_fd, ai.ai_addr, ai.ai_addrlen) };
+    let res = match ecode {
+        -1 => Err(Error::Bind(io::Error::last_os_error())),
+        _ => local_addr(sock_fd),
+    };
+
+    // A failed candidate is closed, so that it does not leak its fd.
+    if res.is_err() {
+        let _ = fd::close_fd(sock_fd);
+    }
+
+    res.map(|addr| (sock_fd, addr))
+}
+
+/// Makes `recv_from()` on `sock_fd` give up after `timeout` by setting `SO_RCVTIMEO`.
+///
+/// A timed out `recv_from()` fails with `EAGAIN`/`EWOULDBLOCK` (`io::ErrorKind::WouldBlock`).
+pub fn set_recv_timeout(sock_fd: i32, timeout: Duration) -> Result<(), Error> {
+    sockopt::set_timeout(sock_fd, libc::SO_RCVTIMEO, timeout).map_err(Error::Setsockopt)
+}
+
+/// Returns the address `sock_fd` is bound to by using `getsockname()`.
+pub fn local_addr(sock_fd: i32) -> Result<SocketAddr, Error> {
+    // SAFETY: All zero `sockaddr_storage` is a valid initialization.
+    // Read will happen after it is written by `getsockname()`.
+    let mut sa: libc::sockaddr_storage = unsafe { mem::zeroed() };
+    let mut sa_len = mem::size_of_val(&sa) as libc::socklen_t;
+
+    // SAFETY: `sa` is big enough for any address family, and `sa_len` holds its size.
+    let ecode =
+        unsafe { libc::getsockname(sock_fd, &raw mut sa as *mut libc::sockaddr, &raw mut sa_len) };
+    if ecode == -1 {
+        return Err(Error::Getsockname(io::Error::last_os_error()));
+    }
+
+    sockaddr::to_socket_addr(&sa).ok_or(Error::InvalidAddrFamily(sa.ss_family as i32))
+}
+
+/// Sends `buf` as a single datagram to `addr`.
+///
+/// Returns the number of bytes sent, which is always `buf.len()`.
+/// A datagram that is too large or only partially sent is an error, see `inet::check_sendto()`.
+pub fn send_to(sock_fd: i32, buf: &[u8], addr: &SocketAddr) -> Result<usize, Error> {
+    let (sa, sa_len) = sockaddr::from_socket_addr(addr);
+
+    // SAFETY: `buf` is a valid, initialized slice and `sa` holds a valid address of `sa_len` bytes.
+    let bytes = unsafe {
+        trace::sendto(
+            sock_fd,
+            buf.as_ptr() as *const libc::c_void,
+            buf.len(),
+            0,
+            &raw const sa as *const libc::sockaddr,
+            sa_len,
+        )
+    };
+    inet::check_sendto(bytes, buf.len()).map_err(Error::Sendto)
+}
+
+/// Receives a single datagram into `buf`.
+///
+/// Returns the number of bytes received and the address of the sender.
+pub fn recv_from(sock_fd: i32, buf: &mut [u8]) -> Result<(usize, SocketAddr), Error> {
+    // SAFETY: All zero `sockaddr_storage` is a valid initialization.
+    // Read will happen after it is written by `recvfrom()`.
+    let mut sa: libc::sockaddr_storage = unsafe { mem::zeroed() };
+    let mut sa_len = mem::size_of_val(&sa) as libc::socklen_t;
+
+    // SAFETY: `buf` is a valid, initialized slice and `sa` is big enough for any address family.
+    let bytes = unsafe {
+        trace::recvfrom(
+            sock_fd,
+            buf.as_mut_ptr() as *mut libc::c_void,
+            buf.len(),
+            0,
+            &raw mut sa as *mut libc::sockaddr,
+            &raw mut sa_len,
+        )
+    };
+    if bytes == -1 {
+        return Err(Error::Recvfrom(io::Error::last_os_error()));
+    }
+
+    let addr =
+        sockaddr::to_socket_addr(&sa).ok_or(Error::InvalidAddrFamily(sa.ss_family as i32))?;
+
+    Ok((bytes as usize, addr))
+}
+
+/// Waits for the next datagram and returns its size without consuming it.
+///
+/// A datagram that does not fit into the buffer passed to `recvfrom()` is truncated, and the rest of it is lost.
+/// On Linux, `recv()` with `MSG_PEEK | MSG_TRUNC` returns the real size of the datagram instead,
+/// so that a buffer of exactly that size can be allocated before receiving it.
+/// The other platforms do not report it, `None` is returned there.
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub fn peek_datagram_size(sock_fd: i32) -> Result<Option<usize>, Error> {
+    // SAFETY: Nothing is copied into the buffer since its length is 0, a null pointer is valid for it.
+    let bytes = unsafe {
+        trace::recv(
+            sock_fd,
+            std::ptr::null_mut(),
+            0,
+            libc::MSG_PEEK | libc::MSG_TRUNC,
+        )
+    };
+    if bytes == -1 {
+        return Err(Error::Recvfrom(io::Error::last_os_error()));
+    }
+
+    Ok(Some(bytes as usize))
+}
+
+/// Waits for the next datagram and returns its size without consuming it.
+///
+/// This platform cannot report the size of a datagram without receiving it, `None` is always returned.
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+pub fn peek_datagram_size(_sock_fd: i32) -> Result<Option<usize>, Error> {
+    Ok(None)
+}
+
+/// Receives a single datagram into a buffer that is sized for it, so that it is never truncated.
+///
+/// Where `peek_datagram_size()` cannot tell the size, a `fallback_size` byte buffer is used instead.
+///
+/// Returns the datagram and the address of the sender.
+pub fn recv_datagram(sock_fd: i32, fallback_size: usize) -> Result<(Vec<u8>, SocketAddr), Error> {
+    let size = peek_datagram_size(sock_fd)?.unwrap_or(fallback_size);
+
+    let mut buf = vec![0u8; size];
+    let (rbytes, addr) = recv_from(sock_fd, &mut buf)?;
+    buf.truncate(rbytes);
+
+    Ok((buf, addr))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Larger than any default buffer in the examples, but small enough for the default `SO_RCVBUF`.
+    const LARGE: usize = 60_000;
+
+    fn large_datagram() -> Vec<u8> {
+        (0..LARGE).map(|i| (i % 251) as u8).collect()
+    }
+
+    #[test]
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    fn large_datagram_is_received_intact() {
+        let (receiver_fd, receiver_addr) = bind_udp(Some(c"127.0.0.1"), c"0").unwrap();
+        let sender_fd = udp_socket(libc::AF_INET).unwrap();
+        let sent = large_datagram();
+        assert_eq!(send_to(sender_fd, &sent, &receiver_addr).unwrap(), LARGE);
+
+        // Peeking does not consume the datagram.
+        assert_eq!(peek_datagram_size(receiver_fd).unwrap(), Some(LARGE));
+        let (received, from) = recv_datagram(receiver_fd, 100).unwrap();
+
+        assert_eq!(received, sent);
+        // The sender is bound to the wildcard address by `sendto()`, only its port is known up front.
+        assert_eq!(from.port(), local_addr(sender_fd).unwrap().port());
+
+        fd::close_fd(sender_fd).unwrap();
+        fd::close_fd(receiver_fd).unwrap();
+    }
+
+    #[test]
+    fn small_buffer_truncates_the_datagram() {
+        let (receiver_fd, receiver_addr) = bind_udp(Some(c"127.0.0.1"), c"0").unwrap();
+        let sender_fd = udp_socket(libc::AF_INET).unwrap();
+        let sent = large_datagram();
+        send_to(sender_fd, &sent, &receiver_addr).unwrap();
+        send_to(sender_fd, b"next", &receiver_addr).unwrap();
+
+        let mut buf = [0u8; 100];
+        let (rbytes, _) = recv_from(receiver_fd, &mut buf).unwrap();
+        assert_eq!(buf[..rbytes], sent[..100]);
+
+        // The rest of the truncated datagram is lost, the next receive gets the next datagram.
+        let (rbytes, _) = recv_from(receiver_fd, &mut buf).unwrap();
+        assert_eq!(&buf[..rbytes], b"next");
+
+        fd::close_fd(sender_fd).unwrap();
+        fd::close_fd(receiver_fd).unwrap();
+    }
+}
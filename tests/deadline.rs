@@ -0,0 +1,27 @@
+// The deadline is set once for the whole process, so these tests have a test binary of their own.
+
+use std::time::{Duration, Instant};
+
+use bjrs::{deadline, syscall};
+
+const DEADLINE: Duration = Duration::from_millis(200);
+
+#[test]
+fn recv_times_out_when_nothing_connects() {
+    deadline::set(DEADLINE);
+    let started_at = Instant::now();
+
+    let err = syscall::recv(false).unwrap_err();
+
+    assert!(
+        err.to_string()
+            .contains("nothing arrived before the 200ms deadline"),
+        "unexpected error: {}",
+        err
+    );
+    assert!(
+        started_at.elapsed() >= DEADLINE - Duration::from_millis(50),
+        "gave up after {:?}",
+        started_at.elapsed()
+    );
+}
@@ -0,0 +1,103 @@
+// `RLIMIT_NOFILE` is a limit of the whole process, so this test has a test binary of its own.
+#![cfg(target_os = "linux")]
+
+use std::{fs, io, net::SocketAddr, thread};
+
+use bjrs::{client_builder::Client, server_builder::Server, socket_fd::Socket, stream_io};
+
+const CLIENTS: usize = 6;
+
+fn connect(addr: SocketAddr) -> Socket {
+    let (sock, _) = Client::new()
+        .host("127.0.0.1")
+        .port(addr.port())
+        .connect()
+        .unwrap();
+    sock
+}
+
+// Whether the server greeted the client, a turned away client sees an EOF (or a reset) instead.
+fn is_served(sock: &Socket) -> bool {
+    match stream_io::recv_until(sock.as_raw(), &mut Vec::new(), b'\n') {
+        Ok(Some(line)) => {
+            assert_eq!(line, b"hi");
+            true
+        }
+        Ok(None) => false,
+        Err(err) if stream_io::is_reset(&err) => false,
+        Err(err) => panic!("recv error: {}", err),
+    }
+}
+
+fn nofile_limit() -> libc::rlimit {
+    let mut limit = libc::rlimit {
+        rlim_cur: 0,
+        rlim_max: 0,
+    };
+    // SAFETY: `limit` is an initialized `rlimit` for `getrlimit()` to write to.
+    let ecode = unsafe { libc::getrlimit(libc::RLIMIT_NOFILE, &mut limit) };
+    assert_eq!(ecode, 0, "getrlimit error: {}", io::Error::last_os_error());
+    limit
+}
+
+fn set_nofile_limit(limit: &libc::rlimit) {
+    // SAFETY: `limit` is an initialized `rlimit`.
+    let ecode = unsafe { libc::setrlimit(libc::RLIMIT_NOFILE, limit) };
+    assert_eq!(ecode, 0, "setrlimit error: {}", io::Error::last_os_error());
+}
+
+// The highest fd that is open in the process.
+fn max_open_fd() -> u64 {
+    fs::read_dir("/proc/self/fd")
+        .unwrap()
+        .map(|entry| {
+            entry
+                .unwrap()
+                .file_name()
+                .to_str()
+                .unwrap()
+                .parse()
+                .unwrap()
+        })
+        .max()
+        .unwrap()
+}
+
+#[test]
+fn server_survives_running_out_of_fds() {
+    let listener = Server::new()
+        .bind(Some("127.0.0.1"), 0)
+        .backlog(CLIENTS as i32 + 1)
+        .emfile_guard(true)
+        .listen()
+        .unwrap();
+    let addr = listener.local_addr();
+
+    // The connections complete in the backlog, so the clients need no fds once the limit is lowered.
+    let clients: Vec<Socket> = (0..CLIENTS).map(|_| connect(addr)).collect();
+
+    // Only room for a few more fds, fewer than there are clients.
+    let original = nofile_limit();
+    set_nofile_limit(&libc::rlimit {
+        rlim_cur: max_open_fd() + 3,
+        ..original
+    });
+
+    thread::spawn(move || {
+        // The served clients are kept open, so that the server keeps running into the limit.
+        let mut served = Vec::new();
+        listener.serve(move |conn_sock, _| {
+            stream_io::sendall(conn_sock.as_raw(), b"hi\n").unwrap();
+            served.push(conn_sock);
+        })
+    });
+
+    let served = clients.iter().filter(|sock| is_served(sock)).count();
+    assert!(served > 0, "no client was served");
+    assert!(served < CLIENTS, "no client was turned away");
+
+    // The server is still accepting once there are fds to spare again. The `accept()` of the guard may have
+    // started before the limit was raised, in which case it still turns away the first client.
+    set_nofile_limit(&original);
+    assert!((0..2).any(|_| is_served(&connect(addr))));
+}
@@ -0,0 +1,258 @@
+use std::{
+    collections::HashMap,
+    net::SocketAddr,
+    num::NonZeroUsize,
+    sync::{Arc, Mutex, mpsc},
+    thread,
+    time::{Duration, Instant},
+};
+
+use bjrs::{
+    chat,
+    client_builder::Client,
+    inet::ConnectError,
+    listener::{self, ListenerOptions},
+    socket_fd::Socket,
+    stream_io,
+    techniques::{PollserverOptions, run_pollserver, run_pollserver_with},
+};
+
+const MOTD: &[u8] = b"welcome\n";
+
+fn listen() -> (i32, SocketAddr) {
+    listener::bind_listener_with(
+        Some(c"127.0.0.1"),
+        libc::AF_INET,
+        c"0",
+        &ListenerOptions::default(),
+    )
+    .unwrap()
+}
+
+fn connect(addr: SocketAddr) -> Socket {
+    let (sock, _) = Client::new()
+        .host("127.0.0.1")
+        .port(addr.port())
+        .connect()
+        .unwrap();
+    sock
+}
+
+fn recv_line(sock: &Socket, pending: &mut Vec<u8>) -> Option<Vec<u8>> {
+    stream_io::recv_until(sock.as_raw(), pending, b'\n').unwrap()
+}
+
+#[test]
+fn relays_a_message_to_the_other_client_and_closes_everything() {
+    let (listener_fd, addr) = listen();
+
+    let opts = PollserverOptions {
+        max_messages: Some(1),
+        motd: Some(MOTD.to_vec()),
+        ..Default::default()
+    };
+    let server = thread::spawn(move || run_pollserver(listener_fd, &opts));
+
+    // The MOTD arrives once the client is accepted, so both clients are polled before anything is sent.
+    let (sender, receiver) = (connect(addr), connect(addr));
+    let (mut sender_pending, mut receiver_pending) = (Vec::new(), Vec::new());
+    assert_eq!(recv_line(&sender, &mut sender_pending).unwrap(), b"welcome");
+    assert_eq!(
+        recv_line(&receiver, &mut receiver_pending).unwrap(),
+        b"welcome"
+    );
+
+    stream_io::sendall(sender.as_raw(), b"hello\n").unwrap();
+    assert_eq!(
+        recv_line(&receiver, &mut receiver_pending).unwrap(),
+        b"hello"
+    );
+
+    let stats = server.join().unwrap().unwrap();
+    assert_eq!(stats.connections, 2);
+    assert_eq!(stats.bytes_in, 6);
+    assert_eq!(stats.bytes_out, 2 * MOTD.len() as u64 + 6);
+
+    // The sender does not get its own message back, and both clients see the server closing them.
+    assert_eq!(recv_line(&sender, &mut sender_pending), None);
+    assert_eq!(recv_line(&receiver, &mut receiver_pending), None);
+
+    // The listener is closed as well.
+    let res = Client::new().host("127.0.0.1").port(addr.port()).connect();
+    assert!(
+        matches!(res, Err(ConnectError::ConnectionRefused(_))),
+        "the listener is still open: {:?}",
+        res.map(|(_, addr)| addr)
+    );
+}
+
+#[test]
+fn reaps_a_client_that_goes_idle() {
+    let (listener_fd, addr) = listen();
+
+    let idle_timeout = Duration::from_millis(300);
+    let opts = PollserverOptions {
+        idle_timeout: Some(idle_timeout),
+        max_messages: Some(1),
+        motd: Some(MOTD.to_vec()),
+        ..Default::default()
+    };
+    let server = thread::spawn(move || run_pollserver(listener_fd, &opts));
+
+    let idle = connect(addr);
+    let mut idle_pending = Vec::new();
+    assert_eq!(recv_line(&idle, &mut idle_pending).unwrap(), b"welcome");
+
+    // The client never sends anything, so the server closes it once `idle_timeout` passes.
+    let idle_since = Instant::now();
+    assert_eq!(recv_line(&idle, &mut idle_pending), None);
+    assert!(
+        idle_since.elapsed() >= idle_timeout - Duration::from_millis(50),
+        "reaped after {:?}",
+        idle_since.elapsed()
+    );
+
+    // An active client keeps the server going until it relays its message.
+    let active = connect(addr);
+    let mut active_pending = Vec::new();
+    assert_eq!(recv_line(&active, &mut active_pending).unwrap(), b"welcome");
+    stream_io::sendall(active.as_raw(), b"still here\n").unwrap();
+
+    let stats = server.join().unwrap().unwrap();
+    assert_eq!(stats.connections, 2);
+    assert_eq!(stats.clients(), 1);
+}
+
+#[test]
+fn every_flooding_client_makes_progress() {
+    const CLIENTS: usize = 4;
+    const MESSAGES: u64 = 200;
+
+    let (listener_fd, addr) = listen();
+
+    // Each client queues far more than the server reads before it stops, before the server even starts.
+    // The connections complete in the backlog, and the data fits into the receive buffers,
+    // so every client is flooding the server from its very first `poll()` call.
+    let clients: Vec<Socket> = (0..CLIENTS)
+        .map(|_| {
+            let sock = connect(addr);
+            stream_io::sendall(sock.as_raw(), &[b'x'; 32 * 1024]).unwrap();
+            sock
+        })
+        .collect();
+
+    // Only one ready client is served per `poll()` call, and nothing is relayed.
+    let opts = PollserverOptions {
+        max_events: NonZeroUsize::new(1),
+        max_messages: Some(MESSAGES),
+        ..Default::default()
+    };
+    let served = Arc::new(Mutex::new(HashMap::<i32, u64>::new()));
+    let server = {
+        let served = Arc::clone(&served);
+        thread::spawn(move || {
+            run_pollserver_with(listener_fd, &opts, move |sender_fd, _: &[u8], _: &[i32]| {
+                *served.lock().unwrap().entry(sender_fd).or_default() += 1;
+                Vec::new()
+            })
+        })
+    };
+
+    server.join().unwrap().unwrap();
+    drop(clients);
+
+    let served = served.lock().unwrap();
+    assert_eq!(served.len(), CLIENTS, "served: {:?}", served);
+    for (fd, count) in served.iter() {
+        assert!(
+            *count >= MESSAGES / CLIENTS as u64 / 2,
+            "socket {} was served {} times: {:?}",
+            fd,
+            count,
+            served
+        );
+    }
+}
+
+#[test]
+fn removes_a_client_that_resets_its_connection() {
+    let (listener_fd, addr) = listen();
+
+    let opts = PollserverOptions {
+        max_messages: Some(1),
+        motd: Some(MOTD.to_vec()),
+        ..Default::default()
+    };
+    let server = thread::spawn(move || run_pollserver(listener_fd, &opts));
+
+    let reset = connect(addr);
+    assert_eq!(recv_line(&reset, &mut Vec::new()).unwrap(), b"welcome");
+
+    // With a zero linger time, closing the socket sends a `RST` instead of a `FIN`.
+    let linger = libc::linger {
+        l_onoff: 1,
+        l_linger: 0,
+    };
+    // SAFETY: `linger` is an initialized `linger`, and its size is passed along with it.
+    let res = unsafe {
+        libc::setsockopt(
+            reset.as_raw(),
+            libc::SOL_SOCKET,
+            libc::SO_LINGER,
+            &raw const linger as *const libc::c_void,
+            size_of::<libc::linger>() as libc::socklen_t,
+        )
+    };
+    assert_eq!(res, 0);
+    drop(reset);
+
+    // The server keeps serving the other clients after the reset.
+    let active = connect(addr);
+    let mut active_pending = Vec::new();
+    assert_eq!(recv_line(&active, &mut active_pending).unwrap(), b"welcome");
+    stream_io::sendall(active.as_raw(), b"still here\n").unwrap();
+
+    let stats = server.join().unwrap().unwrap();
+    assert_eq!(stats.connections, 2);
+    assert_eq!(stats.clients(), 1);
+}
+
+#[test]
+fn late_client_gets_the_motd_before_the_broadcasts() {
+    let (listener_fd, addr) = listen();
+
+    let opts = PollserverOptions {
+        max_messages: Some(2),
+        motd: Some(MOTD.to_vec()),
+        ..Default::default()
+    };
+    // Reports every handled message, so that the test knows when the server is done with one.
+    let (handled_tx, handled) = mpsc::channel();
+    let server = thread::spawn(move || {
+        run_pollserver_with(
+            listener_fd,
+            &opts,
+            move |sender_fd, msg: &[u8], clients: &[i32]| {
+                let _ = handled_tx.send(());
+                chat::broadcast_to_others(sender_fd, msg, clients)
+            },
+        )
+    });
+
+    let early = connect(addr);
+    let mut early_pending = Vec::new();
+    assert_eq!(recv_line(&early, &mut early_pending).unwrap(), b"welcome");
+    // Nobody else is connected yet, so this message is not relayed to anyone.
+    stream_io::sendall(early.as_raw(), b"first\n").unwrap();
+    handled.recv().unwrap();
+
+    let late = connect(addr);
+    let mut late_pending = Vec::new();
+    assert_eq!(recv_line(&late, &mut late_pending).unwrap(), b"welcome");
+
+    stream_io::sendall(early.as_raw(), b"second\n").unwrap();
+    assert_eq!(recv_line(&late, &mut late_pending).unwrap(), b"second");
+
+    let stats = server.join().unwrap().unwrap();
+    assert_eq!(stats.bytes_out, 2 * MOTD.len() as u64 + 7);
+}
@@ -0,0 +1,209 @@
+use std::{
+    net::SocketAddr,
+    thread,
+    time::{Duration, Instant},
+};
+
+use bjrs::{
+    client_builder::Client,
+    inet::ConnectError,
+    listener::{self, ListenerOptions},
+    socket_fd::Socket,
+    stream_io,
+    techniques::{Bounds, SelectserverOptions, run_selectserver, run_selectserver_with},
+};
+
+const MOTD: &[u8] = b"welcome\n";
+
+fn connect(addr: SocketAddr) -> Socket {
+    let (sock, _) = Client::new()
+        .host("127.0.0.1")
+        .port(addr.port())
+        .connect()
+        .unwrap();
+    sock
+}
+
+fn recv_line(sock: &Socket, pending: &mut Vec<u8>) -> Option<Vec<u8>> {
+    stream_io::recv_until(sock.as_raw(), pending, b'\n').unwrap()
+}
+
+#[test]
+fn relays_a_message_to_the_other_client_and_closes_everything() {
+    let (listener_fd, addr) = listener::bind_listener_with(
+        Some(c"127.0.0.1"),
+        libc::AF_INET,
+        c"0",
+        &ListenerOptions::default(),
+    )
+    .unwrap();
+
+    let opts = SelectserverOptions {
+        bounds: Bounds {
+            broadcasts: Some(1),
+            duration: Some(Duration::from_secs(10)),
+        },
+        motd: Some(MOTD.to_vec()),
+        ..Default::default()
+    };
+    let server = thread::spawn(move || run_selectserver(listener_fd, &opts));
+
+    // The MOTD arrives once the client is accepted, so both clients are in the fd set before anything is sent.
+    let (sender, receiver) = (connect(addr), connect(addr));
+    let (mut sender_pending, mut receiver_pending) = (Vec::new(), Vec::new());
+    assert_eq!(recv_line(&sender, &mut sender_pending).unwrap(), b"welcome");
+    assert_eq!(
+        recv_line(&receiver, &mut receiver_pending).unwrap(),
+        b"welcome"
+    );
+
+    stream_io::sendall(sender.as_raw(), b"hello\n").unwrap();
+    assert_eq!(
+        recv_line(&receiver, &mut receiver_pending).unwrap(),
+        b"hello"
+    );
+
+    let stats = server.join().unwrap().unwrap();
+    assert_eq!(stats.connections, 2);
+    assert_eq!(stats.bytes_in, 6);
+    assert_eq!(stats.bytes_out, 2 * MOTD.len() as u64 + 6);
+
+    // The sender does not get its own message back, and both clients see the server closing them.
+    assert_eq!(recv_line(&sender, &mut sender_pending), None);
+    assert_eq!(recv_line(&receiver, &mut receiver_pending), None);
+
+    // The listener is closed as well.
+    let res = Client::new().host("127.0.0.1").port(addr.port()).connect();
+    assert!(
+        matches!(res, Err(ConnectError::ConnectionRefused(_))),
+        "the listener is still open: {:?}",
+        res.map(|(_, addr)| addr)
+    );
+}
+
+#[test]
+fn custom_handler_echoes_only_to_the_sender() {
+    let (listener_fd, addr) = listener::bind_listener_with(
+        Some(c"127.0.0.1"),
+        libc::AF_INET,
+        c"0",
+        &ListenerOptions::default(),
+    )
+    .unwrap();
+
+    let opts = SelectserverOptions {
+        bounds: Bounds {
+            broadcasts: Some(1),
+            duration: Some(Duration::from_secs(10)),
+        },
+        motd: Some(MOTD.to_vec()),
+        ..Default::default()
+    };
+    let server = thread::spawn(move || {
+        run_selectserver_with(listener_fd, &opts, |sender_fd, msg: &[u8], _: &[i32]| {
+            vec![(sender_fd, msg.to_vec())]
+        })
+    });
+
+    let (sender, other) = (connect(addr), connect(addr));
+    let (mut sender_pending, mut other_pending) = (Vec::new(), Vec::new());
+    assert_eq!(recv_line(&sender, &mut sender_pending).unwrap(), b"welcome");
+    assert_eq!(recv_line(&other, &mut other_pending).unwrap(), b"welcome");
+
+    stream_io::sendall(sender.as_raw(), b"hello\n").unwrap();
+    assert_eq!(recv_line(&sender, &mut sender_pending).unwrap(), b"hello");
+
+    let stats = server.join().unwrap().unwrap();
+    assert_eq!(stats.bytes_out, 2 * MOTD.len() as u64 + 6);
+
+    // The other client sees the server closing it without getting the message.
+    assert_eq!(recv_line(&other, &mut other_pending), None);
+    assert!(other_pending.is_empty());
+    assert_eq!(recv_line(&sender, &mut sender_pending), None);
+}
+
+#[test]
+fn reaps_a_client_that_goes_idle() {
+    let (listener_fd, addr) = listener::bind_listener_with(
+        Some(c"127.0.0.1"),
+        libc::AF_INET,
+        c"0",
+        &ListenerOptions::default(),
+    )
+    .unwrap();
+
+    let idle_timeout = Duration::from_millis(300);
+    let opts = SelectserverOptions {
+        idle_timeout: Some(idle_timeout),
+        bounds: Bounds {
+            broadcasts: Some(1),
+            duration: Some(Duration::from_secs(10)),
+        },
+        motd: Some(MOTD.to_vec()),
+        ..Default::default()
+    };
+    let server = thread::spawn(move || run_selectserver(listener_fd, &opts));
+
+    let idle = connect(addr);
+    let mut idle_pending = Vec::new();
+    assert_eq!(recv_line(&idle, &mut idle_pending).unwrap(), b"welcome");
+    let idle_since = Instant::now();
+
+    // The other client joins later, so it is not idle yet when the first one is reaped.
+    thread::sleep(idle_timeout / 2);
+    let active = connect(addr);
+    let mut active_pending = Vec::new();
+    assert_eq!(recv_line(&active, &mut active_pending).unwrap(), b"welcome");
+
+    // The client never sends anything, so the server closes it once `idle_timeout` passes.
+    assert_eq!(recv_line(&idle, &mut idle_pending), None);
+    assert!(
+        idle_since.elapsed() >= idle_timeout - Duration::from_millis(50),
+        "reaped after {:?}",
+        idle_since.elapsed()
+    );
+    let notice = recv_line(&active, &mut active_pending).unwrap();
+    assert!(
+        notice.ends_with(b"timed out"),
+        "unexpected notice: {}",
+        String::from_utf8_lossy(&notice)
+    );
+
+    // The active client keeps the server going until it relays its message.
+    stream_io::sendall(active.as_raw(), b"still here\n").unwrap();
+
+    let stats = server.join().unwrap().unwrap();
+    assert_eq!(stats.connections, 2);
+    assert_eq!(stats.clients(), 1);
+}
+
+#[test]
+fn drains_the_pending_connections_on_exit() {
+    let (listener_fd, addr) = listener::bind_listener_with(
+        Some(c"127.0.0.1"),
+        libc::AF_INET,
+        c"0",
+        &ListenerOptions::default(),
+    )
+    .unwrap();
+
+    // The handshake completes in the backlog, and the server stops before it ever accepts the client.
+    let client = connect(addr);
+    let opts = SelectserverOptions {
+        bounds: Bounds {
+            broadcasts: None,
+            duration: Some(Duration::ZERO),
+        },
+        drain_on_exit: Some(Duration::from_secs(1)),
+        ..Default::default()
+    };
+    let stats = run_selectserver(listener_fd, &opts).unwrap();
+    assert_eq!(stats.connections, 0);
+
+    let mut pending = Vec::new();
+    assert_eq!(
+        recv_line(&client, &mut pending).unwrap(),
+        b"server shutting down"
+    );
+    assert_eq!(recv_line(&client, &mut pending), None);
+}
@@ -0,0 +1,141 @@
+use bjrs::serialize::{
+    crc32, pack754_32, pack754_64, packi16, packi32, packi64, unpack754_32, unpack754_64,
+    unpacki16, unpacki32, unpacki64, unpacku16, unpacku32, unpacku64,
+};
+
+/// A message with a field of every packed type, the string is prefixed with its length as a u16.
+#[derive(Debug, PartialEq)]
+struct Message {
+    id: u16,
+    offset: i32,
+    ratio: f32,
+    name: String,
+}
+
+impl Message {
+    fn pack(&self) -> Vec<u8> {
+        let mut buf = vec![0u8; 2 + 4 + 4 + 2 + self.name.len()];
+        packi16(&mut buf[0..], self.id);
+        packi32(&mut buf[2..], self.offset as u32);
+        pack754_32(&mut buf[6..], self.ratio);
+        packi16(&mut buf[10..], self.name.len() as u16);
+        buf[12..].copy_from_slice(self.name.as_bytes());
+        buf
+    }
+
+    fn unpack(buf: &[u8]) -> Self {
+        let len = unpacku16(&buf[10..]) as usize;
+        Self {
+            id: unpacku16(&buf[0..]),
+            offset: unpacki32(&buf[2..]),
+            ratio: unpack754_32(&buf[6..]),
+            name: String::from_utf8(buf[12..12 + len].to_vec()).unwrap(),
+        }
+    }
+}
+
+#[test]
+fn struct_round_trip() {
+    let msg = Message {
+        id: 0x0102,
+        offset: -3490,
+        ratio: 0.125,
+        name: String::from("beej"),
+    };
+
+    assert_eq!(Message::unpack(&msg.pack()), msg);
+}
+
+#[test]
+fn big_endian_golden_buffer() {
+    let msg = Message {
+        id: 0x0102,
+        offset: -2,
+        ratio: 1.5,
+        name: String::from("hi"),
+    };
+
+    #[rustfmt::skip]
+    let golden = [
+        0x01, 0x02,             // id
+        0xFF, 0xFF, 0xFF, 0xFE, // offset, two's complement
+        0x3F, 0xC0, 0x00, 0x00, // ratio, IEEE 754 single
+        0x00, 0x02,             // name length
+        b'h', b'i',             // name
+    ];
+
+    assert_eq!(msg.pack(), golden);
+}
+
+#[test]
+fn integers_are_packed_most_significant_byte_first() {
+    let mut buf = [0u8; 8];
+
+    packi16(&mut buf, 0x1234);
+    assert_eq!(buf[..2], [0x12, 0x34]);
+
+    packi32(&mut buf, 0x1234_5678);
+    assert_eq!(buf[..4], [0x12, 0x34, 0x56, 0x78]);
+
+    packi64(&mut buf, 0x0102_0304_0506_0708);
+    assert_eq!(buf, [0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08]);
+
+    pack754_64(&mut buf, 1.5);
+    assert_eq!(buf, [0x3F, 0xF8, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00]);
+}
+
+#[test]
+fn max_values_round_trip() {
+    let mut buf = [0u8; 8];
+
+    packi16(&mut buf, u16::MAX);
+    assert_eq!(buf[..2], [0xFF; 2]);
+    assert_eq!(unpacku16(&buf), u16::MAX);
+    assert_eq!(unpacki16(&buf), -1);
+
+    packi16(&mut buf, i16::MIN as u16);
+    assert_eq!(buf[..2], [0x80, 0x00]);
+    assert_eq!(unpacki16(&buf), i16::MIN);
+
+    packi32(&mut buf, u32::MAX);
+    assert_eq!(unpacku32(&buf), u32::MAX);
+    packi32(&mut buf, i32::MIN as u32);
+    assert_eq!(buf[..4], [0x80, 0x00, 0x00, 0x00]);
+    assert_eq!(unpacki32(&buf), i32::MIN);
+
+    packi64(&mut buf, u64::MAX);
+    assert_eq!(unpacku64(&buf), u64::MAX);
+    packi64(&mut buf, i64::MIN as u64);
+    assert_eq!(unpacki64(&buf), i64::MIN);
+
+    for f in [f32::MAX, f32::MIN, f32::MIN_POSITIVE, f32::INFINITY, -0.0] {
+        pack754_32(&mut buf, f);
+        assert_eq!(unpack754_32(&buf).to_bits(), f.to_bits());
+    }
+    for f in [f64::MAX, f64::MIN, f64::MIN_POSITIVE, f64::NEG_INFINITY] {
+        pack754_64(&mut buf, f);
+        assert_eq!(unpack754_64(&buf).to_bits(), f.to_bits());
+    }
+
+    pack754_32(&mut buf, f32::NAN);
+    assert!(unpack754_32(&buf).is_nan());
+}
+
+#[test]
+fn crc32_check_value() {
+    assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+    assert_eq!(crc32(b""), 0);
+}
+
+#[test]
+#[should_panic]
+fn unpack_panics_on_short_input() {
+    unpacku32(&[0x01, 0x02, 0x03]);
+}
+
+#[test]
+#[should_panic]
+fn pack_panics_on_short_buffer() {
+    let mut buf = [0u8; 1];
+    packi16(&mut buf, 1);
+}
@@ -0,0 +1,56 @@
+// A shutdown request is process-wide, so these tests have a test binary of their own.
+
+use std::time::Duration;
+
+use bjrs::{
+    client_builder::Client,
+    listener::{self, BindRetry, ListenerOptions},
+    signal, stream, stream_io,
+};
+
+#[test]
+fn drains_the_pending_connections_on_shutdown() {
+    let (listener_fd, addr) = listener::bind_listener_with(
+        Some(c"127.0.0.1"),
+        libc::AF_INET,
+        c"0",
+        &ListenerOptions::default(),
+    )
+    .unwrap();
+
+    // The handshake completes in the backlog, and the server is asked to stop before it accepts the client.
+    let (client, _) = Client::new()
+        .host("127.0.0.1")
+        .port(addr.port())
+        .connect()
+        .unwrap();
+    signal::install_shutdown_handler().unwrap();
+    // SAFETY: The shutdown handler is installed, so `SIGINT` only sets the shutdown flag.
+    assert_eq!(unsafe { libc::raise(libc::SIGINT) }, 0);
+    assert!(signal::shutdown_requested());
+
+    let opts = stream::ServerOptions {
+        drain_on_exit: Some(Duration::from_secs(1)),
+        ..Default::default()
+    };
+    stream::server(
+        0,
+        &BindRetry::default(),
+        Some(listener_fd),
+        false,
+        &opts,
+        None,
+    )
+    .unwrap();
+
+    // The client is told why it is dropped instead of receiving "Hello world!" or being reset.
+    let mut pending = Vec::new();
+    assert_eq!(
+        stream_io::recv_until(client.as_raw(), &mut pending, b'\n').unwrap(),
+        Some(b"server shutting down".to_vec())
+    );
+    assert_eq!(
+        stream_io::recv_until(client.as_raw(), &mut pending, b'\n').unwrap(),
+        None
+    );
+}